@@ -1,6 +1,16 @@
+pub mod admin;
 pub mod command;
 pub mod config;
+mod ledger;
+pub mod metrics;
 mod rate;
+mod rate_limit;
+mod spread;
+mod tiers;
 pub mod tracing;
 
+pub use ledger::Ledger;
 pub use rate::Rate;
+pub use rate_limit::RateLimit;
+pub use spread::{Inventory, SpreadCurve};
+pub use tiers::{VolumeTier, VolumeTiers};
@@ -0,0 +1,495 @@
+//! The admin API of the ASB.
+//!
+//! Allows changing the settings of a running ASB and triggering the manual
+//! recovery actions without restarting it. The API is served as JSON-RPC and
+//! every connection has to authenticate with the token stored in the data
+//! directory first.
+use crate::database::{Database, Swap};
+use crate::protocol::alice::event_loop::{EventLoopController, MedianRate};
+use crate::protocol::alice::redeem::Finality;
+use crate::protocol::alice::AliceState;
+use crate::protocol::bob::BobState;
+use crate::protocol::{alice, bob};
+use crate::rpc::{params, Error, Handler, Subscriber};
+use crate::{bitcoin, monero, rpc};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+const TOKEN_FILE_NAME: &str = "admin-token";
+
+/// Reads the token of the admin API from the data directory, generating it
+/// if it does not exist yet.
+pub fn read_or_generate_token(data_dir: &Path) -> Result<String> {
+    rpc::read_or_generate_token(&data_dir.join(TOKEN_FILE_NAME))
+}
+
+/// Keeps track of the swaps that are currently executed, so that they can be
+/// stopped before a manual recovery action is applied to them.
+#[derive(Clone, Debug, Default)]
+pub struct RunningSwaps {
+    inner: Arc<Mutex<HashMap<Uuid, StopSender>>>,
+}
+
+type StopSender = Arc<watch::Sender<bool>>;
+
+impl RunningSwaps {
+    /// Spawns the future returned by `swap`, which has to finish once `true`
+    /// is sent through the given receiver.
+    pub fn spawn<S, F>(&self, swap_id: Uuid, swap: S)
+    where
+        S: FnOnce(watch::Receiver<bool>) -> F,
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let (sender, receiver) = watch::channel(false);
+        let sender = Arc::new(sender);
+        let swap = swap(receiver);
+
+        // Holding the lock while spawning ensures that the task cannot remove
+        // itself before it was inserted.
+        let mut swaps = self.lock();
+
+        let running_swaps = self.clone();
+        let stop = sender.clone();
+        tokio::spawn(async move {
+            swap.await;
+
+            running_swaps.remove(swap_id, &stop);
+        });
+
+        swaps.insert(swap_id, sender);
+    }
+
+    /// Asks the given swap to stop and waits until it did, returns whether it
+    /// was running.
+    ///
+    /// If the swap is in the middle of publishing a transaction this takes
+    /// until the transition is finished and persisted.
+    pub async fn stop(&self, swap_id: Uuid) -> bool {
+        let stop = match self.lock().get(&swap_id) {
+            Some(stop) => stop.clone(),
+            None => return false,
+        };
+
+        // Fails if the swap just finished, which we wait for below anyway.
+        let _ = stop.send(true);
+        stop.closed().await;
+
+        self.remove(swap_id, &stop);
+
+        true
+    }
+
+    pub fn is_running(&self, swap_id: Uuid) -> bool {
+        self.lock().contains_key(&swap_id)
+    }
+
+    /// Only removes the entry of the swap if it was not resumed in the
+    /// meantime.
+    fn remove(&self, swap_id: Uuid, stop: &StopSender) {
+        let mut swaps = self.lock();
+
+        if swaps
+            .get(&swap_id)
+            .map_or(false, |current| Arc::ptr_eq(current, stop))
+        {
+            swaps.remove(&swap_id);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, StopSender>> {
+        self.inner
+            .lock()
+            .expect("running swaps lock to never be poisoned")
+    }
+}
+
+pub struct Admin {
+    event_loop: EventLoopController,
+    rate: MedianRate,
+    running_swaps: RunningSwaps,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Arc<Database>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResumeOnlyParams {
+    resume_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuyLimitsParams {
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    min_buy_btc: bitcoin::Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    max_buy_btc: bitcoin::Amount,
+}
+
+#[derive(Debug, Deserialize)]
+struct AskSpreadParams {
+    ask_spread: Decimal,
+}
+
+#[derive(Debug, Deserialize)]
+struct SwapIdParams {
+    swap_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManualRecoveryParams {
+    swap_id: Uuid,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedeemParams {
+    swap_id: Uuid,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    do_not_await_finality: bool,
+}
+
+#[async_trait]
+impl Handler for Admin {
+    async fn handle(
+        &self,
+        method: &str,
+        params_value: Value,
+        _subscriber: &Subscriber,
+    ) -> Result<Value, Error> {
+        let result = match method {
+            "list_swaps" => self.list_swaps()?,
+            "set_resume_only" => self.set_resume_only(params(params_value)?).await?,
+            "set_buy_limits" => self.set_buy_limits(params(params_value)?).await?,
+            "set_ask_spread" => self.set_ask_spread(params(params_value)?)?,
+            "cancel" => self.cancel(params(params_value)?).await?,
+            "refund" => self.refund(params(params_value)?).await?,
+            "punish" => self.punish(params(params_value)?).await?,
+            "redeem" => self.redeem(params(params_value)?).await?,
+            "safely_abort" => self.safely_abort(params(params_value)?).await?,
+            method => return Err(Error::method_not_found(method)),
+        };
+
+        Ok(result)
+    }
+}
+
+impl Admin {
+    pub fn new(
+        event_loop: EventLoopController,
+        rate: MedianRate,
+        running_swaps: RunningSwaps,
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        monero_wallet: Arc<monero::Wallet>,
+        db: Arc<Database>,
+    ) -> Self {
+        Self {
+            event_loop,
+            rate,
+            running_swaps,
+            bitcoin_wallet,
+            monero_wallet,
+            db,
+        }
+    }
+
+    /// Lists all unfinished swaps, the ones in which we sell XMR as Alice and
+    /// the ones in which we buy XMR as Bob.
+    fn list_swaps(&self) -> Result<Value> {
+        let alice_swaps = self
+            .db
+            .unfinished_alice()?
+            .into_iter()
+            .map(|(swap_id, state)| (swap_id, "alice", AliceState::from(state).to_string()));
+        let bob_swaps = self
+            .db
+            .unfinished_bob()?
+            .into_iter()
+            .map(|(swap_id, state)| (swap_id, "bob", BobState::from(state).to_string()));
+
+        let swaps = alice_swaps
+            .chain(bob_swaps)
+            .map(|(swap_id, role, state)| {
+                json!({
+                    "swap_id": swap_id,
+                    "role": role,
+                    "state": state,
+                    "running": self.running_swaps.is_running(swap_id),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Value::Array(swaps))
+    }
+
+    async fn set_resume_only(&self, params: ResumeOnlyParams) -> Result<Value> {
+        self.event_loop.set_resume_only(params.resume_only).await?;
+
+        Ok(json!({ "resume_only": params.resume_only }))
+    }
+
+    async fn set_buy_limits(&self, params: BuyLimitsParams) -> Result<Value> {
+        if params.min_buy_btc > params.max_buy_btc {
+            bail!(
+                "Minimum buy amount {} must not be greater than maximum buy amount {}",
+                params.min_buy_btc,
+                params.max_buy_btc
+            )
+        }
+
+        self.event_loop
+            .set_buy_limits(params.min_buy_btc, params.max_buy_btc)
+            .await?;
+
+        Ok(json!({
+            "min_buy_btc": params.min_buy_btc.as_btc(),
+            "max_buy_btc": params.max_buy_btc.as_btc(),
+        }))
+    }
+
+    fn set_ask_spread(&self, params: AskSpreadParams) -> Result<Value> {
+        if params.ask_spread < Decimal::from(0) || params.ask_spread > Decimal::from(1) {
+            bail!(
+                "Invalid spread {}, the spread has to be between 0 and 1",
+                params.ask_spread
+            )
+        }
+
+        self.rate.set_ask_spread(params.ask_spread);
+        tracing::info!(ask_spread = %params.ask_spread, "Changed ask spread");
+
+        Ok(json!({ "ask_spread": params.ask_spread.to_string() }))
+    }
+
+    async fn cancel(&self, params: ManualRecoveryParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+
+        let (txid, state) = if self.is_bob(swap_id)? {
+            self.with_swap_stopped(swap_id, async {
+                let (txid, state) = bob::cancel(
+                    swap_id,
+                    self.bitcoin_wallet.clone(),
+                    self.db.clone(),
+                    params.force,
+                )
+                .await??;
+
+                Ok::<_, anyhow::Error>((txid, state.to_string()))
+            })
+            .await?
+        } else {
+            self.with_swap_stopped(swap_id, async {
+                let (txid, state) = alice::cancel(
+                    swap_id,
+                    self.bitcoin_wallet.clone(),
+                    self.db.clone(),
+                    params.force,
+                )
+                .await??;
+
+                Ok::<_, anyhow::Error>((txid, state.to_string()))
+            })
+            .await?
+        };
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "txid": txid.to_string(),
+            "state": state,
+        }))
+    }
+
+    async fn refund(&self, params: ManualRecoveryParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+
+        let state = if self.is_bob(swap_id)? {
+            self.with_swap_stopped(swap_id, async {
+                let state = bob::refund(
+                    swap_id,
+                    self.bitcoin_wallet.clone(),
+                    self.db.clone(),
+                    params.force,
+                )
+                .await??;
+
+                Ok::<_, anyhow::Error>(state.to_string())
+            })
+            .await?
+        } else {
+            self.with_swap_stopped(swap_id, async {
+                let state = alice::refund(
+                    swap_id,
+                    self.bitcoin_wallet.clone(),
+                    self.monero_wallet.clone(),
+                    self.db.clone(),
+                    params.force,
+                )
+                .await??;
+
+                Ok::<_, anyhow::Error>(state.to_string())
+            })
+            .await?
+        };
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "state": state,
+        }))
+    }
+
+    async fn punish(&self, params: ManualRecoveryParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+
+        let (txid, state) = self
+            .with_swap_stopped(swap_id, async {
+                Ok::<_, anyhow::Error>(
+                    alice::punish(
+                        swap_id,
+                        self.bitcoin_wallet.clone(),
+                        self.db.clone(),
+                        params.force,
+                    )
+                    .await??,
+                )
+            })
+            .await?;
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "txid": txid.to_string(),
+            "state": state.to_string(),
+        }))
+    }
+
+    async fn redeem(&self, params: RedeemParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+
+        let (txid, state) = self
+            .with_swap_stopped(
+                swap_id,
+                alice::redeem(
+                    swap_id,
+                    self.bitcoin_wallet.clone(),
+                    self.db.clone(),
+                    params.force,
+                    Finality::from_bool(params.do_not_await_finality),
+                ),
+            )
+            .await?;
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "txid": txid.to_string(),
+            "state": state.to_string(),
+        }))
+    }
+
+    async fn safely_abort(&self, params: SwapIdParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+
+        let state = self
+            .with_swap_stopped(swap_id, alice::safely_abort(swap_id, self.db.clone()))
+            .await?;
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "state": state.to_string(),
+        }))
+    }
+
+    /// Whether we buy XMR in the given swap, i.e. are Bob.
+    fn is_bob(&self, swap_id: Uuid) -> Result<bool> {
+        Ok(matches!(self.db.get_state(swap_id)?, Swap::Bob(_)))
+    }
+
+    /// Applies a manual recovery action to a swap.
+    ///
+    /// A running swap is stopped first, so that it does not race the action.
+    /// It stops at the end of its current state, so that no half-finished
+    /// transition is lost. Afterwards the swap is resumed from the database if
+    /// it was running or the action succeeded; finished swaps are not
+    /// resumed.
+    async fn with_swap_stopped<T>(
+        &self,
+        swap_id: Uuid,
+        action: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let was_running = self.running_swaps.stop(swap_id).await;
+        if was_running {
+            tracing::info!(%swap_id, "Stopped swap for manual recovery");
+        }
+
+        let result = action.await;
+
+        if was_running || result.is_ok() {
+            self.event_loop.resume_swap(swap_id).await?;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn stopping_a_swap_waits_until_it_finished_its_state() {
+        let running_swaps = RunningSwaps::default();
+        let swap_id = Uuid::new_v4();
+        let (finished_sender, mut finished) = oneshot::channel();
+
+        running_swaps.spawn(swap_id, |mut stop| async move {
+            while !*stop.borrow() {
+                stop.changed().await.unwrap();
+            }
+            // a transition that has to be finished before stopping
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            finished_sender.send(()).unwrap();
+        });
+        assert!(running_swaps.is_running(swap_id));
+
+        let was_running = timeout(Duration::from_secs(5), running_swaps.stop(swap_id))
+            .await
+            .unwrap();
+
+        assert!(was_running);
+        assert!(finished.try_recv().is_ok());
+        assert!(!running_swaps.is_running(swap_id));
+    }
+
+    #[tokio::test]
+    async fn stopping_unknown_swap_returns_false() {
+        let running_swaps = RunningSwaps::default();
+
+        assert!(!running_swaps.stop(Uuid::new_v4()).await);
+    }
+
+    #[tokio::test]
+    async fn previous_run_of_swap_does_not_remove_resumed_swap() {
+        let running_swaps = RunningSwaps::default();
+        let swap_id = Uuid::new_v4();
+
+        running_swaps.spawn(swap_id, |_| futures::future::pending());
+        let previous = running_swaps.lock().get(&swap_id).cloned().unwrap();
+        running_swaps.spawn(swap_id, |_| futures::future::pending());
+
+        running_swaps.remove(swap_id, &previous);
+
+        assert!(running_swaps.is_running(swap_id));
+    }
+}
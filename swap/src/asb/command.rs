@@ -1,13 +1,16 @@
 use crate::asb::config::GetDefaults;
 use crate::bitcoin::Amount;
+use crate::database::{parse_date, ArchiveFormat, HistoryFilter, StateCategory};
 use crate::env;
 use crate::env::GetConfig;
+use crate::seed::PassphraseSource;
 use anyhow::{bail, Result};
 use bitcoin::Address;
 use serde::Serialize;
 use std::ffi::OsString;
 use std::path::PathBuf;
 use structopt::StructOpt;
+use time::Date;
 use uuid::Uuid;
 
 pub fn parse_args<I, T>(raw_args: I) -> Result<Arguments>
@@ -21,40 +24,117 @@ where
     let is_json = args.json;
     let is_testnet = args.testnet;
     let config = args.config;
+    let seed_passphrase =
+        PassphraseSource::from_options(args.seed_passphrase_env, args.seed_passphrase_fd);
     let command: RawCommand = args.cmd;
 
     let arguments = match command {
         RawCommand::Start { resume_only } => Arguments {
             testnet: is_testnet,
             json: is_json,
+            seed_passphrase,
             config_path: config_path(config, is_testnet)?,
             env_config: env_config(is_testnet),
             cmd: Command::Start { resume_only },
         },
-        RawCommand::History => Arguments {
+        RawCommand::History {
+            swap_id,
+            category,
+            from,
+            to,
+            json,
+        } => Arguments {
             testnet: is_testnet,
             json: is_json,
+            seed_passphrase,
             config_path: config_path(config, is_testnet)?,
             env_config: env_config(is_testnet),
-            cmd: Command::History,
+            cmd: Command::History {
+                swap_id,
+                filter: HistoryFilter { category, from, to },
+                json,
+            },
         },
-        RawCommand::WithdrawBtc { amount, address } => Arguments {
+        RawCommand::WithdrawBtc {
+            amount,
+            address,
+            await_confirmation,
+        } => Arguments {
             testnet: is_testnet,
             json: is_json,
+            seed_passphrase,
             config_path: config_path(config, is_testnet)?,
             env_config: env_config(is_testnet),
             cmd: Command::WithdrawBtc {
                 amount,
                 address: bitcoin_address(address, is_testnet)?,
+                await_confirmation,
             },
         },
         RawCommand::Balance => Arguments {
             testnet: is_testnet,
             json: is_json,
+            seed_passphrase,
             config_path: config_path(config, is_testnet)?,
             env_config: env_config(is_testnet),
             cmd: Command::Balance,
         },
+        RawCommand::ExportSeed { passphrase } => Arguments {
+            testnet: is_testnet,
+            json: is_json,
+            seed_passphrase,
+            config_path: config_path(config, is_testnet)?,
+            env_config: env_config(is_testnet),
+            cmd: Command::ExportSeed { passphrase },
+        },
+        RawCommand::RestoreSeed { passphrase, force } => Arguments {
+            testnet: is_testnet,
+            json: is_json,
+            seed_passphrase,
+            config_path: config_path(config, is_testnet)?,
+            env_config: env_config(is_testnet),
+            cmd: Command::RestoreSeed { passphrase, force },
+        },
+        RawCommand::EncryptSeed => Arguments {
+            testnet: is_testnet,
+            json: is_json,
+            seed_passphrase,
+            config_path: config_path(config, is_testnet)?,
+            env_config: env_config(is_testnet),
+            cmd: Command::EncryptSeed,
+        },
+        RawCommand::ExportSwaps {
+            file,
+            format,
+            include_seed,
+        } => Arguments {
+            testnet: is_testnet,
+            json: is_json,
+            seed_passphrase,
+            config_path: config_path(config, is_testnet)?,
+            env_config: env_config(is_testnet),
+            cmd: Command::ExportSwaps {
+                file,
+                format,
+                include_seed,
+            },
+        },
+        RawCommand::ImportSwaps { file } => Arguments {
+            testnet: is_testnet,
+            json: is_json,
+            seed_passphrase,
+            config_path: config_path(config, is_testnet)?,
+            env_config: env_config(is_testnet),
+            cmd: Command::ImportSwaps { file },
+        },
+        RawCommand::MigrateDatabase => Arguments {
+            testnet: is_testnet,
+            json: is_json,
+            seed_passphrase,
+            config_path: config_path(config, is_testnet)?,
+            env_config: env_config(is_testnet),
+            cmd: Command::MigrateDatabase,
+        },
         RawCommand::ManualRecovery(manual_recovery) => match manual_recovery {
             ManualRecovery::Redeem {
                 redeem_params: RecoverCommandParams { swap_id, force },
@@ -62,6 +142,7 @@ where
             } => Arguments {
                 testnet: is_testnet,
                 json: is_json,
+                seed_passphrase,
                 config_path: config_path(config, is_testnet)?,
                 env_config: env_config(is_testnet),
                 cmd: Command::Redeem {
@@ -75,6 +156,7 @@ where
             } => Arguments {
                 testnet: is_testnet,
                 json: is_json,
+                seed_passphrase,
                 config_path: config_path(config, is_testnet)?,
                 env_config: env_config(is_testnet),
                 cmd: Command::Cancel { swap_id, force },
@@ -84,6 +166,7 @@ where
             } => Arguments {
                 testnet: is_testnet,
                 json: is_json,
+                seed_passphrase,
                 config_path: config_path(config, is_testnet)?,
                 env_config: env_config(is_testnet),
                 cmd: Command::Refund { swap_id, force },
@@ -93,6 +176,7 @@ where
             } => Arguments {
                 testnet: is_testnet,
                 json: is_json,
+                seed_passphrase,
                 config_path: config_path(config, is_testnet)?,
                 env_config: env_config(is_testnet),
                 cmd: Command::Punish { swap_id, force },
@@ -100,6 +184,7 @@ where
             ManualRecovery::SafelyAbort { swap_id } => Arguments {
                 testnet: is_testnet,
                 json: is_json,
+                seed_passphrase,
                 config_path: config_path(config, is_testnet)?,
                 env_config: env_config(is_testnet),
                 cmd: Command::SafelyAbort { swap_id },
@@ -160,6 +245,7 @@ pub struct BitcoinAddressNetworkMismatch {
 pub struct Arguments {
     pub testnet: bool,
     pub json: bool,
+    pub seed_passphrase: PassphraseSource,
     pub config_path: PathBuf,
     pub env_config: env::Config,
     pub cmd: Command,
@@ -170,10 +256,15 @@ pub enum Command {
     Start {
         resume_only: bool,
     },
-    History,
+    History {
+        swap_id: Option<Uuid>,
+        filter: HistoryFilter,
+        json: bool,
+    },
     WithdrawBtc {
         amount: Option<Amount>,
         address: Address,
+        await_confirmation: bool,
     },
     Balance,
     Redeem {
@@ -196,6 +287,23 @@ pub enum Command {
     SafelyAbort {
         swap_id: Uuid,
     },
+    ExportSeed {
+        passphrase: bool,
+    },
+    RestoreSeed {
+        passphrase: bool,
+        force: bool,
+    },
+    EncryptSeed,
+    ExportSwaps {
+        file: PathBuf,
+        format: ArchiveFormat,
+        include_seed: bool,
+    },
+    ImportSwaps {
+        file: PathBuf,
+    },
+    MigrateDatabase,
 }
 
 #[derive(structopt::StructOpt, Debug)]
@@ -222,6 +330,19 @@ pub struct RawArguments {
     )]
     pub config: Option<PathBuf>,
 
+    #[structopt(
+        long = "seed-passphrase-env",
+        help = "Read the passphrase of the seed file from this environment variable instead of asking for it. New seed files are encrypted with it."
+    )]
+    pub seed_passphrase_env: Option<String>,
+
+    #[structopt(
+        long = "seed-passphrase-fd",
+        help = "Read the passphrase of the seed file from this file descriptor instead of asking for it. New seed files are encrypted with it.",
+        conflicts_with = "seed-passphrase-env"
+    )]
+    pub seed_passphrase_fd: Option<u32>,
+
     #[structopt(subcommand)]
     pub cmd: RawCommand,
 }
@@ -237,8 +358,42 @@ pub enum RawCommand {
         )]
         resume_only: bool,
     },
-    #[structopt(about = "Prints swap-id and the state of each swap ever made.")]
-    History,
+    #[structopt(
+        about = "Prints each swap ever made with its amounts, counterparty, times and transactions."
+    )]
+    History {
+        #[structopt(
+            long = "swap-id",
+            help = "Prints every state of this swap, including the transactions and transfer proofs, instead of the list of swaps."
+        )]
+        swap_id: Option<Uuid>,
+
+        #[structopt(
+            long = "category",
+            help = "Only lists swaps of this category: active, completed, refunded, punished or aborted.",
+            conflicts_with = "swap-id"
+        )]
+        category: Option<StateCategory>,
+
+        #[structopt(
+            long = "from",
+            help = "Only lists swaps started on or after this day, given as YYYY-MM-DD in UTC.",
+            parse(try_from_str = parse_date),
+            conflicts_with = "swap-id"
+        )]
+        from: Option<Date>,
+
+        #[structopt(
+            long = "to",
+            help = "Only lists swaps started on or before this day, given as YYYY-MM-DD in UTC.",
+            parse(try_from_str = parse_date),
+            conflicts_with = "swap-id"
+        )]
+        to: Option<Date>,
+
+        #[structopt(long = "json", help = "Prints JSON instead of a table.")]
+        json: bool,
+    },
     #[structopt(about = "Allows withdrawing BTC from the internal Bitcoin wallet.")]
     WithdrawBtc {
         #[structopt(
@@ -248,11 +403,62 @@ pub enum RawCommand {
         amount: Option<Amount>,
         #[structopt(long = "address", help = "The address to receive the Bitcoin.")]
         address: Address,
+        #[structopt(
+            long = "await-confirmation",
+            help = "Wait until the withdrawal is confirmed and replace it with a higher fee if necessary."
+        )]
+        await_confirmation: bool,
     },
     #[structopt(
         about = "Prints the Bitcoin and Monero balance. Requires the monero-wallet-rpc to be running."
     )]
     Balance,
+    #[structopt(about = "Prints the seed as BIP39 mnemonic to back it up.")]
+    ExportSeed {
+        #[structopt(
+            long = "passphrase",
+            help = "Protect the mnemonic with a passphrase that is asked for."
+        )]
+        passphrase: bool,
+    },
+    #[structopt(about = "Restores the seed from a BIP39 mnemonic.")]
+    RestoreSeed {
+        #[structopt(
+            long = "passphrase",
+            help = "Ask for the passphrase the mnemonic was exported with."
+        )]
+        passphrase: bool,
+        #[structopt(
+            long = "force",
+            help = "Replace an existing seed file. Refused as long as any swap is unfinished."
+        )]
+        force: bool,
+    },
+    #[structopt(about = "Encrypts a plaintext seed file with a passphrase.")]
+    EncryptSeed,
+    #[structopt(about = "Writes all swaps to an archive to move them to another machine.")]
+    ExportSwaps {
+        #[structopt(long = "file", help = "The archive to create.")]
+        file: PathBuf,
+        #[structopt(
+            long = "format",
+            help = "The format of the archive, json or cbor.",
+            default_value = "json"
+        )]
+        format: ArchiveFormat,
+        #[structopt(
+            long = "include-seed",
+            help = "Adds the seed file to the archive, encrypted if the seed file is encrypted."
+        )]
+        include_seed: bool,
+    },
+    #[structopt(about = "Imports the swaps of an archive created with export-swaps.")]
+    ImportSwaps {
+        #[structopt(long = "file", help = "The archive to import.")]
+        file: PathBuf,
+    },
+    #[structopt(about = "Copies the database to SQLite, which is used from then on.")]
+    MigrateDatabase,
     #[structopt(about = "Contains sub-commands for recovering a swap manually.")]
     ManualRecovery(ManualRecovery),
 }
@@ -338,6 +544,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path.clone(),
             env_config: mainnet_env_config,
             cmd: Command::Start { resume_only: false },
@@ -349,9 +556,60 @@ mod tests {
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::History {
+                swap_id: None,
+                filter: HistoryFilter::default(),
+                json: false,
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![
+            BINARY_NAME,
+            "history",
+            "--category",
+            "refunded",
+            "--from",
+            "2021-06-01",
+            "--to",
+            "2021-06-30",
+            "--json",
+        ];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::History {
+                swap_id: None,
+                filter: HistoryFilter {
+                    category: Some(StateCategory::Refunded),
+                    from: Some(Date::try_from_ymd(2021, 6, 1).unwrap()),
+                    to: Some(Date::try_from_ymd(2021, 6, 30).unwrap()),
+                },
+                json: true,
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![BINARY_NAME, "history", "--swap-id", SWAP_ID];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path.clone(),
             env_config: mainnet_env_config,
-            cmd: Command::History,
+            cmd: Command::History {
+                swap_id: Some(Uuid::from_str(SWAP_ID).unwrap()),
+                filter: HistoryFilter::default(),
+                json: false,
+            },
         };
         let args = parse_args(raw_ars).unwrap();
         assert_eq!(expected_args, args);
@@ -360,6 +618,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path.clone(),
             env_config: mainnet_env_config,
             cmd: Command::Balance,
@@ -367,20 +626,133 @@ mod tests {
         let args = parse_args(raw_ars).unwrap();
         assert_eq!(expected_args, args);
 
+        let raw_ars = vec![BINARY_NAME, "export-seed", "--passphrase"];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::ExportSeed { passphrase: true },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![BINARY_NAME, "--seed-passphrase-fd", "3", "encrypt-seed"];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Fd(3),
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::EncryptSeed,
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![BINARY_NAME, "restore-seed", "--force"];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::RestoreSeed {
+                passphrase: false,
+                force: true,
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![
+            BINARY_NAME,
+            "export-swaps",
+            "--file",
+            "swaps.cbor",
+            "--format",
+            "cbor",
+            "--include-seed",
+        ];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::ExportSwaps {
+                file: PathBuf::from("swaps.cbor"),
+                format: ArchiveFormat::Cbor,
+                include_seed: true,
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![BINARY_NAME, "import-swaps", "--file", "swaps.cbor"];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::ImportSwaps {
+                file: PathBuf::from("swaps.cbor"),
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![BINARY_NAME, "migrate-database"];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::MigrateDatabase,
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
+        let raw_ars = vec![
+            BINARY_NAME,
+            "withdraw-btc",
+            "--address",
+            BITCOIN_MAINNET_ADDRESS,
+        ];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            seed_passphrase: PassphraseSource::Prompt,
+            config_path: default_mainnet_conf_path.clone(),
+            env_config: mainnet_env_config,
+            cmd: Command::WithdrawBtc {
+                amount: None,
+                address: Address::from_str(BITCOIN_MAINNET_ADDRESS).unwrap(),
+                await_confirmation: false,
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+
         let raw_ars = vec![
             BINARY_NAME,
             "withdraw-btc",
             "--address",
             BITCOIN_MAINNET_ADDRESS,
+            "--await-confirmation",
         ];
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path.clone(),
             env_config: mainnet_env_config,
             cmd: Command::WithdrawBtc {
                 amount: None,
                 address: Address::from_str(BITCOIN_MAINNET_ADDRESS).unwrap(),
+                await_confirmation: true,
             },
         };
         let args = parse_args(raw_ars).unwrap();
@@ -396,6 +768,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path.clone(),
             env_config: mainnet_env_config,
             cmd: Command::Cancel {
@@ -416,6 +789,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path.clone(),
             env_config: mainnet_env_config,
             cmd: Command::Refund {
@@ -436,6 +810,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path.clone(),
             env_config: mainnet_env_config,
             cmd: Command::Punish {
@@ -456,6 +831,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: false,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_mainnet_conf_path,
             env_config: mainnet_env_config,
             cmd: Command::SafelyAbort {
@@ -475,6 +851,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path.clone(),
             env_config: testnet_env_config,
             cmd: Command::Start { resume_only: false },
@@ -486,9 +863,14 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path.clone(),
             env_config: testnet_env_config,
-            cmd: Command::History,
+            cmd: Command::History {
+                swap_id: None,
+                filter: HistoryFilter::default(),
+                json: false,
+            },
         };
         let args = parse_args(raw_ars).unwrap();
         assert_eq!(expected_args, args);
@@ -497,6 +879,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path.clone(),
             env_config: testnet_env_config,
             cmd: Command::Balance,
@@ -514,11 +897,13 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path.clone(),
             env_config: testnet_env_config,
             cmd: Command::WithdrawBtc {
                 amount: None,
                 address: Address::from_str(BITCOIN_TESTNET_ADDRESS).unwrap(),
+                await_confirmation: false,
             },
         };
         let args = parse_args(raw_ars).unwrap();
@@ -535,6 +920,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path.clone(),
             env_config: testnet_env_config,
             cmd: Command::Cancel {
@@ -556,6 +942,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path.clone(),
             env_config: testnet_env_config,
             cmd: Command::Refund {
@@ -577,6 +964,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path.clone(),
             env_config: testnet_env_config,
             cmd: Command::Punish {
@@ -598,6 +986,7 @@ mod tests {
         let expected_args = Arguments {
             testnet: true,
             json: false,
+            seed_passphrase: PassphraseSource::Prompt,
             config_path: default_testnet_conf_path,
             env_config: testnet_env_config,
             cmd: Command::SafelyAbort {
@@ -0,0 +1,301 @@
+use crate::{bitcoin, monero};
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long we hold funds for a peer whose spot price we accepted, but that
+/// did not complete the execution setup yet.
+const PENDING_RESERVATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Keeps track of the funds we promised to swaps but did not lock yet.
+///
+/// Funds are reserved for a peer once we accept its spot price and handed
+/// over to the swap once the execution setup is done. The reservation is
+/// released if the swap is aborted before we lock the funds and committed once
+/// we published the lock transaction, at which point the funds left our
+/// balance.
+///
+/// We reserve XMR for swaps in which we sell XMR and BTC for swaps in which we
+/// buy XMR.
+///
+/// Clones share the same reservations, so the running swaps can update the
+/// ledger the spot price requests are checked against.
+#[derive(Clone, Debug)]
+pub struct Ledger<A = monero::Amount> {
+    inner: Arc<Mutex<Reservations<A>>>,
+}
+
+#[derive(Debug)]
+struct Reservations<A> {
+    balance: A,
+    pending: HashMap<PeerId, (A, Instant)>,
+    swaps: HashMap<Uuid, A>,
+    pending_timeout: Duration,
+}
+
+/// An amount that can be kept in a [`Ledger`].
+pub trait Reservable: Copy + Debug {
+    fn to_atomic(self) -> u64;
+    fn from_atomic(atomic: u64) -> Self;
+}
+
+impl Reservable for monero::Amount {
+    fn to_atomic(self) -> u64 {
+        self.as_piconero()
+    }
+
+    fn from_atomic(atomic: u64) -> Self {
+        monero::Amount::from_piconero(atomic)
+    }
+}
+
+impl Reservable for bitcoin::Amount {
+    fn to_atomic(self) -> u64 {
+        self.as_sat()
+    }
+
+    fn from_atomic(atomic: u64) -> Self {
+        bitcoin::Amount::from_sat(atomic)
+    }
+}
+
+impl<A> Ledger<A>
+where
+    A: Reservable,
+{
+    pub fn new(balance: A) -> Self {
+        Self::with_pending_timeout(balance, PENDING_RESERVATION_TIMEOUT)
+    }
+
+    fn with_pending_timeout(balance: A, pending_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Reservations {
+                balance,
+                pending: HashMap::default(),
+                swaps: HashMap::default(),
+                pending_timeout,
+            })),
+        }
+    }
+
+    pub fn update_balance(&self, balance: A) {
+        self.lock().balance = balance;
+    }
+
+    /// Our balance minus everything that is reserved.
+    pub fn available(&self) -> A {
+        self.available_excluding(None)
+    }
+
+    /// What is available to `peer`, whose own reservation gets replaced if it
+    /// asks again.
+    pub fn available_for(&self, peer: &PeerId) -> A {
+        self.available_excluding(Some(peer))
+    }
+
+    fn available_excluding(&self, peer: Option<&PeerId>) -> A {
+        let mut reservations = self.lock();
+        reservations.expire_pending();
+
+        let reserved = reservations
+            .pending
+            .iter()
+            .filter(|(reserved_for, _)| Some(*reserved_for) != peer)
+            .map(|(_, (amount, _))| amount.to_atomic())
+            .chain(reservations.swaps.values().map(|amount| amount.to_atomic()))
+            .sum::<u64>();
+
+        A::from_atomic(reservations.balance.to_atomic().saturating_sub(reserved))
+    }
+
+    /// The number of peers we reserved funds for that did not set up their
+    /// swap yet.
+    pub fn pending(&self) -> usize {
+        let mut reservations = self.lock();
+        reservations.expire_pending();
+
+        reservations.pending.len()
+    }
+
+    /// Whether we reserved funds for `peer`, which did not set up its swap
+    /// yet.
+    pub fn is_pending(&self, peer: &PeerId) -> bool {
+        let mut reservations = self.lock();
+        reservations.expire_pending();
+
+        reservations.pending.contains_key(peer)
+    }
+
+    /// Reserves `amount` for `peer`, replacing an earlier reservation of the
+    /// same peer.
+    pub fn reserve(&self, peer: PeerId, amount: A) {
+        self.lock().pending.insert(peer, (amount, Instant::now()));
+    }
+
+    /// Moves the reservation of `peer` to the swap it set up.
+    pub fn assign(&self, peer: PeerId, swap_id: Uuid, amount: A) {
+        let mut reservations = self.lock();
+        reservations.pending.remove(&peer);
+        reservations.swaps.insert(swap_id, amount);
+    }
+
+    /// Reserves `amount` for a swap that was resumed before locking the funds.
+    pub fn reserve_swap(&self, swap_id: Uuid, amount: A) {
+        self.lock().swaps.insert(swap_id, amount);
+    }
+
+    /// Releases the reservation of a peer that did not set up a swap.
+    pub fn release_peer(&self, peer: &PeerId) {
+        self.lock().pending.remove(peer);
+    }
+
+    /// Releases the reservation of a swap that was aborted before we locked
+    /// the funds.
+    pub fn release(&self, swap_id: Uuid) {
+        self.lock().swaps.remove(&swap_id);
+    }
+
+    /// Takes the reserved funds of a swap off our balance once we published
+    /// the lock transaction.
+    ///
+    /// The next balance update replaces the balance with the one of the
+    /// wallet again.
+    pub fn commit(&self, swap_id: Uuid) {
+        let mut reservations = self.lock();
+
+        if let Some(amount) = reservations.swaps.remove(&swap_id) {
+            reservations.balance = A::from_atomic(
+                reservations
+                    .balance
+                    .to_atomic()
+                    .saturating_sub(amount.to_atomic()),
+            );
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Reservations<A>> {
+        self.inner.lock().expect("ledger mutex not to be poisoned")
+    }
+}
+
+impl<A> Reservations<A> {
+    fn expire_pending(&mut self) {
+        let pending_timeout = self.pending_timeout;
+        self.pending
+            .retain(|_, (_, reserved_at)| reserved_at.elapsed() < pending_timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xmr(xmr: f64) -> monero::Amount {
+        monero::Amount::from_monero(xmr).unwrap()
+    }
+
+    #[test]
+    fn reservations_reduce_available_balance() {
+        let ledger = Ledger::new(xmr(3.0));
+
+        ledger.reserve(PeerId::random(), xmr(1.0));
+        ledger.reserve_swap(Uuid::new_v4(), xmr(1.5));
+
+        assert_eq!(ledger.available(), xmr(0.5));
+    }
+
+    #[test]
+    fn new_reservation_of_peer_replaces_previous_one() {
+        let ledger = Ledger::new(xmr(3.0));
+        let peer = PeerId::random();
+
+        ledger.reserve(peer, xmr(1.0));
+        ledger.reserve(peer, xmr(2.0));
+
+        assert_eq!(ledger.available(), xmr(1.0));
+        assert_eq!(ledger.available_for(&peer), xmr(3.0));
+    }
+
+    #[test]
+    fn assigned_reservation_is_released_on_abort() {
+        let ledger = Ledger::new(xmr(3.0));
+        let peer = PeerId::random();
+        let swap_id = Uuid::new_v4();
+
+        ledger.reserve(peer, xmr(1.0));
+        ledger.assign(peer, swap_id, xmr(1.0));
+        assert_eq!(ledger.available(), xmr(2.0));
+
+        ledger.release(swap_id);
+        assert_eq!(ledger.available(), xmr(3.0));
+    }
+
+    #[test]
+    fn committed_reservation_is_taken_off_the_balance() {
+        let ledger = Ledger::new(xmr(3.0));
+        let peer = PeerId::random();
+        let swap_id = Uuid::new_v4();
+
+        ledger.reserve(peer, xmr(1.0));
+        ledger.assign(peer, swap_id, xmr(1.0));
+        ledger.commit(swap_id);
+        assert_eq!(ledger.available(), xmr(2.0));
+
+        // the wallet reports the balance after the lock transfer
+        ledger.update_balance(xmr(2.0));
+        assert_eq!(ledger.available(), xmr(2.0));
+    }
+
+    #[test]
+    fn reservations_are_pending_until_assigned() {
+        let ledger = Ledger::new(xmr(3.0));
+        let peer = PeerId::random();
+
+        ledger.reserve(peer, xmr(1.0));
+        ledger.reserve(PeerId::random(), xmr(1.0));
+        assert!(ledger.is_pending(&peer));
+        assert_eq!(ledger.pending(), 2);
+
+        ledger.assign(peer, Uuid::new_v4(), xmr(1.0));
+        assert!(!ledger.is_pending(&peer));
+        assert_eq!(ledger.pending(), 1);
+    }
+
+    #[test]
+    fn pending_reservations_expire() {
+        let ledger = Ledger::with_pending_timeout(xmr(3.0), Duration::from_secs(0));
+
+        ledger.reserve(PeerId::random(), xmr(1.0));
+
+        assert_eq!(ledger.available(), xmr(3.0));
+    }
+
+    #[test]
+    fn available_balance_does_not_underflow() {
+        let ledger = Ledger::new(xmr(1.0));
+
+        ledger.reserve_swap(Uuid::new_v4(), xmr(1.0));
+        ledger.update_balance(xmr(0.5));
+
+        assert_eq!(ledger.available(), monero::Amount::ZERO);
+    }
+
+    #[test]
+    fn bitcoin_reservations_reduce_available_balance() {
+        let ledger = Ledger::new(bitcoin::Amount::from_sat(100_000));
+        let peer = PeerId::random();
+        let swap_id = Uuid::new_v4();
+
+        ledger.reserve(peer, bitcoin::Amount::from_sat(40_000));
+        ledger.assign(peer, swap_id, bitcoin::Amount::from_sat(40_000));
+        ledger.reserve(PeerId::random(), bitcoin::Amount::from_sat(50_000));
+
+        assert_eq!(ledger.available(), bitcoin::Amount::from_sat(10_000));
+
+        ledger.commit(swap_id);
+        assert_eq!(ledger.available(), bitcoin::Amount::from_sat(10_000));
+    }
+}
@@ -0,0 +1,633 @@
+//! Prometheus metrics of the ASB.
+//!
+//! The metrics are collected in memory and served in the Prometheus text
+//! exposition format on `GET /metrics` by a minimal HTTP listener.
+use crate::asb::Rate;
+use crate::database::{Alice, AliceEndState, Database, Swap};
+use crate::protocol::alice::event_loop::LatestRate;
+use crate::protocol::alice::spot_price::Error;
+use crate::{bitcoin, monero};
+use anyhow::{bail, Context, Result};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+use uuid::Uuid;
+
+/// How often the balances and the rate are refreshed.
+const GAUGE_UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The upper bounds (in seconds) of the buckets of the state duration
+/// histograms, ranging from a second to a day.
+const STATE_DURATION_BUCKETS: [f64; 10] = [
+    1.0, 10.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 21600.0, 86400.0,
+];
+
+const SATS_PER_BTC: u64 = 100_000_000;
+const PICONERO_PER_XMR: u64 = 1_000_000_000_000;
+
+/// Requests larger than this are not a scrape and are rejected.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// Connections that do not send a complete request in time are closed.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DECLINE_REASONS: [&str; 7] = [
+    "resume_only_mode",
+    "amount_below_minimum",
+    "amount_above_maximum",
+    "balance_too_low",
+    "latest_rate_fetch_failed",
+    "sell_quote_calculation_failed",
+    "blockchain_network_mismatch",
+];
+
+const END_STATES: [&str; 4] = [
+    "safely_aborted",
+    "btc_redeemed",
+    "xmr_refunded",
+    "btc_punished",
+];
+
+/// A cheaply cloneable handle to the metrics of the ASB.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    quote_requests: u64,
+    spot_price_requests: u64,
+    declined_swap_requests: BTreeMap<&'static str, u64>,
+
+    bitcoin_balance: Option<Decimal>,
+    monero_balance: Option<Decimal>,
+    rate_ask: Option<Decimal>,
+    rate_bid: Option<Decimal>,
+    connected_peers: usize,
+
+    /// The state each swap is currently in and since when.
+    current_states: HashMap<Uuid, (&'static str, Instant)>,
+    state_durations: BTreeMap<&'static str, Histogram>,
+    finished_swaps: BTreeMap<&'static str, u64>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            quote_requests: 0,
+            spot_price_requests: 0,
+            declined_swap_requests: DECLINE_REASONS.iter().map(|reason| (*reason, 0)).collect(),
+            bitcoin_balance: None,
+            monero_balance: None,
+            rate_ask: None,
+            rate_bid: None,
+            connected_peers: 0,
+            current_states: HashMap::new(),
+            state_durations: BTreeMap::new(),
+            finished_swaps: END_STATES.iter().map(|state| (*state, 0)).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// The number of observations per bucket, not cumulative.
+    buckets: [u64; STATE_DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = STATE_DURATION_BUCKETS
+            .iter()
+            .position(|upper_bound| value <= *upper_bound)
+        {
+            self.buckets[index] += 1;
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+impl Metrics {
+    pub fn quote_requested(&self) {
+        self.lock().quote_requests += 1;
+    }
+
+    pub fn spot_price_requested(&self) {
+        self.lock().spot_price_requests += 1;
+    }
+
+    pub fn swap_request_declined(&self, error: &Error) {
+        *self
+            .lock()
+            .declined_swap_requests
+            .entry(decline_reason(error))
+            .or_insert(0) += 1;
+    }
+
+    pub fn set_balances(&self, bitcoin: bitcoin::Amount, monero: monero::Amount) {
+        let mut inner = self.lock();
+
+        inner.bitcoin_balance = Some(as_btc(bitcoin));
+        inner.monero_balance =
+            Some((monero.as_piconero_decimal() / Decimal::from(PICONERO_PER_XMR)).normalize());
+    }
+
+    pub fn set_rate(&self, rate: Rate) -> Result<()> {
+        let ask = rate.ask()?;
+        let bid = rate.bid()?;
+
+        let mut inner = self.lock();
+        inner.rate_ask = Some(as_btc(ask));
+        inner.rate_bid = Some(as_btc(bid));
+
+        Ok(())
+    }
+
+    pub fn set_connected_peers(&self, connected_peers: usize) {
+        self.lock().connected_peers = connected_peers;
+    }
+
+    /// Records that a swap entered the given state.
+    ///
+    /// The time spent in the previous state of the swap is observed in the
+    /// histogram of that state. Swaps that were not seen before, e.g. resumed
+    /// ones, start being timed from now on.
+    pub fn state_entered(&self, swap_id: Uuid, state: &Alice) {
+        let now = Instant::now();
+        let mut inner = self.lock();
+
+        if let Some((previous_state, since)) = inner.current_states.remove(&swap_id) {
+            inner
+                .state_durations
+                .entry(previous_state)
+                .or_default()
+                .observe(now.duration_since(since).as_secs_f64());
+        }
+
+        match state {
+            Alice::Done(end_state) => {
+                *inner
+                    .finished_swaps
+                    .entry(end_state_label(*end_state))
+                    .or_insert(0) += 1;
+            }
+            state => {
+                inner
+                    .current_states
+                    .insert(swap_id, (state_label(state), now));
+            }
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let inner = self.lock();
+        let mut out = String::new();
+
+        write_header(
+            &mut out,
+            "asb_quote_requests_total",
+            "counter",
+            "Number of quote requests received.",
+        );
+        out.push_str(&format!(
+            "asb_quote_requests_total {}\n",
+            inner.quote_requests
+        ));
+
+        write_header(
+            &mut out,
+            "asb_spot_price_requests_total",
+            "counter",
+            "Number of spot price requests received.",
+        );
+        out.push_str(&format!(
+            "asb_spot_price_requests_total {}\n",
+            inner.spot_price_requests
+        ));
+
+        write_header(
+            &mut out,
+            "asb_swap_requests_declined_total",
+            "counter",
+            "Number of declined spot price requests by reason.",
+        );
+        for (reason, count) in &inner.declined_swap_requests {
+            out.push_str(&format!(
+                "asb_swap_requests_declined_total{{reason=\"{}\"}} {}\n",
+                reason, count
+            ));
+        }
+
+        write_gauge(
+            &mut out,
+            "asb_bitcoin_balance_btc",
+            "Balance of the Bitcoin wallet.",
+            inner.bitcoin_balance,
+        );
+        write_gauge(
+            &mut out,
+            "asb_monero_balance_xmr",
+            "Balance of the Monero wallet.",
+            inner.monero_balance,
+        );
+        write_gauge(
+            &mut out,
+            "asb_rate_ask_btc",
+            "Price in BTC at which 1 XMR is sold, including the spread.",
+            inner.rate_ask,
+        );
+        write_gauge(
+            &mut out,
+            "asb_rate_bid_btc",
+            "Price in BTC at which 1 XMR is bought, including the spread.",
+            inner.rate_bid,
+        );
+        write_gauge(
+            &mut out,
+            "asb_connected_peers",
+            "Number of peers we are connected to.",
+            Some(Decimal::from(inner.connected_peers)),
+        );
+
+        write_header(
+            &mut out,
+            "asb_swap_state_duration_seconds",
+            "histogram",
+            "Time swaps spent in each state.",
+        );
+        for (state, histogram) in &inner.state_durations {
+            let mut cumulative = 0;
+            for (upper_bound, count) in STATE_DURATION_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                out.push_str(&format!(
+                    "asb_swap_state_duration_seconds_bucket{{state=\"{}\",le=\"{}\"}} {}\n",
+                    state, upper_bound, cumulative
+                ));
+            }
+            out.push_str(&format!(
+                "asb_swap_state_duration_seconds_bucket{{state=\"{}\",le=\"+Inf\"}} {}\n",
+                state, histogram.count
+            ));
+            out.push_str(&format!(
+                "asb_swap_state_duration_seconds_sum{{state=\"{}\"}} {}\n",
+                state, histogram.sum
+            ));
+            out.push_str(&format!(
+                "asb_swap_state_duration_seconds_count{{state=\"{}\"}} {}\n",
+                state, histogram.count
+            ));
+        }
+
+        write_header(
+            &mut out,
+            "asb_swaps_finished_total",
+            "counter",
+            "Number of swaps finished by end state.",
+        );
+        for (state, count) in &inner.finished_swaps {
+            out.push_str(&format!(
+                "asb_swaps_finished_total{{state=\"{}\"}} {}\n",
+                state, count
+            ));
+        }
+
+        out
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .expect("metrics lock to never be poisoned")
+    }
+}
+
+/// Serves the metrics on `GET /metrics` to every connection accepted by the
+/// listener.
+pub async fn serve(listener: TcpListener, metrics: Metrics) -> Result<()> {
+    loop {
+        let (stream, peer) = listener
+            .accept()
+            .await
+            .context("Failed to accept metrics connection")?;
+
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(stream, metrics, REQUEST_TIMEOUT).await {
+                tracing::debug!(%peer, "Failed to serve metrics: {:#}", error);
+            }
+        });
+    }
+}
+
+/// Keeps the state metrics up to date by following all state transitions of
+/// swaps in which we are Alice.
+pub async fn track_swap_states(metrics: Metrics, db: Arc<Database>) {
+    let mut state_updates = db.state_updates();
+
+    loop {
+        match state_updates.recv().await {
+            Ok((swap_id, Swap::Alice(state))) => metrics.state_entered(swap_id, &state),
+            Ok((_, Swap::Bob(_))) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Metrics lagged behind, skipped {} state updates", skipped);
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Periodically refreshes the balance and rate gauges.
+pub async fn update_gauges<LR>(
+    metrics: Metrics,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    mut latest_rate: LR,
+) where
+    LR: LatestRate,
+{
+    let mut interval = tokio::time::interval(GAUGE_UPDATE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match (
+            bitcoin_wallet.balance().await,
+            monero_wallet.get_balance().await,
+        ) {
+            (Ok(bitcoin_balance), Ok(monero_balance)) => {
+                metrics.set_balances(bitcoin_balance, monero_balance)
+            }
+            (Err(error), _) | (_, Err(error)) => {
+                tracing::debug!("Failed to update balance metrics: {:#}", error)
+            }
+        }
+
+        match latest_rate.latest_rate() {
+            Ok(rate) => {
+                if let Err(error) = metrics.set_rate(rate) {
+                    tracing::debug!("Failed to update rate metrics: {:#}", error);
+                }
+            }
+            Err(error) => tracing::debug!("Failed to update rate metrics: {:#}", error),
+        }
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    metrics: Metrics,
+    request_timeout: Duration,
+) -> Result<()> {
+    let request = timeout(request_timeout, read_request(&mut stream))
+        .await
+        .context("Timed out reading the request")??;
+
+    let request_line = request
+        .split(|byte| *byte == b'\n')
+        .next()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => http_response(
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            &metrics.render(),
+        ),
+        (Some("GET"), Some(_)) => http_response("404 Not Found", "text/plain", "Not Found\n"),
+        _ => http_response(
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n",
+        ),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            bail!("Connection closed before the request was complete")
+        }
+
+        request.extend_from_slice(&buffer[..read]);
+        if request.len() > MAX_REQUEST_SIZE {
+            bail!("Request too large")
+        }
+    }
+
+    Ok(request)
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str(&format!("# HELP {} {}\n", name, help));
+    out.push_str(&format!("# TYPE {} {}\n", name, kind));
+}
+
+/// Writes a gauge, gauges without a value yet are omitted.
+fn write_gauge(out: &mut String, name: &str, help: &str, value: Option<Decimal>) {
+    if let Some(value) = value {
+        write_header(out, name, "gauge", help);
+        out.push_str(&format!("{} {}\n", name, value));
+    }
+}
+
+fn as_btc(amount: bitcoin::Amount) -> Decimal {
+    (Decimal::from(amount.as_sat()) / Decimal::from(SATS_PER_BTC)).normalize()
+}
+
+fn decline_reason(error: &Error) -> &'static str {
+    match error {
+        Error::ResumeOnlyMode => "resume_only_mode",
+        Error::AmountBelowMinimum { .. } => "amount_below_minimum",
+        Error::AmountAboveMaximum { .. } => "amount_above_maximum",
+        Error::BalanceTooLow { .. } => "balance_too_low",
+        Error::LatestRateFetchFailed(_) => "latest_rate_fetch_failed",
+        Error::SellQuoteCalculationFailed(_) => "sell_quote_calculation_failed",
+        Error::BlockchainNetworkMismatch { .. } => "blockchain_network_mismatch",
+    }
+}
+
+fn state_label(state: &Alice) -> &'static str {
+    match state {
+        Alice::Started { .. } => "started",
+        Alice::BtcLocked { .. } => "btc_locked",
+        Alice::XmrLockTransactionSent { .. } => "xmr_lock_transaction_sent",
+        Alice::XmrLocked { .. } => "xmr_locked",
+        Alice::XmrLockTransferProofSent { .. } => "xmr_lock_transfer_proof_sent",
+        Alice::EncSigLearned { .. } => "enc_sig_learned",
+        Alice::BtcRedeemTransactionPublished { .. } => "btc_redeem_transaction_published",
+        Alice::CancelTimelockExpired { .. } => "cancel_timelock_expired",
+        Alice::BtcCancelled { .. } => "btc_cancelled",
+        Alice::BtcPunishable { .. } => "btc_punishable",
+        Alice::BtcRefunded { .. } => "btc_refunded",
+        Alice::Done(end_state) => end_state_label(*end_state),
+    }
+}
+
+fn end_state_label(end_state: AliceEndState) -> &'static str {
+    match end_state {
+        AliceEndState::SafelyAborted => "safely_aborted",
+        AliceEndState::BtcRedeemed => "btc_redeemed",
+        AliceEndState::XmrRefunded => "xmr_refunded",
+        AliceEndState::BtcPunished => "btc_punished",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_declined_swap_requests_per_reason() {
+        let metrics = Metrics::default();
+
+        metrics.swap_request_declined(&Error::ResumeOnlyMode);
+        metrics.swap_request_declined(&Error::ResumeOnlyMode);
+        metrics.swap_request_declined(&Error::AmountBelowMinimum {
+            min: bitcoin::Amount::from_sat(2),
+            buy: bitcoin::Amount::from_sat(1),
+        });
+
+        let rendered = metrics.render();
+
+        assert!(
+            rendered.contains("asb_swap_requests_declined_total{reason=\"resume_only_mode\"} 2\n")
+        );
+        assert!(rendered
+            .contains("asb_swap_requests_declined_total{reason=\"amount_below_minimum\"} 1\n"));
+        assert!(
+            rendered.contains("asb_swap_requests_declined_total{reason=\"balance_too_low\"} 0\n")
+        );
+    }
+
+    #[test]
+    fn gauges_are_omitted_until_set() {
+        let metrics = Metrics::default();
+        assert!(!metrics.render().contains("asb_bitcoin_balance_btc"));
+
+        metrics.set_balances(
+            bitcoin::Amount::from_sat(150_000_000),
+            monero::Amount::from_piconero(500_000_000_000),
+        );
+        metrics
+            .set_rate(Rate::new(
+                bitcoin::Amount::from_sat(1_000_000),
+                Decimal::new(1, 2),
+            ))
+            .unwrap();
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("asb_bitcoin_balance_btc 1.5\n"));
+        assert!(rendered.contains("asb_monero_balance_xmr 0.5\n"));
+        assert!(rendered.contains("asb_rate_ask_btc 0.0101\n"));
+        assert!(rendered.contains("asb_rate_bid_btc 0.0099\n"));
+    }
+
+    #[test]
+    fn finished_swaps_are_counted_per_end_state() {
+        let metrics = Metrics::default();
+        let swap_id = Uuid::new_v4();
+
+        metrics.state_entered(swap_id, &Alice::Done(AliceEndState::BtcRedeemed));
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("asb_swaps_finished_total{state=\"btc_redeemed\"} 1\n"));
+        assert!(rendered.contains("asb_swaps_finished_total{state=\"btc_punished\"} 0\n"));
+        assert!(metrics.lock().current_states.is_empty());
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative_when_rendered() {
+        let metrics = Metrics::default();
+        {
+            let mut inner = metrics.lock();
+            let histogram = inner.state_durations.entry("started").or_default();
+            histogram.observe(0.5);
+            histogram.observe(30.0);
+            histogram.observe(100_000.0);
+        }
+
+        let rendered = metrics.render();
+        assert!(rendered
+            .contains("asb_swap_state_duration_seconds_bucket{state=\"started\",le=\"1\"} 1\n"));
+        assert!(rendered
+            .contains("asb_swap_state_duration_seconds_bucket{state=\"started\",le=\"60\"} 2\n"));
+        assert!(rendered.contains(
+            "asb_swap_state_duration_seconds_bucket{state=\"started\",le=\"86400\"} 2\n"
+        ));
+        assert!(rendered
+            .contains("asb_swap_state_duration_seconds_bucket{state=\"started\",le=\"+Inf\"} 3\n"));
+        assert!(rendered.contains("asb_swap_state_duration_seconds_count{state=\"started\"} 3\n"));
+    }
+
+    #[tokio::test]
+    async fn serves_metrics_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let metrics = Metrics::default();
+        metrics.quote_requested();
+        tokio::spawn(serve(listener, metrics));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("asb_quote_requests_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn given_incomplete_request_then_closes_connection_after_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(address).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        client
+            .write_all(b"GET /metrics HTTP/1.1\r\n")
+            .await
+            .unwrap();
+
+        let result =
+            handle_connection(stream, Metrics::default(), Duration::from_millis(100)).await;
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Timed out reading the request"
+        );
+    }
+}
@@ -25,6 +25,15 @@ impl Rate {
         Self { ask, ask_spread }
     }
 
+    /// Adds `extra_spread` to the spread. A negative extra spread lowers the
+    /// spread, but never below zero.
+    pub fn with_extra_spread(self, extra_spread: Decimal) -> Self {
+        Self {
+            ask: self.ask,
+            ask_spread: (self.ask_spread + extra_spread).max(ZERO_SPREAD),
+        }
+    }
+
     /// Computes the asking price at which we are willing to sell 1 XMR.
     ///
     /// This applies the spread to the market asking price.
@@ -42,6 +51,44 @@ impl Rate {
         Ok(self.ask + additional_sats)
     }
 
+    /// Computes the bidding price at which we are willing to buy 1 XMR.
+    ///
+    /// This applies the spread below the market asking price.
+    pub fn bid(&self) -> Result<bitcoin::Amount> {
+        let sats = self.ask.as_sat();
+        let sats = Decimal::from(sats);
+
+        let deducted_sats = sats * self.ask_spread;
+        let deducted_sats = bitcoin::Amount::from_sat(
+            deducted_sats
+                .to_u64()
+                .context("Failed to fit spread into u64")?,
+        );
+
+        self.ask
+            .checked_sub(deducted_sats)
+            .context("Spread must not exceed the market asking price")
+    }
+
+    /// Calculate a buy quote for a given XMR amount.
+    pub fn buy_quote(&self, base: monero::Amount) -> Result<bitcoin::Amount> {
+        // quote (btc) = rate * base (xmr)
+
+        let rate_in_sats = Decimal::from(self.bid()?.as_sat());
+        let base_in_xmr = base
+            .as_piconero_decimal()
+            .checked_div(Decimal::from(monero::Amount::ONE_XMR.as_piconero()))
+            .context("Division overflow")?;
+
+        let quote_in_sats = rate_in_sats
+            .checked_mul(base_in_xmr)
+            .context("Multiplication overflow")?
+            .to_u64()
+            .context("Failed to fit satoshi amount into a u64")?;
+
+        Ok(bitcoin::Amount::from_sat(quote_in_sats))
+    }
+
     /// Calculate a sell quote for a given BTC amount.
     pub fn sell_quote(&self, quote: bitcoin::Amount) -> Result<monero::Amount> {
         Self::quote(self.ask()?, quote)
@@ -98,6 +145,28 @@ mod tests {
         assert_eq!(xmr_amount, monero::Amount::from_monero(1000.0).unwrap())
     }
 
+    #[test]
+    fn buy_quote() {
+        let asking_price = bitcoin::Amount::from_btc(0.002_500).unwrap();
+        let rate = Rate::new(asking_price, ZERO_SPREAD);
+
+        let xmr_amount = monero::Amount::from_monero(1000.0).unwrap();
+
+        let btc_amount = rate.buy_quote(xmr_amount).unwrap();
+
+        assert_eq!(btc_amount, bitcoin::Amount::from_btc(2.5).unwrap())
+    }
+
+    #[test]
+    fn applies_spread_to_bidding_price() {
+        let asking_price = bitcoin::Amount::from_sat(100);
+        let rate = Rate::new(asking_price, TWO_PERCENT);
+
+        let amount = rate.bid().unwrap();
+
+        assert_eq!(amount.as_sat(), 98);
+    }
+
     #[test]
     fn applies_spread_to_asking_price() {
         let asking_price = bitcoin::Amount::from_sat(100);
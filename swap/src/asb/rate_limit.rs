@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Limits how many swaps we start within a sliding window.
+///
+/// Swaps in which we buy XMR require us to lock our BTC first. A taker that
+/// never locks its XMR keeps our BTC locked until we refund it and makes us
+/// pay the fees of the lock, cancel and refund transactions. Limiting the
+/// number of these swaps bounds what a taker can make us spend.
+#[derive(Clone, Debug)]
+pub struct RateLimit {
+    max: usize,
+    window: Duration,
+    started: VecDeque<Instant>,
+}
+
+impl RateLimit {
+    pub fn per_hour(max: usize) -> Self {
+        Self::new(max, Duration::from_secs(60 * 60))
+    }
+
+    fn new(max: usize, window: Duration) -> Self {
+        Self {
+            max,
+            window,
+            started: VecDeque::default(),
+        }
+    }
+
+    /// Whether starting another swap exceeds the limit, given that `pending`
+    /// swaps we accepted are still being set up.
+    pub fn is_exceeded_with(&mut self, pending: usize) -> bool {
+        let window = self.window;
+        while let Some(started) = self.started.front() {
+            if started.elapsed() < window {
+                break;
+            }
+            self.started.pop_front();
+        }
+
+        self.started.len() + pending >= self.max
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Records that a swap was started.
+    pub fn record(&mut self) {
+        self.started.push_back(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_is_exceeded_once_max_swaps_started() {
+        let mut limit = RateLimit::per_hour(2);
+
+        limit.record();
+        assert!(!limit.is_exceeded_with(0));
+
+        limit.record();
+        assert!(limit.is_exceeded_with(0));
+    }
+
+    #[test]
+    fn pending_swaps_count_towards_limit() {
+        let mut limit = RateLimit::per_hour(2);
+
+        limit.record();
+
+        assert!(limit.is_exceeded_with(1));
+    }
+
+    #[test]
+    fn swaps_leave_the_window() {
+        let mut limit = RateLimit::new(1, Duration::from_secs(0));
+
+        limit.record();
+
+        assert!(!limit.is_exceeded_with(0));
+    }
+
+    #[test]
+    fn zero_disables_swaps() {
+        let mut limit = RateLimit::per_hour(0);
+
+        assert!(limit.is_exceeded_with(0));
+    }
+}
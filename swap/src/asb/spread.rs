@@ -0,0 +1,166 @@
+use crate::{bitcoin, monero};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
+
+/// Our balances on both sides of the trade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inventory {
+    pub xmr: monero::Amount,
+    pub btc: bitcoin::Amount,
+}
+
+/// Adapts the spread to our inventory.
+///
+/// The closer the XMR balance gets to `xmr_floor`, the closer the spread gets
+/// to `max_spread`, which makes us sell less XMR. The closer the BTC balance
+/// gets to `btc_floor`, the closer the spread gets to `min_spread`, which makes
+/// us sell more XMR. Above the respective target balance, a side does not
+/// affect the spread at all. Setting a floor and target to zero disables that
+/// side.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpreadCurve {
+    pub max_spread: Decimal,
+    #[serde(with = "crate::monero::monero_amount::as_xmr")]
+    pub xmr_floor: monero::Amount,
+    #[serde(with = "crate::monero::monero_amount::as_xmr")]
+    pub xmr_target: monero::Amount,
+    pub min_spread: Decimal,
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    pub btc_floor: bitcoin::Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    pub btc_target: bitcoin::Amount,
+}
+
+impl SpreadCurve {
+    /// The spread to apply instead of `ask_spread` given our `inventory`.
+    ///
+    /// Both sides move the spread linearly between `ask_spread` and their
+    /// bound. If both sides are short, their effects add up. The result never
+    /// leaves the range between `min_spread` and `max_spread`.
+    pub fn spread(&self, ask_spread: Decimal, inventory: Inventory) -> Decimal {
+        let xmr_shortage = shortage(
+            inventory.xmr.as_piconero_decimal(),
+            self.xmr_floor.as_piconero_decimal(),
+            self.xmr_target.as_piconero_decimal(),
+        );
+        let btc_shortage = shortage(
+            Decimal::from(inventory.btc.as_sat()),
+            Decimal::from(self.btc_floor.as_sat()),
+            Decimal::from(self.btc_target.as_sat()),
+        );
+
+        let widening = (self.max_spread - ask_spread) * xmr_shortage;
+        let tightening = (ask_spread - self.min_spread) * btc_shortage;
+
+        (ask_spread + widening - tightening)
+            .max(self.min_spread)
+            .min(self.max_spread)
+    }
+}
+
+/// How far `balance` dropped below `target` towards `floor`, from 0 at or
+/// above the target to 1 at or below the floor.
+fn shortage(balance: Decimal, floor: Decimal, target: Decimal) -> Decimal {
+    if balance >= target {
+        return dec!(0);
+    }
+    if balance <= floor {
+        return dec!(1);
+    }
+
+    (target - balance) / (target - floor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asb::Rate;
+
+    fn curve() -> SpreadCurve {
+        SpreadCurve {
+            max_spread: dec!(0.10),
+            xmr_floor: monero::Amount::from_monero(10.0).unwrap(),
+            xmr_target: monero::Amount::from_monero(110.0).unwrap(),
+            min_spread: dec!(0.01),
+            btc_floor: bitcoin::Amount::from_btc(0.1).unwrap(),
+            btc_target: bitcoin::Amount::from_btc(1.1).unwrap(),
+        }
+    }
+
+    fn inventory(xmr: f64, btc: f64) -> Inventory {
+        Inventory {
+            xmr: monero::Amount::from_monero(xmr).unwrap(),
+            btc: bitcoin::Amount::from_btc(btc).unwrap(),
+        }
+    }
+
+    fn sell_quote(spread: Decimal) -> monero::Amount {
+        let asking_price = bitcoin::Amount::from_btc(0.004).unwrap();
+
+        Rate::new(asking_price, spread)
+            .sell_quote(bitcoin::Amount::from_btc(0.1).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn keeps_ask_spread_with_enough_inventory() {
+        let spread = curve().spread(dec!(0.02), inventory(200.0, 2.0));
+
+        assert_eq!(spread, dec!(0.02));
+        assert_eq!(sell_quote(spread), sell_quote(dec!(0.02)));
+    }
+
+    #[test]
+    fn widens_spread_as_xmr_balance_drops_to_floor() {
+        let curve = curve();
+
+        let half_way = curve.spread(dec!(0.02), inventory(60.0, 2.0));
+        let at_floor = curve.spread(dec!(0.02), inventory(10.0, 2.0));
+        let below_floor = curve.spread(dec!(0.02), inventory(1.0, 2.0));
+
+        assert_eq!(half_way, dec!(0.06));
+        assert_eq!(at_floor, dec!(0.10));
+        assert_eq!(below_floor, dec!(0.10));
+        assert!(sell_quote(half_way) < sell_quote(dec!(0.02)));
+        assert!(sell_quote(at_floor) < sell_quote(half_way));
+    }
+
+    #[test]
+    fn tightens_spread_as_btc_balance_drops_to_floor() {
+        let curve = curve();
+
+        let half_way = curve.spread(dec!(0.02), inventory(200.0, 0.6));
+        let at_floor = curve.spread(dec!(0.02), inventory(200.0, 0.1));
+
+        assert_eq!(half_way, dec!(0.015));
+        assert_eq!(at_floor, dec!(0.01));
+        assert!(sell_quote(half_way) > sell_quote(dec!(0.02)));
+        assert!(sell_quote(at_floor) > sell_quote(half_way));
+    }
+
+    #[test]
+    fn combines_both_sides_within_bounds() {
+        let curve = curve();
+
+        let both_half_way = curve.spread(dec!(0.02), inventory(60.0, 0.6));
+        let both_at_floor = curve.spread(dec!(0.02), inventory(10.0, 0.1));
+
+        assert_eq!(both_half_way, dec!(0.055));
+        assert_eq!(both_at_floor, dec!(0.09));
+    }
+
+    #[test]
+    fn zero_floor_and_target_disable_a_side() {
+        let curve = SpreadCurve {
+            btc_floor: bitcoin::Amount::ZERO,
+            btc_target: bitcoin::Amount::ZERO,
+            ..curve()
+        };
+
+        let spread = curve.spread(dec!(0.02), inventory(200.0, 0.0));
+
+        assert_eq!(spread, dec!(0.02));
+    }
+}
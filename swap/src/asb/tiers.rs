@@ -0,0 +1,174 @@
+use crate::asb::Rate;
+use crate::bitcoin;
+use crate::network::quote::PriceTier;
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Prices swaps of at least `min_btc` differently.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VolumeTier {
+    #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+    pub min_btc: bitcoin::Amount,
+    /// Added to the spread of swaps in this tier, a negative value gives a
+    /// discount.
+    pub extra_spread: Decimal,
+}
+
+/// The volume tiers, ordered by their minimum amount.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VolumeTiers(Vec<VolumeTier>);
+
+impl VolumeTiers {
+    pub fn new(mut tiers: Vec<VolumeTier>) -> Self {
+        tiers.sort_by_key(|tier| tier.min_btc);
+
+        Self(tiers)
+    }
+
+    /// The rate for swapping `btc`, i.e. `rate` with the extra spread of the
+    /// highest tier `btc` reaches.
+    pub fn rate_for(&self, rate: Rate, btc: bitcoin::Amount) -> Rate {
+        match self.0.iter().rev().find(|tier| tier.min_btc <= btc) {
+            Some(tier) => rate.with_extra_spread(tier.extra_spread),
+            None => rate,
+        }
+    }
+
+    /// Splits the range between `min_buy` and `max_buy` at the tiers and
+    /// prices each part.
+    pub fn price_tiers(
+        &self,
+        rate: Rate,
+        min_buy: bitcoin::Amount,
+        max_buy: bitcoin::Amount,
+    ) -> Result<Vec<PriceTier>> {
+        let mut lower_bounds = vec![min_buy];
+        lower_bounds.extend(
+            self.0
+                .iter()
+                .map(|tier| tier.min_btc)
+                .filter(|min_btc| min_buy < *min_btc && *min_btc <= max_buy),
+        );
+        lower_bounds.dedup();
+
+        let upper_bounds = lower_bounds
+            .iter()
+            .skip(1)
+            .map(|next| *next - bitcoin::Amount::from_sat(1))
+            .chain(std::iter::once(max_buy));
+
+        lower_bounds
+            .iter()
+            .zip(upper_bounds)
+            .map(|(min_quantity, max_quantity)| {
+                Ok(PriceTier {
+                    price: self.rate_for(rate, *min_quantity).ask()?,
+                    min_quantity: *min_quantity,
+                    max_quantity,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monero;
+    use rust_decimal_macros::dec;
+
+    fn btc(btc: f64) -> bitcoin::Amount {
+        bitcoin::Amount::from_btc(btc).unwrap()
+    }
+
+    fn tiers() -> VolumeTiers {
+        VolumeTiers::new(vec![
+            VolumeTier {
+                min_btc: btc(1.0),
+                extra_spread: dec!(-0.01),
+            },
+            VolumeTier {
+                min_btc: btc(0.1),
+                extra_spread: dec!(0.01),
+            },
+        ])
+    }
+
+    #[test]
+    fn applies_extra_spread_of_highest_tier_reached() {
+        let rate = Rate::new(bitcoin::Amount::from_sat(100_000), dec!(0.02));
+        let tiers = tiers();
+
+        assert_eq!(
+            tiers.rate_for(rate, btc(0.01)).ask().unwrap().as_sat(),
+            102_000
+        );
+        assert_eq!(
+            tiers.rate_for(rate, btc(0.1)).ask().unwrap().as_sat(),
+            103_000
+        );
+        assert_eq!(
+            tiers.rate_for(rate, btc(2.0)).ask().unwrap().as_sat(),
+            101_000
+        );
+    }
+
+    #[test]
+    fn spread_does_not_drop_below_zero() {
+        let rate = Rate::new(bitcoin::Amount::from_sat(100_000), dec!(0.005));
+
+        assert_eq!(
+            tiers().rate_for(rate, btc(2.0)).ask().unwrap().as_sat(),
+            100_000
+        );
+    }
+
+    #[test]
+    fn splits_buy_range_into_price_tiers() {
+        let rate = Rate::new(bitcoin::Amount::from_sat(100_000), dec!(0.02));
+
+        let price_tiers = tiers().price_tiers(rate, btc(0.01), btc(0.5)).unwrap();
+
+        assert_eq!(price_tiers, vec![
+            PriceTier {
+                price: bitcoin::Amount::from_sat(102_000),
+                min_quantity: btc(0.01),
+                max_quantity: btc(0.1) - bitcoin::Amount::from_sat(1),
+            },
+            PriceTier {
+                price: bitcoin::Amount::from_sat(103_000),
+                min_quantity: btc(0.1),
+                max_quantity: btc(0.5),
+            },
+        ]);
+    }
+
+    #[test]
+    fn without_tiers_quotes_single_price() {
+        let rate = Rate::new(bitcoin::Amount::from_sat(100_000), dec!(0.02));
+
+        let price_tiers = VolumeTiers::default()
+            .price_tiers(rate, btc(0.01), btc(0.5))
+            .unwrap();
+
+        assert_eq!(price_tiers, vec![PriceTier {
+            price: bitcoin::Amount::from_sat(102_000),
+            min_quantity: btc(0.01),
+            max_quantity: btc(0.5),
+        }]);
+    }
+
+    #[test]
+    fn larger_tier_with_discount_yields_more_xmr_per_btc() {
+        let rate = Rate::new(bitcoin::Amount::from_btc(0.004).unwrap(), dec!(0.02));
+        let tiers = tiers();
+
+        let discounted = tiers.rate_for(rate, btc(1.0)).sell_quote(btc(1.0)).unwrap();
+        let undiscounted = rate.sell_quote(btc(1.0)).unwrap();
+
+        assert!(discounted > undiscounted);
+        assert!(undiscounted > monero::Amount::ZERO);
+    }
+}
@@ -18,23 +18,34 @@ use libp2p::core::Multiaddr;
 use libp2p::Swarm;
 use prettytable::{row, Table};
 use std::env;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 use structopt::clap;
 use structopt::clap::ErrorKind;
+use swap::asb::admin::Admin;
 use swap::asb::command::{parse_args, Arguments, Command};
 use swap::asb::config::{
     initial_setup, query_user_for_initial_config, read_config, Config, ConfigNotInitialized,
 };
-use swap::database::Database;
+use swap::asb::{admin, metrics, Inventory};
+use swap::bitcoin::wallet::Strategy;
+use swap::database::{Archive, Database, StateTransition, SwapSummary};
 use swap::monero::Amount;
-use swap::network::swarm;
+use swap::network::rendezvous::XmrBtcNamespace;
+use swap::network::spot_price::BlockchainNetwork;
+use swap::network::{rendezvous, swarm};
 use swap::protocol::alice;
-use swap::protocol::alice::event_loop::KrakenRate;
-use swap::protocol::alice::{redeem, run, EventLoop};
-use swap::seed::Seed;
+use swap::protocol::alice::event_loop::{LatestRate, MedianRate};
+use swap::protocol::alice::{redeem, run_until_stopped, EventLoop};
+use swap::seed::{self, Seed};
 use swap::tor::AuthenticatedClient;
-use swap::{asb, bitcoin, kraken, monero, tor};
+use swap::{asb, bitcoin, monero, price_feed, rpc, tor};
+use time::OffsetDateTime;
+use tokio::net::TcpListener;
 use tracing::{debug, info, warn};
 use tracing_subscriber::filter::LevelFilter;
 
@@ -48,6 +59,7 @@ async fn main() -> Result<()> {
     let Arguments {
         testnet,
         json,
+        seed_passphrase,
         config_path,
         env_config,
         cmd,
@@ -99,11 +111,109 @@ async fn main() -> Result<()> {
 
     let db_path = config.data.dir.join("database");
 
+    // The database has to be migrated before it is opened, sled does not
+    // allow to open it twice.
+    if let Command::MigrateDatabase = cmd {
+        let sqlite_path = Database::migrate_to_sqlite(&db_path)
+            .await
+            .context("Failed to migrate database")?;
+
+        info!(path = %sqlite_path.display(), "Migrated the database to SQLite");
+
+        return Ok(());
+    }
+
     let db = Database::open(config.data.dir.join(db_path).as_path())
         .context("Could not open database")?;
 
-    let seed =
-        Seed::from_file_or_generate(&config.data.dir).expect("Could not retrieve/initialize seed");
+    if let Command::RestoreSeed { passphrase, force } = cmd {
+        let mnemonic = seed::query_user_for_mnemonic()?;
+        let passphrase = if passphrase {
+            seed::query_user_for_passphrase(false)?
+        } else {
+            String::new()
+        };
+        let seed = Seed::from_mnemonic(&mnemonic, &passphrase)?;
+
+        if Seed::exists_in(&config.data.dir) && db.has_unfinished_swaps()? {
+            bail!("Refusing to replace the seed while swaps are unfinished, finish or abort them first")
+        }
+        seed.restore_to(&config.data.dir, force, &seed_passphrase)?;
+
+        info!(
+            peer_id = %seed.derive_libp2p_identity().public().into_peer_id(),
+            "Restored seed"
+        );
+
+        return Ok(());
+    }
+
+    if let Command::EncryptSeed = cmd {
+        Seed::encrypt_file(&config.data.dir, &seed_passphrase)
+            .context("Failed to encrypt seed file")?;
+
+        info!("Encrypted the seed file, the passphrase is asked for from now on");
+
+        return Ok(());
+    }
+
+    let network = BlockchainNetwork {
+        bitcoin: env_config.bitcoin_network,
+        monero: env_config.monero_network,
+    };
+
+    if let Command::ExportSwaps {
+        file,
+        format,
+        include_seed,
+    } = &cmd
+    {
+        let mut archive = db.export(network)?;
+        if *include_seed {
+            archive.seed =
+                Some(Seed::file_contents(&config.data.dir).context("Failed to read seed file")?);
+        }
+
+        let mut archive_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(file)
+            .with_context(|| format!("Failed to create {}", file.display()))?;
+        archive_file.write_all(&archive.to_vec(*format)?)?;
+
+        info!(
+            swaps = archive.swap_ids().len(),
+            file = %file.display(),
+            "Exported swaps"
+        );
+
+        return Ok(());
+    }
+
+    if let Command::ImportSwaps { file } = &cmd {
+        let archive = Archive::from_slice(
+            &std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?,
+        )?;
+        archive.ensure_compatible(network)?;
+
+        match archive.seed.as_deref() {
+            Some(seed_file) => Seed::import_file(&config.data.dir, seed_file)
+                .context("Failed to import seed file")?,
+            None if !Seed::exists_in(&config.data.dir) => warn!(
+                "The archive contains no seed, a new seed and thereby a new peer-id is generated on start"
+            ),
+            None => {}
+        }
+
+        for swap_id in db.import(archive, network).await? {
+            info!(%swap_id, state = %db.get_state(swap_id)?, "Imported swap");
+        }
+
+        return Ok(());
+    }
+
+    let seed = Seed::from_file_or_generate(&config.data.dir, &seed_passphrase)
+        .context("Failed to read in seed file")?;
 
     match cmd {
         Command::Start { resume_only } => {
@@ -125,7 +235,35 @@ async fn main() -> Result<()> {
                 info!(%monero_balance, "Initialized Monero wallet");
             }
 
-            let kraken_price_updates = kraken::connect()?;
+            let cold_storage = match config.bitcoin.cold_storage.as_deref() {
+                Some(descriptor) => {
+                    let cold_storage = bitcoin::ColdStorage::new(
+                        descriptor,
+                        env_config.bitcoin_network,
+                        &config.data.dir.join("cold-storage"),
+                    )?;
+                    info!("Sending the Bitcoin of swaps to cold storage");
+
+                    Some(Arc::new(cold_storage))
+                }
+                None => None,
+            };
+
+            let price_sources = config
+                .maker
+                .price_sources
+                .iter()
+                .map(|source| {
+                    info!(%source, "Connecting to price source");
+                    source.connect()
+                })
+                .collect();
+            let price_feed = price_feed::Median::new(
+                price_sources,
+                config.maker.min_agreeing_sources,
+                config.maker.max_price_deviation,
+                Duration::from_secs(config.maker.max_price_age_secs),
+            );
 
             // setup Tor hidden services
             let tor_client =
@@ -146,16 +284,46 @@ async fn main() -> Result<()> {
 
             let current_balance = monero_wallet.get_balance().await?;
             let lock_fee = monero_wallet.static_tx_fee_estimate();
-            let kraken_rate = KrakenRate::new(config.maker.ask_spread, kraken_price_updates);
+            let mut median_rate = MedianRate::new(
+                config.maker.ask_spread,
+                config.maker.spread_curve,
+                price_feed,
+            );
+            median_rate.update_inventory(Inventory {
+                xmr: monero_balance,
+                btc: bitcoin_balance,
+            });
+            let rendezvous = match config.network.rendezvous_point.as_ref() {
+                Some(rendezvous_point) => {
+                    if config.network.external_addresses.is_empty() {
+                        bail!("Registering at a rendezvous point requires at least one external address in the network config");
+                    }
+
+                    let (rendezvous_node, rendezvous_address) =
+                        rendezvous::split_peer_id(rendezvous_point)?;
+                    info!(%rendezvous_point, "Registering at rendezvous point");
+
+                    Some(alice::rendezvous::Behaviour::new(
+                        rendezvous_node,
+                        rendezvous_address,
+                        XmrBtcNamespace::from_is_testnet(testnet),
+                        config.network.external_addresses.clone(),
+                    ))
+                }
+                None => None,
+            };
             let mut swarm = swarm::alice(
                 &seed,
                 current_balance,
                 lock_fee,
                 config.maker.min_buy_btc,
                 config.maker.max_buy_btc,
-                kraken_rate.clone(),
+                median_rate.clone(),
+                asb::VolumeTiers::new(config.maker.volume_tiers.clone()),
+                Duration::from_secs(config.maker.quote_validity_secs),
                 resume_only,
                 env_config,
+                rendezvous,
             )?;
 
             for listen in config.network.listen {
@@ -163,27 +331,87 @@ async fn main() -> Result<()> {
                     .with_context(|| format!("Failed to listen on network interface {}", listen))?;
             }
 
+            let bitcoin_wallet = Arc::new(bitcoin_wallet);
+            let monero_wallet = Arc::new(monero_wallet);
+            let db = Arc::new(db);
+
             let (event_loop, mut swap_receiver) = EventLoop::new(
                 swarm,
                 env_config,
-                Arc::new(bitcoin_wallet),
-                Arc::new(monero_wallet),
-                Arc::new(db),
-                kraken_rate.clone(),
+                bitcoin_wallet.clone(),
+                cold_storage,
+                monero_wallet.clone(),
+                db.clone(),
+                median_rate.clone(),
                 config.maker.min_buy_btc,
                 config.maker.max_buy_btc,
+                config.maker.min_buy_xmr,
+                config.maker.max_buy_xmr,
+                config.maker.max_buy_xmr_swaps_per_hour,
             )
             .unwrap();
 
+            let running_swaps = event_loop.running_swaps();
+
+            if let Some(admin_config) = config.admin.as_ref() {
+                let token = admin::read_or_generate_token(&config.data.dir)?;
+                let listener = TcpListener::bind(admin_config.listen)
+                    .await
+                    .with_context(|| {
+                        format!("Failed to listen for admin API on {}", admin_config.listen)
+                    })?;
+                info!(address = %admin_config.listen, "Admin API listening");
+
+                let admin = Admin::new(
+                    event_loop.controller(),
+                    median_rate.clone(),
+                    running_swaps.clone(),
+                    bitcoin_wallet.clone(),
+                    monero_wallet.clone(),
+                    db.clone(),
+                );
+                tokio::spawn(async move {
+                    if let Err(error) =
+                        rpc::serve_authenticated(listener, Arc::new(admin), token).await
+                    {
+                        tracing::error!("Admin API stopped. Error {:#}", error);
+                    }
+                });
+            }
+
+            if let Some(metrics_config) = config.metrics.as_ref() {
+                let listener = TcpListener::bind(metrics_config.listen)
+                    .await
+                    .with_context(|| {
+                        format!("Failed to listen for metrics on {}", metrics_config.listen)
+                    })?;
+                info!(address = %metrics_config.listen, "Serving metrics");
+
+                let metrics = event_loop.metrics();
+                tokio::spawn(metrics::track_swap_states(metrics.clone(), db));
+                tokio::spawn(metrics::update_gauges(
+                    metrics.clone(),
+                    bitcoin_wallet,
+                    monero_wallet,
+                    median_rate.clone(),
+                ));
+                tokio::spawn(async move {
+                    if let Err(error) = metrics::serve(listener, metrics).await {
+                        tracing::error!("Metrics endpoint stopped. Error {:#}", error);
+                    }
+                });
+            }
+
             tokio::spawn(async move {
                 while let Some(swap) = swap_receiver.recv().await {
-                    let rate = kraken_rate.clone();
-                    tokio::spawn(async move {
-                        let swap_id = swap.swap_id;
-                        match run(swap, rate).await {
-                            Ok(state) => {
+                    let rate = median_rate.clone();
+                    let swap_id = swap.swap_id;
+                    running_swaps.spawn(swap_id, |stop| async move {
+                        match run_until_stopped(swap, rate, stop).await {
+                            Ok(Some(state)) => {
                                 tracing::debug!(%swap_id, %state, "Swap finished with state")
                             }
+                            Ok(None) => tracing::info!(%swap_id, "Stopped swap"),
                             Err(error) => {
                                 tracing::error!(%swap_id, "Swap failed. Error {:#}", error)
                             }
@@ -196,19 +424,64 @@ async fn main() -> Result<()> {
 
             event_loop.run().await;
         }
-        Command::History => {
+        Command::History {
+            swap_id: None,
+            filter,
+            json: print_json,
+        } => {
+            let summaries = db.summaries(filter)?;
+
+            if print_json {
+                let summaries = summaries
+                    .iter()
+                    .map(SwapSummary::to_json)
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            } else {
+                // Print the table to stdout
+                history_table(&summaries).printstd();
+            }
+        }
+        Command::History {
+            swap_id: Some(swap_id),
+            json: print_json,
+            ..
+        } => {
+            let history = db.history(swap_id)?;
+
+            if print_json {
+                let mut summary = db.summary(swap_id)?.to_json();
+                summary["history"] = history.iter().map(StateTransition::to_json).collect();
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+
+                return Ok(());
+            }
+
             let mut table = Table::new();
 
-            table.add_row(row!["SWAP ID", "STATE"]);
+            table.add_row(row!["TIME", "STATE", "DETAILS"]);
 
-            for (swap_id, state) in db.all_alice()? {
-                table.add_row(row![swap_id, state]);
+            if history.is_empty() {
+                // Swaps started before the history was recorded only have
+                // their latest state
+                let state = db.get_state(swap_id)?;
+                table.add_row(row!["unknown", state, format_details(state.details())]);
+            }
+            for transition in history {
+                table.add_row(row![
+                    transition.timestamp.format("%F %T UTC"),
+                    transition.state,
+                    format_details(transition.state.details())
+                ]);
             }
 
-            // Print the table to stdout
             table.printstd();
         }
-        Command::WithdrawBtc { amount, address } => {
+        Command::WithdrawBtc {
+            amount,
+            address,
+            await_confirmation,
+        } => {
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config).await?;
 
             let amount = match amount {
@@ -223,7 +496,18 @@ async fn main() -> Result<()> {
             let psbt = bitcoin_wallet.send_to_address(address, amount).await?;
             let signed_tx = bitcoin_wallet.sign_and_finalize(psbt).await?;
 
-            bitcoin_wallet.broadcast(signed_tx, "withdraw").await?;
+            bitcoin_wallet
+                .broadcast(signed_tx.clone(), "withdraw")
+                .await?;
+
+            if await_confirmation {
+                tracing::info!(
+                    "Waiting for the withdrawal to confirm, bumping its fee if necessary"
+                );
+                bitcoin_wallet
+                    .bump_fee_until_confirmed(signed_tx, "withdraw", Strategy::Rbf, None)
+                    .await?;
+            }
         }
         Command::Balance => {
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config).await?;
@@ -291,11 +575,71 @@ async fn main() -> Result<()> {
 
             tracing::info!("Redeem transaction successfully published with id {}", txid);
         }
+        Command::ExportSeed { passphrase } => {
+            let passphrase = if passphrase {
+                seed::query_user_for_passphrase(true)?
+            } else {
+                String::new()
+            };
+
+            println!("{}", seed.to_mnemonic(&passphrase)?);
+            info!(
+                peer_id = %seed.derive_libp2p_identity().public().into_peer_id(),
+                "Exported seed"
+            );
+        }
+        Command::RestoreSeed { .. }
+        | Command::EncryptSeed
+        | Command::ExportSwaps { .. }
+        | Command::ImportSwaps { .. }
+        | Command::MigrateDatabase => {
+            unreachable!("handled before the seed is loaded")
+        }
     }
 
     Ok(())
 }
 
+fn history_table(summaries: &[SwapSummary]) -> Table {
+    let mut table = Table::new();
+
+    table.add_row(row![
+        "SWAP ID", "STATE", "BTC", "XMR", "RATE", "PEER ID", "STARTED", "UPDATED", "DETAILS"
+    ]);
+
+    for summary in summaries {
+        table.add_row(row![
+            summary.swap_id,
+            summary.state,
+            format_optional(summary.btc_amount),
+            format_optional(summary.xmr_amount),
+            format_optional(summary.rate()),
+            format_optional(summary.peer_id),
+            format_time(summary.started_at),
+            format_time(summary.updated_at),
+            format_details(summary.details.clone())
+        ]);
+    }
+
+    table
+}
+
+fn format_details(details: Vec<(&'static str, String)>) -> String {
+    details
+        .into_iter()
+        .map(|(label, value)| format!("{}: {}", label, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_optional<T: Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "unknown".to_owned(), |value| value.to_string())
+}
+
+fn format_time(time: Option<OffsetDateTime>) -> String {
+    format_optional(time.map(|time| time.format("%F %T UTC")))
+}
+
 async fn init_bitcoin_wallet(
     config: &Config,
     seed: &Seed,
@@ -304,8 +648,11 @@ async fn init_bitcoin_wallet(
     debug!("Opening Bitcoin wallet");
     let wallet_dir = config.data.dir.join("wallet");
 
+    let backend = config.bitcoin.backend()?;
+    debug!(%backend, "Connecting to Bitcoin backend");
+
     let wallet = bitcoin::Wallet::new(
-        config.bitcoin.electrum_rpc_url.clone(),
+        backend,
         &wallet_dir,
         seed.derive_extended_private_key(env_config.bitcoin_network)?,
         env_config,
@@ -6,7 +6,11 @@ async fn main() -> Result<()> {
         tracing_subscriber::fmt().with_env_filter("debug").finish(),
     )?;
 
-    let mut ticker = swap::kraken::connect().context("Failed to connect to kraken")?;
+    let mut ticker = swap::price_feed::kraken::connect(
+        swap::price_feed::kraken::DEFAULT_WEBSOCKET_URL
+            .parse()
+            .context("Failed to parse Kraken websocket url")?,
+    );
 
     loop {
         match ticker.wait_for_next_update().await? {
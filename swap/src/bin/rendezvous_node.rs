@@ -0,0 +1,104 @@
+#![warn(
+    unused_extern_crates,
+    missing_copy_implementations,
+    rust_2018_idioms,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::fallible_impl_from,
+    clippy::cast_precision_loss,
+    clippy::cast_possible_wrap,
+    clippy::dbg_macro
+)]
+#![forbid(unsafe_code)]
+#![allow(non_snake_case)]
+
+//! A rendezvous point at which ASBs register and CLIs discover them.
+//!
+//! The peer id of the rendezvous point is derived from the seed in its data
+//! directory, so that it stays the same across restarts. ASBs and CLIs are
+//! configured with the listen address followed by `/p2p/<peer-id>`.
+
+use anyhow::{Context, Result};
+use libp2p::core::Multiaddr;
+use libp2p::swarm::SwarmEvent;
+use libp2p::Swarm;
+use std::path::PathBuf;
+use structopt::StructOpt;
+use swap::network::rendezvous::server::OutEvent;
+use swap::network::swarm;
+use swap::seed::{PassphraseSource, Seed};
+use tracing_subscriber::filter::LevelFilter;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "rendezvous_node",
+    about = "Rendezvous point at which ASBs register and CLIs discover them"
+)]
+struct Arguments {
+    #[structopt(
+        long = "data-dir",
+        help = "The directory of the seed file the peer id is derived from",
+        parse(from_os_str)
+    )]
+    data_dir: PathBuf,
+
+    #[structopt(
+        long = "listen",
+        help = "The address to listen on, can be given multiple times",
+        default_value = "/ip4/0.0.0.0/tcp/8888"
+    )]
+    listen: Vec<Multiaddr>,
+
+    #[structopt(
+        long = "seed-passphrase-env",
+        help = "Read the passphrase of an encrypted seed file from this environment variable instead of prompting for it"
+    )]
+    seed_passphrase_env: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let Arguments {
+        data_dir,
+        listen,
+        seed_passphrase_env,
+    } = Arguments::from_args();
+
+    tracing::subscriber::set_global_default(
+        tracing_subscriber::fmt()
+            .with_env_filter(format!("{},swap=debug", LevelFilter::INFO))
+            .finish(),
+    )?;
+
+    let seed = Seed::from_file_or_generate(
+        &data_dir,
+        &PassphraseSource::from_options(seed_passphrase_env, None),
+    )
+    .context("Failed to read in seed file")?;
+
+    let mut swarm = swarm::rendezvous_node(&seed)?;
+    for address in listen {
+        Swarm::listen_on(&mut swarm, address.clone())
+            .with_context(|| format!("Failed to listen on {}", address))?;
+    }
+
+    tracing::info!(peer_id = %swarm.local_peer_id(), "Rendezvous point running");
+
+    loop {
+        match swarm.next_event().await {
+            SwarmEvent::Behaviour(OutEvent::PeerRegistered { peer, namespace }) => {
+                tracing::info!(%peer, %namespace, "Peer registered");
+            }
+            SwarmEvent::Behaviour(OutEvent::RegistrationDeclined { peer, error }) => {
+                tracing::warn!(%peer, "Declined registration: {}", error);
+            }
+            SwarmEvent::Behaviour(OutEvent::DiscoverServed { peer, namespace }) => {
+                tracing::debug!(%peer, %namespace, "Served discovery request");
+            }
+            SwarmEvent::NewListenAddr(address) => {
+                tracing::info!(%address, "Listening on");
+            }
+            _ => {}
+        }
+    }
+}
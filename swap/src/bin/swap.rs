@@ -14,24 +14,38 @@
 
 use anyhow::{bail, Context, Result};
 use prettytable::{row, Table};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
 use std::cmp::min;
 use std::env;
+use std::fmt::Display;
+use std::fs::OpenOptions;
 use std::future::Future;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use swap::bitcoin::TxLock;
-use swap::cli::command::{parse_args_and_apply_defaults, Arguments, Command, ParseResult};
-use swap::database::Database;
+use swap::bitcoin::wallet::BackendConfig;
+use swap::bitcoin::{TxLock, TxPunish, TxRedeem};
+use swap::cli::command::{
+    parse_args_and_apply_defaults, Arguments, Command, ExternalSigningConfig, ParseResult,
+};
+use swap::cli::daemon::Daemon;
+use swap::cli::list_sellers::{list_sellers, Status};
+use swap::database::{Archive, Database, StateTransition, SwapSummary};
 use swap::env::Config;
-use swap::network::quote::BidQuote;
+use swap::network::quote::{AskQuote, TieredBidQuote};
+use swap::network::spot_price::BlockchainNetwork;
 use swap::network::swarm;
-use swap::protocol::bob;
+use swap::protocol::alice::event_loop::NoRate;
+use swap::protocol::alice::{taker, AliceState};
 use swap::protocol::bob::{EventLoop, Swap};
-use swap::seed::Seed;
-use swap::{bitcoin, cli, monero};
+use swap::protocol::{alice, bob};
+use swap::seed::{self, Seed};
+use swap::{bitcoin, cli, monero, rpc};
+use time::OffsetDateTime;
+use tokio::net::TcpListener;
 use tracing::{debug, error, info, warn};
-use url::Url;
 use uuid::Uuid;
 
 #[macro_use]
@@ -44,6 +58,7 @@ async fn main() -> Result<()> {
         data_dir,
         debug,
         json,
+        seed_passphrase,
         cmd,
     } = match parse_args_and_apply_defaults(env::args_os())? {
         ParseResult::Arguments(args) => args,
@@ -57,10 +72,13 @@ async fn main() -> Result<()> {
         Command::BuyXmr {
             seller_peer_id,
             seller_addr,
-            bitcoin_electrum_rpc_url,
+            external_funding,
+            bitcoin_backend,
             bitcoin_target_block,
             monero_receive_address,
+            monero_receive_splits,
             monero_daemon_address,
+            external_signing,
             tor_socks5_port,
         } => {
             let swap_id = Uuid::new_v4();
@@ -68,53 +86,91 @@ async fn main() -> Result<()> {
             cli::tracing::init(debug, json, data_dir.join("logs"), swap_id)?;
             let db = Database::open(data_dir.join("database").as_path())
                 .context("Failed to open database")?;
-            let seed = Seed::from_file_or_generate(data_dir.as_path())
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
                 .context("Failed to read in seed file")?;
 
-            let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
-                &seed,
-                data_dir.clone(),
-                env_config,
-                bitcoin_target_block,
-            )
-            .await?;
+            let descriptor = external_signing
+                .as_ref()
+                .and_then(|external_signing| external_signing.descriptor.as_deref());
+            let bitcoin_wallet = match descriptor {
+                Some(descriptor) => {
+                    init_watch_only_bitcoin_wallet(
+                        bitcoin_backend,
+                        descriptor,
+                        data_dir.clone(),
+                        env_config,
+                        bitcoin_target_block,
+                    )
+                    .await?
+                }
+                None => {
+                    init_bitcoin_wallet(
+                        bitcoin_backend,
+                        &seed,
+                        data_dir.clone(),
+                        env_config,
+                        bitcoin_target_block,
+                    )
+                    .await?
+                }
+            };
             let (monero_wallet, _process) =
                 init_monero_wallet(data_dir, monero_daemon_address, env_config).await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
 
             let mut swarm = swarm::bob(&seed, seller_peer_id, tor_socks5_port).await?;
+            db.insert_address(seller_peer_id, seller_addr.clone())
+                .await?;
             swarm
                 .behaviour_mut()
                 .add_address(seller_peer_id, seller_addr);
 
             let our_peer_id = swarm.local_peer_id();
             tracing::debug!(peer_id = %our_peer_id, "Initializing network module");
+            let funding_signer = match (external_funding, &external_signing) {
+                (Some(_), Some(external_signing)) => Some(external_signing.signer.clone()),
+                _ => None,
+            };
             let (event_loop, mut event_loop_handle) = EventLoop::new(
                 swap_id,
                 swarm,
                 seller_peer_id,
                 bitcoin_wallet.clone(),
                 env_config,
+                funding_signer,
             )?;
             let event_loop = tokio::spawn(event_loop.run());
 
-            let max_givable = || bitcoin_wallet.max_giveable(TxLock::script_size());
-            let (amount, fees) = determine_btc_to_swap(
-                event_loop_handle.request_quote(),
-                bitcoin_wallet.new_address(),
-                || bitcoin_wallet.balance(),
-                max_givable,
-                || bitcoin_wallet.sync(),
-            )
-            .await?;
+            let amount = match external_funding {
+                Some(amount) => {
+                    check_externally_funded_amount(event_loop_handle.request_quote(), amount)
+                        .await?;
+
+                    info!(%amount, %swap_id, "Swapping BTC paid from an external wallet");
 
-            info!(%amount, %fees, %swap_id,  "Swapping");
+                    amount
+                }
+                None => {
+                    let max_givable = || bitcoin_wallet.max_giveable(TxLock::script_size());
+                    let (amount, fees) = determine_btc_to_swap(
+                        event_loop_handle.request_quote(),
+                        bitcoin_wallet.new_address(),
+                        || bitcoin_wallet.balance(),
+                        max_givable,
+                        || bitcoin_wallet.sync(),
+                    )
+                    .await?;
+
+                    info!(%amount, %fees, %swap_id,  "Swapping");
+
+                    amount
+                }
+            };
 
             db.insert_peer_id(swap_id, seller_peer_id).await?;
 
             let swap = Swap::new(
-                db,
+                Arc::new(db),
                 swap_id,
                 bitcoin_wallet,
                 Arc::new(monero_wallet),
@@ -122,7 +178,15 @@ async fn main() -> Result<()> {
                 event_loop_handle,
                 monero_receive_address,
                 amount,
-            );
+            )
+            .with_monero_splits(monero_receive_splits);
+            let swap = match external_signing {
+                Some(ExternalSigningConfig {
+                    descriptor: Some(_),
+                    signer,
+                }) => swap.with_external_signer(signer),
+                _ => swap,
+            };
 
             tokio::select! {
                 result = event_loop => {
@@ -134,16 +198,114 @@ async fn main() -> Result<()> {
                 }
             }
         }
-        Command::History => {
+        Command::History {
+            swap_id: None,
+            filter,
+            json: print_json,
+        } => {
+            let db = Database::open(data_dir.join("database").as_path())
+                .context("Failed to open database")?;
+
+            let summaries = db.summaries(filter)?;
+
+            if print_json {
+                let summaries = summaries
+                    .iter()
+                    .map(SwapSummary::to_json)
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+            } else {
+                // Print the table to stdout
+                history_table(&summaries).printstd();
+            }
+        }
+        Command::History {
+            swap_id: Some(swap_id),
+            json: print_json,
+            ..
+        } => {
             let db = Database::open(data_dir.join("database").as_path())
                 .context("Failed to open database")?;
 
+            let history = db.history(swap_id)?;
+
+            if print_json {
+                let mut summary = db.summary(swap_id)?.to_json();
+                summary["history"] = history.iter().map(StateTransition::to_json).collect();
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+
+                return Ok(());
+            }
+
             let mut table = Table::new();
 
-            table.add_row(row!["SWAP ID", "STATE"]);
+            table.add_row(row!["TIME", "STATE", "DETAILS"]);
+
+            if history.is_empty() {
+                // Swaps started before the history was recorded only have
+                // their latest state
+                let state = db.get_state(swap_id)?;
+                table.add_row(row!["unknown", state, format_details(state.details())]);
+            }
+            for transition in history {
+                table.add_row(row![
+                    transition.timestamp.format("%F %T UTC"),
+                    transition.state,
+                    format_details(transition.state.details())
+                ]);
+            }
+
+            table.printstd();
+        }
+        Command::ListSellers {
+            rendezvous_node_peer_id,
+            rendezvous_node_addr,
+            namespace,
+            tor_socks5_port,
+        } => {
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
+                .context("Failed to read in seed file")?;
+
+            let sellers = list_sellers(
+                rendezvous_node_peer_id,
+                rendezvous_node_addr,
+                namespace,
+                tor_socks5_port,
+                &seed,
+            )
+            .await?;
 
-            for (swap_id, state) in db.all_bob()? {
-                table.add_row(row![swap_id, state]);
+            let mut table = Table::new();
+
+            table.add_row(row![
+                "PEER ID",
+                "ADDRESS",
+                "PRICE",
+                "MIN QUANTITY",
+                "MAX QUANTITY"
+            ]);
+
+            for seller in sellers {
+                match seller.status {
+                    Status::Online(quote) => {
+                        table.add_row(row![
+                            seller.peer_id,
+                            seller.multiaddr,
+                            quote.price,
+                            quote.min_quantity,
+                            quote.max_quantity
+                        ]);
+                    }
+                    Status::Unreachable => {
+                        table.add_row(row![
+                            seller.peer_id,
+                            seller.multiaddr,
+                            "unreachable",
+                            "",
+                            ""
+                        ]);
+                    }
+                }
             }
 
             // Print the table to stdout
@@ -152,30 +314,49 @@ async fn main() -> Result<()> {
         Command::Resume {
             swap_id,
             seller_addr,
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
             monero_receive_address,
+            monero_receive_splits,
             monero_daemon_address,
+            external_signing,
             tor_socks5_port,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), swap_id)?;
             let db = Database::open(data_dir.join("database").as_path())
                 .context("Failed to open database")?;
-            let seed = Seed::from_file_or_generate(data_dir.as_path())
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
                 .context("Failed to read in seed file")?;
 
             if monero_receive_address.network != env_config.monero_network {
                 bail!("The given monero address is on network {:?}, expected address of network {:?}.", monero_receive_address.network, env_config.monero_network)
             }
 
-            let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
-                &seed,
-                data_dir.clone(),
-                env_config,
-                bitcoin_target_block,
-            )
-            .await?;
+            let descriptor = external_signing
+                .as_ref()
+                .and_then(|external_signing| external_signing.descriptor.as_deref());
+            let bitcoin_wallet = match descriptor {
+                Some(descriptor) => {
+                    init_watch_only_bitcoin_wallet(
+                        bitcoin_backend,
+                        descriptor,
+                        data_dir.clone(),
+                        env_config,
+                        bitcoin_target_block,
+                    )
+                    .await?
+                }
+                None => {
+                    init_bitcoin_wallet(
+                        bitcoin_backend,
+                        &seed,
+                        data_dir.clone(),
+                        env_config,
+                        bitcoin_target_block,
+                    )
+                    .await?
+                }
+            };
             let (monero_wallet, _process) =
                 init_monero_wallet(data_dir, monero_daemon_address, env_config).await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
@@ -185,6 +366,8 @@ async fn main() -> Result<()> {
             let mut swarm = swarm::bob(&seed, seller_peer_id, tor_socks5_port).await?;
             let our_peer_id = swarm.local_peer_id();
             tracing::debug!(peer_id = %our_peer_id, "Initializing network module");
+            db.insert_address(seller_peer_id, seller_addr.clone())
+                .await?;
             swarm
                 .behaviour_mut()
                 .add_address(seller_peer_id, seller_addr);
@@ -195,18 +378,27 @@ async fn main() -> Result<()> {
                 seller_peer_id,
                 bitcoin_wallet.clone(),
                 env_config,
+                None,
             )?;
             let handle = tokio::spawn(event_loop.run());
 
             let swap = Swap::from_db(
-                db,
+                Arc::new(db),
                 swap_id,
                 bitcoin_wallet,
                 Arc::new(monero_wallet),
                 env_config,
                 event_loop_handle,
                 monero_receive_address,
-            )?;
+            )?
+            .with_monero_splits(monero_receive_splits);
+            let swap = match external_signing {
+                Some(ExternalSigningConfig {
+                    descriptor: Some(_),
+                    signer,
+                }) => swap.with_external_signer(signer),
+                _ => swap,
+            };
 
             tokio::select! {
                 event_loop_result = handle => {
@@ -217,20 +409,222 @@ async fn main() -> Result<()> {
                 }
             }
         }
+        Command::SellXmr {
+            buyer_peer_id,
+            buyer_addr,
+            bitcoin_backend,
+            bitcoin_target_block,
+            bitcoin_receive_address,
+            monero_daemon_address,
+            monero_refund_address,
+            tor_socks5_port,
+        } => {
+            let swap_id = Uuid::new_v4();
+
+            cli::tracing::init(debug, json, data_dir.join("logs"), swap_id)?;
+            let db = Database::open(data_dir.join("database").as_path())
+                .context("Failed to open database")?;
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
+                .context("Failed to read in seed file")?;
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                bitcoin_backend,
+                &seed,
+                data_dir.clone(),
+                env_config,
+                bitcoin_target_block,
+            )
+            .await?;
+            let (monero_wallet, _process) =
+                init_monero_wallet(data_dir, monero_daemon_address, env_config).await?;
+            let monero_wallet = match monero_refund_address {
+                Some(address) => monero_wallet.sweep_generated_wallets_to(address),
+                None => monero_wallet,
+            };
+            let bitcoin_wallet = Arc::new(bitcoin_wallet);
+            let monero_wallet = Arc::new(monero_wallet);
+
+            let mut swarm = swarm::taker(&seed, buyer_peer_id, tor_socks5_port).await?;
+            db.insert_address(buyer_peer_id, buyer_addr.clone()).await?;
+            swarm.behaviour_mut().add_address(buyer_peer_id, buyer_addr);
+
+            let our_peer_id = swarm.local_peer_id();
+            tracing::debug!(peer_id = %our_peer_id, "Initializing network module");
+            let (event_loop, mut event_loop_handle, swap_event_loop_handle) =
+                taker::EventLoop::new(swap_id, swarm, buyer_peer_id, env_config)?;
+            let event_loop = tokio::spawn(event_loop.run());
+
+            let wallet = monero_wallet.as_ref();
+            let xmr = determine_xmr_to_swap(
+                event_loop_handle.request_quote(),
+                wallet.get_main_address(),
+                || wallet.get_unlocked_balance(),
+                || async move { wallet.refresh().await.map(|_| ()) },
+                wallet.static_tx_fee_estimate(),
+            )
+            .await?;
+
+            let btc = event_loop_handle.request_spot_price(xmr).await?;
+
+            info!(%btc, %xmr, %swap_id, "Swapping");
+
+            let tx_redeem_fee = bitcoin_wallet.estimate_fee(TxRedeem::weight(), btc).await?;
+            let tx_punish_fee = bitcoin_wallet.estimate_fee(TxPunish::weight(), btc).await?;
+            let state0 = alice::State0::new(
+                btc,
+                xmr,
+                env_config,
+                bitcoin_receive_address.clone(),
+                bitcoin_receive_address,
+                None,
+                tx_redeem_fee,
+                tx_punish_fee,
+                &mut OsRng,
+            )?;
+            let state3 = event_loop_handle.execution_setup(state0).await?;
+
+            db.insert_peer_id(swap_id, buyer_peer_id).await?;
+
+            let swap = alice::Swap {
+                state: AliceState::Started {
+                    state3: Box::new(state3),
+                },
+                event_loop_handle: swap_event_loop_handle,
+                bitcoin_wallet,
+                monero_wallet,
+                env_config,
+                swap_id,
+                db: Arc::new(db),
+            };
+
+            tokio::select! {
+                result = event_loop => {
+                    result
+                        .context("EventLoop panicked")?;
+                },
+                result = alice::run(swap, NoRate) => {
+                    result.context("Failed to complete swap")?;
+                }
+            }
+        }
+        Command::ResumeSellXmr {
+            swap_id,
+            buyer_addr,
+            bitcoin_backend,
+            bitcoin_target_block,
+            monero_daemon_address,
+            monero_refund_address,
+            tor_socks5_port,
+        } => {
+            cli::tracing::init(debug, json, data_dir.join("logs"), swap_id)?;
+            let db = Database::open(data_dir.join("database").as_path())
+                .context("Failed to open database")?;
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
+                .context("Failed to read in seed file")?;
+
+            let state = db.get_state(swap_id)?.try_into_alice()?.into();
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                bitcoin_backend,
+                &seed,
+                data_dir.clone(),
+                env_config,
+                bitcoin_target_block,
+            )
+            .await?;
+            let (monero_wallet, _process) =
+                init_monero_wallet(data_dir, monero_daemon_address, env_config).await?;
+            let monero_wallet = match monero_refund_address {
+                Some(address) => monero_wallet.sweep_generated_wallets_to(address),
+                None => monero_wallet,
+            };
+
+            let buyer_peer_id = db.get_peer_id(swap_id)?;
+
+            let mut swarm = swarm::taker(&seed, buyer_peer_id, tor_socks5_port).await?;
+            let our_peer_id = swarm.local_peer_id();
+            tracing::debug!(peer_id = %our_peer_id, "Initializing network module");
+            db.insert_address(buyer_peer_id, buyer_addr.clone()).await?;
+            swarm.behaviour_mut().add_address(buyer_peer_id, buyer_addr);
+
+            let (event_loop, _event_loop_handle, swap_event_loop_handle) =
+                taker::EventLoop::new(swap_id, swarm, buyer_peer_id, env_config)?;
+            let handle = tokio::spawn(event_loop.run());
+
+            let swap = alice::Swap {
+                state,
+                event_loop_handle: swap_event_loop_handle,
+                bitcoin_wallet: Arc::new(bitcoin_wallet),
+                monero_wallet: Arc::new(monero_wallet),
+                env_config,
+                swap_id,
+                db: Arc::new(db),
+            };
+
+            tokio::select! {
+                event_loop_result = handle => {
+                    event_loop_result?;
+                },
+                swap_result = alice::run(swap, NoRate) => {
+                    swap_result?;
+                }
+            }
+        }
+        Command::Daemon {
+            rpc_listen_address,
+            bitcoin_backend,
+            bitcoin_target_block,
+            monero_daemon_address,
+            tor_socks5_port,
+        } => {
+            cli::tracing::init_daemon(debug, json, data_dir.join("logs"))?;
+            let db = Database::open(data_dir.join("database").as_path())
+                .context("Failed to open database")?;
+            let token = cli::daemon::read_or_generate_token(&data_dir)?;
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
+                .context("Failed to read in seed file")?;
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                bitcoin_backend,
+                &seed,
+                data_dir.clone(),
+                env_config,
+                bitcoin_target_block,
+            )
+            .await?;
+            let (monero_wallet, _process) =
+                init_monero_wallet(data_dir, monero_daemon_address, env_config).await?;
+
+            let listener = TcpListener::bind(rpc_listen_address)
+                .await
+                .with_context(|| format!("Failed to listen on {}", rpc_listen_address))?;
+            info!(%rpc_listen_address, "JSON-RPC server listening");
+
+            let daemon = Daemon::new(
+                Arc::new(bitcoin_wallet),
+                Arc::new(monero_wallet),
+                Arc::new(db),
+                seed,
+                env_config,
+                tor_socks5_port,
+            );
+
+            rpc::serve_authenticated(listener, Arc::new(daemon), token).await?;
+        }
         Command::Cancel {
             swap_id,
             force,
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), swap_id)?;
             let db = Database::open(data_dir.join("database").as_path())
                 .context("Failed to open database")?;
-            let seed = Seed::from_file_or_generate(data_dir.as_path())
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
                 .context("Failed to read in seed file")?;
 
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir,
                 env_config,
@@ -238,7 +632,8 @@ async fn main() -> Result<()> {
             )
             .await?;
 
-            let cancel = bob::cancel(swap_id, Arc::new(bitcoin_wallet), db, force).await?;
+            let cancel =
+                bob::cancel(swap_id, Arc::new(bitcoin_wallet), Arc::new(db), force).await?;
 
             match cancel {
                 Ok((txid, _)) => {
@@ -252,17 +647,17 @@ async fn main() -> Result<()> {
         Command::Refund {
             swap_id,
             force,
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), swap_id)?;
             let db = Database::open(data_dir.join("database").as_path())
                 .context("Failed to open database")?;
-            let seed = Seed::from_file_or_generate(data_dir.as_path())
+            let seed = Seed::from_file_or_generate(data_dir.as_path(), &seed_passphrase)
                 .context("Failed to read in seed file")?;
 
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir,
                 env_config,
@@ -270,14 +665,124 @@ async fn main() -> Result<()> {
             )
             .await?;
 
-            bob::refund(swap_id, Arc::new(bitcoin_wallet), db, force).await??;
+            bob::refund(swap_id, Arc::new(bitcoin_wallet), Arc::new(db), force).await??;
+        }
+        Command::ExportSeed { passphrase } => {
+            let seed = Seed::from_data_dir(data_dir.as_path(), &seed_passphrase)
+                .context("Failed to read in seed file")?;
+            let passphrase = if passphrase {
+                seed::query_user_for_passphrase(true)?
+            } else {
+                String::new()
+            };
+
+            println!("{}", seed.to_mnemonic(&passphrase)?);
+            println!(
+                "Peer id of this seed: {}",
+                seed.derive_libp2p_identity().public().into_peer_id()
+            );
+        }
+        Command::RestoreSeed { passphrase, force } => {
+            let mnemonic = seed::query_user_for_mnemonic()?;
+            let passphrase = if passphrase {
+                seed::query_user_for_passphrase(false)?
+            } else {
+                String::new()
+            };
+            let seed = Seed::from_mnemonic(&mnemonic, &passphrase)?;
+
+            if Seed::exists_in(data_dir.as_path()) {
+                let db = Database::open(data_dir.join("database").as_path())
+                    .context("Failed to open database")?;
+                if db.has_unfinished_swaps()? {
+                    bail!("Refusing to replace the seed while swaps are unfinished, finish or abort them first")
+                }
+            }
+            seed.restore_to(data_dir.as_path(), force, &seed_passphrase)?;
+
+            println!(
+                "Restored seed with peer id {}",
+                seed.derive_libp2p_identity().public().into_peer_id()
+            );
+        }
+        Command::EncryptSeed => {
+            Seed::encrypt_file(data_dir.as_path(), &seed_passphrase)
+                .context("Failed to encrypt seed file")?;
+
+            println!("Encrypted the seed file, the passphrase is asked for from now on");
+        }
+        Command::ExportSwaps {
+            file,
+            format,
+            include_seed,
+        } => {
+            let db = Database::open(data_dir.join("database").as_path())
+                .context("Failed to open database")?;
+
+            let mut archive = db.export(BlockchainNetwork {
+                bitcoin: env_config.bitcoin_network,
+                monero: env_config.monero_network,
+            })?;
+            if include_seed {
+                archive.seed = Some(
+                    Seed::file_contents(data_dir.as_path()).context("Failed to read seed file")?,
+                );
+            }
+
+            let mut archive_file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&file)
+                .with_context(|| format!("Failed to create {}", file.display()))?;
+            archive_file.write_all(&archive.to_vec(format)?)?;
+
+            println!(
+                "Exported {} swaps to {}",
+                archive.swap_ids().len(),
+                file.display()
+            );
+        }
+        Command::ImportSwaps { file } => {
+            let network = BlockchainNetwork {
+                bitcoin: env_config.bitcoin_network,
+                monero: env_config.monero_network,
+            };
+
+            let archive = Archive::from_slice(
+                &std::fs::read(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?,
+            )?;
+            archive.ensure_compatible(network)?;
+
+            match archive.seed.as_deref() {
+                Some(seed_file) => Seed::import_file(data_dir.as_path(), seed_file)
+                    .context("Failed to import seed file")?,
+                None if !Seed::exists_in(data_dir.as_path()) => println!(
+                    "The archive contains no seed, copy the seed file or restore the seed before resuming swaps"
+                ),
+                None => {}
+            }
+
+            let db = Database::open(data_dir.join("database").as_path())
+                .context("Failed to open database")?;
+
+            for swap_id in db.import(archive, network).await? {
+                println!("Imported swap {}: {}", swap_id, db.get_state(swap_id)?);
+            }
+        }
+        Command::MigrateDatabase => {
+            let sqlite_path = Database::migrate_to_sqlite(data_dir.join("database").as_path())
+                .await
+                .context("Failed to migrate database")?;
+
+            println!("Migrated the database to {}", sqlite_path.display());
         }
     };
     Ok(())
 }
 
 async fn init_bitcoin_wallet(
-    electrum_rpc_url: Url,
+    backend: BackendConfig,
     seed: &Seed,
     data_dir: PathBuf,
     env_config: Config,
@@ -286,7 +791,7 @@ async fn init_bitcoin_wallet(
     let wallet_dir = data_dir.join("wallet");
 
     let wallet = bitcoin::Wallet::new(
-        electrum_rpc_url.clone(),
+        backend,
         &wallet_dir,
         seed.derive_extended_private_key(env_config.bitcoin_network)?,
         env_config,
@@ -300,6 +805,34 @@ async fn init_bitcoin_wallet(
     Ok(wallet)
 }
 
+/// A wallet for the funds of `descriptor`, whose keys are held by an external
+/// signer. Every descriptor gets a database of its own.
+async fn init_watch_only_bitcoin_wallet(
+    backend: BackendConfig,
+    descriptor: &str,
+    data_dir: PathBuf,
+    env_config: Config,
+    bitcoin_target_block: usize,
+) -> Result<bitcoin::Wallet> {
+    let wallet_dir = data_dir
+        .join("external-wallets")
+        .join(format!("{:x}", Sha256::digest(descriptor.as_bytes())));
+
+    let wallet = bitcoin::Wallet::new_watch_only(
+        backend,
+        &wallet_dir,
+        descriptor,
+        env_config,
+        bitcoin_target_block,
+    )
+    .await
+    .context("Failed to initialize watch-only Bitcoin wallet")?;
+
+    wallet.sync().await?;
+
+    Ok(wallet)
+}
+
 async fn init_monero_wallet(
     data_dir: PathBuf,
     monero_daemon_address: String,
@@ -325,8 +858,48 @@ async fn init_monero_wallet(
     Ok((monero_wallet, monero_wallet_rpc_process))
 }
 
+fn history_table(summaries: &[SwapSummary]) -> Table {
+    let mut table = Table::new();
+
+    table.add_row(row![
+        "SWAP ID", "STATE", "BTC", "XMR", "RATE", "PEER ID", "STARTED", "UPDATED", "DETAILS"
+    ]);
+
+    for summary in summaries {
+        table.add_row(row![
+            summary.swap_id,
+            summary.state,
+            format_optional(summary.btc_amount),
+            format_optional(summary.xmr_amount),
+            format_optional(summary.rate()),
+            format_optional(summary.peer_id),
+            format_time(summary.started_at),
+            format_time(summary.updated_at),
+            format_details(summary.details.clone())
+        ]);
+    }
+
+    table
+}
+
+fn format_details(details: Vec<(&'static str, String)>) -> String {
+    details
+        .into_iter()
+        .map(|(label, value)| format!("{}: {}", label, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_optional<T: Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "unknown".to_owned(), |value| value.to_string())
+}
+
+fn format_time(time: Option<OffsetDateTime>) -> String {
+    format_optional(time.map(|time| time.format("%F %T UTC")))
+}
+
 async fn determine_btc_to_swap<FB, TB, FMG, TMG, FS, TS>(
-    bid_quote: impl Future<Output = Result<BidQuote>>,
+    bid_quote: impl Future<Output = Result<TieredBidQuote>>,
     get_new_address: impl Future<Output = Result<bitcoin::Address>>,
     balance: FB,
     max_giveable: FMG,
@@ -340,23 +913,16 @@ where
     TS: Future<Output = Result<()>>,
     FS: Fn() -> TS,
 {
-    debug!("Requesting quote");
-    let bid_quote = bid_quote.await?;
-    info!(
-        price = %bid_quote.price,
-        minimum_amount = %bid_quote.min_quantity,
-        maximum_amount = %bid_quote.max_quantity,
-        "Received quote: 1 XMR ~ ",
-    );
+    let bid_quote = request_bid_quote(bid_quote).await?;
 
     let mut current_maximum_giveable = max_giveable().await?;
 
     let max_giveable = if current_maximum_giveable == bitcoin::Amount::ZERO
-        || current_maximum_giveable < bid_quote.min_quantity
+        || current_maximum_giveable < bid_quote.min_quantity()
     {
         let deposit_address = get_new_address.await?;
-        let minimum_amount = bid_quote.min_quantity;
-        let maximum_amount = bid_quote.max_quantity;
+        let minimum_amount = bid_quote.min_quantity();
+        let maximum_amount = bid_quote.max_quantity();
 
         info!(
             %deposit_address,
@@ -381,7 +947,7 @@ where
                     "Received BTC",
                 );
 
-                if current_maximum_giveable >= bid_quote.min_quantity {
+                if current_maximum_giveable >= bid_quote.min_quantity() {
                     break;
                 } else {
                     tracing::info!(
@@ -403,19 +969,151 @@ where
     let balance = balance().await?;
     let fees = balance - max_giveable;
 
-    let max_accepted = bid_quote.max_quantity;
+    let max_accepted = bid_quote.max_quantity();
 
     let btc_swap_amount = min(max_giveable, max_accepted);
 
     Ok((btc_swap_amount, fees))
 }
 
+/// Checks that the seller accepts `amount`, which is paid into the swap from an
+/// external wallet.
+async fn check_externally_funded_amount(
+    bid_quote: impl Future<Output = Result<TieredBidQuote>>,
+    amount: bitcoin::Amount,
+) -> Result<()> {
+    let bid_quote = request_bid_quote(bid_quote).await?;
+
+    let minimum_amount = bid_quote.min_quantity();
+    let maximum_amount = bid_quote.max_quantity();
+    if amount < minimum_amount || amount > maximum_amount {
+        bail!(
+            "The seller accepts between {} and {}, but the external wallet is to pay {}",
+            minimum_amount,
+            maximum_amount,
+            amount
+        )
+    }
+
+    Ok(())
+}
+
+async fn request_bid_quote(
+    bid_quote: impl Future<Output = Result<TieredBidQuote>>,
+) -> Result<TieredBidQuote> {
+    debug!("Requesting quote");
+    let bid_quote = bid_quote.await?;
+    for tier in bid_quote.tiers.iter() {
+        info!(
+            price = %tier.price,
+            minimum_amount = %tier.min_quantity,
+            maximum_amount = %tier.max_quantity,
+            "Received quote: 1 XMR ~ ",
+        );
+    }
+    if let Some(valid_for) = bid_quote.valid_for() {
+        info!(
+            "The seller guarantees these prices for swaps started within the next {}s",
+            valid_for.as_secs()
+        );
+    }
+
+    Ok(bid_quote)
+}
+
+/// Waits until enough XMR is unlocked in the monitoring wallet and returns the
+/// amount of XMR to swap.
+///
+/// The fee of the XMR lock transaction is kept in the wallet.
+async fn determine_xmr_to_swap<FB, TB, FR, TR>(
+    ask_quote: impl Future<Output = Result<AskQuote>>,
+    deposit_address: monero::Address,
+    unlocked_balance: FB,
+    refresh: FR,
+    lock_fee: monero::Amount,
+) -> Result<monero::Amount>
+where
+    TB: Future<Output = Result<monero::Amount>>,
+    FB: Fn() -> TB,
+    TR: Future<Output = Result<()>>,
+    FR: Fn() -> TR,
+{
+    debug!("Requesting quote");
+    let ask_quote = ask_quote.await?;
+    info!(
+        price = %ask_quote.price,
+        minimum_amount = %ask_quote.min_quantity,
+        maximum_amount = %ask_quote.max_quantity,
+        "Received quote: 1 XMR ~ ",
+    );
+
+    let spendable = |balance: monero::Amount| {
+        if balance > lock_fee {
+            balance - lock_fee
+        } else {
+            monero::Amount::ZERO
+        }
+    };
+
+    let mut current_unlocked_balance = unlocked_balance().await?;
+
+    if spendable(current_unlocked_balance) == monero::Amount::ZERO
+        || spendable(current_unlocked_balance) < ask_quote.min_quantity
+    {
+        let minimum_amount = ask_quote.min_quantity;
+        let maximum_amount = ask_quote.max_quantity;
+
+        info!(
+            %deposit_address,
+            %current_unlocked_balance,
+            %minimum_amount,
+            %maximum_amount,
+            "Please deposit XMR you want to swap to",
+        );
+
+        loop {
+            refresh().await?;
+
+            let new_unlocked_balance = unlocked_balance().await?;
+
+            if new_unlocked_balance != current_unlocked_balance {
+                current_unlocked_balance = new_unlocked_balance;
+
+                tracing::info!(%current_unlocked_balance, "Received XMR");
+
+                if spendable(current_unlocked_balance) >= ask_quote.min_quantity {
+                    break;
+                } else {
+                    tracing::info!(
+                        %minimum_amount,
+                        %deposit_address,
+                        "Please deposit more, not enough unlocked XMR to trigger swap with",
+                    );
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    let max_giveable = spendable(current_unlocked_balance);
+
+    let xmr_swap_amount = if max_giveable > ask_quote.max_quantity {
+        ask_quote.max_quantity
+    } else {
+        max_giveable
+    };
+
+    Ok(xmr_swap_amount)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::determine_btc_to_swap;
+    use crate::{determine_btc_to_swap, determine_xmr_to_swap};
     use ::bitcoin::Amount;
     use std::sync::Mutex;
+    use swap::network::quote::PriceTier;
     use tracing::subscriber;
 
     struct MaxGiveable {
@@ -631,22 +1329,143 @@ mod tests {
         assert!(matches!(error, tokio::time::error::Elapsed { .. }))
     }
 
-    fn quote_with_max(btc: f64) -> BidQuote {
-        BidQuote {
+    fn quote_with_max(btc: f64) -> TieredBidQuote {
+        TieredBidQuote::new(
+            vec![PriceTier {
+                price: Amount::from_btc(0.001).unwrap(),
+                max_quantity: Amount::from_btc(btc).unwrap(),
+                min_quantity: Amount::ZERO,
+            }],
+            Duration::from_secs(60),
+        )
+    }
+
+    fn quote_with_min(btc: f64) -> TieredBidQuote {
+        TieredBidQuote::new(
+            vec![PriceTier {
+                price: Amount::from_btc(0.001).unwrap(),
+                max_quantity: Amount::max_value(),
+                min_quantity: Amount::from_btc(btc).unwrap(),
+            }],
+            Duration::from_secs(60),
+        )
+    }
+
+    #[tokio::test]
+    async fn given_no_unlocked_balance_and_deposit_less_than_max_swaps_deposit_minus_fee() {
+        let _guard = subscriber::set_default(tracing_subscriber::fmt().with_test_writer().finish());
+        let balances = Arc::new(Mutex::new(UnlockedBalance::new(vec![
+            monero::Amount::ZERO,
+            xmr(0.5),
+        ])));
+
+        let amount = determine_xmr_to_swap(
+            async { Ok(ask_quote_with_max(1.0)) },
+            dummy_monero_address(),
+            || async { balances.lock().unwrap().give() },
+            || async { Ok(()) },
+            xmr(0.01),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(amount, xmr(0.49))
+    }
+
+    #[tokio::test]
+    async fn given_unlocked_balance_above_max_quantity_swaps_max_quantity() {
+        let _guard = subscriber::set_default(tracing_subscriber::fmt().with_test_writer().finish());
+        let balances = Arc::new(Mutex::new(UnlockedBalance::new(vec![xmr(5.0)])));
+
+        let amount = determine_xmr_to_swap(
+            async { Ok(ask_quote_with_max(1.0)) },
+            dummy_monero_address(),
+            || async { balances.lock().unwrap().give() },
+            || async { panic!("should not refresh when initial balance is sufficient") },
+            xmr(0.01),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(amount, xmr(1.0))
+    }
+
+    #[tokio::test]
+    async fn given_unlocked_balance_below_min_keep_waiting() {
+        let _guard = subscriber::set_default(tracing_subscriber::fmt().with_test_writer().finish());
+        let balances = Arc::new(Mutex::new(UnlockedBalance::new(vec![
+            monero::Amount::ZERO,
+            xmr(0.1),
+            xmr(0.1),
+            xmr(0.1),
+            xmr(0.1),
+        ])));
+
+        let error = tokio::time::timeout(
+            Duration::from_secs(1),
+            determine_xmr_to_swap(
+                async { Ok(ask_quote_with_min(0.5)) },
+                dummy_monero_address(),
+                || async { balances.lock().unwrap().give() },
+                || async { Ok(()) },
+                xmr(0.01),
+            ),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(matches!(error, tokio::time::error::Elapsed { .. }))
+    }
+
+    struct UnlockedBalance {
+        amounts: Vec<monero::Amount>,
+        call_counter: usize,
+    }
+
+    impl UnlockedBalance {
+        fn new(amounts: Vec<monero::Amount>) -> Self {
+            Self {
+                amounts,
+                call_counter: 0,
+            }
+        }
+
+        fn give(&mut self) -> Result<monero::Amount> {
+            let amount = self
+                .amounts
+                .get(self.call_counter)
+                .ok_or_else(|| anyhow::anyhow!("No more balances available"))?;
+            self.call_counter += 1;
+            Ok(*amount)
+        }
+    }
+
+    fn xmr(amount: f64) -> monero::Amount {
+        monero::Amount::from_monero(amount).unwrap()
+    }
+
+    fn ask_quote_with_max(max: f64) -> AskQuote {
+        AskQuote {
             price: Amount::from_btc(0.001).unwrap(),
-            max_quantity: Amount::from_btc(btc).unwrap(),
-            min_quantity: Amount::ZERO,
+            max_quantity: xmr(max),
+            min_quantity: monero::Amount::ZERO,
         }
     }
 
-    fn quote_with_min(btc: f64) -> BidQuote {
-        BidQuote {
+    fn ask_quote_with_min(min: f64) -> AskQuote {
+        AskQuote {
             price: Amount::from_btc(0.001).unwrap(),
-            max_quantity: Amount::max_value(),
-            min_quantity: Amount::from_btc(btc).unwrap(),
+            max_quantity: xmr(100.0),
+            min_quantity: xmr(min),
         }
     }
 
+    fn dummy_monero_address() -> monero::Address {
+        "53gEuGZUhP9JMEBZoGaFNzhwEgiG7hwQdMCqFxiyiTeFPmkbt1mAoNybEUvYBKHcnrSgxnVWgZsTvRBaHBNXPa8tHiCU51a"
+            .parse()
+            .unwrap()
+    }
+
     async fn get_dummy_address() -> Result<bitcoin::Address> {
         Ok("1PdfytjS7C8wwd9Lq5o4x9aXA2YRqaCpH6".parse()?)
     }
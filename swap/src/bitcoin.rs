@@ -1,6 +1,8 @@
 pub mod wallet;
 
 mod cancel;
+mod cold_storage;
+mod external_signer;
 mod lock;
 mod punish;
 mod redeem;
@@ -8,6 +10,8 @@ mod refund;
 mod timelocks;
 
 pub use crate::bitcoin::cancel::{CancelTimelock, PunishTimelock, TxCancel};
+pub use crate::bitcoin::cold_storage::ColdStorage;
+pub use crate::bitcoin::external_signer::ExternalSigner;
 pub use crate::bitcoin::lock::TxLock;
 pub use crate::bitcoin::punish::TxPunish;
 pub use crate::bitcoin::redeem::TxRedeem;
@@ -37,6 +41,44 @@ use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::str::FromStr;
 
+/// The value of the output through which Alice speeds up her redeem and
+/// punish transactions with CPFP when their proceeds go to cold storage.
+const ANCHOR_VALUE: u64 = 10_000;
+
+/// The index of the anchor output of the redeem and punish transactions.
+pub const ANCHOR_VOUT: u32 = 1;
+
+/// The weight the P2WPKH anchor output adds to a transaction.
+pub const ANCHOR_WEIGHT: usize = 124;
+
+/// The smallest value an output of any standard script can have and still be
+/// relayed.
+const DUST_LIMIT: u64 = 546;
+
+/// Splits the anchor output off the single output of a redeem or punish
+/// transaction.
+///
+/// Fails if what is left of the output would be dust.
+fn add_anchor(transaction: &mut Transaction, anchor_address: &Address) -> Result<()> {
+    let value = transaction.output[0].value;
+    transaction.output[0].value = value
+        .checked_sub(ANCHOR_VALUE)
+        .filter(|remaining| *remaining >= DUST_LIMIT)
+        .with_context(|| {
+            format!(
+                "Output of {} is too small to split off an anchor output of {}",
+                Amount::from_sat(value),
+                Amount::from_sat(ANCHOR_VALUE)
+            )
+        })?;
+    transaction.output.push(::bitcoin::TxOut {
+        value: ANCHOR_VALUE,
+        script_pubkey: anchor_address.script_pubkey(),
+    });
+
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(remote = "Network")]
 #[allow(non_camel_case_types)]
@@ -246,6 +288,32 @@ pub fn current_epoch(
     ExpiredTimelocks::None
 }
 
+/// Computes the number of blocks until the next timelock expires.
+///
+/// Returns `0` once the punish timelock expired, from then on every block
+/// counts.
+pub fn blocks_until_next_epoch(
+    cancel_timelock: CancelTimelock,
+    punish_timelock: PunishTimelock,
+    tx_lock_status: ScriptStatus,
+    tx_cancel_status: ScriptStatus,
+) -> u32 {
+    match current_epoch(
+        cancel_timelock,
+        punish_timelock,
+        tx_lock_status,
+        tx_cancel_status,
+    ) {
+        ExpiredTimelocks::None => {
+            u32::from(cancel_timelock).saturating_sub(tx_lock_status.confirmations())
+        }
+        ExpiredTimelocks::Cancel => {
+            u32::from(punish_timelock).saturating_sub(tx_cancel_status.confirmations())
+        }
+        ExpiredTimelocks::Punish => 0,
+    }
+}
+
 #[derive(Clone, Copy, thiserror::Error, Debug)]
 #[error("transaction does not spend anything")]
 pub struct NoInputs;
@@ -315,6 +383,42 @@ mod tests {
         assert_eq!(expired_timelock, ExpiredTimelocks::Punish)
     }
 
+    #[test]
+    fn blocks_until_next_epoch_counts_down_to_cancel_then_punish() {
+        let cancel_timelock = CancelTimelock::new(5);
+        let punish_timelock = PunishTimelock::new(3);
+
+        let unconfirmed_lock = blocks_until_next_epoch(
+            cancel_timelock,
+            punish_timelock,
+            ScriptStatus::InMempool,
+            ScriptStatus::Unseen,
+        );
+        let confirmed_lock = blocks_until_next_epoch(
+            cancel_timelock,
+            punish_timelock,
+            ScriptStatus::from_confirmations(4),
+            ScriptStatus::Unseen,
+        );
+        let confirmed_cancel = blocks_until_next_epoch(
+            cancel_timelock,
+            punish_timelock,
+            ScriptStatus::from_confirmations(7),
+            ScriptStatus::from_confirmations(1),
+        );
+        let punish_expired = blocks_until_next_epoch(
+            cancel_timelock,
+            punish_timelock,
+            ScriptStatus::from_confirmations(10),
+            ScriptStatus::from_confirmations(3),
+        );
+
+        assert_eq!(unconfirmed_lock, 5);
+        assert_eq!(confirmed_lock, 1);
+        assert_eq!(confirmed_cancel, 2);
+        assert_eq!(punish_expired, 0);
+    }
+
     #[tokio::test]
     async fn calculate_transaction_weights() {
         let alice_wallet = Wallet::new_funded_default_fees(Amount::ONE_BTC.as_sat());
@@ -341,6 +445,7 @@ mod tests {
             config,
             redeem_address,
             punish_address,
+            None,
             tx_redeem_fee,
             tx_punish_fee,
             &mut OsRng,
@@ -397,6 +502,35 @@ mod tests {
         assert_weight(refund_transaction, TxRefund::weight(), "TxRefund");
     }
 
+    #[test]
+    fn anchor_is_only_split_off_outputs_that_stay_above_dust() {
+        let anchor_address = Address::p2wpkh(
+            &SecretKey::new_random(&mut OsRng).public().into(),
+            Network::Regtest,
+        )
+        .unwrap();
+        let transaction_with_output = |value| Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![::bitcoin::TxOut {
+                value,
+                script_pubkey: anchor_address.script_pubkey(),
+            }],
+        };
+
+        let mut transaction = transaction_with_output(ANCHOR_VALUE + DUST_LIMIT);
+        add_anchor(&mut transaction, &anchor_address).unwrap();
+        assert_eq!(transaction.output[0].value, DUST_LIMIT);
+        assert_eq!(transaction.output[ANCHOR_VOUT as usize].value, ANCHOR_VALUE);
+
+        let mut transaction = transaction_with_output(ANCHOR_VALUE + DUST_LIMIT - 1);
+        assert!(add_anchor(&mut transaction, &anchor_address).is_err());
+
+        let mut transaction = transaction_with_output(ANCHOR_VALUE - 1);
+        assert!(add_anchor(&mut transaction, &anchor_address).is_err());
+    }
+
     // Weights fluctuate because of the length of the signatures. Valid ecdsa
     // signatures can have 68, 69, 70, 71, or 72 bytes. Since most of our
     // transactions have 2 signatures the weight can be up to 8 bytes less than
@@ -27,6 +27,12 @@ impl CancelTimelock {
     }
 }
 
+impl From<CancelTimelock> for u32 {
+    fn from(timelock: CancelTimelock) -> Self {
+        timelock.0
+    }
+}
+
 impl Add<CancelTimelock> for BlockHeight {
     type Output = BlockHeight;
 
@@ -61,6 +67,12 @@ impl PunishTimelock {
     }
 }
 
+impl From<PunishTimelock> for u32 {
+    fn from(timelock: PunishTimelock) -> Self {
+        timelock.0
+    }
+}
+
 impl Add<PunishTimelock> for BlockHeight {
     type Output = BlockHeight;
 
@@ -0,0 +1,131 @@
+use crate::bitcoin::{Address, Network};
+use anyhow::{bail, Context, Result};
+use bdk::blockchain::OfflineBlockchain;
+use bdk::wallet::AddressIndex;
+use std::path::Path;
+use tokio::sync::Mutex;
+
+const SLED_TREE_NAME: &str = "cold_storage";
+
+/// Derives addresses of a wallet whose keys we do not hold, e.g. a hardware
+/// wallet, so that funds sent to them never touch the hot wallet.
+///
+/// The derivation index is stored in a database of its own, so an address is
+/// not handed out twice, not even across restarts. Addresses that end up not
+/// being used can be handed back with [`ColdStorage::reuse`], so that they do
+/// not count towards the gap limit of the wallet.
+pub struct ColdStorage {
+    wallet: Mutex<bdk::Wallet<OfflineBlockchain, bdk::sled::Tree>>,
+    unused: Mutex<Vec<Address>>,
+}
+
+impl ColdStorage {
+    /// `descriptor` is either an output descriptor with a wildcard, e.g.
+    /// `wpkh(xpub.../0/*)`, or an extended public key, which stands for
+    /// `wpkh(xpub/0/*)`.
+    pub fn new(descriptor: &str, network: Network, dir: &Path) -> Result<Self> {
+        let descriptor = descriptor.trim();
+        let descriptor = if descriptor.contains('(') {
+            descriptor.to_owned()
+        } else {
+            format!("wpkh({}/0/*)", descriptor)
+        };
+
+        if !descriptor.contains('*') {
+            bail!(
+                "Cold storage descriptor {} does not derive more than one address",
+                descriptor
+            )
+        }
+
+        let db = bdk::sled::open(dir)?.open_tree(SLED_TREE_NAME)?;
+        let wallet = bdk::Wallet::new_offline(descriptor.as_str(), None, network, db)
+            .with_context(|| format!("Invalid cold storage descriptor {}", descriptor))?;
+
+        Ok(Self {
+            wallet: Mutex::new(wallet),
+            unused: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Returns an address that was handed back as unused, or derives a new
+    /// one.
+    pub async fn new_address(&self) -> Result<Address> {
+        if let Some(address) = self.unused.lock().await.pop() {
+            return Ok(address);
+        }
+
+        let address = self
+            .wallet
+            .lock()
+            .await
+            .get_address(AddressIndex::New)
+            .context("Failed to derive new cold storage address")?;
+
+        Ok(address)
+    }
+
+    /// Hands out the given address again, it must not have received any
+    /// funds yet, e.g. because the swap it was meant for was not set up.
+    ///
+    /// Unused addresses are only kept in memory, after a restart new ones
+    /// are derived.
+    pub async fn reuse(&self, address: Address) {
+        self.unused.lock().await.push(address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XPUB: &str = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+
+    #[tokio::test]
+    async fn bare_xpub_derives_native_segwit_addresses() {
+        let dir = tempfile::tempdir().unwrap();
+        let cold_storage = ColdStorage::new(XPUB, Network::Bitcoin, dir.path()).unwrap();
+
+        let address = cold_storage.new_address().await.unwrap();
+
+        assert!(address.to_string().starts_with("bc1q"));
+    }
+
+    #[tokio::test]
+    async fn does_not_reuse_addresses_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let descriptor = format!("wpkh({}/0/*)", XPUB);
+
+        let cold_storage = ColdStorage::new(&descriptor, Network::Bitcoin, dir.path()).unwrap();
+        let first = cold_storage.new_address().await.unwrap();
+        let second = cold_storage.new_address().await.unwrap();
+        drop(cold_storage);
+
+        let cold_storage = ColdStorage::new(&descriptor, Network::Bitcoin, dir.path()).unwrap();
+        let third = cold_storage.new_address().await.unwrap();
+
+        assert_ne!(first, second);
+        assert_ne!(first, third);
+        assert_ne!(second, third);
+    }
+
+    #[tokio::test]
+    async fn hands_out_unused_addresses_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let cold_storage = ColdStorage::new(XPUB, Network::Bitcoin, dir.path()).unwrap();
+
+        let first = cold_storage.new_address().await.unwrap();
+        cold_storage.reuse(first.clone()).await;
+
+        assert_eq!(cold_storage.new_address().await.unwrap(), first);
+        assert_ne!(cold_storage.new_address().await.unwrap(), first);
+    }
+
+    #[test]
+    fn rejects_descriptor_without_wildcard() {
+        let dir = tempfile::tempdir().unwrap();
+        let descriptor = format!("wpkh({}/0/0)", XPUB);
+
+        assert!(ColdStorage::new(&descriptor, Network::Bitcoin, dir.path()).is_err());
+    }
+}
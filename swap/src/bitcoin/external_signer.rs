@@ -0,0 +1,276 @@
+use crate::bitcoin::{Address, Amount, Transaction};
+use ::bitcoin::consensus::encode::{deserialize, serialize};
+use ::bitcoin::hashes::hex::FromHex;
+use ::bitcoin::util::psbt::PartiallySignedTransaction;
+use anyhow::{bail, Context, Result};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Binary PSBTs, as exported by many wallets, start with these bytes.
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+/// The base64 encoding of [`PSBT_MAGIC`].
+const PSBT_BASE64_PREFIX: &str = "cHNidP";
+
+/// How often to look for the signed PSBT in the PSBT directory.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Hands the Bitcoin lock transaction to a wallet outside of the CLI for
+/// signing, e.g. a hardware or multisig wallet, or has that wallet build it.
+///
+/// Signed transactions are imported as PSBT, either base64 encoded or binary,
+/// or as hex encoded raw transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExternalSigner {
+    /// Print the PSBT to stdout and read the signed PSBT from stdin.
+    Stdio,
+    /// Write the PSBT to `<swap-id>.psbt` in the directory and wait for
+    /// `<swap-id>.signed.psbt` to appear next to it.
+    Directory(PathBuf),
+}
+
+impl ExternalSigner {
+    /// Exports `psbt` and waits for the signed PSBT to be imported.
+    pub async fn sign(
+        &self,
+        swap_id: Uuid,
+        psbt: PartiallySignedTransaction,
+    ) -> Result<PartiallySignedTransaction> {
+        let encoded = encode(&psbt);
+
+        match self {
+            ExternalSigner::Stdio => {
+                println!("Sign the following PSBT and paste the signed PSBT as base64:");
+                println!("{}", encoded);
+
+                read_stdin().await
+            }
+            ExternalSigner::Directory(dir) => {
+                let unsigned = dir.join(format!("{}.psbt", swap_id));
+                let signed = dir.join(format!("{}.signed.psbt", swap_id));
+
+                write(dir, &unsigned, encoded).await?;
+
+                tracing::info!(
+                    psbt = %unsigned.display(),
+                    signed_psbt = %signed.display(),
+                    "Waiting for the Bitcoin lock transaction to be signed"
+                );
+
+                wait_for(&signed).await
+            }
+        }
+    }
+
+    /// Asks for a transaction that pays exactly `amount` to `address` and
+    /// waits for it to be imported.
+    ///
+    /// The transaction must not be published, because the swap is only safe
+    /// once the other party signed the cancel transaction for it.
+    pub async fn fund(
+        &self,
+        swap_id: Uuid,
+        address: &Address,
+        amount: Amount,
+    ) -> Result<PartiallySignedTransaction> {
+        let payment_request = format!("bitcoin:{}?amount={}", address, amount.as_btc());
+
+        match self {
+            ExternalSigner::Stdio => {
+                println!(
+                    "Create and sign a transaction that pays exactly {} to {} (script {:x}), but do not publish it: {}",
+                    amount,
+                    address,
+                    address.script_pubkey(),
+                    payment_request
+                );
+                println!("Paste the signed PSBT as base64 or the signed transaction as hex:");
+
+                read_stdin().await
+            }
+            ExternalSigner::Directory(dir) => {
+                let request = dir.join(format!("{}.lock-output", swap_id));
+                let signed = dir.join(format!("{}.signed.psbt", swap_id));
+
+                write(dir, &request, payment_request).await?;
+
+                tracing::info!(
+                    %address,
+                    %amount,
+                    lock_output = %request.display(),
+                    signed_psbt = %signed.display(),
+                    "Waiting for the signed, unpublished transaction paying into the Bitcoin lock output"
+                );
+
+                wait_for(&signed).await
+            }
+        }
+    }
+}
+
+async fn read_stdin() -> Result<PartiallySignedTransaction> {
+    let line = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line).map(|_| line)
+    })
+    .await?
+    .context("Failed to read signed PSBT from stdin")?;
+
+    decode(line.as_bytes())
+}
+
+async fn write(dir: &Path, file: &Path, content: String) -> Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(file, content)
+        .await
+        .with_context(|| format!("Failed to write {}", file.display()))?;
+
+    Ok(())
+}
+
+async fn wait_for(signed: &Path) -> Result<PartiallySignedTransaction> {
+    loop {
+        match tokio::fs::read(signed).await {
+            Ok(content) => return decode(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                tokio::time::sleep(POLL_INTERVAL).await
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read signed PSBT from {}", signed.display())
+                })
+            }
+        }
+    }
+}
+
+fn encode(psbt: &PartiallySignedTransaction) -> String {
+    base64::encode(serialize(psbt))
+}
+
+/// Decodes a PSBT that is either base64 encoded or binary, or a hex encoded
+/// signed transaction.
+fn decode(content: &[u8]) -> Result<PartiallySignedTransaction> {
+    let bytes = if content.starts_with(PSBT_MAGIC) {
+        content.to_vec()
+    } else {
+        let text = std::str::from_utf8(content)
+            .context("Neither a binary PSBT nor text")?
+            .trim();
+
+        if !text.starts_with(PSBT_BASE64_PREFIX) {
+            let tx = Vec::<u8>::from_hex(text)
+                .context("Neither a base64 PSBT nor a hex encoded transaction")?;
+            let tx = deserialize(&tx).context("Failed to deserialize transaction")?;
+
+            return from_signed_tx(tx);
+        }
+
+        base64::decode(text).context("Failed to decode PSBT from base64")?
+    };
+
+    if bytes.is_empty() {
+        bail!("Signed PSBT is empty")
+    }
+
+    let psbt = deserialize(&bytes).context("Failed to deserialize PSBT")?;
+
+    Ok(psbt)
+}
+
+/// Wraps a signed transaction into a PSBT whose inputs are finalized with the
+/// signatures of the transaction.
+fn from_signed_tx(tx: Transaction) -> Result<PartiallySignedTransaction> {
+    let mut unsigned_tx = tx.clone();
+    for input in unsigned_tx.input.iter_mut() {
+        input.script_sig = Default::default();
+        input.witness = Vec::new();
+    }
+
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(unsigned_tx)?;
+    for (psbt_input, input) in psbt.inputs.iter_mut().zip(tx.input) {
+        if !input.script_sig.is_empty() {
+            psbt_input.final_script_sig = Some(input.script_sig);
+        }
+        if !input.witness.is_empty() {
+            psbt_input.final_script_witness = Some(input.witness);
+        }
+    }
+
+    Ok(psbt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::{OutPoint, Transaction, TxIn, TxOut};
+
+    fn psbt() -> PartiallySignedTransaction {
+        PartiallySignedTransaction::from_unsigned_tx(Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::default(),
+                script_sig: Default::default(),
+                sequence: 0xFFFF_FFFF,
+                witness: Vec::new(),
+            }],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: Default::default(),
+            }],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn decodes_base64_psbt_with_trailing_newline() {
+        let encoded = format!("{}\n", encode(&psbt()));
+
+        assert_eq!(decode(encoded.as_bytes()).unwrap(), psbt());
+    }
+
+    #[test]
+    fn decodes_binary_psbt() {
+        let bytes = serialize(&psbt());
+
+        assert_eq!(decode(&bytes).unwrap(), psbt());
+    }
+
+    #[test]
+    fn decodes_signed_transaction_keeping_txid_and_signatures() {
+        let mut tx = psbt().extract_tx();
+        tx.input[0].witness = vec![vec![1; 72], vec![2; 33]];
+        let hex = serialize(&tx)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        let decoded = decode(hex.as_bytes()).unwrap();
+
+        assert_eq!(decoded.global.unsigned_tx.txid(), tx.txid());
+        assert_eq!(decoded.extract_tx(), tx);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(decode(b"not a psbt").is_err());
+        assert!(decode(b"").is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_signed_psbt_from_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let swap_id = Uuid::new_v4();
+        let signed = dir.path().join(format!("{}.signed.psbt", swap_id));
+        tokio::fs::write(&signed, encode(&psbt())).await.unwrap();
+
+        let signer = ExternalSigner::Directory(dir.path().to_path_buf());
+        let imported = signer.sign(swap_id, psbt()).await.unwrap();
+
+        assert_eq!(imported, psbt());
+        assert!(dir.path().join(format!("{}.psbt", swap_id)).exists());
+    }
+}
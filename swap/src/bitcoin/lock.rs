@@ -1,6 +1,6 @@
 use crate::bitcoin::wallet::{EstimateFeeRate, Watchable};
 use crate::bitcoin::{
-    build_shared_output_descriptor, Address, Amount, PublicKey, Transaction, Wallet,
+    build_shared_output_descriptor, Address, Amount, Network, PublicKey, Transaction, Wallet,
 };
 use ::bitcoin::util::psbt::PartiallySignedTransaction;
 use ::bitcoin::{OutPoint, TxIn, TxOut, Txid};
@@ -42,6 +42,14 @@ impl TxLock {
         })
     }
 
+    /// The address of the shared output, for paying into it from a wallet
+    /// that does not build the lock transaction through [`TxLock::new`].
+    pub fn address(A: PublicKey, B: PublicKey, network: Network) -> Address {
+        build_shared_output_descriptor(A.0, B.0)
+            .address(network)
+            .expect("can derive address from descriptor")
+    }
+
     /// Creates an instance of `TxLock` from a PSBT, the public keys of the
     /// parties and the specified amount.
     ///
@@ -92,6 +100,30 @@ impl TxLock {
         })
     }
 
+    /// Whether all inputs are signed already, which is the case if the lock
+    /// transaction was funded by an external wallet.
+    pub fn is_signed(&self) -> bool {
+        self.inner
+            .inputs
+            .iter()
+            .all(|input| input.final_script_witness.is_some() || input.final_script_sig.is_some())
+    }
+
+    /// Whether all inputs are signed with a witness, i.e. spend segwit
+    /// outputs.
+    ///
+    /// The signatures of any other input can be altered by third parties,
+    /// which changes the txid of the lock transaction that the cancel and
+    /// refund transactions are signed for.
+    pub fn spends_only_witness_outputs(&self) -> bool {
+        self.inner.inputs.iter().all(|input| {
+            input
+                .final_script_witness
+                .as_ref()
+                .map_or(false, |witness| !witness.is_empty())
+        })
+    }
+
     pub fn lock_amount(&self) -> Amount {
         Amount::from_sat(self.inner.clone().extract_tx().output[self.lock_output_vout()].value)
     }
@@ -242,6 +274,42 @@ mod tests {
         result.expect_err("PSBT to be invalid");
     }
 
+    #[tokio::test]
+    async fn given_funding_transaction_of_external_wallet_then_reconstructs_signed_txlock() {
+        let (A, B) = alice_and_bob();
+        let wallet = Wallet::new_funded_default_fees(50000);
+        let agreed_amount = Amount::from_sat(10000);
+
+        let mut psbt = bob_make_psbt(A, B, &wallet, agreed_amount).await;
+        assert_eq!(
+            psbt.global.unsigned_tx.output[0].script_pubkey,
+            TxLock::address(A, B, wallet.get_network()).script_pubkey()
+        );
+        for input in psbt.inputs.iter_mut() {
+            input.final_script_witness = Some(vec![vec![1; 72], vec![2; 33]]);
+        }
+        let tx_lock = TxLock::from_psbt(psbt, A, B, agreed_amount).unwrap();
+
+        assert!(tx_lock.is_signed());
+        assert!(tx_lock.spends_only_witness_outputs());
+    }
+
+    #[tokio::test]
+    async fn given_funding_transaction_spending_non_witness_output_then_txid_is_malleable() {
+        let (A, B) = alice_and_bob();
+        let wallet = Wallet::new_funded_default_fees(50000);
+        let agreed_amount = Amount::from_sat(10000);
+
+        let mut psbt = bob_make_psbt(A, B, &wallet, agreed_amount).await;
+        for input in psbt.inputs.iter_mut() {
+            input.final_script_sig = Some(Script::from(vec![1; 72]));
+        }
+        let tx_lock = TxLock::from_psbt(psbt, A, B, agreed_amount).unwrap();
+
+        assert!(tx_lock.is_signed());
+        assert!(!tx_lock.spends_only_witness_outputs());
+    }
+
     /// Helper function that represents Bob's action of constructing the PSBT.
     ///
     /// Extracting this allows us to keep the tests concise.
@@ -1,5 +1,7 @@
 use crate::bitcoin::wallet::Watchable;
-use crate::bitcoin::{self, Address, Amount, PunishTimelock, Transaction, TxCancel, Txid};
+use crate::bitcoin::{
+    self, add_anchor, Address, Amount, PunishTimelock, Transaction, TxCancel, Txid,
+};
 use ::bitcoin::util::bip143::SigHashCache;
 use ::bitcoin::{SigHash, SigHashType};
 use anyhow::{Context, Result};
@@ -16,14 +18,20 @@ pub struct TxPunish {
 }
 
 impl TxPunish {
+    /// With an `anchor_address`, the transaction has a second output paying
+    /// to it, see [`crate::bitcoin::ANCHOR_VOUT`].
     pub fn new(
         tx_cancel: &TxCancel,
         punish_address: &Address,
+        anchor_address: Option<&Address>,
         punish_timelock: PunishTimelock,
         spending_fee: Amount,
-    ) -> Self {
-        let tx_punish =
+    ) -> Result<Self> {
+        let mut tx_punish =
             tx_cancel.build_spend_transaction(punish_address, Some(punish_timelock), spending_fee);
+        if let Some(anchor_address) = anchor_address {
+            add_anchor(&mut tx_punish, anchor_address)?;
+        }
 
         let digest = SigHashCache::new(&tx_punish).signature_hash(
             0, // Only one input: cancel transaction
@@ -32,12 +40,12 @@ impl TxPunish {
             SigHashType::All,
         );
 
-        Self {
+        Ok(Self {
             inner: tx_punish,
             digest,
             cancel_output_descriptor: tx_cancel.output_descriptor.clone(),
             watch_script: punish_address.script_pubkey(),
-        }
+        })
     }
 
     pub fn digest(&self) -> SigHash {
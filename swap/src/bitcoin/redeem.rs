@@ -1,7 +1,7 @@
 use crate::bitcoin::wallet::Watchable;
 use crate::bitcoin::{
-    verify_encsig, verify_sig, Address, Amount, EmptyWitnessStack, EncryptedSignature, NoInputs,
-    NotThreeWitnesses, PublicKey, SecretKey, TooManyInputs, Transaction, TxLock,
+    add_anchor, verify_encsig, verify_sig, Address, Amount, EmptyWitnessStack, EncryptedSignature,
+    NoInputs, NotThreeWitnesses, PublicKey, SecretKey, TooManyInputs, Transaction, TxLock,
 };
 use ::bitcoin::util::bip143::SigHashCache;
 use ::bitcoin::{SigHash, SigHashType, Txid};
@@ -24,10 +24,20 @@ pub struct TxRedeem {
 }
 
 impl TxRedeem {
-    pub fn new(tx_lock: &TxLock, redeem_address: &Address, spending_fee: Amount) -> Self {
+    /// With an `anchor_address`, the transaction has a second output paying
+    /// to it, see [`crate::bitcoin::ANCHOR_VOUT`].
+    pub fn new(
+        tx_lock: &TxLock,
+        redeem_address: &Address,
+        anchor_address: Option<&Address>,
+        spending_fee: Amount,
+    ) -> Result<Self> {
         // lock_input is the shared output that is now being used as an input for the
         // redeem transaction
-        let tx_redeem = tx_lock.build_spend_transaction(redeem_address, None, spending_fee);
+        let mut tx_redeem = tx_lock.build_spend_transaction(redeem_address, None, spending_fee);
+        if let Some(anchor_address) = anchor_address {
+            add_anchor(&mut tx_redeem, anchor_address)?;
+        }
 
         let digest = SigHashCache::new(&tx_redeem).signature_hash(
             0, // Only one input: lock_input (lock transaction)
@@ -36,12 +46,12 @@ impl TxRedeem {
             SigHashType::All,
         );
 
-        Self {
+        Ok(Self {
             inner: tx_redeem,
             digest,
             lock_output_descriptor: tx_lock.output_descriptor.clone(),
             watch_script: redeem_address.script_pubkey(),
-        }
+        })
     }
 
     pub fn txid(&self) -> Txid {
@@ -4,27 +4,34 @@ use crate::env;
 use ::bitcoin::util::psbt::PartiallySignedTransaction;
 use ::bitcoin::Txid;
 use anyhow::{bail, Context, Result};
-use bdk::blockchain::{noop_progress, Blockchain, ElectrumBlockchain};
-use bdk::database::BatchDatabase;
+use async_trait::async_trait;
+use bdk::blockchain::noop_progress;
+use bdk::database::{BatchDatabase, Database};
 use bdk::descriptor::Segwitv0;
-use bdk::electrum_client::{ElectrumApi, GetHistoryRes};
 use bdk::keys::DerivableKey;
 use bdk::wallet::AddressIndex;
 use bdk::{FeeRate, KeychainKind};
 use bitcoin::{Network, Script};
-use reqwest::Url;
 use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
-use std::convert::TryFrom;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use time::OffsetDateTime;
 use tokio::sync::{watch, Mutex};
 
+mod backend;
+mod fee_bump;
+mod sync;
+
+pub use backend::{Backend, BackendConfig, HistoryEntry};
+pub use fee_bump::{Deadline, Strategy};
+use sync::{Snapshot, WalletBlockchain};
+
 const SLED_TREE_NAME: &str = "default_tree";
 
 /// Assuming we add a spread of 3% we don't want to pay more than 3% of the
@@ -33,49 +40,95 @@ const MAX_RELATIVE_TX_FEE: Decimal = dec!(0.03);
 const MAX_ABSOLUTE_TX_FEE: Decimal = dec!(100_000);
 const DUST_AMOUNT: u64 = 546;
 
-pub struct Wallet<B = ElectrumBlockchain, D = bdk::sled::Tree, C = Client> {
+/// How long to wait before asking the backend again if it does not confirm
+/// the finality of a transaction yet.
+const FINALITY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct Wallet<B = WalletBlockchain, D = bdk::sled::Tree, C = Client> {
     client: Arc<Mutex<C>>,
     wallet: Arc<Mutex<bdk::Wallet<B, D>>>,
     finality_confirmations: u32,
     network: Network,
     target_block: usize,
+    fee_bump_interval: Duration,
 }
 
 impl Wallet {
     pub async fn new(
-        electrum_rpc_url: Url,
+        backend_config: BackendConfig,
         wallet_dir: &Path,
         key: impl DerivableKey<Segwitv0> + Clone,
         env_config: env::Config,
         target_block: usize,
     ) -> Result<Self> {
-        let client = bdk::electrum_client::Client::new(electrum_rpc_url.as_str())
-            .context("Failed to initialize Electrum RPC client")?;
+        let database = bdk::sled::open(wallet_dir)?;
+        let backend = backend_config
+            .connect(&database)
+            .await
+            .with_context(|| format!("Failed to connect to {}", backend_config))?;
 
-        let db = bdk::sled::open(wallet_dir)?.open_tree(SLED_TREE_NAME)?;
+        let db = database.open_tree(SLED_TREE_NAME)?;
 
         let wallet = bdk::Wallet::new(
             bdk::template::Bip84(key.clone(), KeychainKind::External),
             Some(bdk::template::Bip84(key, KeychainKind::Internal)),
             env_config.bitcoin_network,
             db,
-            ElectrumBlockchain::from(client),
+            WalletBlockchain::new(&backend_config, backend.clone()),
         )?;
 
-        let electrum = bdk::electrum_client::Client::new(electrum_rpc_url.as_str())
-            .context("Failed to initialize Electrum RPC client")?;
+        Self::from_bdk_wallet(wallet, backend, env_config, target_block).await
+    }
+
+    /// Creates a wallet that tracks the funds of `descriptor` without holding
+    /// its keys.
+    ///
+    /// The wallet builds transactions, but they have to be signed by a wallet
+    /// that holds the keys, see [`crate::bitcoin::ExternalSigner`].
+    pub async fn new_watch_only(
+        backend_config: BackendConfig,
+        wallet_dir: &Path,
+        descriptor: &str,
+        env_config: env::Config,
+        target_block: usize,
+    ) -> Result<Self> {
+        let database = bdk::sled::open(wallet_dir)?;
+        let backend = backend_config
+            .connect(&database)
+            .await
+            .with_context(|| format!("Failed to connect to {}", backend_config))?;
+
+        let db = database.open_tree(SLED_TREE_NAME)?;
 
+        let wallet = bdk::Wallet::new(
+            descriptor,
+            None,
+            env_config.bitcoin_network,
+            db,
+            WalletBlockchain::new(&backend_config, backend.clone()),
+        )
+        .with_context(|| format!("Invalid descriptor {}", descriptor))?;
+
+        Self::from_bdk_wallet(wallet, backend, env_config, target_block).await
+    }
+
+    async fn from_bdk_wallet(
+        wallet: bdk::Wallet<WalletBlockchain, bdk::sled::Tree>,
+        backend: Arc<dyn Backend>,
+        env_config: env::Config,
+        target_block: usize,
+    ) -> Result<Self> {
         let network = wallet.network();
 
         Ok(Self {
-            client: Arc::new(Mutex::new(Client::new(
-                electrum,
-                env_config.bitcoin_sync_interval(),
-            )?)),
+            client: Arc::new(Mutex::new(
+                Client::new(backend, env_config.bitcoin_sync_interval()).await?,
+            )),
             wallet: Arc::new(Mutex::new(wallet)),
             finality_confirmations: env_config.bitcoin_finality_confirmations,
             network,
             target_block,
+            fee_bump_interval: env_config.bitcoin_avg_block_time,
         })
     }
 
@@ -96,13 +149,10 @@ impl Wallet {
             .subscribe_to((txid, transaction.output[0].script_pubkey.clone()))
             .await;
 
-        self.wallet
-            .lock()
-            .await
-            .broadcast(transaction)
-            .with_context(|| {
-                format!("Failed to broadcast Bitcoin {} transaction {}", kind, txid)
-            })?;
+        let backend = self.client.lock().await.backend.clone();
+        backend.broadcast(&transaction).await.with_context(|| {
+            format!("Failed to broadcast Bitcoin {} transaction {}", kind, txid)
+        })?;
 
         tracing::info!(%txid, %kind, "Published Bitcoin transaction");
 
@@ -119,12 +169,13 @@ impl Wallet {
     where
         T: Watchable,
     {
-        self.client.lock().await.status_of_script(tx)
+        self.client.lock().await.status_of_script(tx).await
     }
 
     pub async fn subscribe_to(&self, tx: impl Watchable + Send + 'static) -> Subscription {
         let txid = tx.id();
         let script = tx.script();
+        let backend = self.client.lock().await.backend.clone();
 
         let sub = self
             .client
@@ -135,6 +186,7 @@ impl Wallet {
             .or_insert_with(|| {
                 let (sender, receiver) = watch::channel(ScriptStatus::Unseen);
                 let client = self.client.clone();
+                let script_to_remove = script.clone();
 
                 tokio::spawn(async move {
                     let mut last_status = None;
@@ -142,7 +194,7 @@ impl Wallet {
                     loop {
                         tokio::time::sleep(Duration::from_secs(5)).await;
 
-                        let new_status = match client.lock().await.status_of_script(&tx) {
+                        let new_status = match client.lock().await.status_of_script(&tx).await {
                             Ok(new_status) => new_status,
                             Err(error) => {
                                 tracing::warn!(%txid, "Failed to get status of script. Error {:#}", error);
@@ -160,7 +212,11 @@ impl Wallet {
 
                         if all_receivers_gone {
                             tracing::debug!(%txid, "All receivers gone, removing subscription");
-                            client.lock().await.subscriptions.remove(&(txid, script));
+                            client
+                                .lock()
+                                .await
+                                .subscriptions
+                                .remove(&(txid, script_to_remove));
                             return;
                         }
                     }
@@ -170,6 +226,8 @@ impl Wallet {
                     receiver,
                     finality_confirmations: self.finality_confirmations,
                     txid,
+                    script,
+                    backend,
                 }
             })
             .clone();
@@ -179,14 +237,31 @@ impl Wallet {
 }
 
 /// Represents a subscription to the status of a given transaction.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Subscription {
     receiver: watch::Receiver<ScriptStatus>,
     finality_confirmations: u32,
     txid: Txid,
+    script: Script,
+    backend: Arc<dyn Backend>,
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("receiver", &self.receiver)
+            .field("finality_confirmations", &self.finality_confirmations)
+            .field("txid", &self.txid)
+            .field("script", &self.script)
+            .finish()
+    }
 }
 
 impl Subscription {
+    /// Waits until the transaction has the configured finality confirmations.
+    ///
+    /// Before returning, the confirmations are asked for once more, which
+    /// backends configured to do so cross-check with a second source.
     pub async fn wait_until_final(&self) -> Result<()> {
         let conf_target = self.finality_confirmations;
         let txid = self.txid;
@@ -195,23 +270,40 @@ impl Subscription {
 
         let mut seen_confirmations = 0;
 
-        self.wait_until(|status| match status {
-            ScriptStatus::Confirmed(inner) => {
-                let confirmations = inner.confirmations();
+        loop {
+            self.wait_until(|status| match status {
+                ScriptStatus::Confirmed(inner) => {
+                    let confirmations = inner.confirmations();
+
+                    if confirmations > seen_confirmations {
+                        tracing::info!(%txid,
+                            seen_confirmations = %confirmations,
+                            needed_confirmations = %conf_target,
+                            "Waiting for Bitcoin transaction finality");
+                        seen_confirmations = confirmations;
+                    }
 
-                if confirmations > seen_confirmations {
-                    tracing::info!(%txid,
-                        seen_confirmations = %confirmations,
-                        needed_confirmations = %conf_target,
-                        "Waiting for Bitcoin transaction finality");
-                    seen_confirmations = confirmations;
+                    inner.meets_target(conf_target)
                 }
+                _ => false,
+            })
+            .await?;
 
-                inner.meets_target(conf_target)
+            match self.backend.confirmations(txid, &self.script).await {
+                Ok(confirmations) if confirmations >= conf_target => return Ok(()),
+                Ok(confirmations) => {
+                    tracing::warn!(%txid,
+                        %confirmations,
+                        needed_confirmations = %conf_target,
+                        "Bitcoin backend does not confirm finality of transaction yet");
+                }
+                Err(error) => {
+                    tracing::warn!(%txid, "Failed to confirm finality of Bitcoin transaction: {:#}", error);
+                }
             }
-            _ => false,
-        })
-        .await
+
+            tokio::time::sleep(FINALITY_CHECK_INTERVAL).await;
+        }
     }
 
     pub async fn wait_until_seen(&self) -> Result<()> {
@@ -258,6 +350,24 @@ where
         Ok(tx)
     }
 
+    /// Finalizes a PSBT that was signed by another wallet.
+    ///
+    /// Signers either finalize the PSBT themselves or only add their
+    /// signatures, in which case the descriptor of this wallet is used to
+    /// finalize it.
+    pub async fn finalize(&self, psbt: PartiallySignedTransaction) -> Result<Transaction> {
+        let finalized = psbt
+            .inputs
+            .iter()
+            .all(|input| input.final_script_witness.is_some() || input.final_script_sig.is_some());
+
+        if finalized {
+            return Ok(psbt.extract_tx());
+        }
+
+        self.sign_and_finalize(psbt).await
+    }
+
     pub async fn balance(&self) -> Result<Amount> {
         let balance = self
             .wallet
@@ -297,7 +407,8 @@ where
     /// Builds a partially signed transaction
     ///
     /// Ensures that the address script is at output index `0`
-    /// for the partially signed transaction.
+    /// for the partially signed transaction. The transaction signals
+    /// replaceability, so that its fee can be bumped.
     pub async fn send_to_address(
         &self,
         address: Address,
@@ -307,14 +418,19 @@ where
             bail!("Cannot build PSBT because network of given address is {} but wallet is on network {}", address.network, self.network);
         }
 
+        let fee_rate = self
+            .client
+            .lock()
+            .await
+            .estimate_feerate(self.target_block)
+            .await?;
         let wallet = self.wallet.lock().await;
-        let client = self.client.lock().await;
-        let fee_rate = client.estimate_feerate(self.target_block)?;
         let script = address.script_pubkey();
 
         let mut tx_builder = wallet.build_tx();
         tx_builder.add_recipient(script.clone(), amount.as_sat());
         tx_builder.fee_rate(fee_rate);
+        tx_builder.enable_rbf();
         let (psbt, _details) = tx_builder.finish()?;
         let mut psbt: PartiallySignedTransaction = psbt;
 
@@ -350,13 +466,13 @@ where
             return Ok(Amount::ZERO);
         }
         let client = self.client.lock().await;
-        let min_relay_fee = client.min_relay_fee()?.as_sat();
+        let min_relay_fee = client.min_relay_fee().await?.as_sat();
 
         if balance < min_relay_fee {
             return Ok(Amount::ZERO);
         }
 
-        let fee_rate = client.estimate_feerate(self.target_block)?;
+        let fee_rate = client.estimate_feerate(self.target_block).await?;
 
         let mut tx_builder = wallet.build_tx();
 
@@ -385,9 +501,9 @@ where
         transfer_amount: bitcoin::Amount,
     ) -> Result<bitcoin::Amount> {
         let client = self.client.lock().await;
-        let fee_rate = client.estimate_feerate(self.target_block)?;
+        let fee_rate = client.estimate_feerate(self.target_block).await?;
 
-        let min_relay_fee = client.min_relay_fee()?;
+        let min_relay_fee = client.min_relay_fee().await?;
         tracing::debug!("Min relay fee: {}", min_relay_fee);
 
         estimate_fee(weight, transfer_amount, fee_rate, min_relay_fee)
@@ -462,25 +578,74 @@ fn estimate_fee(
     Ok(amount)
 }
 
-impl<B, D, C> Wallet<B, D, C>
-where
-    B: Blockchain,
-    D: BatchDatabase,
-{
+impl Wallet {
     pub async fn get_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
-        let tx = self.wallet.lock().await.client().get_tx(&txid)?;
+        let backend = self.client.lock().await.backend.clone();
 
-        Ok(tx)
+        backend.transaction(txid).await
+    }
+
+    /// Makes the backend watch the given scripts of a swap that started at
+    /// `since`, so that their history is complete whenever we ask for it.
+    pub async fn watch(&self, scripts: &[Script], since: OffsetDateTime) -> Result<()> {
+        let backend = self.client.lock().await.backend.clone();
+
+        backend
+            .watch(scripts, since)
+            .await
+            .context("Failed to watch scripts of swap")
     }
 
+    /// Syncs the wallet with the history of its scripts.
+    ///
+    /// Backends without a bdk blockchain are synced from a snapshot. bdk
+    /// derives and stores more scripts while syncing, hence we sync again
+    /// until the histories of all stored scripts are known.
     pub async fn sync(&self) -> Result<()> {
-        self.wallet
-            .lock()
+        let wallet = self.wallet.lock().await;
+        let prefetched = match wallet.client() {
+            WalletBlockchain::Electrum(_) => {
+                return wallet
+                    .sync(noop_progress(), None)
+                    .context("Failed to sync balance of Bitcoin wallet");
+            }
+            WalletBlockchain::Prefetched(prefetched) => prefetched,
+        };
+        let backend = prefetched.backend();
+
+        loop {
+            let (keychains, known_txids) = {
+                let database = wallet.database();
+                let keychains = vec![
+                    database.iter_script_pubkeys(Some(KeychainKind::External))?,
+                    database.iter_script_pubkeys(Some(KeychainKind::Internal))?,
+                ];
+                let known_txids = database
+                    .iter_raw_txs()?
+                    .iter()
+                    .map(Transaction::txid)
+                    .collect::<HashSet<_>>();
+
+                (keychains, known_txids)
+            };
+            let scripts_before = keychains.iter().map(Vec::len).sum::<usize>();
+
+            let snapshot = Snapshot::fetch(backend.as_ref(), keychains, |txid| {
+                known_txids.contains(txid)
+            })
             .await
-            .sync(noop_progress(), None)
-            .context("Failed to sync balance of Bitcoin wallet")?;
+            .context("Failed to fetch history of Bitcoin wallet")?;
 
-        Ok(())
+            prefetched.prefetched(snapshot);
+            wallet
+                .sync(noop_progress(), None)
+                .context("Failed to sync balance of Bitcoin wallet")?;
+
+            let scripts_after = wallet.database().iter_script_pubkeys(None)?.len();
+            if scripts_after == scripts_before {
+                return Ok(());
+            }
+        }
     }
 }
 
@@ -491,9 +656,10 @@ impl<B, D, C> Wallet<B, D, C> {
     }
 }
 
+#[async_trait]
 pub trait EstimateFeeRate {
-    fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate>;
-    fn min_relay_fee(&self) -> Result<bitcoin::Amount>;
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate>;
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount>;
 }
 
 #[cfg(test)]
@@ -503,12 +669,13 @@ pub struct StaticFeeRate {
 }
 
 #[cfg(test)]
+#[async_trait]
 impl EstimateFeeRate for StaticFeeRate {
-    fn estimate_feerate(&self, _target_block: usize) -> Result<FeeRate> {
+    async fn estimate_feerate(&self, _target_block: usize) -> Result<FeeRate> {
         Ok(self.fee_rate)
     }
 
-    fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
         Ok(self.min_relay_fee)
     }
 }
@@ -561,10 +728,76 @@ impl Wallet<(), bdk::database::MemoryDatabase, StaticFeeRate> {
             finality_confirmations: 1,
             network: Network::Regtest,
             target_block: 1,
+            fee_bump_interval: Duration::from_secs(1),
         }
     }
 }
 
+#[cfg(test)]
+impl Wallet {
+    /// Creates a wallet whose chain backend fails every request, for tests of
+    /// code that holds a wallet without using it.
+    pub async fn new_offline(env_config: env::Config) -> Self {
+        let backend: Arc<dyn Backend> = Arc::new(OfflineBackend);
+        let db = bdk::sled::Config::new()
+            .temporary(true)
+            .open()
+            .unwrap()
+            .open_tree(SLED_TREE_NAME)
+            .unwrap();
+        let key = ::bitcoin::util::bip32::ExtendedPrivKey::new_master(
+            env_config.bitcoin_network,
+            &[0u8; 32],
+        )
+        .unwrap();
+
+        let wallet = bdk::Wallet::new(
+            bdk::template::Bip84(key, KeychainKind::External),
+            Some(bdk::template::Bip84(key, KeychainKind::Internal)),
+            env_config.bitcoin_network,
+            db,
+            WalletBlockchain::Prefetched(sync::Prefetched::new(backend.clone())),
+        )
+        .unwrap();
+
+        Self::from_bdk_wallet(wallet, backend, env_config, 1)
+            .await
+            .unwrap()
+    }
+}
+
+/// A chain backend that only knows the genesis block.
+#[cfg(test)]
+struct OfflineBackend;
+
+#[cfg(test)]
+#[async_trait]
+impl Backend for OfflineBackend {
+    async fn block_height(&self) -> Result<BlockHeight> {
+        Ok(BlockHeight::from(0))
+    }
+
+    async fn script_histories(&self, _: &[Script]) -> Result<Vec<Vec<HistoryEntry>>> {
+        bail!("Backend is offline")
+    }
+
+    async fn transaction(&self, _: Txid) -> Result<Option<Transaction>> {
+        bail!("Backend is offline")
+    }
+
+    async fn broadcast(&self, _: &Transaction) -> Result<()> {
+        bail!("Backend is offline")
+    }
+
+    async fn estimate_feerate(&self, _: usize) -> Result<FeeRate> {
+        bail!("Backend is offline")
+    }
+
+    async fn min_relay_fee(&self) -> Result<Amount> {
+        bail!("Backend is offline")
+    }
+}
+
 /// Defines a watchable transaction.
 ///
 /// For a transaction to be watchable, we need to know two things: Its
@@ -587,25 +820,25 @@ impl Watchable for (Txid, Script) {
 }
 
 pub struct Client {
-    electrum: bdk::electrum_client::Client,
+    backend: Arc<dyn Backend>,
     latest_block_height: BlockHeight,
     last_sync: Instant,
     sync_interval: Duration,
-    script_history: BTreeMap<Script, Vec<GetHistoryRes>>,
+    script_history: BTreeMap<Script, Vec<HistoryEntry>>,
     subscriptions: HashMap<(Txid, Script), Subscription>,
 }
 
 impl Client {
-    fn new(electrum: bdk::electrum_client::Client, interval: Duration) -> Result<Self> {
+    async fn new(backend: Arc<dyn Backend>, interval: Duration) -> Result<Self> {
         // Initially fetch the latest block for storing the height.
-        // We do not act on this subscription after this call.
-        let latest_block = electrum
-            .block_headers_subscribe()
-            .context("Failed to subscribe to header notifications")?;
+        let latest_block_height = backend
+            .block_height()
+            .await
+            .context("Failed to get latest block height")?;
 
         Ok(Self {
-            electrum,
-            latest_block_height: BlockHeight::try_from(latest_block)?,
+            backend,
+            latest_block_height,
             last_sync: Instant::now(),
             sync_interval: interval,
             script_history: Default::default(),
@@ -613,20 +846,20 @@ impl Client {
         })
     }
 
-    fn update_state(&mut self) -> Result<()> {
+    async fn update_state(&mut self) -> Result<()> {
         let now = Instant::now();
         if now < self.last_sync + self.sync_interval {
             return Ok(());
         }
 
         self.last_sync = now;
-        self.update_latest_block()?;
-        self.update_script_histories()?;
+        self.update_latest_block().await?;
+        self.update_script_histories().await?;
 
         Ok(())
     }
 
-    fn status_of_script<T>(&mut self, tx: &T) -> Result<ScriptStatus>
+    async fn status_of_script<T>(&mut self, tx: &T) -> Result<ScriptStatus>
     where
         T: Watchable,
     {
@@ -637,13 +870,13 @@ impl Client {
             self.script_history.insert(script.clone(), vec![]);
         }
 
-        self.update_state()?;
+        self.update_state().await?;
 
         let history = self.script_history.entry(script).or_default();
 
         let history_of_tx = history
             .iter()
-            .filter(|entry| entry.tx_hash == txid)
+            .filter(|entry| entry.txid == txid)
             .collect::<Vec<_>>();
 
         match history_of_tx.as_slice() {
@@ -653,31 +886,25 @@ impl Client {
                     tracing::warn!("Found more than a single history entry for script. This is highly unexpected and those history entries will be ignored")
                 }
 
-                if last.height <= 0 {
-                    Ok(ScriptStatus::InMempool)
-                } else {
-                    Ok(ScriptStatus::Confirmed(
+                match last.height {
+                    None => Ok(ScriptStatus::InMempool),
+                    Some(height) => Ok(ScriptStatus::Confirmed(
                         Confirmed::from_inclusion_and_latest_block(
-                            u32::try_from(last.height)?,
+                            height,
                             u32::from(self.latest_block_height),
                         ),
-                    ))
+                    )),
                 }
             }
         }
     }
 
-    fn update_latest_block(&mut self) -> Result<()> {
-        // Fetch the latest block for storing the height.
-        // We do not act on this subscription after this call, as we cannot rely on
-        // subscription push notifications because eventually the Electrum server will
-        // close the connection and subscriptions are not automatically renewed
-        // upon renewing the connection.
-        let latest_block = self
-            .electrum
-            .block_headers_subscribe()
-            .context("Failed to subscribe to header notifications")?;
-        let latest_block_height = BlockHeight::try_from(latest_block)?;
+    async fn update_latest_block(&mut self) -> Result<()> {
+        let latest_block_height = self
+            .backend
+            .block_height()
+            .await
+            .context("Failed to get latest block height")?;
 
         if latest_block_height > self.latest_block_height {
             tracing::debug!(
@@ -690,44 +917,39 @@ impl Client {
         Ok(())
     }
 
-    fn update_script_histories(&mut self) -> Result<()> {
+    async fn update_script_histories(&mut self) -> Result<()> {
+        let scripts = self.script_history.keys().cloned().collect::<Vec<_>>();
         let histories = self
-            .electrum
-            .batch_script_get_history(self.script_history.keys())
+            .backend
+            .script_histories(&scripts)
+            .await
             .context("Failed to get script histories")?;
 
-        if histories.len() != self.script_history.len() {
+        if histories.len() != scripts.len() {
             bail!(
                 "Expected {} history entries, received {}",
-                self.script_history.len(),
+                scripts.len(),
                 histories.len()
             );
         }
 
-        let scripts = self.script_history.keys().cloned();
-        let histories = histories.into_iter();
-
-        self.script_history = scripts.zip(histories).collect::<BTreeMap<_, _>>();
+        self.script_history = scripts
+            .into_iter()
+            .zip(histories)
+            .collect::<BTreeMap<_, _>>();
 
         Ok(())
     }
 }
 
+#[async_trait]
 impl EstimateFeeRate for Client {
-    fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
-        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L213
-        // Returned estimated fees are per BTC/kb.
-        let fee_per_byte = self.electrum.estimate_fee(target_block)?;
-        // we do not expect fees being that high.
-        #[allow(clippy::cast_possible_truncation)]
-        Ok(FeeRate::from_btc_per_kvb(fee_per_byte as f32))
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        self.backend.estimate_feerate(target_block).await
     }
 
-    fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
-        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L219
-        // Returned fee is in BTC/kb
-        let relay_fee = bitcoin::Amount::from_btc(self.electrum.relay_fee()?)?;
-        Ok(relay_fee)
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
+        self.backend.min_relay_fee().await
     }
 }
 
@@ -804,6 +1026,14 @@ impl ScriptStatus {
     pub fn has_been_seen(&self) -> bool {
         matches!(self, ScriptStatus::InMempool | ScriptStatus::Confirmed(_))
     }
+
+    /// The number of confirmations, `0` if the script is not confirmed yet.
+    pub fn confirmations(&self) -> u32 {
+        match self {
+            ScriptStatus::Confirmed(inner) => inner.confirmations(),
+            _ => 0,
+        }
+    }
 }
 
 impl fmt::Display for ScriptStatus {
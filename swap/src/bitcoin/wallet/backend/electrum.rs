@@ -0,0 +1,290 @@
+use super::{confirmations_of, Backend, HistoryEntry};
+use crate::bitcoin::timelocks::BlockHeight;
+use crate::bitcoin::{Amount, Transaction, Txid};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bdk::electrum_client::{Client, ElectrumApi, GetHistoryRes};
+use bdk::FeeRate;
+use bitcoin::Script;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Mutex;
+use url::Url;
+
+/// The number of servers that have to agree on the confirmations of a
+/// transaction before it is treated as final, if cross-checking is enabled.
+const CROSS_CHECK_QUORUM: usize = 2;
+
+/// Follows the blockchain through a list of Electrum servers.
+///
+/// Requests go to the server that answered last. If it fails, the next server
+/// in the list is tried.
+pub struct Electrum {
+    servers: Vec<Server>,
+    active: AtomicUsize,
+    cross_check: bool,
+}
+
+impl Electrum {
+    /// Does not connect to the servers yet, so a server being down when the
+    /// wallet starts is handled like one going down later.
+    pub fn new(urls: &[Url], cross_check: bool) -> Result<Self> {
+        if urls.is_empty() {
+            bail!("At least one Electrum server is required")
+        }
+        if cross_check && urls.len() < CROSS_CHECK_QUORUM {
+            bail!(
+                "Cross-checking requires at least {} Electrum servers",
+                CROSS_CHECK_QUORUM
+            )
+        }
+
+        Ok(Self {
+            servers: urls.iter().cloned().map(Server::new).collect(),
+            active: AtomicUsize::new(0),
+            cross_check,
+        })
+    }
+
+    async fn with_failover<T, F>(&self, call: F) -> Result<T>
+    where
+        T: Send,
+        F: Fn(&Client) -> Result<T, bdk::electrum_client::Error> + Send + Sync,
+    {
+        let active = self.active.load(Ordering::SeqCst);
+        let mut last_error = None;
+
+        for index in rotation(active, self.servers.len()) {
+            let server = &self.servers[index];
+
+            match server.call(&call).await {
+                Ok(value) => {
+                    if index != active {
+                        tracing::info!(url = %server.url, "Failed over to Electrum server");
+                        self.active.store(index, Ordering::SeqCst);
+                    }
+
+                    return Ok(value);
+                }
+                Err(error) => {
+                    tracing::warn!(url = %server.url, "Electrum server failed: {:#}", error);
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error
+            .expect("at least one server")
+            .context("All Electrum servers failed"))
+    }
+}
+
+#[async_trait]
+impl Backend for Electrum {
+    async fn block_height(&self) -> Result<BlockHeight> {
+        // We do not act on this subscription after this call, as we cannot rely on
+        // subscription push notifications because eventually the Electrum server will
+        // close the connection and subscriptions are not automatically renewed
+        // upon renewing the connection.
+        let latest_block = self
+            .with_failover(|client| client.block_headers_subscribe())
+            .await
+            .context("Failed to subscribe to header notifications")?;
+
+        BlockHeight::try_from(latest_block)
+    }
+
+    async fn script_histories(&self, scripts: &[Script]) -> Result<Vec<Vec<HistoryEntry>>> {
+        let histories = self
+            .with_failover(|client| client.batch_script_get_history(scripts))
+            .await
+            .context("Failed to get script histories")?;
+
+        let histories = histories
+            .into_iter()
+            .map(|history| history.into_iter().map(HistoryEntry::from).collect())
+            .collect();
+
+        Ok(histories)
+    }
+
+    async fn transaction(&self, txid: Txid) -> Result<Option<Transaction>> {
+        let transaction = self
+            .with_failover(|client| client.transaction_get(&txid))
+            .await
+            .with_context(|| format!("Failed to get transaction {}", txid))?;
+
+        Ok(Some(transaction))
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        self.with_failover(|client| client.transaction_broadcast(transaction))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L213
+        // Returned estimated fees are per BTC/kb.
+        let fee_per_byte = self
+            .with_failover(|client| client.estimate_fee(target_block))
+            .await?;
+        // we do not expect fees being that high.
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(FeeRate::from_btc_per_kvb(fee_per_byte as f32))
+    }
+
+    async fn min_relay_fee(&self) -> Result<Amount> {
+        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L219
+        // Returned fee is in BTC/kb
+        let relay_fee = self.with_failover(|client| client.relay_fee()).await?;
+
+        Ok(Amount::from_btc(relay_fee)?)
+    }
+
+    /// With cross-checking enabled, asks [`CROSS_CHECK_QUORUM`] servers and
+    /// returns the lowest number of confirmations any of them reports. A
+    /// single lying or lagging server can thus not make us treat a
+    /// transaction as final too early.
+    async fn confirmations(&self, txid: Txid, script: &Script) -> Result<u32> {
+        let quorum = if self.cross_check {
+            CROSS_CHECK_QUORUM
+        } else {
+            1
+        };
+        let active = self.active.load(Ordering::SeqCst);
+
+        let mut answers = Vec::with_capacity(quorum);
+        for index in rotation(active, self.servers.len()) {
+            if answers.len() == quorum {
+                break;
+            }
+
+            let server = &self.servers[index];
+            match server.confirmations(txid, script).await {
+                Ok(confirmations) => answers.push((&server.url, confirmations)),
+                Err(error) => {
+                    tracing::warn!(url = %server.url, "Electrum server failed: {:#}", error)
+                }
+            }
+        }
+
+        if answers.len() < quorum {
+            bail!(
+                "Only {} of the {} Electrum servers needed to cross-check transaction {} answered",
+                answers.len(),
+                quorum,
+                txid
+            )
+        }
+
+        let lowest = answers
+            .iter()
+            .map(|(_, confirmations)| *confirmations)
+            .min()
+            .expect("at least one answer");
+
+        for (url, confirmations) in answers.iter().filter(|(_, c)| *c != lowest) {
+            tracing::warn!(
+                %txid,
+                %url,
+                %confirmations,
+                lowest_confirmations = %lowest,
+                "Electrum servers disagree on the confirmations of transaction"
+            );
+        }
+
+        Ok(lowest)
+    }
+}
+
+struct Server {
+    url: Url,
+    /// Connected on first use and dropped after an error, so the next call
+    /// reconnects.
+    client: Mutex<Option<Client>>,
+}
+
+impl Server {
+    fn new(url: Url) -> Self {
+        Self {
+            url,
+            client: Mutex::new(None),
+        }
+    }
+
+    async fn call<T>(
+        &self,
+        call: impl FnOnce(&Client) -> Result<T, bdk::electrum_client::Error>,
+    ) -> Result<T> {
+        let mut guard = self.client.lock().await;
+
+        if guard.is_none() {
+            let client = Client::new(self.url.as_str())
+                .context("Failed to initialize Electrum RPC client")?;
+            *guard = Some(client);
+        }
+
+        let client = guard.as_ref().expect("client to be initialized");
+        match call(client) {
+            Ok(value) => Ok(value),
+            Err(error) => {
+                *guard = None;
+                Err(error.into())
+            }
+        }
+    }
+
+    async fn confirmations(&self, txid: Txid, script: &Script) -> Result<u32> {
+        let block_height =
+            BlockHeight::try_from(self.call(|client| client.block_headers_subscribe()).await?)?;
+        let history = self
+            .call(|client| client.script_get_history(script))
+            .await?
+            .into_iter()
+            .map(HistoryEntry::from)
+            .collect::<Vec<_>>();
+
+        Ok(confirmations_of(txid, &history, block_height))
+    }
+}
+
+impl From<GetHistoryRes> for HistoryEntry {
+    fn from(entry: GetHistoryRes) -> Self {
+        HistoryEntry {
+            txid: entry.tx_hash,
+            // Electrum reports transactions in the mempool with a height of 0 or -1
+            height: u32::try_from(entry.height)
+                .ok()
+                .filter(|height| *height > 0),
+        }
+    }
+}
+
+/// The indices of `len` servers, starting at `start` and wrapping around.
+fn rotation(start: usize, len: usize) -> impl Iterator<Item = usize> {
+    (0..len).map(move |offset| (start + offset) % len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_starts_at_active_server_and_wraps_around() {
+        assert_eq!(rotation(0, 3).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(rotation(2, 3).collect::<Vec<_>>(), vec![2, 0, 1]);
+        assert_eq!(rotation(0, 1).collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn cross_check_requires_two_servers() {
+        let url = Url::parse("tcp://localhost:50001").unwrap();
+
+        assert!(Electrum::new(&[url.clone()], true).is_err());
+        assert!(Electrum::new(&[url.clone(), url.clone()], true).is_ok());
+        assert!(Electrum::new(&[url], false).is_ok());
+        assert!(Electrum::new(&[], false).is_err());
+    }
+}
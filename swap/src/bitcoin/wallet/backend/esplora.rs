@@ -0,0 +1,248 @@
+use super::{Backend, HistoryEntry};
+use crate::bitcoin::timelocks::BlockHeight;
+use crate::bitcoin::{Amount, Transaction, Txid};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bdk::FeeRate;
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::Script;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+use url::Url;
+
+/// Esplora returns the confirmed transactions of a script in pages of this
+/// size.
+const CONFIRMED_PAGE_SIZE: usize = 25;
+
+/// Esplora does not expose the min relay fee of its node, we assume the
+/// default of bitcoind.
+const MIN_RELAY_FEE_SATS_PER_KVB: u64 = 1_000;
+
+/// Follows the blockchain through an Esplora HTTP API.
+pub struct Esplora {
+    client: reqwest::Client,
+    base_url: Url,
+}
+
+impl Esplora {
+    pub fn new(mut base_url: Url) -> Self {
+        // without a trailing slash, joining replaces the last path segment
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+
+        Self {
+            client: reqwest::Client::new(),
+            base_url,
+        }
+    }
+
+    async fn get(&self, path: &str) -> Result<reqwest::Response> {
+        let url = self.base_url.join(path)?;
+        let response = self
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .with_context(|| format!("Failed to request {}", url))?;
+
+        Ok(response)
+    }
+
+    async fn script_history(&self, script: &Script) -> Result<Vec<HistoryEntry>> {
+        let script_hash = sha256::Hash::hash(script.as_bytes()).into_inner().to_hex();
+
+        let mut history = Vec::new();
+        let mut page = self
+            .get(&format!("scripthash/{}/txs", script_hash))
+            .await?
+            .error_for_status()?
+            .json::<Vec<EsploraTransaction>>()
+            .await?;
+
+        loop {
+            let confirmed = page.iter().filter(|tx| tx.status.confirmed).count();
+            let last_confirmed = page
+                .iter()
+                .rev()
+                .find(|tx| tx.status.confirmed)
+                .map(|tx| tx.txid);
+
+            history.extend(page.into_iter().map(HistoryEntry::from));
+
+            // The first page contains the mempool transactions in addition to
+            // a full page of confirmed ones.
+            let last_seen = match last_confirmed {
+                Some(txid) if confirmed == CONFIRMED_PAGE_SIZE => txid,
+                _ => break,
+            };
+
+            page = self
+                .get(&format!(
+                    "scripthash/{}/txs/chain/{}",
+                    script_hash, last_seen
+                ))
+                .await?
+                .error_for_status()?
+                .json::<Vec<EsploraTransaction>>()
+                .await?;
+        }
+
+        Ok(history)
+    }
+}
+
+#[async_trait]
+impl Backend for Esplora {
+    async fn block_height(&self) -> Result<BlockHeight> {
+        let height = self
+            .get("blocks/tip/height")
+            .await?
+            .error_for_status()?
+            .text()
+            .await?
+            .trim()
+            .parse::<u32>()
+            .context("Failed to parse block height")?;
+
+        Ok(BlockHeight::from(height))
+    }
+
+    async fn script_histories(&self, scripts: &[Script]) -> Result<Vec<Vec<HistoryEntry>>> {
+        let mut histories = Vec::with_capacity(scripts.len());
+        for script in scripts {
+            histories.push(self.script_history(script).await?);
+        }
+
+        Ok(histories)
+    }
+
+    async fn transaction(&self, txid: Txid) -> Result<Option<Transaction>> {
+        let response = self.get(&format!("tx/{}/hex", txid)).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let hex = response.error_for_status()?.text().await?;
+        let bytes = Vec::<u8>::from_hex(hex.trim()).context("Transaction is not valid hex")?;
+        let transaction = deserialize(&bytes).context("Failed to deserialize transaction")?;
+
+        Ok(Some(transaction))
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let response = self
+            .client
+            .post(self.base_url.join("tx")?)
+            .body(serialize_hex(transaction))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let message = response.text().await?;
+            bail!("Esplora rejected transaction with {}: {}", status, message)
+        }
+
+        Ok(())
+    }
+
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        let estimates = self
+            .get("fee-estimates")
+            .await?
+            .error_for_status()?
+            .json::<HashMap<String, f32>>()
+            .await?
+            .into_iter()
+            .filter_map(|(target, sats_per_vb)| Some((target.parse().ok()?, sats_per_vb)))
+            .collect::<Vec<_>>();
+
+        match select_estimate(estimates, target_block) {
+            Some(sats_per_vb) => Ok(FeeRate::from_sat_per_vb(sats_per_vb)),
+            None => {
+                // Esplora has no estimates without enough recent transactions,
+                // e.g. on regtest
+                tracing::debug!("No fee estimate, falling back to the min relay fee");
+                let min_relay_fee = self.min_relay_fee().await?;
+
+                #[allow(clippy::cast_possible_truncation)]
+                Ok(FeeRate::from_btc_per_kvb(min_relay_fee.as_btc() as f32))
+            }
+        }
+    }
+
+    async fn min_relay_fee(&self) -> Result<Amount> {
+        Ok(Amount::from_sat(MIN_RELAY_FEE_SATS_PER_KVB))
+    }
+}
+
+/// Selects the estimate for the highest target not above `target_block`,
+/// falling back to the lowest target if there is none.
+fn select_estimate(estimates: Vec<(usize, f32)>, target_block: usize) -> Option<f32> {
+    let within_target = estimates
+        .iter()
+        .filter(|(target, _)| *target <= target_block)
+        .max_by_key(|(target, _)| *target);
+    let lowest_target = estimates.iter().min_by_key(|(target, _)| *target);
+
+    within_target
+        .or(lowest_target)
+        .map(|(_, sats_per_vb)| *sats_per_vb)
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTransaction {
+    txid: Txid,
+    status: EsploraStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraStatus {
+    confirmed: bool,
+    block_height: Option<u32>,
+}
+
+impl From<EsploraTransaction> for HistoryEntry {
+    fn from(transaction: EsploraTransaction) -> Self {
+        HistoryEntry {
+            txid: transaction.txid,
+            height: transaction.status.block_height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_estimate_for_highest_target_within_target_block() {
+        let estimates = vec![(1, 30.0), (3, 20.0), (6, 10.0)];
+
+        assert_eq!(select_estimate(estimates.clone(), 1), Some(30.0));
+        assert_eq!(select_estimate(estimates.clone(), 5), Some(20.0));
+        assert_eq!(select_estimate(estimates, 144), Some(10.0));
+    }
+
+    #[test]
+    fn falls_back_to_lowest_target() {
+        let estimates = vec![(2, 30.0), (6, 10.0)];
+
+        assert_eq!(select_estimate(estimates, 1), Some(30.0));
+        assert_eq!(select_estimate(vec![], 1), None);
+    }
+
+    #[test]
+    fn base_url_gets_trailing_slash() {
+        let esplora = Esplora::new(Url::parse("https://blockstream.info/api").unwrap());
+
+        assert_eq!(
+            esplora.base_url.join("blocks/tip/height").unwrap().as_str(),
+            "https://blockstream.info/api/blocks/tip/height"
+        );
+    }
+}
@@ -0,0 +1,616 @@
+//! Bumping the fee of transactions that are stuck in the mempool.
+//!
+//! Transactions whose output belongs to us are sped up with
+//! child-pays-for-parent (CPFP), transactions built entirely by our wallet
+//! are replaced with replace-by-fee (RBF). The closer the next timelock of a
+//! swap, the lower the confirmation target we estimate the fee rate for.
+use super::{EstimateFeeRate, Subscription, Wallet, Watchable, MAX_ABSOLUTE_TX_FEE};
+use crate::bitcoin::{
+    blocks_until_next_epoch, Amount, CancelTimelock, PunishTimelock, Transaction, TxCancel, TxLock,
+    Txid,
+};
+use ::bitcoin::{OutPoint, Script};
+use anyhow::{bail, Context, Result};
+use bdk::FeeRate;
+use rust_decimal::prelude::*;
+use rust_decimal::Decimal;
+use std::cmp::{max, min};
+use std::convert::TryFrom;
+
+/// The virtual size of a CPFP child spending a single P2WPKH output to a
+/// single P2WPKH output.
+const CPFP_CHILD_VSIZE: u64 = 110;
+
+/// The smallest value of a P2WPKH output that is relayed.
+const P2WPKH_DUST_LIMIT: u64 = 294;
+
+/// How the fee of a transaction is bumped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Spend the output at `vout`, which has to belong to our wallet, with a
+    /// child transaction paying for its parent.
+    Cpfp { vout: u32 },
+    /// Replace the transaction, which has to be built by our wallet and signal
+    /// replaceability, with one paying a higher fee.
+    ///
+    /// Never use this for transactions that other transactions of a swap were
+    /// signed for, replacing them changes their txid.
+    Rbf,
+}
+
+/// The timelocks of a swap that a transaction has to confirm before.
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    tx_lock: (Txid, Script),
+    tx_cancel: (Txid, Script),
+    cancel_timelock: CancelTimelock,
+    punish_timelock: PunishTimelock,
+}
+
+impl Deadline {
+    pub fn new(
+        tx_lock: &TxLock,
+        tx_cancel: &TxCancel,
+        cancel_timelock: CancelTimelock,
+        punish_timelock: PunishTimelock,
+    ) -> Self {
+        Self {
+            tx_lock: (tx_lock.id(), tx_lock.script()),
+            tx_cancel: (tx_cancel.id(), tx_cancel.script()),
+            cancel_timelock,
+            punish_timelock,
+        }
+    }
+
+    async fn blocks_left(&self, wallet: &Wallet) -> Result<u32> {
+        let tx_lock_status = wallet.status_of_script(&self.tx_lock).await?;
+        let tx_cancel_status = wallet.status_of_script(&self.tx_cancel).await?;
+
+        Ok(blocks_until_next_epoch(
+            self.cancel_timelock,
+            self.punish_timelock,
+            tx_lock_status,
+            tx_cancel_status,
+        ))
+    }
+}
+
+impl Wallet {
+    /// Bumps the fee of the given, already broadcasted transaction until it
+    /// is confirmed.
+    ///
+    /// Once per block the fee rate of the transaction (including its CPFP
+    /// child) is compared to the currently estimated fee rate, for a
+    /// confirmation target that shrinks as the `deadline` approaches. Failing
+    /// to bump the fee is logged and retried with the next block.
+    ///
+    /// Fails right away if the fee of the transaction cannot be bumped with
+    /// the given strategy at all.
+    pub async fn bump_fee_until_confirmed(
+        &self,
+        transaction: Transaction,
+        kind: &str,
+        strategy: Strategy,
+        deadline: Option<Deadline>,
+    ) -> Result<()> {
+        self.ensure_bumpable(&transaction, strategy).await?;
+        let parent_fee = self.fee_of(&transaction).await?;
+        let parent_vsize = vsize(&transaction);
+
+        let mut bumper = Bumper {
+            kind,
+            strategy,
+            parent: transaction,
+            parent_fee,
+            parent_vsize,
+            child: None,
+            replaced: Vec::new(),
+        };
+
+        loop {
+            tokio::time::sleep(self.fee_bump_interval).await;
+
+            match bumper.is_confirmed(self).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(error) => {
+                    tracing::debug!(%kind, "Failed to get status of transaction. Error {:#}", error);
+                    continue;
+                }
+            }
+
+            if let Err(error) = bumper.bump_if_needed(self, deadline.as_ref()).await {
+                tracing::warn!(txid = %bumper.parent.txid(), %kind, "Failed to bump fee of transaction. Error {:#}", error);
+            }
+        }
+    }
+
+    /// Waits until the given transaction is final while bumping its fee with
+    /// CPFP through the output at `vout` until it is confirmed.
+    pub async fn wait_until_final_bumping_fee(
+        &self,
+        subscription: &Subscription,
+        transaction: Transaction,
+        kind: &str,
+        vout: u32,
+        deadline: Option<Deadline>,
+    ) -> Result<()> {
+        let bump_fee =
+            self.bump_fee_until_confirmed(transaction, kind, Strategy::Cpfp { vout }, deadline);
+
+        tokio::select! {
+            result = subscription.wait_until_final() => result,
+            result = bump_fee => {
+                if let Err(error) = result {
+                    tracing::warn!(%kind, "Unable to bump fee of transaction. Error {:#}", error);
+                }
+
+                subscription.wait_until_final().await
+            }
+        }
+    }
+
+    /// Fails if the fee of the transaction cannot be bumped with the given
+    /// strategy, i.e. if the output to spend with CPFP is not ours.
+    async fn ensure_bumpable(&self, transaction: &Transaction, strategy: Strategy) -> Result<()> {
+        if let Strategy::Cpfp { vout } = strategy {
+            let output = transaction
+                .output
+                .get(vout as usize)
+                .with_context(|| format!("Transaction has no output {}", vout))?;
+
+            if !self.wallet.lock().await.is_mine(&output.script_pubkey)? {
+                bail!(
+                    "Output {} of transaction {} does not belong to our wallet, a child cannot spend it",
+                    vout,
+                    transaction.txid()
+                )
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the index of the change output of a transaction built by our
+    /// wallet, ignoring the output at index `0`.
+    pub async fn change_output(&self, transaction: &Transaction) -> Result<Option<u32>> {
+        let wallet = self.wallet.lock().await;
+
+        for (vout, output) in transaction.output.iter().enumerate().skip(1) {
+            if wallet.is_mine(&output.script_pubkey)? {
+                return Ok(Some(u32::try_from(vout)?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Computes the fee of a transaction from the outputs it spends.
+    async fn fee_of(&self, transaction: &Transaction) -> Result<Amount> {
+        let mut input_value = 0;
+        for input in &transaction.input {
+            let previous = self.get_raw_transaction(input.previous_output.txid).await?;
+            let output = previous
+                .output
+                .get(input.previous_output.vout as usize)
+                .context("Spent output does not exist")?;
+
+            input_value += output.value;
+        }
+
+        let output_value = transaction
+            .output
+            .iter()
+            .map(|output| output.value)
+            .sum::<u64>();
+
+        let fee = input_value
+            .checked_sub(output_value)
+            .context("Transaction spends more than its inputs")?;
+
+        Ok(Amount::from_sat(fee))
+    }
+
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        self.client
+            .lock()
+            .await
+            .estimate_feerate(target_block)
+            .await
+    }
+
+    /// Spends the given output with a child paying `fee`, replacing the
+    /// previous child if there is one.
+    ///
+    /// As long as the fee fits into the spent output the child spends nothing
+    /// else, otherwise the wallet adds inputs to pay for it.
+    async fn pay_for_parent(
+        &self,
+        output: OutPoint,
+        output_value: u64,
+        fee: Amount,
+        previous_child: Option<&Transaction>,
+    ) -> Result<Transaction> {
+        // make the wallet aware of the parent, respectively the previous child
+        self.sync().await?;
+
+        let fits_into_output = fee <= max_child_fee(output_value);
+
+        let psbt = match previous_child {
+            None => {
+                let address = self.new_address().await?;
+                let wallet = self.wallet.lock().await;
+
+                let mut tx_builder = wallet.build_tx();
+                tx_builder.add_utxo(output)?;
+                if fits_into_output {
+                    tx_builder.manually_selected_only();
+                    tx_builder.set_single_recipient(address.script_pubkey());
+                } else {
+                    tx_builder.add_recipient(address.script_pubkey(), output_value);
+                }
+                tx_builder.fee_absolute(fee.as_sat());
+                tx_builder.enable_rbf();
+                let (psbt, _details) = tx_builder.finish()?;
+
+                psbt
+            }
+            Some(previous_child) => {
+                let wallet = self.wallet.lock().await;
+
+                let mut tx_builder = wallet.build_fee_bump(previous_child.txid())?;
+                if fits_into_output && previous_child.output.len() == 1 {
+                    tx_builder.maintain_single_recipient()?;
+                }
+                tx_builder.fee_absolute(fee.as_sat());
+                tx_builder.enable_rbf();
+                let (psbt, _details) = tx_builder.finish()?;
+
+                psbt
+            }
+        };
+
+        self.sign_and_finalize(psbt).await
+    }
+
+    /// Replaces the given transaction with one paying the given fee rate.
+    async fn replace(&self, txid: Txid, fee_rate: FeeRate) -> Result<Transaction> {
+        // make the wallet aware of the transaction to replace
+        self.sync().await?;
+
+        let psbt = {
+            let wallet = self.wallet.lock().await;
+
+            let mut tx_builder = wallet.build_fee_bump(txid)?;
+            tx_builder.fee_rate(fee_rate);
+            tx_builder.enable_rbf();
+            let (psbt, _details) = tx_builder.finish()?;
+
+            psbt
+        };
+
+        self.sign_and_finalize(psbt).await
+    }
+}
+
+/// Tracks the fee bumps of a single transaction.
+struct Bumper<'a> {
+    kind: &'a str,
+    strategy: Strategy,
+    /// The transaction whose fee is bumped, for RBF the latest replacement.
+    parent: Transaction,
+    parent_fee: Amount,
+    parent_vsize: u64,
+    /// The latest CPFP child together with the fee it pays.
+    child: Option<(Transaction, Amount)>,
+    /// The transactions replaced through RBF, any of them may still confirm.
+    replaced: Vec<(Txid, Script)>,
+}
+
+impl Bumper<'_> {
+    async fn is_confirmed(&self, wallet: &Wallet) -> Result<bool> {
+        let parent = (
+            self.parent.txid(),
+            self.parent.output[0].script_pubkey.clone(),
+        );
+
+        for tx in self.replaced.iter().chain(std::iter::once(&parent)) {
+            if wallet.status_of_script(tx).await?.is_confirmed() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    async fn bump_if_needed(&mut self, wallet: &Wallet, deadline: Option<&Deadline>) -> Result<()> {
+        let blocks_left = match deadline {
+            Some(deadline) => Some(deadline.blocks_left(wallet).await?),
+            None => None,
+        };
+        let target_block = confirmation_target(blocks_left, wallet.target_block);
+        let target_fee_rate = wallet.estimate_feerate(target_block).await?;
+        let target_sats_per_vb = Decimal::from_f32(target_fee_rate.as_sat_vb())
+            .context("Failed to parse estimated fee rate")?;
+
+        let (fee, vsize) = match &self.child {
+            Some((child, child_fee)) => (
+                self.parent_fee + *child_fee,
+                self.parent_vsize + vsize(child),
+            ),
+            None => (self.parent_fee, self.parent_vsize),
+        };
+        if sats_per_vb(fee, vsize) >= target_sats_per_vb {
+            return Ok(());
+        }
+
+        let txid = self.parent.txid();
+        tracing::info!(
+            %txid,
+            kind = %self.kind,
+            ?blocks_left,
+            %target_block,
+            "Bumping fee of transaction that is not confirmed yet"
+        );
+
+        match self.strategy {
+            Strategy::Cpfp { vout } => {
+                let child_fee = required_child_fee(
+                    self.parent_fee,
+                    self.parent_vsize,
+                    CPFP_CHILD_VSIZE,
+                    target_sats_per_vb,
+                )?;
+                let spent_output = self
+                    .parent
+                    .output
+                    .get(vout as usize)
+                    .with_context(|| format!("Transaction has no output {}", vout))?;
+                if let Some((_, previous_fee)) = &self.child {
+                    if child_fee <= *previous_fee {
+                        tracing::warn!(%txid, kind = %self.kind, "Unable to bump fee any further");
+                        return Ok(());
+                    }
+                }
+
+                let child = wallet
+                    .pay_for_parent(
+                        OutPoint { txid, vout },
+                        spent_output.value,
+                        child_fee,
+                        self.child.as_ref().map(|(child, _)| child),
+                    )
+                    .await?;
+                wallet.broadcast(child.clone(), "cpfp").await?;
+
+                self.child = Some((child, child_fee));
+            }
+            Strategy::Rbf => {
+                let replacement = wallet.replace(txid, target_fee_rate).await?;
+                let replacement_fee = wallet.fee_of(&replacement).await?;
+                wallet.broadcast(replacement.clone(), self.kind).await?;
+
+                self.replaced
+                    .push((txid, self.parent.output[0].script_pubkey.clone()));
+                self.parent_vsize = vsize(&replacement);
+                self.parent_fee = replacement_fee;
+                self.parent = replacement;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Chooses the confirmation target to estimate the fee rate for.
+///
+/// Without a deadline the configured target is used, otherwise we aim for
+/// confirmation within half of the blocks left until the next timelock
+/// expires.
+fn confirmation_target(blocks_left: Option<u32>, default_target: usize) -> usize {
+    match blocks_left {
+        None => default_target,
+        Some(blocks_left) => {
+            let target = usize::try_from(blocks_left / 2).unwrap_or(default_target);
+
+            max(1, min(target, default_target))
+        }
+    }
+}
+
+/// Computes the fee a child has to pay for the package of parent and child to
+/// reach the target fee rate.
+///
+/// The fee is capped at [`MAX_ABSOLUTE_TX_FEE`] but is at least enough to
+/// relay the child itself at 1 sat/vB.
+fn required_child_fee(
+    parent_fee: Amount,
+    parent_vsize: u64,
+    child_vsize: u64,
+    target_sats_per_vb: Decimal,
+) -> Result<Amount> {
+    let package_fee = (target_sats_per_vb * Decimal::from(parent_vsize + child_vsize)).ceil();
+    let child_fee = package_fee - Decimal::from(parent_fee.as_sat());
+
+    let child_fee = child_fee
+        .max(Decimal::from(child_vsize))
+        .min(MAX_ABSOLUTE_TX_FEE)
+        .to_u64()
+        .context("Failed to fit child fee into u64")?;
+
+    Ok(Amount::from_sat(child_fee))
+}
+
+/// The most a child spending nothing but the given output can pay, e.g. if it
+/// is an anchor output.
+fn max_child_fee(output_value: u64) -> Amount {
+    Amount::from_sat(output_value.saturating_sub(P2WPKH_DUST_LIMIT))
+}
+
+fn sats_per_vb(fee: Amount, vsize: u64) -> Decimal {
+    Decimal::from(fee.as_sat()) / Decimal::from(max(vsize, 1))
+}
+
+fn vsize(transaction: &Transaction) -> u64 {
+    (transaction.get_weight() as u64 + 3) / 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::{ColdStorage, Network, TxRedeem, ANCHOR_WEIGHT};
+    use crate::env::{GetConfig, Regtest};
+    use crate::protocol::{alice, bob};
+    use ::bitcoin::TxOut;
+    use rand::rngs::OsRng;
+    use uuid::Uuid;
+
+    const TPUB: &str = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+
+    #[test]
+    fn without_deadline_uses_default_target() {
+        assert_eq!(confirmation_target(None, 3), 3);
+    }
+
+    #[test]
+    fn target_shrinks_as_timelock_approaches() {
+        assert_eq!(confirmation_target(Some(72), 3), 3);
+        assert_eq!(confirmation_target(Some(4), 3), 2);
+        assert_eq!(confirmation_target(Some(1), 3), 1);
+        assert_eq!(confirmation_target(Some(0), 3), 1);
+    }
+
+    #[test]
+    fn child_pays_for_the_package_to_reach_target_fee_rate() {
+        let child_fee =
+            required_child_fee(Amount::from_sat(200), 200, 110, Decimal::from(10)).unwrap();
+
+        // (200 + 110) vB * 10 sat/vB - 200 sat
+        assert_eq!(child_fee, Amount::from_sat(2_900));
+    }
+
+    #[test]
+    fn child_pays_at_least_its_own_relay_fee() {
+        let child_fee =
+            required_child_fee(Amount::from_sat(10_000), 200, 110, Decimal::from(2)).unwrap();
+
+        assert_eq!(child_fee, Amount::from_sat(110));
+    }
+
+    #[test]
+    fn child_fee_is_capped() {
+        let child_fee =
+            required_child_fee(Amount::from_sat(200), 200, 110, Decimal::from(1_000_000)).unwrap();
+
+        assert_eq!(child_fee.as_sat(), MAX_ABSOLUTE_TX_FEE.to_u64().unwrap());
+    }
+
+    #[test]
+    fn child_fee_fits_into_spent_output_up_to_dust() {
+        assert_eq!(max_child_fee(10_000), Amount::from_sat(9_706));
+        assert_eq!(max_child_fee(100), Amount::ZERO);
+    }
+
+    #[tokio::test]
+    async fn given_output_of_other_wallet_then_cpfp_fails_up_front() {
+        let wallet = Wallet::new_offline(Regtest::get_config()).await;
+        let transaction = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: Script::new(),
+            }],
+        };
+
+        let result = wallet
+            .bump_fee_until_confirmed(transaction, "test", Strategy::Cpfp { vout: 0 }, None)
+            .await;
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("does not belong to our wallet"));
+    }
+
+    #[tokio::test]
+    async fn given_cold_storage_then_low_fee_redeem_can_be_bumped_through_anchor() {
+        let alice_wallet = Wallet::new_offline(Regtest::get_config()).await;
+        let bob_wallet = Wallet::new_funded_default_fees(Amount::ONE_BTC.as_sat());
+        let dir = tempfile::tempdir().unwrap();
+        let cold_storage = ColdStorage::new(TPUB, Network::Regtest, dir.path()).unwrap();
+        let btc_amount = Amount::from_sat(500_000);
+        let xmr_amount = crate::monero::Amount::from_piconero(10000);
+        let spending_fee = Amount::from_sat(1_000);
+        // 1 sat/vB, too low to get the redeem transaction confirmed in time
+        let tx_redeem_fee = Amount::from_sat(((TxRedeem::weight() + ANCHOR_WEIGHT) / 4) as u64);
+
+        let redeem_address = cold_storage.new_address().await.unwrap();
+        let punish_address = cold_storage.new_address().await.unwrap();
+        let anchor_address = alice_wallet.new_address().await.unwrap();
+
+        let config = Regtest::get_config();
+        let alice_state0 = alice::State0::new(
+            btc_amount,
+            xmr_amount,
+            config,
+            redeem_address.clone(),
+            punish_address,
+            Some(anchor_address),
+            tx_redeem_fee,
+            spending_fee,
+            &mut OsRng,
+        )
+        .unwrap();
+        let bob_state0 = bob::State0::new(
+            Uuid::new_v4(),
+            &mut OsRng,
+            btc_amount,
+            xmr_amount,
+            config.bitcoin_cancel_timelock,
+            config.bitcoin_punish_timelock,
+            bob_wallet.new_address().await.unwrap(),
+            config.monero_finality_confirmations,
+            spending_fee,
+            spending_fee,
+        );
+
+        let (_, alice_state1) = alice_state0.receive(bob_state0.next_message()).unwrap();
+        let bob_state1 = bob_state0
+            .receive(&bob_wallet, alice_state1.next_message())
+            .await
+            .unwrap();
+        let alice_state2 = alice_state1.receive(bob_state1.next_message()).unwrap();
+        let bob_state2 = bob_state1.receive(alice_state2.next_message()).unwrap();
+        let alice_state3 = alice_state2.receive(bob_state2.next_message()).unwrap();
+        let (bob_state3, _tx_lock) = bob_state2.lock_btc().await.unwrap();
+        let bob_state4 = bob_state3.xmr_locked(monero_rpc::wallet::BlockHeight { height: 0 });
+
+        let tx_redeem = alice_state3
+            .signed_redeem_transaction(bob_state4.tx_redeem_encsig())
+            .unwrap();
+        let tx_punish = alice_state3.signed_punish_transaction().unwrap();
+
+        assert_eq!(
+            tx_redeem.output[0].script_pubkey,
+            redeem_address.script_pubkey()
+        );
+        assert!(alice_wallet
+            .ensure_bumpable(&tx_redeem, Strategy::Cpfp { vout: 0 })
+            .await
+            .is_err());
+
+        let cpfp = Strategy::Cpfp {
+            vout: alice_state3.cpfp_vout(),
+        };
+        alice_wallet
+            .ensure_bumpable(&tx_redeem, cpfp)
+            .await
+            .unwrap();
+        alice_wallet
+            .ensure_bumpable(&tx_punish, cpfp)
+            .await
+            .unwrap();
+    }
+}
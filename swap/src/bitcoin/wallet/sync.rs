@@ -0,0 +1,573 @@
+//! Syncing the bdk wallet.
+//!
+//! Wallets that follow Electrum servers sync through bdk's own
+//! [`ElectrumBlockchain`]. bdk has no blockchain for bitcoind, and its
+//! Esplora blockchain brings its own HTTP stack, so these backends sync
+//! through our [`Backend`]: everything the sync needs is fetched upfront
+//! into a [`Snapshot`], which the [`Prefetched`] blockchain then writes into
+//! the wallet database when bdk asks it to sync.
+use super::backend::{Backend, BackendConfig, HistoryEntry};
+use crate::bitcoin::{Transaction, Txid};
+use anyhow::{Context, Result};
+use bdk::blockchain::{noop_progress, Blockchain, Capability, ElectrumBlockchain, Progress};
+use bdk::database::{BatchDatabase, BatchOperations, Database};
+use bdk::{FeeRate, KeychainKind, LocalUtxo, TransactionDetails};
+use bitcoin::{OutPoint, Script};
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+/// We stop looking for transactions of a keychain after this many
+/// consecutive unused scripts.
+const STOP_GAP: usize = 20;
+
+/// The history of the wallet's scripts at a given point in time.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    histories: Vec<(Script, Vec<HistoryEntry>)>,
+    /// The transactions of the histories that are not in the database yet.
+    transactions: HashMap<Txid, Transaction>,
+}
+
+impl Snapshot {
+    /// Fetches the histories of the given scripts of each keychain, ordered
+    /// by derivation index, together with the transactions in them that
+    /// `is_known` returns `false` for.
+    pub async fn fetch(
+        backend: &dyn Backend,
+        keychains: Vec<Vec<Script>>,
+        is_known: impl Fn(&Txid) -> bool,
+    ) -> Result<Self> {
+        let mut histories = Vec::new();
+        for scripts in keychains {
+            for chunk in scripts.chunks(STOP_GAP) {
+                let chunk_histories = backend.script_histories(chunk).await?;
+                let used = chunk_histories.iter().any(|history| !history.is_empty());
+
+                histories.extend(chunk.iter().cloned().zip(chunk_histories));
+
+                if !used {
+                    break;
+                }
+            }
+        }
+
+        let mut transactions = HashMap::new();
+        for entry in histories.iter().flat_map(|(_, history)| history) {
+            if is_known(&entry.txid) || transactions.contains_key(&entry.txid) {
+                continue;
+            }
+
+            let transaction = backend
+                .transaction(entry.txid)
+                .await?
+                .with_context(|| format!("Backend does not know transaction {}", entry.txid))?;
+            transactions.insert(entry.txid, transaction);
+        }
+
+        Ok(Self {
+            histories,
+            transactions,
+        })
+    }
+
+    /// Replaces the transactions and UTXOs in the database with the ones of
+    /// this snapshot.
+    fn apply<D: BatchDatabase>(self, database: &mut D) -> Result<(), bdk::Error> {
+        let Snapshot {
+            histories,
+            mut transactions,
+        } = self;
+
+        let mut heights = HashMap::new();
+        let mut last_indexes = HashMap::new();
+        for (script, history) in &histories {
+            if history.is_empty() {
+                continue;
+            }
+
+            if let Some((keychain, index)) = database.get_path_from_script_pubkey(script)? {
+                let last_index = last_indexes.entry(keychain).or_insert(index);
+                *last_index = max(*last_index, index);
+            }
+
+            for entry in history {
+                heights.insert(entry.txid, entry.height);
+            }
+        }
+
+        for txid in heights.keys() {
+            if !transactions.contains_key(txid) {
+                let transaction = database.get_raw_tx(txid)?.ok_or_else(|| {
+                    bdk::Error::Generic(format!("Transaction {} is not in the database", txid))
+                })?;
+                transactions.insert(*txid, transaction);
+            }
+        }
+
+        let mut batch = database.begin_batch();
+
+        for (keychain, index) in last_indexes {
+            if database.get_last_index(keychain)? < Some(index) {
+                batch.set_last_index(keychain, index)?;
+            }
+        }
+
+        let utxos = unspent_outputs(database, &transactions)?;
+        for utxo in database.iter_utxos()? {
+            if !utxos.contains_key(&utxo.outpoint) {
+                batch.del_utxo(&utxo.outpoint)?;
+            }
+        }
+        for utxo in utxos.values() {
+            batch.set_utxo(utxo)?;
+        }
+
+        // Transactions that disappeared from the histories were replaced or
+        // reorged out.
+        for details in database.iter_txs(false)? {
+            if !heights.contains_key(&details.txid) {
+                batch.del_tx(&details.txid, true)?;
+            }
+        }
+        for (txid, transaction) in &transactions {
+            let details = details(database, transaction, &transactions, heights[txid])?;
+            batch.set_tx(&details)?;
+        }
+
+        database.commit_batch(batch)?;
+
+        Ok(())
+    }
+}
+
+/// The outputs of the given transactions that belong to the wallet and are
+/// not spent by any of them.
+fn unspent_outputs<D: Database>(
+    database: &D,
+    transactions: &HashMap<Txid, Transaction>,
+) -> Result<HashMap<OutPoint, LocalUtxo>, bdk::Error> {
+    let spent = transactions
+        .values()
+        .flat_map(|transaction| transaction.input.iter())
+        .map(|input| input.previous_output)
+        .collect::<HashSet<_>>();
+
+    let mut utxos = HashMap::new();
+    for (txid, transaction) in transactions {
+        for (vout, output) in (0u32..).zip(&transaction.output) {
+            let outpoint = OutPoint::new(*txid, vout);
+            if spent.contains(&outpoint) {
+                continue;
+            }
+
+            if let Some((keychain, _)) =
+                database.get_path_from_script_pubkey(&output.script_pubkey)?
+            {
+                utxos.insert(outpoint, LocalUtxo {
+                    outpoint,
+                    txout: output.clone(),
+                    keychain,
+                });
+            }
+        }
+    }
+
+    Ok(utxos)
+}
+
+/// Computes what the transaction means for the wallet.
+///
+/// The fee is only known if all spent outputs are part of the wallet's
+/// history, which is the case for all transactions we sent. We do not fetch
+/// the others just to display the fee of incoming payments.
+fn details<D: Database>(
+    database: &D,
+    transaction: &Transaction,
+    transactions: &HashMap<Txid, Transaction>,
+    height: Option<u32>,
+) -> Result<TransactionDetails, bdk::Error> {
+    let mut received = 0;
+    let mut output_value = 0;
+    for output in &transaction.output {
+        output_value += output.value;
+        if database
+            .get_path_from_script_pubkey(&output.script_pubkey)?
+            .is_some()
+        {
+            received += output.value;
+        }
+    }
+
+    let mut sent = 0;
+    let mut input_value = Some(0);
+    for input in &transaction.input {
+        let spent_output = transactions
+            .get(&input.previous_output.txid)
+            .and_then(|previous| previous.output.get(input.previous_output.vout as usize));
+
+        match spent_output {
+            Some(spent_output) => {
+                input_value = input_value.map(|value| value + spent_output.value);
+                if database
+                    .get_path_from_script_pubkey(&spent_output.script_pubkey)?
+                    .is_some()
+                {
+                    sent += spent_output.value;
+                }
+            }
+            None => input_value = None,
+        }
+    }
+
+    Ok(TransactionDetails {
+        transaction: Some(transaction.clone()),
+        txid: transaction.txid(),
+        // we never look at the time of a transaction
+        timestamp: 0,
+        received,
+        sent,
+        fees: input_value
+            .map(|value| value.saturating_sub(output_value))
+            .unwrap_or(0),
+        height,
+    })
+}
+
+/// A bdk blockchain that syncs from a previously fetched [`Snapshot`].
+///
+/// All other blockchain operations go through the [`Backend`] directly.
+pub struct Prefetched {
+    backend: Arc<dyn Backend>,
+    snapshot: Mutex<Option<Snapshot>>,
+}
+
+impl Prefetched {
+    pub fn new(backend: Arc<dyn Backend>) -> Self {
+        Self {
+            backend,
+            snapshot: Mutex::new(None),
+        }
+    }
+
+    pub fn backend(&self) -> Arc<dyn Backend> {
+        self.backend.clone()
+    }
+
+    /// Sets the snapshot the next sync applies.
+    pub fn prefetched(&self, snapshot: Snapshot) {
+        *self.snapshot.lock().expect("lock not to be poisoned") = Some(snapshot);
+    }
+}
+
+impl Blockchain for Prefetched {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        vec![Capability::FullHistory].into_iter().collect()
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        _stop_gap: Option<usize>,
+        database: &mut D,
+        _progress_update: P,
+    ) -> Result<(), bdk::Error> {
+        let snapshot = self
+            .snapshot
+            .lock()
+            .expect("lock not to be poisoned")
+            .take();
+
+        match snapshot {
+            Some(snapshot) => snapshot.apply(database),
+            None => Ok(()),
+        }
+    }
+
+    fn get_tx(&self, _txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn broadcast(&self, _tx: &Transaction) -> Result<(), bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn get_height(&self) -> Result<u32, bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn estimate_fee(&self, _target: usize) -> Result<FeeRate, bdk::Error> {
+        Err(unsupported())
+    }
+}
+
+/// The bdk blockchain the wallet syncs with.
+pub enum WalletBlockchain {
+    Electrum(ElectrumSync),
+    Prefetched(Prefetched),
+}
+
+impl WalletBlockchain {
+    pub fn new(config: &BackendConfig, backend: Arc<dyn Backend>) -> Self {
+        match config {
+            BackendConfig::Electrum { urls, .. } => Self::Electrum(ElectrumSync::new(urls)),
+            BackendConfig::Bitcoind { .. } | BackendConfig::Esplora { .. } => {
+                Self::Prefetched(Prefetched::new(backend))
+            }
+        }
+    }
+}
+
+impl Blockchain for WalletBlockchain {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        match self {
+            WalletBlockchain::Electrum(electrum) => electrum.get_capabilities(),
+            WalletBlockchain::Prefetched(prefetched) => prefetched.get_capabilities(),
+        }
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), bdk::Error> {
+        match self {
+            WalletBlockchain::Electrum(electrum) => {
+                electrum.setup(stop_gap, database, progress_update)
+            }
+            WalletBlockchain::Prefetched(prefetched) => {
+                prefetched.setup(stop_gap, database, progress_update)
+            }
+        }
+    }
+
+    fn get_tx(&self, _txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn broadcast(&self, _tx: &Transaction) -> Result<(), bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn get_height(&self) -> Result<u32, bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn estimate_fee(&self, _target: usize) -> Result<FeeRate, bdk::Error> {
+        Err(unsupported())
+    }
+}
+
+/// A bdk blockchain that syncs through bdk's [`ElectrumBlockchain`] with the
+/// first of the Electrum servers that succeeds.
+///
+/// All other blockchain operations go through the [`Backend`] directly.
+pub struct ElectrumSync {
+    servers: Vec<(Url, Mutex<Option<ElectrumBlockchain>>)>,
+}
+
+impl ElectrumSync {
+    /// Does not connect to the servers yet, each one is connected to on
+    /// first use and again after it failed.
+    pub fn new(urls: &[Url]) -> Self {
+        Self {
+            servers: urls
+                .iter()
+                .map(|url| (url.clone(), Mutex::new(None)))
+                .collect(),
+        }
+    }
+}
+
+impl Blockchain for ElectrumSync {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        vec![
+            Capability::FullHistory,
+            Capability::GetAnyTx,
+            Capability::AccurateFees,
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// The sync may be tried with several servers, hence its progress is not
+    /// reported.
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        _progress_update: P,
+    ) -> Result<(), bdk::Error> {
+        let mut last_error = None;
+
+        for (url, blockchain) in &self.servers {
+            let mut guard = blockchain.lock().expect("lock not to be poisoned");
+
+            if guard.is_none() {
+                match bdk::electrum_client::Client::new(url.as_str()) {
+                    Ok(client) => *guard = Some(ElectrumBlockchain::from(client)),
+                    Err(error) => {
+                        tracing::warn!(%url, "Failed to connect to Electrum server: {}", error);
+                        last_error = Some(error.into());
+                        continue;
+                    }
+                }
+            }
+
+            let electrum = guard.as_ref().expect("blockchain to be connected");
+            match electrum.setup(stop_gap, database, noop_progress()) {
+                Ok(()) => return Ok(()),
+                Err(error) => {
+                    tracing::warn!(%url, "Failed to sync with Electrum server: {}", error);
+                    *guard = None;
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| bdk::Error::Generic("No Electrum server".to_string())))
+    }
+
+    fn get_tx(&self, _txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn broadcast(&self, _tx: &Transaction) -> Result<(), bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn get_height(&self) -> Result<u32, bdk::Error> {
+        Err(unsupported())
+    }
+
+    fn estimate_fee(&self, _target: usize) -> Result<FeeRate, bdk::Error> {
+        Err(unsupported())
+    }
+}
+
+fn unsupported() -> bdk::Error {
+    bdk::Error::Generic("Not supported by the sync blockchain, use the backend".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::database::MemoryDatabase;
+    use bitcoin::{TxIn, TxOut};
+
+    fn transaction(inputs: Vec<OutPoint>, outputs: Vec<(Script, u64)>) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: 0,
+            input: inputs
+                .into_iter()
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    script_sig: Script::new(),
+                    sequence: 0xFFFF_FFFF,
+                    witness: vec![],
+                })
+                .collect(),
+            output: outputs
+                .into_iter()
+                .map(|(script_pubkey, value)| TxOut {
+                    value,
+                    script_pubkey,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn applying_snapshot_tracks_unspent_outputs_and_fees() {
+        let ours = Script::from(vec![1]);
+        let change = Script::from(vec![2]);
+        let theirs = Script::from(vec![3]);
+
+        let mut database = MemoryDatabase::new();
+        database
+            .set_script_pubkey(&ours, KeychainKind::External, 0)
+            .unwrap();
+        database
+            .set_script_pubkey(&change, KeychainKind::Internal, 0)
+            .unwrap();
+
+        let funding = transaction(vec![OutPoint::default()], vec![(ours.clone(), 10_000)]);
+        let spending = transaction(vec![OutPoint::new(funding.txid(), 0)], vec![
+            (theirs, 6_000),
+            (change.clone(), 3_000),
+        ]);
+
+        let snapshot = Snapshot {
+            histories: vec![
+                (ours, vec![
+                    HistoryEntry {
+                        txid: funding.txid(),
+                        height: Some(100),
+                    },
+                    HistoryEntry {
+                        txid: spending.txid(),
+                        height: None,
+                    },
+                ]),
+                (change, vec![HistoryEntry {
+                    txid: spending.txid(),
+                    height: None,
+                }]),
+            ],
+            transactions: vec![funding.clone(), spending.clone()]
+                .into_iter()
+                .map(|transaction| (transaction.txid(), transaction))
+                .collect(),
+        };
+
+        snapshot.apply(&mut database).unwrap();
+
+        let utxos = database.iter_utxos().unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint, OutPoint::new(spending.txid(), 1));
+
+        let details = database.get_tx(&spending.txid(), false).unwrap().unwrap();
+        assert_eq!(details.sent, 10_000);
+        assert_eq!(details.received, 3_000);
+        assert_eq!(details.fees, 1_000);
+        assert_eq!(details.height, None);
+
+        assert_eq!(
+            database.get_last_index(KeychainKind::Internal).unwrap(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn applying_snapshot_forgets_replaced_transactions() {
+        let ours = Script::from(vec![1]);
+
+        let mut database = MemoryDatabase::new();
+        database
+            .set_script_pubkey(&ours, KeychainKind::External, 0)
+            .unwrap();
+
+        let replaced = transaction(vec![OutPoint::default()], vec![(ours.clone(), 10_000)]);
+        let replacement = transaction(vec![OutPoint::default()], vec![(ours.clone(), 9_000)]);
+
+        let snapshot = |transaction: &Transaction| Snapshot {
+            histories: vec![(ours.clone(), vec![HistoryEntry {
+                txid: transaction.txid(),
+                height: None,
+            }])],
+            transactions: vec![(transaction.txid(), transaction.clone())]
+                .into_iter()
+                .collect(),
+        };
+
+        snapshot(&replaced).apply(&mut database).unwrap();
+        snapshot(&replacement).apply(&mut database).unwrap();
+
+        assert!(database.get_tx(&replaced.txid(), false).unwrap().is_none());
+        let utxos = database.iter_utxos().unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].txout.value, 9_000);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod command;
+pub mod daemon;
+pub mod list_sellers;
 pub mod tracing;
@@ -0,0 +1,653 @@
+use crate::bitcoin::TxLock;
+use crate::database::{Database, Swap};
+use crate::network::swarm;
+use crate::protocol::bob;
+use crate::protocol::bob::{BobState, EventLoop};
+use crate::rpc::{params, Error, Handler, Subscriber};
+use crate::seed::Seed;
+use crate::{bitcoin, env, monero, rpc};
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use libp2p::core::Multiaddr;
+use libp2p::PeerId;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cmp::min;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+const TOKEN_FILE_NAME: &str = "rpc-token";
+
+/// Reads the token of the JSON-RPC API from the data directory, generating it
+/// if it does not exist yet.
+pub fn read_or_generate_token(data_dir: &Path) -> Result<String> {
+    rpc::read_or_generate_token(&data_dir.join(TOKEN_FILE_NAME))
+}
+
+/// Serves the JSON-RPC API of `swap daemon`.
+///
+/// The wallets and the database are opened once and shared by all requests.
+/// Since all swaps share the same `monero-wallet-rpc` instance only one swap
+/// can be running at a time.
+pub struct Daemon {
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    db: Arc<Database>,
+    seed: Seed,
+    env_config: env::Config,
+    tor_socks5_port: u16,
+    running_swap: Arc<Mutex<Option<Uuid>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuyXmrParams {
+    seller_peer_id: String,
+    seller_addr: Multiaddr,
+    monero_receive_address: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResumeParams {
+    swap_id: Uuid,
+    seller_addr: Multiaddr,
+    monero_receive_address: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SwapIdParams {
+    swap_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManualRecoveryParams {
+    swap_id: Uuid,
+    #[serde(default)]
+    force: bool,
+}
+
+#[async_trait]
+impl Handler for Daemon {
+    async fn handle(
+        &self,
+        method: &str,
+        params_value: Value,
+        subscriber: &Subscriber,
+    ) -> Result<Value, Error> {
+        let result = match method {
+            "buy_xmr" => self.buy_xmr(params(params_value)?).await?,
+            "resume" => self.resume(params(params_value)?).await?,
+            "cancel" => self.cancel(params(params_value)?).await?,
+            "refund" => self.refund(params(params_value)?).await?,
+            "history" => self.history()?,
+            "balance" => self.balance().await?,
+            "get_swap_info" => self.get_swap_info(params(params_value)?).await?,
+            "subscribe_state_updates" => self.subscribe_state_updates(subscriber.clone()),
+            method => return Err(Error::method_not_found(method)),
+        };
+
+        Ok(result)
+    }
+}
+
+impl Daemon {
+    pub fn new(
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        monero_wallet: Arc<monero::Wallet>,
+        db: Arc<Database>,
+        seed: Seed,
+        env_config: env::Config,
+        tor_socks5_port: u16,
+    ) -> Self {
+        Self {
+            bitcoin_wallet,
+            monero_wallet,
+            db,
+            seed,
+            env_config,
+            tor_socks5_port,
+            running_swap: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn buy_xmr(&self, params: BuyXmrParams) -> Result<Value> {
+        let seller_peer_id = PeerId::from_str(&params.seller_peer_id)
+            .with_context(|| format!("Failed to parse {} as peer id", params.seller_peer_id))?;
+        let monero_receive_address = self.monero_address(&params.monero_receive_address)?;
+
+        let mut running_swap = self.running_swap.lock().await;
+        if let Some(swap_id) = *running_swap {
+            bail!("Swap {} is still running", swap_id)
+        }
+
+        let swap_id = Uuid::new_v4();
+
+        self.db
+            .insert_address(seller_peer_id, params.seller_addr.clone())
+            .await?;
+
+        let mut swarm = swarm::bob(&self.seed, seller_peer_id, self.tor_socks5_port).await?;
+        swarm
+            .behaviour_mut()
+            .add_address(seller_peer_id, params.seller_addr);
+
+        let (event_loop, mut event_loop_handle) = EventLoop::new(
+            swap_id,
+            swarm,
+            seller_peer_id,
+            self.bitcoin_wallet.clone(),
+            self.env_config,
+            None,
+        )?;
+        let event_loop = tokio::spawn(event_loop.run());
+
+        let bid_quote = match event_loop_handle.request_quote().await {
+            Ok(bid_quote) => bid_quote,
+            Err(error) => {
+                event_loop.abort();
+                return Err(error.context("Failed to request quote"));
+            }
+        };
+
+        self.bitcoin_wallet.sync().await?;
+        let max_giveable = self
+            .bitcoin_wallet
+            .max_giveable(TxLock::script_size())
+            .await?;
+
+        // In contrast to `swap buy-xmr` we don't wait for a deposit, the frontend
+        // is expected to fund the wallet before starting a swap.
+        if max_giveable < bid_quote.min_quantity() {
+            event_loop.abort();
+            bail!(
+                "Not enough BTC to swap, the seller requires at least {} but we can only give {}",
+                bid_quote.min_quantity(),
+                max_giveable
+            )
+        }
+
+        let btc_amount = min(max_giveable, bid_quote.max_quantity());
+
+        tracing::info!(%btc_amount, %swap_id, "Swapping");
+
+        self.db.insert_peer_id(swap_id, seller_peer_id).await?;
+
+        let swap = bob::Swap::new(
+            self.db.clone(),
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.env_config,
+            event_loop_handle,
+            monero_receive_address,
+            btc_amount,
+        );
+
+        *running_swap = Some(swap_id);
+        self.spawn_swap(swap_id, event_loop, swap);
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "btc_amount": btc_amount.as_sat(),
+        }))
+    }
+
+    async fn resume(&self, params: ResumeParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+        let monero_receive_address = self.monero_address(&params.monero_receive_address)?;
+
+        let mut running_swap = self.running_swap.lock().await;
+        if let Some(running_swap_id) = *running_swap {
+            bail!("Swap {} is still running", running_swap_id)
+        }
+
+        let seller_peer_id = self.db.get_peer_id(swap_id)?;
+
+        self.db
+            .insert_address(seller_peer_id, params.seller_addr.clone())
+            .await?;
+
+        let mut swarm = swarm::bob(&self.seed, seller_peer_id, self.tor_socks5_port).await?;
+        swarm
+            .behaviour_mut()
+            .add_address(seller_peer_id, params.seller_addr);
+
+        let (event_loop, event_loop_handle) = EventLoop::new(
+            swap_id,
+            swarm,
+            seller_peer_id,
+            self.bitcoin_wallet.clone(),
+            self.env_config,
+            None,
+        )?;
+
+        let swap = bob::Swap::from_db(
+            self.db.clone(),
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.env_config,
+            event_loop_handle,
+            monero_receive_address,
+        )?;
+        let event_loop = tokio::spawn(event_loop.run());
+
+        *running_swap = Some(swap_id);
+        self.spawn_swap(swap_id, event_loop, swap);
+
+        Ok(json!({ "swap_id": swap_id }))
+    }
+
+    async fn cancel(&self, params: ManualRecoveryParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+        self.ensure_not_running(swap_id).await?;
+
+        let (txid, state) = bob::cancel(
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.db.clone(),
+            params.force,
+        )
+        .await??;
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "txid": txid.to_string(),
+            "state": state.to_string(),
+        }))
+    }
+
+    async fn refund(&self, params: ManualRecoveryParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+        self.ensure_not_running(swap_id).await?;
+
+        let state = bob::refund(
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.db.clone(),
+            params.force,
+        )
+        .await??;
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "state": state.to_string(),
+        }))
+    }
+
+    fn history(&self) -> Result<Value> {
+        let swaps = self
+            .db
+            .all_swaps()?
+            .into_iter()
+            .map(|(swap_id, state)| {
+                json!({
+                    "swap_id": swap_id,
+                    "state": state.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Value::Array(swaps))
+    }
+
+    async fn balance(&self) -> Result<Value> {
+        self.bitcoin_wallet.sync().await?;
+
+        let balance = self.bitcoin_wallet.balance().await?;
+        let max_giveable = self
+            .bitcoin_wallet
+            .max_giveable(TxLock::script_size())
+            .await?;
+
+        Ok(json!({
+            "btc": balance.as_sat(),
+            "max_giveable": max_giveable.as_sat(),
+        }))
+    }
+
+    async fn get_swap_info(&self, params: SwapIdParams) -> Result<Value> {
+        let swap_id = params.swap_id;
+
+        let state = self.db.get_state(swap_id)?;
+        let peer_id = self.db.get_peer_id(swap_id)?;
+        let running = *self.running_swap.lock().await == Some(swap_id);
+
+        Ok(json!({
+            "swap_id": swap_id,
+            "state": state.to_string(),
+            "peer_id": peer_id.to_string(),
+            "running": running,
+        }))
+    }
+
+    /// Forwards all state transitions of swaps in which we are Bob to the
+    /// subscriber as `state_update` notifications.
+    fn subscribe_state_updates(&self, subscriber: Subscriber) -> Value {
+        let mut state_updates = self.db.state_updates();
+
+        tokio::spawn(async move {
+            loop {
+                let (swap_id, bob) = match state_updates.recv().await {
+                    Ok((swap_id, Swap::Bob(bob))) => (swap_id, bob),
+                    Ok((_, Swap::Alice(_))) => continue,
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            "State update subscriber lagged behind, skipped {} updates",
+                            skipped
+                        );
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+
+                let notification = json!({
+                    "swap_id": swap_id,
+                    "state": BobState::from(bob).to_string(),
+                });
+
+                if subscriber.notify("state_update", notification).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Value::Bool(true)
+    }
+
+    fn spawn_swap(&self, swap_id: Uuid, event_loop: tokio::task::JoinHandle<()>, swap: bob::Swap) {
+        let running_swap = self.running_swap.clone();
+
+        tokio::spawn(async move {
+            let result = tokio::select! {
+                result = event_loop => {
+                    result.map_err(|_| anyhow!("EventLoop panicked"))
+                },
+                result = bob::run(swap) => {
+                    result
+                        .context("Failed to complete swap")
+                        .map(|state| tracing::info!(%swap_id, "Swap finished in state {}", state))
+                }
+            };
+
+            if let Err(error) = result {
+                tracing::error!(%swap_id, "{:#}", error);
+            }
+
+            *running_swap.lock().await = None;
+        });
+    }
+
+    async fn ensure_not_running(&self, swap_id: Uuid) -> Result<()> {
+        if *self.running_swap.lock().await == Some(swap_id) {
+            bail!("Swap {} is currently running", swap_id)
+        }
+
+        Ok(())
+    }
+
+    fn monero_address(&self, address: &str) -> Result<monero::Address> {
+        let address = monero::Address::from_str(address)
+            .with_context(|| format!("Failed to parse {} as a monero address", address))?;
+
+        if address.network != self.env_config.monero_network {
+            bail!(
+                "The given monero address is on network {:?}, expected address of network {:?}",
+                address.network,
+                self.env_config.monero_network
+            )
+        }
+
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::bob::{Bob, BobEndState};
+    use crate::env::GetConfig;
+    use crate::rpc::{INTERNAL_ERROR, INVALID_PARAMS, METHOD_NOT_FOUND};
+    use tempfile::TempDir;
+    use tokio::sync::mpsc::UnboundedReceiver;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn given_unknown_method_then_method_not_found() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+
+        let error = daemon
+            .handle("sell_xmr", Value::Null, &subscriber)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code, METHOD_NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn given_params_without_swap_id_then_invalid_params() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+
+        let error = daemon
+            .handle("get_swap_info", json!({ "id": "foo" }), &subscriber)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code, INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn history_lists_all_swaps() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        daemon
+            .db
+            .insert_latest_state(swap_id, state.clone())
+            .await
+            .unwrap();
+
+        let history = daemon
+            .handle("history", Value::Null, &subscriber)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            history,
+            json!([{ "swap_id": swap_id, "state": state.to_string() }])
+        );
+    }
+
+    #[tokio::test]
+    async fn get_swap_info_marks_the_running_swap() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+        let swap_id = Uuid::new_v4();
+        let peer_id = PeerId::random();
+        let state = Swap::Bob(Bob::Started {
+            btc_amount: bitcoin::Amount::from_sat(100_000),
+        });
+        daemon
+            .db
+            .insert_latest_state(swap_id, state.clone())
+            .await
+            .unwrap();
+        daemon.db.insert_peer_id(swap_id, peer_id).await.unwrap();
+        *daemon.running_swap.lock().await = Some(swap_id);
+
+        let info = daemon
+            .handle("get_swap_info", json!({ "swap_id": swap_id }), &subscriber)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            info,
+            json!({
+                "swap_id": swap_id,
+                "state": state.to_string(),
+                "peer_id": peer_id.to_string(),
+                "running": true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn given_running_swap_then_cancel_and_refund_fail() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+        let swap_id = Uuid::new_v4();
+        *daemon.running_swap.lock().await = Some(swap_id);
+
+        for method in &["cancel", "refund"] {
+            let error = daemon
+                .handle(method, json!({ "swap_id": swap_id }), &subscriber)
+                .await
+                .unwrap_err();
+
+            assert_eq!(error.code, INTERNAL_ERROR);
+            assert_eq!(
+                error.message,
+                format!("Swap {} is currently running", swap_id)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn given_invalid_peer_id_then_buy_xmr_fails() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+
+        let error = daemon
+            .handle(
+                "buy_xmr",
+                buy_xmr_params("not-a-peer-id", stagenet_address()),
+                &subscriber,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code, INTERNAL_ERROR);
+        assert!(error
+            .message
+            .starts_with("Failed to parse not-a-peer-id as peer id"));
+    }
+
+    #[tokio::test]
+    async fn given_monero_address_of_other_network_then_buy_xmr_fails() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+        let mainnet_address = address(monero::Network::Mainnet);
+
+        let error = daemon
+            .handle(
+                "buy_xmr",
+                buy_xmr_params(&PeerId::random().to_string(), mainnet_address),
+                &subscriber,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.message,
+            "The given monero address is on network Mainnet, expected address of network Stagenet"
+        );
+        assert!(daemon.db.all_swaps().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn given_running_swap_then_buy_xmr_fails() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, _) = Subscriber::channel();
+        let swap_id = Uuid::new_v4();
+        *daemon.running_swap.lock().await = Some(swap_id);
+
+        let error = daemon
+            .handle(
+                "buy_xmr",
+                buy_xmr_params(&PeerId::random().to_string(), stagenet_address()),
+                &subscriber,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.message, format!("Swap {} is still running", swap_id));
+    }
+
+    #[tokio::test]
+    async fn subscriber_is_notified_about_bob_state_updates() {
+        let (daemon, _db_dir) = daemon().await;
+        let (subscriber, mut notifications) = Subscriber::channel();
+        let subscribed = daemon
+            .handle("subscribe_state_updates", Value::Null, &subscriber)
+            .await
+            .unwrap();
+        assert_eq!(subscribed, Value::Bool(true));
+
+        let swap_id = Uuid::new_v4();
+        let bob = Bob::Done(BobEndState::SafelyAborted);
+        daemon
+            .db
+            .insert_latest_state(swap_id, Swap::Bob(bob.clone()))
+            .await
+            .unwrap();
+
+        let notification = next_notification(&mut notifications).await;
+
+        assert_eq!(notification["method"], "state_update");
+        assert_eq!(
+            notification["params"],
+            json!({ "swap_id": swap_id, "state": BobState::from(bob).to_string() })
+        );
+    }
+
+    async fn daemon() -> (Daemon, TempDir) {
+        let env_config = env::Testnet::get_config();
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let daemon = Daemon::new(
+            Arc::new(bitcoin::Wallet::new_offline(env_config).await),
+            Arc::new(monero::Wallet::new_offline(env_config)),
+            Arc::new(db),
+            Seed::random().unwrap(),
+            env_config,
+            9050,
+        );
+
+        (daemon, db_dir)
+    }
+
+    fn buy_xmr_params(seller_peer_id: &str, monero_receive_address: monero::Address) -> Value {
+        json!({
+            "seller_peer_id": seller_peer_id,
+            "seller_addr": "/ip4/127.0.0.1/tcp/9939",
+            "monero_receive_address": monero_receive_address.to_string(),
+        })
+    }
+
+    fn stagenet_address() -> monero::Address {
+        address(monero::Network::Stagenet)
+    }
+
+    fn address(network: monero::Network) -> monero::Address {
+        let key = monero::PrivateKey::from_scalar(monero::Scalar::one());
+        let public_key = monero::PublicKey::from_private_key(&key);
+
+        monero::Address::standard(network, public_key, public_key)
+    }
+
+    async fn next_notification(notifications: &mut UnboundedReceiver<String>) -> Value {
+        let notification = timeout(Duration::from_secs(5), notifications.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        serde_json::from_str(&notification).unwrap()
+    }
+}
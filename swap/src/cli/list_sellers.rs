@@ -0,0 +1,288 @@
+use crate::network::quote::BidQuote;
+use crate::network::rendezvous::discovery::{Behaviour, OutEvent};
+use crate::network::rendezvous::{Registration, XmrBtcNamespace};
+use crate::network::{rendezvous, swarm};
+use crate::seed::Seed;
+use anyhow::{bail, Result};
+use libp2p::core::Multiaddr;
+use libp2p::swarm::SwarmEvent;
+use libp2p::{PeerId, Swarm};
+use std::collections::HashMap;
+
+/// Discovers the sellers registered at the given rendezvous point and requests
+/// a quote from each of them.
+pub async fn list_sellers(
+    rendezvous_node_peer_id: PeerId,
+    rendezvous_node_addr: Multiaddr,
+    namespace: XmrBtcNamespace,
+    tor_socks5_port: u16,
+    seed: &Seed,
+) -> Result<Vec<Seller>> {
+    let behaviour = Behaviour::new(rendezvous_node_peer_id, rendezvous_node_addr);
+    let swarm = swarm::cli(seed, tor_socks5_port, behaviour).await?;
+
+    let event_loop = EventLoop::new(swarm, rendezvous_node_peer_id, namespace);
+    let sellers = event_loop.run().await?;
+
+    Ok(sellers)
+}
+
+#[derive(Debug, Clone)]
+pub struct Seller {
+    pub peer_id: PeerId,
+    pub multiaddr: Multiaddr,
+    pub status: Status,
+}
+
+#[derive(Debug, Clone)]
+pub enum Status {
+    Online(BidQuote),
+    Unreachable,
+}
+
+#[allow(missing_debug_implementations)]
+pub struct EventLoop {
+    swarm: Swarm<Behaviour>,
+    rendezvous_node_peer_id: PeerId,
+    namespace: XmrBtcNamespace,
+
+    /// The addresses of the sellers we are still waiting on for a quote.
+    pending_quotes: HashMap<PeerId, Multiaddr>,
+    sellers: Vec<Seller>,
+}
+
+impl EventLoop {
+    pub fn new(
+        swarm: Swarm<Behaviour>,
+        rendezvous_node_peer_id: PeerId,
+        namespace: XmrBtcNamespace,
+    ) -> Self {
+        Self {
+            swarm,
+            rendezvous_node_peer_id,
+            namespace,
+            pending_quotes: HashMap::default(),
+            sellers: Vec::new(),
+        }
+    }
+
+    pub async fn run(mut self) -> Result<Vec<Seller>> {
+        self.swarm.behaviour_mut().rendezvous.send_request(
+            &self.rendezvous_node_peer_id,
+            rendezvous::Request::Discover {
+                namespace: self.namespace,
+            },
+        );
+
+        let mut discovered = false;
+
+        loop {
+            match self.swarm.next_event().await {
+                SwarmEvent::Behaviour(OutEvent::Discovered {
+                    peer,
+                    registrations,
+                }) if peer == self.rendezvous_node_peer_id => {
+                    tracing::debug!("Discovered {} sellers", registrations.len());
+                    discovered = true;
+
+                    for Registration { peer_id, addresses } in registrations {
+                        let first_address = match addresses.first() {
+                            Some(address) => address.clone(),
+                            None => continue,
+                        };
+
+                        for address in addresses {
+                            self.swarm
+                                .behaviour_mut()
+                                .quote
+                                .add_address(&peer_id, address);
+                        }
+
+                        self.swarm.behaviour_mut().quote.send_request(&peer_id, ());
+                        self.pending_quotes.insert(peer_id, first_address);
+                    }
+                }
+                SwarmEvent::Behaviour(OutEvent::QuoteReceived { peer, response }) => {
+                    if let Some(multiaddr) = self.pending_quotes.remove(&peer) {
+                        self.sellers.push(Seller {
+                            peer_id: peer,
+                            multiaddr,
+                            status: Status::Online(response),
+                        });
+                    }
+                }
+                SwarmEvent::Behaviour(OutEvent::Failure { peer, error }) => {
+                    if peer == self.rendezvous_node_peer_id && !discovered {
+                        return Err(error.context("Failed to discover sellers at rendezvous point"));
+                    }
+
+                    tracing::debug!(%peer, "Failed to request quote: {:#}", error);
+                    self.mark_unreachable(peer);
+                }
+                SwarmEvent::ConnectionEstablished {
+                    peer_id, endpoint, ..
+                } => {
+                    // remember the address that actually worked
+                    if let Some(multiaddr) = self.pending_quotes.get_mut(&peer_id) {
+                        *multiaddr = endpoint.get_remote_address().clone();
+                    }
+                }
+                SwarmEvent::ConnectionClosed {
+                    peer_id,
+                    num_established,
+                    ..
+                } if num_established == 0 => {
+                    if peer_id == self.rendezvous_node_peer_id && !discovered {
+                        bail!(
+                            "Connection to rendezvous point closed before sellers were discovered"
+                        );
+                    }
+
+                    self.mark_unreachable(peer_id);
+                }
+                _ => {}
+            }
+
+            if discovered && self.pending_quotes.is_empty() {
+                return Ok(self.sellers);
+            }
+        }
+    }
+
+    fn mark_unreachable(&mut self, peer_id: PeerId) {
+        if let Some(multiaddr) = self.pending_quotes.remove(&peer_id) {
+            self.sellers.push(Seller {
+                peer_id,
+                multiaddr,
+                status: Status::Unreachable,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::quote;
+    use crate::network::rendezvous::server;
+    use crate::network::test::new_swarm;
+    use crate::protocol::alice;
+    use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+    use libp2p::NetworkBehaviour;
+    use std::time::Duration;
+
+    /// A seller that registers at the rendezvous point and hands out a fixed
+    /// quote.
+    #[derive(NetworkBehaviour)]
+    #[behaviour(out_event = "SellerEvent", event_process = false)]
+    struct SellerBehaviour {
+        rendezvous: alice::rendezvous::Behaviour,
+        quote: quote::Behaviour,
+    }
+
+    #[derive(Debug)]
+    enum SellerEvent {
+        Registered,
+        Quote(RequestResponseEvent<(), BidQuote>),
+    }
+
+    impl From<alice::rendezvous::OutEvent> for SellerEvent {
+        fn from(_: alice::rendezvous::OutEvent) -> Self {
+            SellerEvent::Registered
+        }
+    }
+
+    impl From<RequestResponseEvent<(), BidQuote>> for SellerEvent {
+        fn from(event: RequestResponseEvent<(), BidQuote>) -> Self {
+            SellerEvent::Quote(event)
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_sellers_registered_at_rendezvous_point() {
+        let (mut rendezvous_node, rendezvous_address, rendezvous_peer_id) =
+            new_swarm(|_, _| server::Behaviour::default());
+        tokio::spawn(async move {
+            loop {
+                rendezvous_node.next_event().await;
+            }
+        });
+
+        let seller_address = format!("/memory/{}", rand::random::<u64>())
+            .parse::<Multiaddr>()
+            .unwrap();
+        let (mut seller, _, seller_peer_id) = new_swarm(|_, _| SellerBehaviour {
+            rendezvous: alice::rendezvous::Behaviour::new(
+                rendezvous_peer_id,
+                rendezvous_address.clone(),
+                XmrBtcNamespace::Testnet,
+                vec![seller_address.clone()],
+            ),
+            quote: quote::alice(),
+        });
+        Swarm::listen_on(&mut seller, seller_address.clone()).unwrap();
+
+        // wait until the seller is registered before we start discovering
+        loop {
+            if let SwarmEvent::Behaviour(SellerEvent::Registered) = seller.next_event().await {
+                break;
+            }
+        }
+        tokio::spawn(async move {
+            loop {
+                if let SwarmEvent::Behaviour(SellerEvent::Quote(RequestResponseEvent::Message {
+                    message: RequestResponseMessage::Request { channel, .. },
+                    ..
+                })) = seller.next_event().await
+                {
+                    let _ = seller.behaviour_mut().quote.send_response(channel, BidQuote {
+                        price: bitcoin::Amount::from_btc(0.01).unwrap(),
+                        min_quantity: bitcoin::Amount::from_btc(0.001).unwrap(),
+                        max_quantity: bitcoin::Amount::from_btc(0.1).unwrap(),
+                    });
+                }
+            }
+        });
+
+        let (cli, _, _) =
+            new_swarm(|_, _| Behaviour::new(rendezvous_peer_id, rendezvous_address.clone()));
+        let event_loop = EventLoop::new(cli, rendezvous_peer_id, XmrBtcNamespace::Testnet);
+
+        let sellers = tokio::time::timeout(Duration::from_secs(10), event_loop.run())
+            .await
+            .expect("to list sellers within 10 seconds")
+            .unwrap();
+
+        assert_eq!(sellers.len(), 1);
+        assert_eq!(sellers[0].peer_id, seller_peer_id);
+        assert_eq!(sellers[0].multiaddr, seller_address);
+        match &sellers[0].status {
+            Status::Online(quote) => {
+                assert_eq!(quote.price, bitcoin::Amount::from_btc(0.01).unwrap())
+            }
+            Status::Unreachable => panic!("expected seller to be online"),
+        }
+    }
+
+    #[tokio::test]
+    async fn given_no_registrations_then_returns_no_sellers() {
+        let (mut rendezvous_node, rendezvous_address, rendezvous_peer_id) =
+            new_swarm(|_, _| server::Behaviour::default());
+        tokio::spawn(async move {
+            loop {
+                rendezvous_node.next_event().await;
+            }
+        });
+
+        let (cli, _, _) =
+            new_swarm(|_, _| Behaviour::new(rendezvous_peer_id, rendezvous_address.clone()));
+        let event_loop = EventLoop::new(cli, rendezvous_peer_id, XmrBtcNamespace::Mainnet);
+
+        let sellers = tokio::time::timeout(Duration::from_secs(10), event_loop.run())
+            .await
+            .expect("to list sellers within 10 seconds")
+            .unwrap();
+
+        assert!(sellers.is_empty());
+    }
+}
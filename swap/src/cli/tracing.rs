@@ -9,6 +9,22 @@ use tracing_subscriber::{fmt, EnvFilter, FmtSubscriber, Layer, Registry};
 use uuid::Uuid;
 
 pub fn init(debug: bool, json: bool, dir: impl AsRef<Path>, swap_id: Uuid) -> Result<()> {
+    init_with_file_name(debug, json, dir, format!("swap-{}.log", swap_id))
+}
+
+/// Initializes logging for `swap daemon`.
+///
+/// Since the daemon runs many swaps all of them are logged to the same file.
+pub fn init_daemon(debug: bool, json: bool, dir: impl AsRef<Path>) -> Result<()> {
+    init_with_file_name(debug, json, dir, "swap-daemon.log".to_owned())
+}
+
+fn init_with_file_name(
+    debug: bool,
+    json: bool,
+    dir: impl AsRef<Path>,
+    file_name: String,
+) -> Result<()> {
     if json {
         let level = if debug { Level::DEBUG } else { Level::INFO };
 
@@ -29,7 +45,7 @@ pub fn init(debug: bool, json: bool, dir: impl AsRef<Path>, swap_id: Uuid) -> Re
 
         let registry = Registry::default().with(level_filter);
 
-        let appender = tracing_appender::rolling::never(dir, format!("swap-{}.log", swap_id));
+        let appender = tracing_appender::rolling::never(dir, file_name);
         let (appender, guard) = tracing_appender::non_blocking(appender);
 
         std::mem::forget(guard);
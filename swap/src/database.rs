@@ -1,18 +1,26 @@
-pub use alice::Alice;
+pub use alice::{Alice, AliceEndState};
+pub use archive::{Archive, ArchiveFormat};
 pub use bob::Bob;
+pub use summary::{parse_date, HistoryFilter, StateCategory, SwapSummary};
 
-use anyhow::{anyhow, bail, Context, Result};
-use itertools::Itertools;
-use libp2p::PeerId;
+use crate::bitcoin::EncryptedSignature;
+use crate::database::backend::{Backend, Filter, Role, Sled, Sqlite};
+use anyhow::{anyhow, bail, Result};
+use libp2p::{Multiaddr, PeerId};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
-use std::path::Path;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
 mod alice;
+mod archive;
+mod backend;
 mod bob;
+mod migrations;
+mod summary;
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum Swap {
@@ -63,121 +71,264 @@ impl Swap {
             Swap::Alice(_) => bail!(NotBob),
         }
     }
+
+    /// The transactions and transfer proofs known in this state, as label and
+    /// value.
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Swap::Alice(alice) => alice.details(),
+            Swap::Bob(bob) => bob.details(),
+        }
+    }
+
+    fn role(&self) -> Role {
+        match self {
+            Swap::Alice(_) => Role::Alice,
+            Swap::Bob(_) => Role::Bob,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self, Swap::Alice(Alice::Done(_)) | Swap::Bob(Bob::Done(_)))
+    }
+}
+
+/// A state a swap transitioned into and when.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateTransition {
+    pub timestamp: OffsetDateTime,
+    pub state: Swap,
 }
 
 pub struct Database {
-    swaps: sled::Tree,
-    peers: sled::Tree,
+    backend: Box<dyn Backend>,
+    state_updates: broadcast::Sender<(Uuid, Swap)>,
 }
 
+/// How many state updates a subscriber can fall behind before it starts
+/// missing updates.
+const STATE_UPDATES_CAPACITY: usize = 64;
+
 impl Database {
+    /// Opens the database and migrates the swaps stored by earlier releases
+    /// to the current schema version.
+    ///
+    /// Once the database has been migrated to SQLite, see
+    /// [`Database::migrate_to_sqlite`], the SQLite database next to `path` is
+    /// opened instead.
     pub fn open(path: &Path) -> Result<Self> {
-        tracing::debug!("Opening database at {}", path.display());
+        let sqlite_path = sqlite_path(path);
+
+        let backend: Box<dyn Backend> = if sqlite_path.exists() {
+            Box::new(Sqlite::open(&sqlite_path)?)
+        } else {
+            Box::new(Sled::open(path)?)
+        };
+        let (state_updates, _) = broadcast::channel(STATE_UPDATES_CAPACITY);
+
+        Ok(Database {
+            backend,
+            state_updates,
+        })
+    }
 
-        let db =
-            sled::open(path).with_context(|| format!("Could not open the DB at {:?}", path))?;
+    /// Copies all swaps, their history, peers, addresses and encrypted
+    /// signatures from the sled database at `path` to a new SQLite database
+    /// and returns the path of the SQLite database.
+    ///
+    /// The sled database is left as it is, but is not opened anymore once the
+    /// migration succeeded.
+    pub async fn migrate_to_sqlite(path: &Path) -> Result<PathBuf> {
+        let sqlite_path = sqlite_path(path);
+        if sqlite_path.exists() {
+            bail!(
+                "The database has been migrated to {} already",
+                sqlite_path.display()
+            )
+        }
 
-        let swaps = db.open_tree("swaps")?;
-        let peers = db.open_tree("peers")?;
+        let sled = Sled::open(path)?;
+        let records = sled.records()?;
+        let addresses = sled.all_addresses()?;
 
-        Ok(Database { swaps, peers })
-    }
+        // Written next to the final file, so an interrupted migration is
+        // started over rather than picked up half done.
+        let temporary_path = sqlite_path.with_extension("sqlite.tmp");
+        if temporary_path.exists() {
+            std::fs::remove_file(&temporary_path)?;
+        }
+        let sqlite = Sqlite::open(&temporary_path)?;
 
-    pub async fn insert_peer_id(&self, swap_id: Uuid, peer_id: PeerId) -> Result<()> {
-        let peer_id_str = peer_id.to_string();
+        let number_of_swaps = records.len();
+        for record in records {
+            let swap_id = record.swap_id;
 
-        let key = serialize(&swap_id)?;
-        let value = serialize(&peer_id_str).context("Could not serialize peer-id")?;
+            if !sqlite.insert_record(record).await? {
+                bail!("Swap {} was stored twice", swap_id)
+            }
+        }
+        for (peer_id, address) in addresses {
+            sqlite.insert_address(peer_id, address).await?;
+        }
 
-        self.peers.insert(key, value)?;
+        let migrated = sqlite.swaps(Filter::default())?.len();
+        if migrated != number_of_swaps {
+            bail!(
+                "Only {} of {} swaps were migrated",
+                migrated,
+                number_of_swaps
+            )
+        }
 
-        self.peers
-            .flush_async()
-            .await
-            .map(|_| ())
-            .context("Could not flush db")
+        drop(sqlite);
+        std::fs::rename(&temporary_path, &sqlite_path)?;
+
+        tracing::info!(
+            "Migrated {} swaps to {}",
+            number_of_swaps,
+            sqlite_path.display()
+        );
+
+        Ok(sqlite_path)
+    }
+
+    pub async fn insert_peer_id(&self, swap_id: Uuid, peer_id: PeerId) -> Result<()> {
+        self.backend.insert_peer_id(swap_id, peer_id).await
     }
 
     pub fn get_peer_id(&self, swap_id: Uuid) -> Result<PeerId> {
-        let key = serialize(&swap_id)?;
+        self.backend
+            .peer_id(swap_id)?
+            .ok_or_else(|| anyhow!("No peer-id found for swap id {} in database", swap_id))
+    }
 
-        let encoded = self
-            .peers
-            .get(&key)?
-            .ok_or_else(|| anyhow!("No peer-id found for swap id {} in database", swap_id))?;
+    /// Remembers an address the peer was reached at, e.g. to dial it again
+    /// when a swap is resumed.
+    pub async fn insert_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()> {
+        self.backend.insert_address(peer_id, address).await
+    }
 
-        let peer_id: String = deserialize(&encoded).context("Could not deserialize peer-id")?;
-        Ok(PeerId::from_str(peer_id.as_str())?)
+    /// Returns the addresses of the peer, in the order they became known.
+    pub fn get_addresses(&self, peer_id: PeerId) -> Result<Vec<Multiaddr>> {
+        self.backend.addresses(peer_id)
+    }
+
+    /// Stores an encrypted signature that was received while no swap was
+    /// waiting for it, so it can be picked up once the swap is resumed.
+    pub async fn insert_encrypted_signature(
+        &self,
+        swap_id: Uuid,
+        encrypted_signature: EncryptedSignature,
+    ) -> Result<()> {
+        self.backend
+            .insert_encrypted_signature(swap_id, encrypted_signature)
+            .await
+    }
+
+    pub fn get_encrypted_signature(&self, swap_id: Uuid) -> Result<Option<EncryptedSignature>> {
+        self.backend.encrypted_signature(swap_id)
     }
 
     pub async fn insert_latest_state(&self, swap_id: Uuid, state: Swap) -> Result<()> {
-        let key = serialize(&swap_id)?;
-        let new_value = serialize(&state).context("Could not serialize new state value")?;
+        self.backend.insert_state(swap_id, &state).await?;
 
-        let old_value = self.swaps.get(&key)?;
+        // Sending only fails if nobody is subscribed, which is fine.
+        let _ = self.state_updates.send((swap_id, state));
 
-        self.swaps
-            .compare_and_swap(key, old_value, Some(new_value))
-            .context("Could not write in the DB")?
-            .context("Stored swap somehow changed, aborting saving")?;
+        Ok(())
+    }
 
-        self.swaps
-            .flush_async()
-            .await
-            .map(|_| ())
-            .context("Could not flush db")
+    /// Subscribes to all states that are inserted from now on.
+    pub fn state_updates(&self) -> broadcast::Receiver<(Uuid, Swap)> {
+        self.state_updates.subscribe()
     }
 
     pub fn get_state(&self, swap_id: Uuid) -> Result<Swap> {
-        let key = serialize(&swap_id)?;
-
-        let encoded = self
-            .swaps
-            .get(&key)?
-            .ok_or_else(|| anyhow!("Swap with id {} not found in database", swap_id))?;
+        self.backend
+            .state(swap_id)?
+            .ok_or_else(|| anyhow!("Swap with id {} not found in database", swap_id))
+    }
 
-        let state = deserialize(&encoded).context("Could not deserialize state")?;
-        Ok(state)
+    /// Returns every state the swap was in, oldest first.
+    ///
+    /// Swaps are only recorded since the release that introduced the history,
+    /// the history of older swaps is empty.
+    pub fn history(&self, swap_id: Uuid) -> Result<Vec<StateTransition>> {
+        self.backend.history(swap_id)
     }
 
+    /// Returns all swaps in which we are Alice.
     pub fn all_alice(&self) -> Result<Vec<(Uuid, Alice)>> {
-        self.all_alice_iter().collect()
+        self.alice_swaps(Filter {
+            role: Some(Role::Alice),
+            unfinished_only: false,
+        })
     }
 
-    fn all_alice_iter(&self) -> impl Iterator<Item = Result<(Uuid, Alice)>> {
-        self.all_swaps_iter().map(|item| {
-            let (swap_id, swap) = item?;
-            Ok((swap_id, swap.try_into_alice()?))
+    /// Returns all swaps in which we are Bob.
+    pub fn all_bob(&self) -> Result<Vec<(Uuid, Bob)>> {
+        self.bob_swaps(Filter {
+            role: Some(Role::Bob),
+            unfinished_only: false,
         })
     }
 
-    pub fn all_bob(&self) -> Result<Vec<(Uuid, Bob)>> {
-        self.all_bob_iter().collect()
+    /// Returns all swaps regardless of the role we played in them.
+    pub fn all_swaps(&self) -> Result<Vec<(Uuid, Swap)>> {
+        self.backend.swaps(Filter::default())
     }
 
-    fn all_bob_iter(&self) -> impl Iterator<Item = Result<(Uuid, Bob)>> {
-        self.all_swaps_iter().map(|item| {
-            let (swap_id, swap) = item?;
-            Ok((swap_id, swap.try_into_bob()?))
+    /// Returns all unfinished swaps in which we are Alice.
+    ///
+    /// Swaps in the role of Bob are skipped, they are resumed separately.
+    pub fn unfinished_alice(&self) -> Result<Vec<(Uuid, Alice)>> {
+        self.alice_swaps(Filter {
+            role: Some(Role::Alice),
+            unfinished_only: true,
         })
     }
 
-    fn all_swaps_iter(&self) -> impl Iterator<Item = Result<(Uuid, Swap)>> {
-        self.swaps.iter().map(|item| {
-            let (key, value) = item.context("Failed to retrieve swap from DB")?;
+    /// Whether there is any unfinished swap, regardless of our role in it.
+    pub fn has_unfinished_swaps(&self) -> Result<bool> {
+        let unfinished = self.backend.swaps(Filter {
+            role: None,
+            unfinished_only: true,
+        })?;
 
-            let swap_id = deserialize::<Uuid>(&key)?;
-            let swap = deserialize::<Swap>(&value).context("Failed to deserialize swap")?;
+        Ok(!unfinished.is_empty())
+    }
 
-            Ok((swap_id, swap))
+    /// Returns all unfinished swaps in which we are Bob.
+    ///
+    /// Swaps in the role of Alice are skipped, they are resumed separately.
+    pub fn unfinished_bob(&self) -> Result<Vec<(Uuid, Bob)>> {
+        self.bob_swaps(Filter {
+            role: Some(Role::Bob),
+            unfinished_only: true,
         })
     }
 
-    pub fn unfinished_alice(&self) -> Result<Vec<(Uuid, Alice)>> {
-        self.all_alice_iter()
-            .filter_ok(|(_swap_id, alice)| !matches!(alice, Alice::Done(_)))
+    fn alice_swaps(&self, filter: Filter) -> Result<Vec<(Uuid, Alice)>> {
+        self.backend
+            .swaps(filter)?
+            .into_iter()
+            .map(|(swap_id, swap)| Ok((swap_id, swap.try_into_alice()?)))
             .collect()
     }
+
+    fn bob_swaps(&self, filter: Filter) -> Result<Vec<(Uuid, Bob)>> {
+        self.backend
+            .swaps(filter)?
+            .into_iter()
+            .map(|(swap_id, swap)| Ok((swap_id, swap.try_into_bob()?)))
+            .collect()
+    }
+}
+
+/// The SQLite database sits next to the sled database, e.g.
+/// `database.sqlite` next to `database`.
+fn sqlite_path(path: &Path) -> PathBuf {
+    path.with_extension("sqlite")
 }
 
 pub fn serialize<T>(t: &T) -> Result<Vec<u8>>
@@ -199,6 +350,9 @@ mod tests {
     use super::*;
     use crate::database::alice::{Alice, AliceEndState};
     use crate::database::bob::{Bob, BobEndState};
+    use ::bitcoin::hashes::Hash;
+    use rand::rngs::OsRng;
+    use std::str::FromStr;
 
     #[tokio::test]
     async fn can_write_and_read_to_multiple_keys() {
@@ -270,7 +424,7 @@ mod tests {
 
         let alice_swaps = db.all_alice().unwrap();
         assert_eq!(alice_swaps.len(), 1);
-        assert!(alice_swaps.contains(&(alice_swap_id, alice_state)));
+        assert!(alice_swaps.contains(&(alice_swap_id, alice_state.clone())));
 
         let bob_state = Bob::Done(BobEndState::SafelyAborted);
         let bob_swap = Swap::Bob(bob_state);
@@ -279,9 +433,8 @@ mod tests {
             .await
             .expect("Failed to save bob state 1");
 
-        let err = db.all_alice().unwrap_err();
-
-        assert_eq!(err.downcast_ref::<NotAlice>().unwrap(), &NotAlice);
+        let alice_swaps = db.all_alice().unwrap();
+        assert_eq!(alice_swaps, vec![(alice_swap_id, alice_state)]);
     }
 
     #[tokio::test]
@@ -298,7 +451,7 @@ mod tests {
 
         let bob_swaps = db.all_bob().unwrap();
         assert_eq!(bob_swaps.len(), 1);
-        assert!(bob_swaps.contains(&(bob_swap_id, bob_state)));
+        assert!(bob_swaps.contains(&(bob_swap_id, bob_state.clone())));
 
         let alice_state = Alice::Done(AliceEndState::BtcPunished);
         let alice_swap = Swap::Alice(alice_state);
@@ -307,9 +460,34 @@ mod tests {
             .await
             .expect("Failed to save alice state 1");
 
-        let err = db.all_bob().unwrap_err();
+        let bob_swaps = db.all_bob().unwrap();
+        assert_eq!(bob_swaps, vec![(bob_swap_id, bob_state)]);
+    }
+
+    #[tokio::test]
+    async fn unfinished_swaps_are_filtered_by_role() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let alice_swap = Swap::Alice(Alice::Done(AliceEndState::BtcPunished));
+        db.insert_latest_state(Uuid::new_v4(), alice_swap).await?;
 
-        assert_eq!(err.downcast_ref::<NotBob>().unwrap(), &NotBob);
+        let bob_state = Bob::Started {
+            btc_amount: bitcoin::Amount::from_sat(100_000),
+        };
+        let bob_swap_id = Uuid::new_v4();
+        db.insert_latest_state(bob_swap_id, Swap::Bob(bob_state.clone()))
+            .await?;
+
+        let bob_swap = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        db.insert_latest_state(Uuid::new_v4(), bob_swap).await?;
+
+        assert_eq!(db.all_swaps()?.len(), 3);
+        assert!(db.unfinished_alice()?.is_empty());
+        assert_eq!(db.unfinished_bob()?, vec![(bob_swap_id, bob_state)]);
+        assert!(db.has_unfinished_swaps()?);
+
+        Ok(())
     }
 
     #[tokio::test]
@@ -334,6 +512,28 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn can_save_and_load_encrypted_signature() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        assert!(db.get_encrypted_signature(swap_id)?.is_none());
+
+        let encrypted_signature = crate::bitcoin::SecretKey::new_random(&mut OsRng).encsign(
+            crate::bitcoin::PublicKey::random(),
+            ::bitcoin::SigHash::from_inner([0u8; 32]),
+        );
+        db.insert_encrypted_signature(swap_id, encrypted_signature.clone())
+            .await?;
+
+        let loaded_encrypted_signature = db.get_encrypted_signature(swap_id)?;
+
+        assert_eq!(loaded_encrypted_signature, Some(encrypted_signature));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_reopen_db() -> Result<()> {
         let db_dir = tempfile::tempdir().unwrap();
@@ -359,4 +559,101 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn history_records_every_state_of_a_swap_in_order() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        let states = vec![
+            Swap::Bob(Bob::Started {
+                btc_amount: bitcoin::Amount::from_sat(100_000),
+            }),
+            Swap::Bob(Bob::Done(BobEndState::SafelyAborted)),
+            Swap::Bob(Bob::Done(BobEndState::XmrRedeemed {
+                tx_lock_id: ::bitcoin::Txid::from_inner([1u8; 32]),
+            })),
+        ];
+        for state in states.iter() {
+            db.insert_latest_state(swap_id, state.clone()).await?;
+        }
+        let other_swap = Swap::Alice(Alice::Done(AliceEndState::BtcPunished));
+        db.insert_latest_state(Uuid::new_v4(), other_swap).await?;
+
+        let history = db.history(swap_id)?;
+
+        assert_eq!(
+            history
+                .iter()
+                .map(|transition| transition.state.clone())
+                .collect::<Vec<_>>(),
+            states
+        );
+        assert!(history
+            .windows(2)
+            .all(|pair| pair[0].timestamp < pair[1].timestamp));
+        assert!(db.history(Uuid::new_v4())?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrating_to_sqlite_keeps_all_swaps() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let path = db_dir.path().join("database");
+
+        let swap_id = Uuid::new_v4();
+        let peer_id = PeerId::random();
+        let address = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9939")?;
+        let (all_swaps, history) = {
+            let db = Database::open(&path)?;
+            db.insert_latest_state(
+                swap_id,
+                Swap::Bob(Bob::Started {
+                    btc_amount: bitcoin::Amount::from_sat(100_000),
+                }),
+            )
+            .await?;
+            db.insert_latest_state(swap_id, Swap::Bob(Bob::Done(BobEndState::SafelyAborted)))
+                .await?;
+            db.insert_peer_id(swap_id, peer_id).await?;
+            db.insert_address(peer_id, address.clone()).await?;
+            let other_swap = Swap::Alice(Alice::Done(AliceEndState::BtcPunished));
+            db.insert_latest_state(Uuid::new_v4(), other_swap).await?;
+
+            (db.all_swaps()?, db.history(swap_id)?)
+        };
+
+        let sqlite_path = Database::migrate_to_sqlite(&path).await?;
+        assert!(sqlite_path.exists());
+        assert!(Database::migrate_to_sqlite(&path).await.is_err());
+
+        let db = Database::open(&path)?;
+        let mut migrated_swaps = db.all_swaps()?;
+        let mut expected_swaps = all_swaps;
+        migrated_swaps.sort_by_key(|(swap_id, _)| *swap_id);
+        expected_swaps.sort_by_key(|(swap_id, _)| *swap_id);
+        assert_eq!(migrated_swaps, expected_swaps);
+        assert_eq!(db.history(swap_id)?, history);
+        assert_eq!(db.get_peer_id(swap_id)?, peer_id);
+        assert_eq!(db.get_addresses(peer_id)?, vec![address]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn inserted_states_are_sent_to_subscribers() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+        let mut state_updates = db.state_updates();
+
+        let swap_id = Uuid::new_v4();
+        let state = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        db.insert_latest_state(swap_id, state.clone()).await?;
+
+        assert_eq!(state_updates.recv().await?, (swap_id, state));
+
+        Ok(())
+    }
 }
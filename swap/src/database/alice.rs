@@ -274,6 +274,103 @@ impl From<Alice> for AliceState {
     }
 }
 
+impl Alice {
+    /// The transactions and transfer proofs known in this state, as label and
+    /// value.
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        let (state3, transfer_proof) = match self {
+            Alice::Started { state3 }
+            | Alice::BtcLocked { state3 }
+            | Alice::BtcRedeemTransactionPublished { state3 } => (state3, None),
+            Alice::XmrLockTransactionSent {
+                state3,
+                transfer_proof,
+                ..
+            }
+            | Alice::XmrLocked {
+                state3,
+                transfer_proof,
+                ..
+            }
+            | Alice::XmrLockTransferProofSent {
+                state3,
+                transfer_proof,
+                ..
+            }
+            | Alice::EncSigLearned {
+                state3,
+                transfer_proof,
+                ..
+            }
+            | Alice::CancelTimelockExpired {
+                state3,
+                transfer_proof,
+                ..
+            }
+            | Alice::BtcCancelled {
+                state3,
+                transfer_proof,
+                ..
+            }
+            | Alice::BtcPunishable {
+                state3,
+                transfer_proof,
+                ..
+            }
+            | Alice::BtcRefunded {
+                state3,
+                transfer_proof,
+                ..
+            } => (state3, Some(transfer_proof)),
+            Alice::Done(_) => return Vec::new(),
+        };
+
+        let mut details = vec![("BTC lock txid", state3.tx_lock.txid().to_string())];
+        if let Some(transfer_proof) = transfer_proof {
+            details.push(("XMR lock tx hash", transfer_proof.tx_hash().to_string()));
+            details.push(("XMR lock tx key", transfer_proof.tx_key().to_string()));
+        }
+        match self {
+            Alice::BtcRedeemTransactionPublished { .. } => {
+                details.push(("BTC redeem txid", state3.tx_redeem().txid().to_string()));
+            }
+            Alice::BtcCancelled { .. } | Alice::BtcPunishable { .. } => {
+                details.push(("BTC cancel txid", state3.tx_cancel().txid().to_string()));
+            }
+            Alice::BtcRefunded { .. } => {
+                details.push(("BTC cancel txid", state3.tx_cancel().txid().to_string()));
+                details.push(("BTC refund txid", state3.tx_refund().txid().to_string()));
+            }
+            _ => {}
+        }
+
+        details
+    }
+
+    /// The BTC and XMR swapped, unless this state does not know them.
+    pub fn amounts(&self) -> (Option<bitcoin::Amount>, Option<monero::Amount>) {
+        let state3 = match self {
+            Alice::Started { state3 }
+            | Alice::BtcLocked { state3 }
+            | Alice::XmrLockTransactionSent { state3, .. }
+            | Alice::XmrLocked { state3, .. }
+            | Alice::XmrLockTransferProofSent { state3, .. }
+            | Alice::EncSigLearned { state3, .. }
+            | Alice::BtcRedeemTransactionPublished { state3 }
+            | Alice::CancelTimelockExpired { state3, .. }
+            | Alice::BtcCancelled { state3, .. }
+            | Alice::BtcPunishable { state3, .. }
+            | Alice::BtcRefunded { state3, .. } => state3,
+            Alice::Done(_) => return (None, None),
+        };
+
+        (
+            Some(state3.tx_lock.lock_amount()),
+            Some(state3.lock_xmr_transfer_request().amount),
+        )
+    }
+}
+
 impl Display for Alice {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
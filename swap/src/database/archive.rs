@@ -0,0 +1,327 @@
+use crate::bitcoin::EncryptedSignature;
+use crate::database::backend::SwapRecord;
+use crate::database::migrations::{self, Versioned};
+use crate::database::{deserialize, serialize, Database, Swap};
+use crate::network::spot_price::BlockchainNetwork;
+use anyhow::{bail, Context, Result};
+use libp2p::PeerId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// The version of the layout of the archive, the swaps in it are tagged with
+/// their schema version.
+const ARCHIVE_VERSION: u32 = 1;
+
+/// The swaps of a database, e.g. to move them to another machine.
+///
+/// Swaps are archived as they are stored, so an archive of an older release
+/// is migrated when it is imported.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Archive {
+    version: u32,
+    network: BlockchainNetwork,
+    swaps: Vec<ArchivedSwap>,
+    /// The seed file, still encrypted if it is encrypted.
+    pub seed: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ArchivedSwap {
+    swap_id: Uuid,
+    state: Bytes,
+    history: Vec<ArchivedTransition>,
+    peer_id: Option<String>,
+    encrypted_signature: Option<Bytes>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ArchivedTransition {
+    /// Microseconds since the unix epoch.
+    timestamp: u64,
+    state: Bytes,
+}
+
+/// Stored records, which are base64 encoded because not all of them can be
+/// represented in JSON.
+#[derive(Debug)]
+struct Bytes(Vec<u8>);
+
+impl Serialize for Bytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Bytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = base64::decode(&encoded).map_err(serde::de::Error::custom)?;
+
+        Ok(Bytes(bytes))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, strum::Display, strum::EnumString)]
+pub enum ArchiveFormat {
+    #[strum(serialize = "json")]
+    Json,
+    #[strum(serialize = "cbor")]
+    Cbor,
+}
+
+impl Archive {
+    pub fn swap_ids(&self) -> Vec<Uuid> {
+        self.swaps.iter().map(|swap| swap.swap_id).collect()
+    }
+
+    pub fn to_vec(&self, format: ArchiveFormat) -> Result<Vec<u8>> {
+        let bytes = match format {
+            ArchiveFormat::Json => serde_json::to_vec_pretty(self)?,
+            ArchiveFormat::Cbor => serde_cbor::to_vec(self)?,
+        };
+
+        Ok(bytes)
+    }
+
+    /// Reads an archive in either format.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let is_json = bytes
+            .iter()
+            .find(|byte| !byte.is_ascii_whitespace())
+            .map_or(false, |byte| *byte == b'{');
+
+        let archive = if is_json {
+            serde_json::from_slice(bytes).context("Failed to read JSON archive")?
+        } else {
+            serde_cbor::from_slice(bytes).context("Failed to read CBOR archive")?
+        };
+
+        Ok(archive)
+    }
+
+    /// Fails unless the archive can be imported by this release and was
+    /// exported on the same networks.
+    pub fn ensure_compatible(&self, network: BlockchainNetwork) -> Result<()> {
+        if self.version != ARCHIVE_VERSION {
+            bail!(
+                "Unsupported archive version {}, expected version {}",
+                self.version,
+                ARCHIVE_VERSION
+            )
+        }
+
+        if self.network != network {
+            bail!(
+                "The archive contains swaps on {:?}, but we are on {:?}",
+                self.network,
+                network
+            )
+        }
+
+        Ok(())
+    }
+}
+
+impl Database {
+    /// Exports all swaps including their history, peer id and encrypted
+    /// signature, but without the seed.
+    pub fn export(&self, network: BlockchainNetwork) -> Result<Archive> {
+        let swaps = self
+            .backend
+            .records()?
+            .into_iter()
+            .map(|record| {
+                let encrypted_signature = record
+                    .encrypted_signature
+                    .map(|encrypted_signature| serialize(&encrypted_signature))
+                    .transpose()?
+                    .map(Bytes);
+
+                Ok(ArchivedSwap {
+                    swap_id: record.swap_id,
+                    state: Bytes(record.state),
+                    history: record
+                        .history
+                        .into_iter()
+                        .map(|(timestamp, state)| ArchivedTransition {
+                            timestamp,
+                            state: Bytes(state),
+                        })
+                        .collect(),
+                    peer_id: record.peer_id.map(|peer_id| peer_id.to_string()),
+                    encrypted_signature,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Archive {
+            version: ARCHIVE_VERSION,
+            network,
+            swaps,
+            seed: None,
+        })
+    }
+
+    /// Imports the swaps of the archive that are not in the database yet and
+    /// returns their ids.
+    ///
+    /// Every swap is checked before it is imported and written in one
+    /// transaction, together with its history, peer id and encrypted
+    /// signature.
+    pub async fn import(&self, archive: Archive, network: BlockchainNetwork) -> Result<Vec<Uuid>> {
+        archive.ensure_compatible(network)?;
+
+        let mut imported = Vec::new();
+
+        for archived in archive.swaps {
+            let swap_id = archived.swap_id;
+
+            let state = import_state(&archived.state)
+                .with_context(|| format!("Invalid swap {} in archive", swap_id))?;
+            let history = archived
+                .history
+                .iter()
+                .map(|transition| Ok((transition.timestamp, import_state(&transition.state)?)))
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Invalid history of swap {} in archive", swap_id))?;
+            let peer_id = archived
+                .peer_id
+                .map(|peer_id| PeerId::from_str(&peer_id))
+                .transpose()
+                .with_context(|| format!("Invalid peer-id of swap {} in archive", swap_id))?;
+            let encrypted_signature = archived
+                .encrypted_signature
+                .map(|Bytes(encrypted_signature)| {
+                    deserialize::<EncryptedSignature>(&encrypted_signature)
+                })
+                .transpose()
+                .with_context(|| {
+                    format!("Invalid encrypted signature of swap {} in archive", swap_id)
+                })?;
+
+            let record = SwapRecord {
+                swap_id,
+                state,
+                history,
+                peer_id,
+                encrypted_signature,
+            };
+
+            if self.backend.insert_record(record).await? {
+                imported.push(swap_id);
+            } else {
+                tracing::warn!(%swap_id, "Skipping swap that is in the database already");
+            }
+        }
+
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::bob::BobEndState;
+    use crate::database::{Alice, AliceEndState, Bob};
+    use ::bitcoin::hashes::Hash;
+
+    const NETWORK: BlockchainNetwork = BlockchainNetwork {
+        bitcoin: ::bitcoin::Network::Testnet,
+        monero: monero::Network::Stagenet,
+    };
+
+    async fn database_with_swaps() -> (tempfile::TempDir, Database, Uuid) {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path()).unwrap();
+
+        let swap_id = Uuid::new_v4();
+        db.insert_latest_state(
+            swap_id,
+            Swap::Bob(Bob::Started {
+                btc_amount: bitcoin::Amount::from_sat(100_000),
+            }),
+        )
+        .await
+        .unwrap();
+        db.insert_latest_state(
+            swap_id,
+            Swap::Bob(Bob::Done(BobEndState::XmrRedeemed {
+                tx_lock_id: ::bitcoin::Txid::from_inner([1u8; 32]),
+            })),
+        )
+        .await
+        .unwrap();
+        db.insert_peer_id(swap_id, PeerId::random()).await.unwrap();
+
+        let other_swap = Swap::Alice(Alice::Done(AliceEndState::BtcPunished));
+        db.insert_latest_state(Uuid::new_v4(), other_swap)
+            .await
+            .unwrap();
+
+        (db_dir, db, swap_id)
+    }
+
+    #[tokio::test]
+    async fn imports_swaps_exported_in_either_format() {
+        let (_old_dir, old_db, swap_id) = database_with_swaps().await;
+
+        for format in [ArchiveFormat::Json, ArchiveFormat::Cbor].iter() {
+            let bytes = old_db.export(NETWORK).unwrap().to_vec(*format).unwrap();
+
+            let new_dir = tempfile::tempdir().unwrap();
+            let new_db = Database::open(new_dir.path()).unwrap();
+            let archive = Archive::from_slice(&bytes).unwrap();
+            let mut imported = new_db.import(archive, NETWORK).await.unwrap();
+            imported.sort();
+
+            let mut expected = old_db.export(NETWORK).unwrap().swap_ids();
+            expected.sort();
+            assert_eq!(imported, expected);
+            assert_eq!(
+                new_db.get_state(swap_id).unwrap(),
+                old_db.get_state(swap_id).unwrap()
+            );
+            assert_eq!(
+                new_db.history(swap_id).unwrap(),
+                old_db.history(swap_id).unwrap()
+            );
+            assert_eq!(
+                new_db.get_peer_id(swap_id).unwrap(),
+                old_db.get_peer_id(swap_id).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_swaps_that_exist_already() {
+        let (_dir, db, _) = database_with_swaps().await;
+
+        let archive = db.export(NETWORK).unwrap();
+        let imported = db.import(archive, NETWORK).await.unwrap();
+
+        assert!(imported.is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuses_archive_of_other_network() {
+        let (_old_dir, old_db, _) = database_with_swaps().await;
+        let archive = old_db.export(NETWORK).unwrap();
+
+        let new_dir = tempfile::tempdir().unwrap();
+        let new_db = Database::open(new_dir.path()).unwrap();
+        let mainnet = BlockchainNetwork {
+            bitcoin: ::bitcoin::Network::Bitcoin,
+            monero: monero::Network::Mainnet,
+        };
+
+        assert!(new_db.import(archive, mainnet).await.is_err());
+        assert!(new_db.all_swaps().unwrap().is_empty());
+    }
+}
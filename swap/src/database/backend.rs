@@ -0,0 +1,135 @@
+//! The storage the database keeps its swaps in.
+//!
+//! Swaps are stored in sled unless the database has been migrated to
+//! [`Sqlite`], whose tables can be inspected and backed up with standard
+//! tools. Either way, swaps are stored as CBOR tagged with the schema version
+//! they were written with.
+use crate::bitcoin::EncryptedSignature;
+use crate::database::migrations::Versioned;
+use crate::database::{deserialize, serialize, StateTransition, Swap};
+use anyhow::Result;
+use async_trait::async_trait;
+use libp2p::{Multiaddr, PeerId};
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+mod sled;
+mod sqlite;
+
+pub use self::sled::Sled;
+pub use self::sqlite::Sqlite;
+
+#[async_trait]
+pub trait Backend: Send + Sync + 'static {
+    /// Stores `state` as the latest state of the swap and appends it to the
+    /// history of the swap.
+    async fn insert_state(&self, swap_id: Uuid, state: &Swap) -> Result<()>;
+
+    /// Returns `None` if there is no swap with this id.
+    fn state(&self, swap_id: Uuid) -> Result<Option<Swap>>;
+
+    /// Returns the latest state of every swap that matches `filter`.
+    fn swaps(&self, filter: Filter) -> Result<Vec<(Uuid, Swap)>>;
+
+    /// Returns every state the swap was in, oldest first.
+    fn history(&self, swap_id: Uuid) -> Result<Vec<StateTransition>>;
+
+    async fn insert_peer_id(&self, swap_id: Uuid, peer_id: PeerId) -> Result<()>;
+
+    fn peer_id(&self, swap_id: Uuid) -> Result<Option<PeerId>>;
+
+    /// Remembers an address of the peer, addresses that are known already are
+    /// ignored.
+    async fn insert_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()>;
+
+    fn addresses(&self, peer_id: PeerId) -> Result<Vec<Multiaddr>>;
+
+    /// Returns the addresses of all peers.
+    fn all_addresses(&self) -> Result<Vec<(PeerId, Multiaddr)>>;
+
+    async fn insert_encrypted_signature(
+        &self,
+        swap_id: Uuid,
+        encrypted_signature: EncryptedSignature,
+    ) -> Result<()>;
+
+    fn encrypted_signature(&self, swap_id: Uuid) -> Result<Option<EncryptedSignature>>;
+
+    /// Returns everything stored about every swap, e.g. to export it.
+    fn records(&self) -> Result<Vec<SwapRecord>>;
+
+    /// Stores a swap with everything about it at once, unless a swap with
+    /// that id is stored already.
+    ///
+    /// Returns whether the swap was stored.
+    async fn insert_record(&self, record: SwapRecord) -> Result<bool>;
+}
+
+/// Which swaps to return, all of them by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Filter {
+    pub role: Option<Role>,
+    pub unfinished_only: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, strum::Display, strum::EnumString)]
+pub enum Role {
+    #[strum(serialize = "alice")]
+    Alice,
+    #[strum(serialize = "bob")]
+    Bob,
+}
+
+impl Filter {
+    pub fn matches(&self, swap: &Swap) -> bool {
+        let role_matches = match self.role {
+            Some(role) => role == swap.role(),
+            None => true,
+        };
+
+        role_matches && !(self.unfinished_only && swap.is_finished())
+    }
+}
+
+/// A swap with everything stored about it, as it is stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapRecord {
+    pub swap_id: Uuid,
+    /// The latest state, as CBOR tagged with its schema version.
+    pub state: Vec<u8>,
+    /// The states of the swap, oldest first, each with its time in
+    /// microseconds since the unix epoch.
+    pub history: Vec<(u64, Vec<u8>)>,
+    pub peer_id: Option<PeerId>,
+    pub encrypted_signature: Option<EncryptedSignature>,
+}
+
+/// The time of a new state transition in microseconds since the unix epoch.
+///
+/// The time is moved forward if needed, so a transition never sorts before
+/// the `previous` one of the same swap.
+fn next_timestamp(previous: Option<u64>) -> Result<u64> {
+    let now: u64 = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_micros()
+        .try_into()?;
+
+    Ok(match previous {
+        Some(previous) => now.max(previous + 1),
+        None => now,
+    })
+}
+
+fn timestamp_from_micros(micros: u64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(micros) * 1_000)
+}
+
+fn encode_state(state: &Swap) -> Result<Vec<u8>> {
+    serialize(&Versioned::current(state))
+}
+
+fn decode_state(stored: &[u8]) -> Result<Swap> {
+    deserialize::<Versioned<Swap>>(stored)?.into_current()
+}
@@ -0,0 +1,339 @@
+use crate::bitcoin::EncryptedSignature;
+use crate::database::backend::{
+    decode_state, encode_state, next_timestamp, timestamp_from_micros, Backend, Filter, SwapRecord,
+};
+use crate::database::{deserialize, migrations, serialize, StateTransition, Swap};
+use ::sled::transaction::ConflictableTransactionError;
+use ::sled::Transactional;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use libp2p::{Multiaddr, PeerId};
+use std::convert::TryInto;
+use std::path::Path;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// The length of the swap id that prefixes the keys of the history.
+const SWAP_ID_LENGTH: usize = 16;
+
+/// Keeps the swaps in sled trees, keyed by the CBOR encoded swap id.
+pub struct Sled {
+    swaps: ::sled::Tree,
+    history: ::sled::Tree,
+    peers: ::sled::Tree,
+    peer_addresses: ::sled::Tree,
+    encrypted_signatures: ::sled::Tree,
+}
+
+impl Sled {
+    /// Opens the database and migrates the swaps stored by earlier releases
+    /// to the current schema version.
+    pub fn open(path: &Path) -> Result<Self> {
+        tracing::debug!("Opening database at {}", path.display());
+
+        let db =
+            ::sled::open(path).with_context(|| format!("Could not open the DB at {:?}", path))?;
+
+        let swaps = db.open_tree("swaps")?;
+        let history = db.open_tree("history")?;
+        let peers = db.open_tree("peers")?;
+        let peer_addresses = db.open_tree("addresses")?;
+        let encrypted_signatures = db.open_tree("encrypted_signatures")?;
+        let meta = db.open_tree("meta")?;
+
+        migrations::run(&swaps, &history, &meta).context("Failed to migrate database")?;
+
+        Ok(Sled {
+            swaps,
+            history,
+            peers,
+            peer_addresses,
+            encrypted_signatures,
+        })
+    }
+
+    /// History keys are the swap id followed by the time of the transition in
+    /// microseconds, so the states of a swap are next to each other and in
+    /// order.
+    fn next_history_key(&self, swap_id: Uuid) -> Result<Vec<u8>> {
+        let previous = match self.history.scan_prefix(swap_id.as_bytes()).next_back() {
+            Some(item) => {
+                let (last, _) = item?;
+
+                Some(history_key_micros(&last)?)
+            }
+            None => None,
+        };
+
+        Ok(history_key(swap_id, next_timestamp(previous)?))
+    }
+
+    fn stored_history(&self, swap_id: Uuid) -> Result<Vec<(u64, Vec<u8>)>> {
+        self.history
+            .scan_prefix(swap_id.as_bytes())
+            .map(|item| {
+                let (key, value) = item.context("Failed to retrieve state transition from DB")?;
+
+                Ok((history_key_micros(&key)?, value.to_vec()))
+            })
+            .collect()
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.swaps
+            .flush_async()
+            .await
+            .map(|_| ())
+            .context("Could not flush db")
+    }
+}
+
+#[async_trait]
+impl Backend for Sled {
+    async fn insert_state(&self, swap_id: Uuid, state: &Swap) -> Result<()> {
+        let key = serialize(&swap_id)?;
+        let new_value = encode_state(state).context("Could not serialize new state value")?;
+
+        let old_value = self.swaps.get(&key)?;
+
+        self.swaps
+            .compare_and_swap(key, old_value, Some(new_value.clone()))
+            .context("Could not write in the DB")?
+            .context("Stored swap somehow changed, aborting saving")?;
+
+        self.history
+            .insert(self.next_history_key(swap_id)?, new_value)
+            .context("Could not write in the DB")?;
+
+        self.flush().await
+    }
+
+    fn state(&self, swap_id: Uuid) -> Result<Option<Swap>> {
+        let key = serialize(&swap_id)?;
+
+        self.swaps
+            .get(&key)?
+            .map(|encoded| decode_state(&encoded).context("Could not deserialize state"))
+            .transpose()
+    }
+
+    fn swaps(&self, filter: Filter) -> Result<Vec<(Uuid, Swap)>> {
+        let mut swaps = Vec::new();
+
+        for item in self.swaps.iter() {
+            let (key, value) = item.context("Failed to retrieve swap from DB")?;
+
+            let swap_id = deserialize::<Uuid>(&key)?;
+            let swap = decode_state(&value).context("Failed to deserialize swap")?;
+
+            if filter.matches(&swap) {
+                swaps.push((swap_id, swap));
+            }
+        }
+
+        Ok(swaps)
+    }
+
+    fn history(&self, swap_id: Uuid) -> Result<Vec<StateTransition>> {
+        self.stored_history(swap_id)?
+            .into_iter()
+            .map(|(micros, value)| {
+                let state =
+                    decode_state(&value).context("Failed to deserialize state transition")?;
+
+                Ok(StateTransition {
+                    timestamp: timestamp_from_micros(micros),
+                    state,
+                })
+            })
+            .collect()
+    }
+
+    async fn insert_peer_id(&self, swap_id: Uuid, peer_id: PeerId) -> Result<()> {
+        let peer_id_str = peer_id.to_string();
+
+        let key = serialize(&swap_id)?;
+        let value = serialize(&peer_id_str).context("Could not serialize peer-id")?;
+
+        self.peers.insert(key, value)?;
+
+        self.flush().await
+    }
+
+    fn peer_id(&self, swap_id: Uuid) -> Result<Option<PeerId>> {
+        let key = serialize(&swap_id)?;
+
+        let encoded = match self.peers.get(&key)? {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+
+        let peer_id: String = deserialize(&encoded).context("Could not deserialize peer-id")?;
+        Ok(Some(PeerId::from_str(peer_id.as_str())?))
+    }
+
+    async fn insert_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()> {
+        let mut addresses = self.addresses(peer_id)?;
+        if addresses.contains(&address) {
+            return Ok(());
+        }
+        addresses.push(address);
+
+        let value = serialize(
+            &addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect::<Vec<_>>(),
+        )
+        .context("Could not serialize addresses")?;
+
+        self.peer_addresses.insert(peer_id.to_bytes(), value)?;
+
+        self.flush().await
+    }
+
+    fn addresses(&self, peer_id: PeerId) -> Result<Vec<Multiaddr>> {
+        match self.peer_addresses.get(peer_id.to_bytes())? {
+            Some(encoded) => decode_addresses(&encoded),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn all_addresses(&self) -> Result<Vec<(PeerId, Multiaddr)>> {
+        let mut all_addresses = Vec::new();
+
+        for item in self.peer_addresses.iter() {
+            let (key, value) = item.context("Failed to retrieve addresses from DB")?;
+
+            let peer_id = PeerId::from_bytes(&key)?;
+            for address in decode_addresses(&value)? {
+                all_addresses.push((peer_id, address));
+            }
+        }
+
+        Ok(all_addresses)
+    }
+
+    async fn insert_encrypted_signature(
+        &self,
+        swap_id: Uuid,
+        encrypted_signature: EncryptedSignature,
+    ) -> Result<()> {
+        let key = serialize(&swap_id)?;
+        let value =
+            serialize(&encrypted_signature).context("Could not serialize encrypted signature")?;
+
+        self.encrypted_signatures.insert(key, value)?;
+
+        self.flush().await
+    }
+
+    fn encrypted_signature(&self, swap_id: Uuid) -> Result<Option<EncryptedSignature>> {
+        let key = serialize(&swap_id)?;
+
+        let encoded = match self.encrypted_signatures.get(&key)? {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+
+        let encrypted_signature =
+            deserialize(&encoded).context("Could not deserialize encrypted signature")?;
+        Ok(Some(encrypted_signature))
+    }
+
+    fn records(&self) -> Result<Vec<SwapRecord>> {
+        self.swaps
+            .iter()
+            .map(|item| {
+                let (key, state) = item.context("Failed to retrieve swap from DB")?;
+                let swap_id = deserialize::<Uuid>(&key)?;
+
+                Ok(SwapRecord {
+                    swap_id,
+                    state: state.to_vec(),
+                    history: self.stored_history(swap_id)?,
+                    peer_id: self.peer_id(swap_id)?,
+                    encrypted_signature: self.encrypted_signature(swap_id)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn insert_record(&self, record: SwapRecord) -> Result<bool> {
+        let SwapRecord {
+            swap_id,
+            state,
+            history,
+            peer_id,
+            encrypted_signature,
+        } = record;
+        let key = serialize(&swap_id)?;
+
+        if self.swaps.contains_key(&key)? {
+            return Ok(false);
+        }
+
+        let transitions = history
+            .iter()
+            .map(|(micros, state)| (history_key(swap_id, *micros), state))
+            .collect::<Vec<_>>();
+        let peer_id = peer_id
+            .map(|peer_id| serialize(&peer_id.to_string()))
+            .transpose()?;
+        let encrypted_signature = encrypted_signature
+            .map(|encrypted_signature| serialize(&encrypted_signature))
+            .transpose()?;
+
+        (
+            &self.swaps,
+            &self.history,
+            &self.peers,
+            &self.encrypted_signatures,
+        )
+            .transaction(|(swaps, history, peers, encrypted_signatures)| {
+                swaps.insert(key.as_slice(), state.as_slice())?;
+                for (transition_key, transition) in transitions.iter() {
+                    history.insert(transition_key.as_slice(), transition.as_slice())?;
+                }
+                if let Some(peer_id) = peer_id.as_ref() {
+                    peers.insert(key.as_slice(), peer_id.as_slice())?;
+                }
+                if let Some(encrypted_signature) = encrypted_signature.as_ref() {
+                    encrypted_signatures.insert(key.as_slice(), encrypted_signature.as_slice())?;
+                }
+
+                Ok::<_, ConflictableTransactionError<()>>(())
+            })
+            .map_err(|e| anyhow!("Failed to insert swap {}: {:?}", swap_id, e))?;
+
+        self.flush().await?;
+
+        Ok(true)
+    }
+}
+
+fn history_key(swap_id: Uuid, micros: u64) -> Vec<u8> {
+    let mut key = swap_id.as_bytes().to_vec();
+    key.extend_from_slice(&micros.to_be_bytes());
+
+    key
+}
+
+/// The time of a transition from its key in the history, see
+/// [`Sled::next_history_key`].
+fn history_key_micros(key: &[u8]) -> Result<u64> {
+    let micros = key
+        .get(SWAP_ID_LENGTH..)
+        .and_then(|micros| micros.try_into().ok())
+        .map(u64::from_be_bytes)
+        .context("Invalid history key")?;
+
+    Ok(micros)
+}
+
+fn decode_addresses(encoded: &[u8]) -> Result<Vec<Multiaddr>> {
+    deserialize::<Vec<String>>(encoded)?
+        .iter()
+        .map(|address| Ok(Multiaddr::from_str(address)?))
+        .collect()
+}
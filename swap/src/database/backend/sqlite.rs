@@ -0,0 +1,556 @@
+use crate::bitcoin::EncryptedSignature;
+use crate::database::backend::{
+    decode_state, encode_state, next_timestamp, timestamp_from_micros, Backend, Filter, SwapRecord,
+};
+use crate::database::migrations::CURRENT_VERSION;
+use crate::database::{deserialize, serialize, StateTransition, Swap};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use libp2p::{Multiaddr, PeerId};
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::convert::TryFrom;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+/// Besides the stored swap, the tables keep its role, whether it is finished
+/// and its state as text, so swaps can be queried without decoding them.
+///
+/// Times are microseconds since the unix epoch.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS swaps (
+    swap_id TEXT PRIMARY KEY NOT NULL,
+    role TEXT NOT NULL,
+    finished INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    record BLOB NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS swaps_by_role ON swaps (role, finished);
+CREATE INDEX IF NOT EXISTS swaps_by_finished ON swaps (finished);
+
+CREATE TABLE IF NOT EXISTS state_transitions (
+    swap_id TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    record BLOB NOT NULL,
+    PRIMARY KEY (swap_id, timestamp)
+);
+
+CREATE TABLE IF NOT EXISTS peers (
+    swap_id TEXT PRIMARY KEY NOT NULL,
+    peer_id TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS addresses (
+    peer_id TEXT NOT NULL,
+    address TEXT NOT NULL,
+    PRIMARY KEY (peer_id, address)
+);
+
+CREATE TABLE IF NOT EXISTS encrypted_signatures (
+    swap_id TEXT PRIMARY KEY NOT NULL,
+    encrypted_signature BLOB NOT NULL
+);
+";
+
+/// Keeps the swaps in a SQLite database, with tables for the swaps, their
+/// state transitions, peers, addresses and encrypted signatures.
+///
+/// Writes run on the blocking thread pool of tokio, so that waiting for the
+/// disk does not stall other tasks. Reads are synchronous, like the rest of
+/// the [`Backend`] interface.
+pub struct Sqlite {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl Sqlite {
+    /// Opens the database, creating its tables if they do not exist yet.
+    pub fn open(path: &Path) -> Result<Self> {
+        tracing::debug!("Opening database at {}", path.display());
+
+        let connection = Connection::open(path)
+            .with_context(|| format!("Could not open the DB at {:?}", path))?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("Failed to create tables")?;
+
+        let version = connection
+            .query_row("SELECT version FROM schema_version", params![], |row| {
+                row.get::<_, u32>(0)
+            })
+            .optional()?;
+        match version {
+            None => {
+                connection.execute("INSERT INTO schema_version (version) VALUES (?1)", params![
+                    CURRENT_VERSION
+                ])?;
+            }
+            Some(version) if version != CURRENT_VERSION => bail!(
+                "The database is of schema version {}, but this release only supports version {}",
+                version,
+                CURRENT_VERSION
+            ),
+            Some(_) => {}
+        }
+
+        Ok(Sqlite {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        lock(&self.connection)
+    }
+
+    /// Runs `write` with the connection on a thread that is allowed to block.
+    async fn write<F, T>(&self, write: F) -> Result<T>
+    where
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let connection = self.connection.clone();
+
+        tokio::task::spawn_blocking(move || write(&mut lock(&connection)))
+            .await
+            .context("Database write was aborted")?
+    }
+}
+
+fn lock(connection: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    connection
+        .lock()
+        .expect("no other thread to panic while holding the connection")
+}
+
+#[async_trait]
+impl Backend for Sqlite {
+    async fn insert_state(&self, swap_id: Uuid, state: &Swap) -> Result<()> {
+        let record = encode_state(state).context("Could not serialize new state value")?;
+        let state = state.clone();
+
+        self.write(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let previous = transaction.query_row(
+                "SELECT MAX(timestamp) FROM state_transitions WHERE swap_id = ?1",
+                params![swap_id.to_string()],
+                |row| row.get::<_, Option<i64>>(0),
+            )?;
+            let previous = previous.map(from_sql_timestamp).transpose()?;
+            let timestamp = to_sql_timestamp(next_timestamp(previous)?)?;
+
+            upsert_swap(&transaction, swap_id, &state, &record, timestamp)?;
+            insert_transition(&transaction, swap_id, &state, &record, timestamp)?;
+
+            transaction.commit().context("Could not write in the DB")
+        })
+        .await
+    }
+
+    fn state(&self, swap_id: Uuid) -> Result<Option<Swap>> {
+        self.connection()
+            .query_row(
+                "SELECT record FROM swaps WHERE swap_id = ?1",
+                params![swap_id.to_string()],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .map(|record| decode_state(&record).context("Could not deserialize state"))
+            .transpose()
+    }
+
+    fn swaps(&self, filter: Filter) -> Result<Vec<(Uuid, Swap)>> {
+        let role = filter.role.map(|role| role.to_string());
+
+        let mut conditions = Vec::new();
+        let mut values: Vec<&dyn ToSql> = Vec::new();
+        if let Some(role) = role.as_ref() {
+            conditions.push("role = ?");
+            values.push(role);
+        }
+        if filter.unfinished_only {
+            conditions.push("finished = 0");
+        }
+
+        let mut query = "SELECT swap_id, record FROM swaps".to_owned();
+        if !conditions.is_empty() {
+            query.push_str(" WHERE ");
+            query.push_str(&conditions.join(" AND "));
+        }
+        query.push_str(" ORDER BY swap_id");
+
+        let connection = self.connection();
+        let mut statement = connection.prepare(&query)?;
+        let rows = statement.query_map(values.as_slice(), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+
+        rows.map(|row| {
+            let (swap_id, record) = row.context("Failed to retrieve swap from DB")?;
+            let swap = decode_state(&record).context("Failed to deserialize swap")?;
+
+            Ok((Uuid::from_str(&swap_id)?, swap))
+        })
+        .collect()
+    }
+
+    fn history(&self, swap_id: Uuid) -> Result<Vec<StateTransition>> {
+        stored_history(&self.connection(), swap_id)?
+            .into_iter()
+            .map(|(micros, record)| {
+                let state =
+                    decode_state(&record).context("Failed to deserialize state transition")?;
+
+                Ok(StateTransition {
+                    timestamp: timestamp_from_micros(micros),
+                    state,
+                })
+            })
+            .collect()
+    }
+
+    async fn insert_peer_id(&self, swap_id: Uuid, peer_id: PeerId) -> Result<()> {
+        self.write(move |connection| insert_peer_id(connection, swap_id, peer_id))
+            .await
+    }
+
+    fn peer_id(&self, swap_id: Uuid) -> Result<Option<PeerId>> {
+        peer_id(&self.connection(), swap_id)
+    }
+
+    async fn insert_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()> {
+        self.write(move |connection| {
+            connection.execute(
+                "INSERT OR IGNORE INTO addresses (peer_id, address) VALUES (?1, ?2)",
+                params![peer_id.to_string(), address.to_string()],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    fn addresses(&self, peer_id: PeerId) -> Result<Vec<Multiaddr>> {
+        let connection = self.connection();
+        let mut statement = connection
+            .prepare("SELECT address FROM addresses WHERE peer_id = ?1 ORDER BY rowid")?;
+        let rows =
+            statement.query_map(params![peer_id.to_string()], |row| row.get::<_, String>(0))?;
+
+        rows.map(|address| Ok(Multiaddr::from_str(&address?)?))
+            .collect()
+    }
+
+    fn all_addresses(&self) -> Result<Vec<(PeerId, Multiaddr)>> {
+        let connection = self.connection();
+        let mut statement =
+            connection.prepare("SELECT peer_id, address FROM addresses ORDER BY rowid")?;
+        let rows = statement.query_map(params![], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        rows.map(|row| {
+            let (peer_id, address) = row?;
+
+            Ok((PeerId::from_str(&peer_id)?, Multiaddr::from_str(&address)?))
+        })
+        .collect()
+    }
+
+    async fn insert_encrypted_signature(
+        &self,
+        swap_id: Uuid,
+        encrypted_signature: EncryptedSignature,
+    ) -> Result<()> {
+        self.write(move |connection| {
+            insert_encrypted_signature(connection, swap_id, &encrypted_signature)
+        })
+        .await
+    }
+
+    fn encrypted_signature(&self, swap_id: Uuid) -> Result<Option<EncryptedSignature>> {
+        encrypted_signature(&self.connection(), swap_id)
+    }
+
+    fn records(&self) -> Result<Vec<SwapRecord>> {
+        let connection = self.connection();
+        let mut statement =
+            connection.prepare("SELECT swap_id, record FROM swaps ORDER BY swap_id")?;
+        let rows = statement.query_map(params![], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+        })?;
+
+        rows.map(|row| {
+            let (swap_id, state) = row.context("Failed to retrieve swap from DB")?;
+            let swap_id = Uuid::from_str(&swap_id)?;
+
+            Ok(SwapRecord {
+                swap_id,
+                state,
+                history: stored_history(&connection, swap_id)?,
+                peer_id: peer_id(&connection, swap_id)?,
+                encrypted_signature: encrypted_signature(&connection, swap_id)?,
+            })
+        })
+        .collect()
+    }
+
+    async fn insert_record(&self, record: SwapRecord) -> Result<bool> {
+        self.write(move |connection| {
+            let swap_id = record.swap_id;
+
+            let transaction = connection.transaction()?;
+
+            let exists = transaction
+                .query_row(
+                    "SELECT 1 FROM swaps WHERE swap_id = ?1",
+                    params![swap_id.to_string()],
+                    |_| Ok(()),
+                )
+                .optional()?
+                .is_some();
+            if exists {
+                return Ok(false);
+            }
+
+            let mut updated_at = None;
+            for (micros, stored) in record.history.iter() {
+                let state =
+                    decode_state(stored).context("Failed to deserialize state transition")?;
+                let timestamp = to_sql_timestamp(*micros)?;
+
+                insert_transition(&transaction, swap_id, &state, stored, timestamp)?;
+                updated_at = Some(timestamp);
+            }
+            let updated_at = match updated_at {
+                Some(updated_at) => updated_at,
+                None => to_sql_timestamp(next_timestamp(None)?)?,
+            };
+
+            let state = decode_state(&record.state).context("Failed to deserialize swap")?;
+            upsert_swap(&transaction, swap_id, &state, &record.state, updated_at)?;
+
+            if let Some(peer_id) = record.peer_id {
+                insert_peer_id(&transaction, swap_id, peer_id)?;
+            }
+            if let Some(encrypted_signature) = record.encrypted_signature.as_ref() {
+                insert_encrypted_signature(&transaction, swap_id, encrypted_signature)?;
+            }
+
+            transaction
+                .commit()
+                .with_context(|| format!("Failed to insert swap {}", swap_id))?;
+
+            Ok(true)
+        })
+        .await
+    }
+}
+
+fn upsert_swap(
+    connection: &Connection,
+    swap_id: Uuid,
+    state: &Swap,
+    record: &[u8],
+    timestamp: i64,
+) -> Result<()> {
+    connection.execute(
+        "INSERT INTO swaps (swap_id, role, finished, state, record, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT (swap_id) DO UPDATE SET
+                role = excluded.role,
+                finished = excluded.finished,
+                state = excluded.state,
+                record = excluded.record,
+                updated_at = excluded.updated_at",
+        params![
+            swap_id.to_string(),
+            state.role().to_string(),
+            state.is_finished(),
+            state.to_string(),
+            record,
+            timestamp
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn insert_transition(
+    connection: &Connection,
+    swap_id: Uuid,
+    state: &Swap,
+    record: &[u8],
+    timestamp: i64,
+) -> Result<()> {
+    connection.execute(
+        "INSERT INTO state_transitions (swap_id, timestamp, state, record)
+            VALUES (?1, ?2, ?3, ?4)",
+        params![swap_id.to_string(), timestamp, state.to_string(), record],
+    )?;
+
+    Ok(())
+}
+
+fn stored_history(connection: &Connection, swap_id: Uuid) -> Result<Vec<(u64, Vec<u8>)>> {
+    let mut statement = connection.prepare(
+        "SELECT timestamp, record FROM state_transitions WHERE swap_id = ?1 ORDER BY timestamp",
+    )?;
+    let rows = statement.query_map(params![swap_id.to_string()], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+    })?;
+
+    rows.map(|row| {
+        let (timestamp, record) = row.context("Failed to retrieve state transition from DB")?;
+
+        Ok((from_sql_timestamp(timestamp)?, record))
+    })
+    .collect()
+}
+
+fn insert_peer_id(connection: &Connection, swap_id: Uuid, peer_id: PeerId) -> Result<()> {
+    connection.execute(
+        "INSERT OR REPLACE INTO peers (swap_id, peer_id) VALUES (?1, ?2)",
+        params![swap_id.to_string(), peer_id.to_string()],
+    )?;
+
+    Ok(())
+}
+
+fn peer_id(connection: &Connection, swap_id: Uuid) -> Result<Option<PeerId>> {
+    let peer_id = connection
+        .query_row(
+            "SELECT peer_id FROM peers WHERE swap_id = ?1",
+            params![swap_id.to_string()],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+
+    Ok(peer_id
+        .map(|peer_id| PeerId::from_str(&peer_id))
+        .transpose()?)
+}
+
+fn insert_encrypted_signature(
+    connection: &Connection,
+    swap_id: Uuid,
+    encrypted_signature: &EncryptedSignature,
+) -> Result<()> {
+    let encoded =
+        serialize(encrypted_signature).context("Could not serialize encrypted signature")?;
+
+    connection.execute(
+        "INSERT OR REPLACE INTO encrypted_signatures (swap_id, encrypted_signature)
+            VALUES (?1, ?2)",
+        params![swap_id.to_string(), encoded],
+    )?;
+
+    Ok(())
+}
+
+fn encrypted_signature(
+    connection: &Connection,
+    swap_id: Uuid,
+) -> Result<Option<EncryptedSignature>> {
+    connection
+        .query_row(
+            "SELECT encrypted_signature FROM encrypted_signatures WHERE swap_id = ?1",
+            params![swap_id.to_string()],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .optional()?
+        .map(|encoded| deserialize(&encoded).context("Could not deserialize encrypted signature"))
+        .transpose()
+}
+
+fn to_sql_timestamp(micros: u64) -> Result<i64> {
+    Ok(i64::try_from(micros)?)
+}
+
+fn from_sql_timestamp(timestamp: i64) -> Result<u64> {
+    Ok(u64::try_from(timestamp)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::alice::{Alice, AliceEndState};
+    use crate::database::backend::Role;
+    use crate::database::bob::{Bob, BobEndState};
+
+    #[tokio::test]
+    async fn queries_swaps_by_role_and_whether_they_are_finished() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let sqlite = Sqlite::open(&db_dir.path().join("swaps.sqlite"))?;
+
+        let alice_id = Uuid::new_v4();
+        let alice_swap = Swap::Alice(Alice::Done(AliceEndState::BtcPunished));
+        sqlite.insert_state(alice_id, &alice_swap).await?;
+
+        let bob_id = Uuid::new_v4();
+        let bob_swap = Swap::Bob(Bob::Started {
+            btc_amount: bitcoin::Amount::from_sat(100_000),
+        });
+        sqlite.insert_state(bob_id, &bob_swap).await?;
+
+        let alice_only = Filter {
+            role: Some(Role::Alice),
+            unfinished_only: false,
+        };
+        let unfinished_only = Filter {
+            role: None,
+            unfinished_only: true,
+        };
+        assert_eq!(sqlite.swaps(alice_only)?, vec![(alice_id, alice_swap)]);
+        assert_eq!(sqlite.swaps(unfinished_only)?, vec![(bob_id, bob_swap)]);
+        assert_eq!(sqlite.swaps(Filter::default())?.len(), 2);
+
+        let finished_bob_swap = Swap::Bob(Bob::Done(BobEndState::SafelyAborted));
+        sqlite.insert_state(bob_id, &finished_bob_swap).await?;
+
+        assert!(sqlite.swaps(unfinished_only)?.is_empty());
+        assert_eq!(sqlite.history(bob_id)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn remembers_each_address_of_a_peer_once() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let sqlite = Sqlite::open(&db_dir.path().join("swaps.sqlite"))?;
+
+        let peer_id = PeerId::random();
+        let first = Multiaddr::from_str("/ip4/127.0.0.1/tcp/9939")?;
+        let second = Multiaddr::from_str("/dns4/example.com/tcp/9939")?;
+        sqlite.insert_address(peer_id, first.clone()).await?;
+        sqlite.insert_address(peer_id, second.clone()).await?;
+        sqlite.insert_address(peer_id, first.clone()).await?;
+
+        assert_eq!(sqlite.addresses(peer_id)?, vec![first, second]);
+        assert!(sqlite.addresses(PeerId::random())?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_database_of_newer_schema_version() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let path = db_dir.path().join("swaps.sqlite");
+
+        drop(Sqlite::open(&path)?);
+        Connection::open(&path)?.execute("UPDATE schema_version SET version = ?1", params![
+            CURRENT_VERSION + 1
+        ])?;
+
+        assert!(Sqlite::open(&path).is_err());
+
+        Ok(())
+    }
+}
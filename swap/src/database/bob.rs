@@ -1,3 +1,4 @@
+use crate::monero;
 use crate::monero::TransferProof;
 use crate::protocol::bob;
 use crate::protocol::bob::BobState;
@@ -104,6 +105,72 @@ impl From<Bob> for BobState {
     }
 }
 
+impl Bob {
+    /// The transactions and transfer proofs known in this state, as label and
+    /// value.
+    pub fn details(&self) -> Vec<(&'static str, String)> {
+        let tx_lock_id = |txid: bitcoin::Txid| vec![("BTC lock txid", txid.to_string())];
+
+        match self {
+            Bob::Started { .. } | Bob::Done(BobEndState::SafelyAborted) => Vec::new(),
+            Bob::ExecutionSetupDone { state2 } => tx_lock_id(state2.tx_lock_id()),
+            Bob::BtcLocked { state3 } => tx_lock_id(state3.tx_lock_id()),
+            Bob::XmrLockProofReceived {
+                state,
+                lock_transfer_proof,
+                ..
+            } => {
+                let mut details = tx_lock_id(state.tx_lock_id());
+                details.push((
+                    "XMR lock tx hash",
+                    lock_transfer_proof.tx_hash().to_string(),
+                ));
+                details.push(("XMR lock tx key", lock_transfer_proof.tx_key().to_string()));
+                details
+            }
+            Bob::XmrLocked { state4 } | Bob::EncSigSent { state4 } => {
+                tx_lock_id(state4.tx_lock.txid())
+            }
+            Bob::BtcRedeemed(state5) => tx_lock_id(state5.tx_lock_id()),
+            Bob::CancelTimelockExpired(state6) | Bob::BtcCancelled(state6) => {
+                let mut details = tx_lock_id(state6.tx_lock_id());
+                details.push(("BTC cancel txid", state6.tx_cancel_id().to_string()));
+                details
+            }
+            Bob::Done(BobEndState::BtcRefunded(state6)) => {
+                let mut details = tx_lock_id(state6.tx_lock_id());
+                details.push(("BTC cancel txid", state6.tx_cancel_id().to_string()));
+                details.push(("BTC refund txid", state6.tx_refund_id().to_string()));
+                details
+            }
+            Bob::Done(BobEndState::XmrRedeemed { tx_lock_id: txid })
+            | Bob::Done(BobEndState::BtcPunished { tx_lock_id: txid }) => tx_lock_id(*txid),
+        }
+    }
+
+    /// The BTC and XMR swapped, as far as this state knows them.
+    pub fn amounts(&self) -> (Option<bitcoin::Amount>, Option<monero::Amount>) {
+        match self {
+            Bob::Started { btc_amount } => (Some(*btc_amount), None),
+            Bob::ExecutionSetupDone { state2 } => {
+                (Some(state2.tx_lock_amount()), Some(state2.xmr()))
+            }
+            Bob::BtcLocked { state3 } | Bob::XmrLockProofReceived { state: state3, .. } => {
+                (Some(state3.tx_lock.lock_amount()), Some(state3.xmr()))
+            }
+            Bob::XmrLocked { state4 } | Bob::EncSigSent { state4 } => {
+                (Some(state4.tx_lock.lock_amount()), None)
+            }
+            Bob::BtcRedeemed(state5) => (Some(state5.tx_lock_amount()), None),
+            Bob::CancelTimelockExpired(state6) | Bob::BtcCancelled(state6) => {
+                (Some(state6.tx_lock_amount()), None)
+            }
+            Bob::Done(BobEndState::BtcRefunded(state6)) => (Some(state6.tx_lock_amount()), None),
+            Bob::Done(_) => (None, None),
+        }
+    }
+}
+
 impl Display for Bob {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -0,0 +1,255 @@
+use crate::database::{deserialize, serialize};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use sled::transaction::ConflictableTransactionError;
+use sled::Transactional;
+use std::collections::BTreeMap;
+
+/// The schema version of the swaps written by this release.
+pub const CURRENT_VERSION: u32 = 1;
+
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+type Migration = fn(Value) -> Result<Value>;
+
+/// `MIGRATIONS[n]` upgrades a swap of schema version `n` to version `n + 1`.
+///
+/// Swaps are migrated as CBOR values, because the types they were written
+/// with may not exist anymore. A migration only changes the swap, the
+/// envelope is taken care of.
+const MIGRATIONS: &[Migration] = &[unversioned];
+
+/// A record tagged with the schema version it was written with.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Versioned<T> {
+    pub version: u32,
+    pub record: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn current(record: T) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            record,
+        }
+    }
+
+    pub fn into_current(self) -> Result<T> {
+        if self.version != CURRENT_VERSION {
+            bail!(
+                "Expected a record of schema version {}, got version {}",
+                CURRENT_VERSION,
+                self.version
+            )
+        }
+
+        Ok(self.record)
+    }
+}
+
+/// Version 0 stored the swap without an envelope, which does not change the
+/// swap itself.
+fn unversioned(swap: Value) -> Result<Value> {
+    Ok(swap)
+}
+
+/// Upgrades the swaps and their history to the current schema version in one
+/// transaction.
+///
+/// A database without a schema version is of version 0, unless it is empty.
+pub fn run(swaps: &sled::Tree, history: &sled::Tree, meta: &sled::Tree) -> Result<()> {
+    let version = match meta.get(SCHEMA_VERSION_KEY)? {
+        Some(version) => deserialize::<u32>(&version).context("Invalid schema version")?,
+        None if swaps.is_empty() => CURRENT_VERSION,
+        None => 0,
+    };
+
+    if version > CURRENT_VERSION {
+        bail!(
+            "The database is of schema version {}, but this release only supports up to version {}",
+            version,
+            CURRENT_VERSION
+        )
+    }
+
+    let (upgraded_swaps, upgraded_history) = if version < CURRENT_VERSION {
+        tracing::info!(
+            from = version,
+            to = CURRENT_VERSION,
+            "Migrating database to new schema version"
+        );
+
+        (
+            upgrade_tree(swaps, version)?,
+            upgrade_tree(history, version)?,
+        )
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    let current_version = serialize(&CURRENT_VERSION)?;
+
+    (swaps, history, meta)
+        .transaction(|(swaps, history, meta)| {
+            for (key, value) in upgraded_swaps.iter() {
+                swaps.insert(key, value.as_slice())?;
+            }
+            for (key, value) in upgraded_history.iter() {
+                history.insert(key, value.as_slice())?;
+            }
+            meta.insert(SCHEMA_VERSION_KEY, current_version.as_slice())?;
+
+            Ok::<_, ConflictableTransactionError<()>>(())
+        })
+        .map_err(|e| anyhow!("Failed to migrate database: {:?}", e))?;
+
+    Ok(())
+}
+
+fn upgrade_tree(tree: &sled::Tree, version: u32) -> Result<Vec<(sled::IVec, Vec<u8>)>> {
+    tree.iter()
+        .map(|item| {
+            let (key, value) = item?;
+            let upgraded =
+                upgrade(deserialize(&value)?, version).context("Failed to migrate swap")?;
+
+            Ok((key, serialize(&upgraded)?))
+        })
+        .collect()
+}
+
+/// Upgrades a swap that was exported from a database, which is always in an
+/// envelope, to the current schema version.
+pub fn upgrade_record(stored: &[u8]) -> Result<Vec<u8>> {
+    let stored = deserialize::<Value>(stored)?;
+    let version = serde_cbor::value::from_value::<Versioned<Value>>(stored.clone())?.version;
+
+    if version > CURRENT_VERSION {
+        bail!(
+            "The swap is of schema version {}, but this release only supports up to version {}",
+            version,
+            CURRENT_VERSION
+        )
+    }
+
+    serialize(&upgrade(stored, version)?)
+}
+
+/// Upgrades a stored swap of schema version `version` to a current envelope.
+pub fn upgrade(stored: Value, version: u32) -> Result<Value> {
+    let mut swap = if version == 0 {
+        stored
+    } else {
+        serde_cbor::value::from_value::<Versioned<Value>>(stored)?.record
+    };
+
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        swap = migration(swap)?;
+    }
+
+    let mut envelope = BTreeMap::new();
+    envelope.insert(
+        Value::Text("version".to_owned()),
+        Value::Integer(CURRENT_VERSION.into()),
+    );
+    envelope.insert(Value::Text("record".to_owned()), swap);
+
+    Ok(Value::Map(envelope))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::bob::BobEndState;
+    use crate::database::{Alice, AliceEndState, Bob, Database, Swap};
+    use ::bitcoin::hashes::hex::FromHex;
+    use uuid::Uuid;
+
+    const V0_SWAPS: &str = include_str!("fixtures/v0_swaps.txt");
+
+    fn v0_swaps() -> Vec<(String, Vec<u8>)> {
+        V0_SWAPS
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let (name, hex) = line.split_once(' ').unwrap();
+                (name.to_owned(), Vec::<u8>::from_hex(hex).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn there_is_a_migration_to_every_version() {
+        assert_eq!(MIGRATIONS.len(), CURRENT_VERSION as usize);
+    }
+
+    #[test]
+    fn migrates_database_written_before_schema_versioning() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let mut swap_ids = Vec::new();
+        {
+            let db = sled::open(db_dir.path()).unwrap();
+            let swaps = db.open_tree("swaps").unwrap();
+            for (name, value) in v0_swaps() {
+                let swap_id = Uuid::new_v4();
+                swaps.insert(serialize(&swap_id).unwrap(), value).unwrap();
+                swap_ids.push((name, swap_id));
+            }
+            db.flush().unwrap();
+        }
+
+        let db = Database::open(db_dir.path()).unwrap();
+
+        for (name, swap_id) in swap_ids {
+            let expected = match name.as_str() {
+                "bob_started" => Swap::Bob(Bob::Started {
+                    btc_amount: bitcoin::Amount::from_sat(100_000),
+                }),
+                "alice_btc_punished" => Swap::Alice(Alice::Done(AliceEndState::BtcPunished)),
+                "bob_safely_aborted" => Swap::Bob(Bob::Done(BobEndState::SafelyAborted)),
+                other => panic!("unknown fixture {}", other),
+            };
+
+            assert_eq!(db.get_state(swap_id).unwrap(), expected);
+        }
+        assert_eq!(db.all_swaps().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn migration_is_not_repeated_on_reopen() {
+        let db_dir = tempfile::tempdir().unwrap();
+        let swap_id = Uuid::new_v4();
+        {
+            let db = sled::open(db_dir.path()).unwrap();
+            let (_, value) = v0_swaps().remove(0);
+            db.open_tree("swaps")
+                .unwrap()
+                .insert(serialize(&swap_id).unwrap(), value)
+                .unwrap();
+            db.flush().unwrap();
+        }
+
+        drop(Database::open(db_dir.path()).unwrap());
+        let db = Database::open(db_dir.path()).unwrap();
+
+        assert!(db.get_state(swap_id).is_ok());
+    }
+
+    #[test]
+    fn refuses_database_of_newer_schema_version() {
+        let db_dir = tempfile::tempdir().unwrap();
+        {
+            let db = sled::open(db_dir.path()).unwrap();
+            db.open_tree("meta")
+                .unwrap()
+                .insert(
+                    SCHEMA_VERSION_KEY,
+                    serialize(&(CURRENT_VERSION + 1)).unwrap(),
+                )
+                .unwrap();
+            db.flush().unwrap();
+        }
+
+        assert!(Database::open(db_dir.path()).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::database::bob::BobEndState;
+use crate::database::{Alice, AliceEndState, Bob, Database, StateTransition, Swap};
+use crate::{bitcoin, monero};
+use anyhow::Result;
+use libp2p::PeerId;
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
+use time::{Date, Format, OffsetDateTime};
+use uuid::Uuid;
+
+/// What became of a swap.
+#[derive(Clone, Copy, Debug, PartialEq, strum::Display, strum::EnumString)]
+pub enum StateCategory {
+    #[strum(serialize = "active")]
+    Active,
+    #[strum(serialize = "completed")]
+    Completed,
+    #[strum(serialize = "refunded")]
+    Refunded,
+    #[strum(serialize = "punished")]
+    Punished,
+    /// Ended before any funds were locked.
+    #[strum(serialize = "aborted")]
+    Aborted,
+}
+
+impl Swap {
+    pub fn category(&self) -> StateCategory {
+        match self {
+            Swap::Alice(Alice::Done(AliceEndState::BtcRedeemed))
+            | Swap::Bob(Bob::Done(BobEndState::XmrRedeemed { .. })) => StateCategory::Completed,
+            Swap::Alice(Alice::Done(AliceEndState::XmrRefunded))
+            | Swap::Bob(Bob::Done(BobEndState::BtcRefunded(_))) => StateCategory::Refunded,
+            Swap::Alice(Alice::Done(AliceEndState::BtcPunished))
+            | Swap::Bob(Bob::Done(BobEndState::BtcPunished { .. })) => StateCategory::Punished,
+            Swap::Alice(Alice::Done(AliceEndState::SafelyAborted))
+            | Swap::Bob(Bob::Done(BobEndState::SafelyAborted)) => StateCategory::Aborted,
+            Swap::Alice(_) | Swap::Bob(_) => StateCategory::Active,
+        }
+    }
+
+    fn amounts(&self) -> (Option<bitcoin::Amount>, Option<monero::Amount>) {
+        match self {
+            Swap::Alice(alice) => alice.amounts(),
+            Swap::Bob(bob) => bob.amounts(),
+        }
+    }
+}
+
+/// Everything known about a swap, gathered from all states it was in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapSummary {
+    pub swap_id: Uuid,
+    pub state: Swap,
+    pub btc_amount: Option<bitcoin::Amount>,
+    pub xmr_amount: Option<monero::Amount>,
+    pub peer_id: Option<PeerId>,
+    /// Unknown for swaps started before the history was recorded.
+    pub started_at: Option<OffsetDateTime>,
+    pub updated_at: Option<OffsetDateTime>,
+    /// The transactions and transfer proofs of all states, as label and value.
+    pub details: Vec<(&'static str, String)>,
+}
+
+impl SwapSummary {
+    pub fn category(&self) -> StateCategory {
+        self.state.category()
+    }
+
+    /// The price of one XMR, if both amounts are known.
+    pub fn rate(&self) -> Option<bitcoin::Amount> {
+        let btc = u128::from(self.btc_amount?.as_sat());
+        let xmr = u128::from(self.xmr_amount?.as_piconero());
+        if xmr == 0 {
+            return None;
+        }
+
+        let sats_per_xmr = btc * 1_000_000_000_000 / xmr;
+
+        u64::try_from(sats_per_xmr)
+            .ok()
+            .map(bitcoin::Amount::from_sat)
+    }
+
+    /// Amounts are in satoshi and piconero, times in RFC 3339.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "swap_id": self.swap_id,
+            "role": self.state.role().to_string(),
+            "state": self.state.to_string(),
+            "category": self.category().to_string(),
+            "btc_amount_sat": self.btc_amount.map(|amount| amount.as_sat()),
+            "xmr_amount_piconero": self.xmr_amount.map(|amount| amount.as_piconero()),
+            "rate_sat_per_xmr": self.rate().map(|rate| rate.as_sat()),
+            "peer_id": self.peer_id.map(|peer_id| peer_id.to_string()),
+            "started_at": self.started_at.map(|time| time.format(Format::Rfc3339)),
+            "updated_at": self.updated_at.map(|time| time.format(Format::Rfc3339)),
+            "transactions": details_json(&self.details),
+        })
+    }
+}
+
+impl StateTransition {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "timestamp": self.timestamp.format(Format::Rfc3339),
+            "state": self.state.to_string(),
+            "transactions": details_json(&self.state.details()),
+        })
+    }
+}
+
+/// Labels become snake case keys, e.g. `btc_lock_txid`.
+fn details_json(details: &[(&'static str, String)]) -> Map<String, Value> {
+    details
+        .iter()
+        .map(|(label, value)| (label.to_lowercase().replace(' ', "_"), json!(value)))
+        .collect()
+}
+
+/// Which swaps to summarize, all of them by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HistoryFilter {
+    pub category: Option<StateCategory>,
+    /// Only swaps started on or after this day (UTC).
+    pub from: Option<Date>,
+    /// Only swaps started on or before this day (UTC).
+    pub to: Option<Date>,
+}
+
+impl HistoryFilter {
+    /// Swaps whose start is unknown never match a date range.
+    fn matches(&self, summary: &SwapSummary) -> bool {
+        if let Some(category) = self.category {
+            if summary.category() != category {
+                return false;
+            }
+        }
+
+        if self.from.is_none() && self.to.is_none() {
+            return true;
+        }
+        let started_on = match summary.started_at {
+            Some(started_at) => started_at.date(),
+            None => return false,
+        };
+
+        self.from.map_or(true, |from| started_on >= from)
+            && self.to.map_or(true, |to| started_on <= to)
+    }
+}
+
+/// Parses a day given as `YYYY-MM-DD`.
+pub fn parse_date(date: &str) -> Result<Date> {
+    Ok(Date::parse(date, "%F")?)
+}
+
+impl Database {
+    pub fn summary(&self, swap_id: Uuid) -> Result<SwapSummary> {
+        let state = self.get_state(swap_id)?;
+        let history = self.history(swap_id)?;
+
+        // Later states forget what earlier states knew, e.g. the amounts
+        let mut states = history
+            .iter()
+            .map(|transition| &transition.state)
+            .collect::<Vec<_>>();
+        states.push(&state);
+
+        let mut btc_amount = None;
+        let mut xmr_amount = None;
+        let mut details: Vec<(&'static str, String)> = Vec::new();
+        for state in states {
+            let (btc, xmr) = state.amounts();
+            btc_amount = btc_amount.or(btc);
+            xmr_amount = xmr_amount.or(xmr);
+
+            for (label, value) in state.details() {
+                if !details.iter().any(|(known, _)| *known == label) {
+                    details.push((label, value));
+                }
+            }
+        }
+
+        Ok(SwapSummary {
+            swap_id,
+            btc_amount,
+            xmr_amount,
+            peer_id: self.backend.peer_id(swap_id)?,
+            started_at: history.first().map(|transition| transition.timestamp),
+            updated_at: history.last().map(|transition| transition.timestamp),
+            details,
+            state,
+        })
+    }
+
+    /// Summarizes the swaps that match `filter`, the most recently started
+    /// first.
+    pub fn summaries(&self, filter: HistoryFilter) -> Result<Vec<SwapSummary>> {
+        let mut summaries = Vec::new();
+        for (swap_id, _) in self.all_swaps()? {
+            let summary = self.summary(swap_id)?;
+
+            if filter.matches(&summary) {
+                summaries.push(summary);
+            }
+        }
+
+        summaries.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+
+        Ok(summaries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::hashes::Hash;
+
+    #[tokio::test]
+    async fn summary_keeps_what_earlier_states_knew() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path())?;
+
+        let swap_id = Uuid::new_v4();
+        let btc_amount = bitcoin::Amount::from_sat(100_000);
+        let tx_lock_id = ::bitcoin::Txid::from_inner([1u8; 32]);
+        db.insert_latest_state(swap_id, Swap::Bob(Bob::Started { btc_amount }))
+            .await?;
+        db.insert_latest_state(
+            swap_id,
+            Swap::Bob(Bob::Done(BobEndState::XmrRedeemed { tx_lock_id })),
+        )
+        .await?;
+        let peer_id = PeerId::random();
+        db.insert_peer_id(swap_id, peer_id).await?;
+
+        let summary = db.summary(swap_id)?;
+
+        assert_eq!(summary.btc_amount, Some(btc_amount));
+        assert_eq!(summary.xmr_amount, None);
+        assert_eq!(summary.rate(), None);
+        assert_eq!(summary.peer_id, Some(peer_id));
+        assert_eq!(summary.category(), StateCategory::Completed);
+        assert_eq!(summary.details, vec![(
+            "BTC lock txid",
+            tx_lock_id.to_string()
+        )]);
+        assert!(summary.started_at < summary.updated_at);
+        assert_eq!(summary.to_json()["btc_amount_sat"], json!(100_000));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn summaries_are_filtered_by_category_and_date() -> Result<()> {
+        let db_dir = tempfile::tempdir().unwrap();
+        let db = Database::open(db_dir.path())?;
+
+        let active_swap_id = Uuid::new_v4();
+        db.insert_latest_state(
+            active_swap_id,
+            Swap::Bob(Bob::Started {
+                btc_amount: bitcoin::Amount::from_sat(100_000),
+            }),
+        )
+        .await?;
+        let punished = Swap::Alice(Alice::Done(AliceEndState::BtcPunished));
+        db.insert_latest_state(Uuid::new_v4(), punished).await?;
+
+        let active_only = HistoryFilter {
+            category: Some(StateCategory::Active),
+            ..HistoryFilter::default()
+        };
+        let active = db.summaries(active_only)?;
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].swap_id, active_swap_id);
+
+        let today = OffsetDateTime::now_utc().date();
+        let since_today = HistoryFilter {
+            from: Some(today),
+            ..HistoryFilter::default()
+        };
+        let until_yesterday = HistoryFilter {
+            to: Some(today.previous_day()),
+            ..HistoryFilter::default()
+        };
+        assert_eq!(db.summaries(since_today)?.len(), 2);
+        assert!(db.summaries(until_yesterday)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parses_dates() {
+        assert_eq!(
+            parse_date("2021-06-01").unwrap(),
+            Date::try_from_ymd(2021, 6, 1).unwrap()
+        );
+        assert!(parse_date("01.06.2021").is_err());
+    }
+}
@@ -22,10 +22,11 @@ pub mod cli;
 pub mod database;
 pub mod env;
 pub mod fs;
-pub mod kraken;
 pub mod monero;
 pub mod network;
+pub mod price_feed;
 pub mod protocol;
+pub mod rpc;
 pub mod seed;
 pub mod tor;
 
@@ -4,7 +4,7 @@ mod wallet_rpc;
 pub use ::monero::network::Network;
 pub use ::monero::{Address, PrivateKey, PublicKey};
 pub use curve25519_dalek::scalar::Scalar;
-pub use wallet::Wallet;
+pub use wallet::{Split, Wallet};
 pub use wallet_rpc::{WalletRpc, WalletRpcProcess};
 
 use crate::bitcoin;
@@ -274,6 +274,38 @@ pub mod monero_amount {
 
         Ok(amount)
     }
+
+    /// Serializes an [`Amount`] as a floating point number of XMR, for use in
+    /// human-readable formats such as config files.
+    pub mod as_xmr {
+        use crate::monero::{Amount, PICONERO_OFFSET};
+        use rust_decimal::prelude::ToPrimitive;
+        use rust_decimal::Decimal;
+        use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S>(x: &Amount, s: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let xmr = (x.as_piconero_decimal() / Decimal::from(PICONERO_OFFSET))
+                .to_f64()
+                .ok_or_else(|| ser::Error::custom("Monero amount does not fit into f64"))?;
+
+            s.serialize_f64(xmr)
+        }
+
+        pub fn deserialize<'de, D>(
+            deserializer: D,
+        ) -> Result<Amount, <D as Deserializer<'de>>::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let xmr = f64::deserialize(deserializer)?;
+            let amount = Amount::from_monero(xmr).map_err(de::Error::custom)?;
+
+            Ok(amount)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -357,6 +389,18 @@ mod tests {
         assert_eq!(key, decoded);
     }
 
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    pub struct MoneroAmountAsXmr(#[serde(with = "monero_amount::as_xmr")] crate::monero::Amount);
+
+    #[test]
+    fn serde_monero_amount_as_xmr() {
+        let amount = MoneroAmountAsXmr(crate::monero::Amount::from_monero(0.25).unwrap());
+        let encoded = serde_json::to_string(&amount).unwrap();
+        assert_eq!(encoded, "0.25");
+        let decoded: MoneroAmountAsXmr = serde_json::from_str(&encoded).unwrap();
+        assert_eq!(amount, decoded);
+    }
+
     #[test]
     fn serde_monero_amount() {
         let amount = MoneroAmount(crate::monero::Amount::from_piconero(1000));
@@ -3,9 +3,12 @@ use crate::monero::{
     Amount, InsufficientFunds, PrivateViewKey, PublicViewKey, TransferProof, TxHash,
 };
 use ::monero::{Address, Network, PrivateKey, PublicKey};
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use monero_rpc::wallet;
-use monero_rpc::wallet::{BlockHeight, CheckTxKey, MoneroWalletRpc as _, Refreshed};
+use monero_rpc::wallet::{BlockHeight, CheckTxKey, Destination, MoneroWalletRpc as _, Refreshed};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::fmt;
 use std::future::Future;
 use std::str::FromStr;
 use std::time::Duration;
@@ -19,6 +22,7 @@ pub struct Wallet {
     network: Network,
     name: String,
     main_address: monero::Address,
+    sweep_address: monero::Address,
     sync_interval: Duration,
 }
 
@@ -50,10 +54,18 @@ impl Wallet {
             network: env_config.monero_network,
             name,
             main_address,
+            sweep_address: main_address,
             sync_interval: env_config.monero_sync_interval(),
         })
     }
 
+    /// Sweep the funds of wallets generated in [`Wallet::create_from`] to
+    /// `address` instead of the main address of this wallet.
+    pub fn sweep_generated_wallets_to(mut self, address: Address) -> Self {
+        self.sweep_address = address;
+        self
+    }
+
     /// Re-open the wallet using the internally stored name.
     pub async fn re_open(&self) -> Result<()> {
         self.inner
@@ -110,8 +122,9 @@ impl Wallet {
 
     /// Close the wallet and open (load) another wallet by generating it from
     /// keys. The generated wallet will be opened, all funds sweeped to the
-    /// main_address and then the wallet will be re-loaded using the internally
-    /// stored name.
+    /// main_address (or the address set with
+    /// [`Wallet::sweep_generated_wallets_to`]) and then the wallet will be
+    /// re-loaded using the internally stored name.
     pub async fn create_from(
         &self,
         file_name: String,
@@ -145,18 +158,18 @@ impl Wallet {
 
         // Try to send all the funds from the generated wallet to the default wallet
         match wallet.refresh().await {
-            Ok(_) => match wallet.sweep_all(self.main_address.to_string()).await {
+            Ok(_) => match wallet.sweep_all(self.sweep_address.to_string()).await {
                 Ok(sweep_all) => {
                     for tx in sweep_all.tx_hash_list {
                         tracing::info!(
                             %tx,
-                            monero_address = %self.main_address,
+                            monero_address = %self.sweep_address,
                             "Monero transferred back to default wallet");
                     }
                 }
                 Err(error) => {
                     tracing::warn!(
-                        address = %self.main_address,
+                        address = %self.sweep_address,
                         "Transferring Monero back to default wallet failed. Error {:#}", error
                     );
                 }
@@ -258,6 +271,43 @@ impl Wallet {
         Ok(tx_hashes)
     }
 
+    /// Sends the unlocked balance to `splits` according to their percentage
+    /// and the rest to `address` in a single transaction.
+    ///
+    /// Without splits this is the same as [`Wallet::sweep_all`]. Because the
+    /// fee is only known after building the transaction, a fee estimate per
+    /// destination is held back and stays in this wallet.
+    pub async fn sweep_all_split(&self, address: Address, splits: &[Split]) -> Result<Vec<TxHash>> {
+        if splits.is_empty() {
+            return self.sweep_all(address).await;
+        }
+
+        let wallet = self.inner.lock().await;
+
+        let unlocked = wallet.get_balance(0).await?.unlocked_balance;
+        let fee_reserve = self.static_tx_fee_estimate().as_piconero() * (splits.len() as u64 + 1);
+        let amount = match unlocked.checked_sub(fee_reserve) {
+            Some(amount) if amount > 0 => Amount::from_piconero(amount),
+            _ => bail!(
+                "Unlocked balance of {} does not cover the fees of splitting it",
+                Amount::from_piconero(unlocked)
+            ),
+        };
+
+        let destinations = split_amounts(amount, address, splits)
+            .into_iter()
+            .filter(|(_, amount)| *amount > Amount::ZERO)
+            .map(|(address, amount)| Destination {
+                amount: amount.as_piconero(),
+                address: address.to_string(),
+            })
+            .collect();
+
+        let transfer = wallet.transfer(0, destinations, true).await?;
+
+        Ok(vec![TxHash(transfer.tx_hash)])
+    }
+
     /// Get the balance of the primary account.
     pub async fn get_balance(&self) -> Result<Amount> {
         let amount = self.inner.lock().await.get_balance(0).await?.balance;
@@ -265,6 +315,20 @@ impl Wallet {
         Ok(Amount::from_piconero(amount))
     }
 
+    /// Get the unlocked balance of the primary account, i.e. the funds that
+    /// can be spent right now.
+    pub async fn get_unlocked_balance(&self) -> Result<Amount> {
+        let amount = self
+            .inner
+            .lock()
+            .await
+            .get_balance(0)
+            .await?
+            .unlocked_balance;
+
+        Ok(Amount::from_piconero(amount))
+    }
+
     pub async fn block_height(&self) -> Result<BlockHeight> {
         Ok(self.inner.lock().await.get_height().await?)
     }
@@ -283,6 +347,68 @@ impl Wallet {
     }
 }
 
+/// A share of the XMR received in a swap that goes to `address`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Split {
+    pub address: Address,
+    pub percent: Decimal,
+}
+
+impl FromStr for Split {
+    type Err = anyhow::Error;
+
+    /// Parses `ADDRESS:PERCENT`, e.g. `4A...:30` or `4A...:12.5`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, percent) = match s.rsplit_once(':') {
+            Some(parts) => parts,
+            None => bail!("Expected ADDRESS:PERCENT but got {}", s),
+        };
+
+        let address = Address::from_str(address)
+            .with_context(|| format!("Failed to parse {} as a monero address", address))?;
+        let percent = Decimal::from_str(percent)
+            .with_context(|| format!("Failed to parse {} as a percentage", percent))?;
+
+        Ok(Self { address, percent })
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.address, self.percent)
+    }
+}
+
+/// Divides `amount` among `splits`, rounding each share down, and gives what
+/// is left to `address`.
+fn split_amounts(amount: Amount, address: Address, splits: &[Split]) -> Vec<(Address, Amount)> {
+    let total = amount.as_piconero_decimal();
+
+    let mut amounts = splits
+        .iter()
+        .map(|split| {
+            let share = (total * split.percent / Decimal::from(100))
+                .floor()
+                .to_u64()
+                .unwrap_or(0)
+                .min(amount.as_piconero());
+
+            (split.address, Amount::from_piconero(share))
+        })
+        .collect::<Vec<_>>();
+
+    let distributed = amounts
+        .iter()
+        .map(|(_, share)| share.as_piconero())
+        .sum::<u64>();
+    amounts.push((
+        address,
+        Amount::from_piconero(amount.as_piconero().saturating_sub(distributed)),
+    ));
+
+    amounts
+}
+
 #[derive(Debug)]
 pub struct TransferRequest {
     pub public_spend_key: PublicKey,
@@ -351,13 +477,95 @@ where
     Ok(())
 }
 
+#[cfg(test)]
+impl Wallet {
+    /// Creates a wallet that is not connected to a wallet RPC, for tests of
+    /// code that holds a wallet without using it.
+    pub fn new_offline(env_config: Config) -> Self {
+        let key = PrivateKey::from_scalar(crate::monero::Scalar::one());
+        let public_key = PublicKey::from_private_key(&key);
+        let main_address = Address::standard(env_config.monero_network, public_key, public_key);
+
+        Self {
+            inner: Mutex::new(
+                wallet::Client::localhost(0).expect("localhost to be a valid wallet RPC url"),
+            ),
+            network: env_config.monero_network,
+            name: String::from("offline"),
+            main_address,
+            sweep_address: main_address,
+            sync_interval: env_config.monero_sync_interval(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use monero_rpc::wallet::CheckTxKey;
+    use rust_decimal_macros::dec;
     use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
     use std::sync::Arc;
 
+    const SAVINGS: &str = "53gEuGZUhP9JMEBZoGaFNzhwEgiG7hwQdMCqFxiyiTeFPmkbt1mAoNybEUvYBKHcnrSgxnVWgZsTvRBaHBNXPa8tHiCU51a";
+    const SPENDING: &str = "44Ato7HveWidJYUAVw5QffEcEtSH1DwzSP3FPPkHxNAS4LX9CqgucphTisH978FLHE34YNEx7FcbBfQLQUU8m3NUC4VqsRa";
+
+    #[test]
+    fn parses_split() {
+        let split = Split::from_str(&format!("{}:12.5", SAVINGS)).unwrap();
+
+        assert_eq!(split, Split {
+            address: Address::from_str(SAVINGS).unwrap(),
+            percent: dec!(12.5),
+        });
+        assert_eq!(split.to_string(), format!("{}:12.5", SAVINGS));
+    }
+
+    #[test]
+    fn rejects_split_without_percentage() {
+        assert!(Split::from_str(SAVINGS).is_err());
+        assert!(Split::from_str(&format!("{}:abc", SAVINGS)).is_err());
+    }
+
+    #[test]
+    fn splits_amount_by_percentage_and_sends_rest_to_address() {
+        let savings = Address::from_str(SAVINGS).unwrap();
+        let spending = Address::from_str(SPENDING).unwrap();
+
+        let amounts = split_amounts(Amount::from_piconero(1_000), spending, &[Split {
+            address: savings,
+            percent: dec!(70),
+        }]);
+
+        assert_eq!(amounts, vec![
+            (savings, Amount::from_piconero(700)),
+            (spending, Amount::from_piconero(300))
+        ]);
+    }
+
+    #[test]
+    fn rounds_shares_down_and_leaves_remainder_to_address() {
+        let savings = Address::from_str(SAVINGS).unwrap();
+        let spending = Address::from_str(SPENDING).unwrap();
+
+        let amounts = split_amounts(Amount::from_piconero(10), spending, &[
+            Split {
+                address: savings,
+                percent: dec!(33.3),
+            },
+            Split {
+                address: savings,
+                percent: dec!(33.3),
+            },
+        ]);
+
+        assert_eq!(amounts, vec![
+            (savings, Amount::from_piconero(3)),
+            (savings, Amount::from_piconero(3)),
+            (spending, Amount::from_piconero(4))
+        ]);
+    }
+
     #[tokio::test]
     async fn given_exact_confirmations_does_not_fetch_tx_again() {
         let requests = Arc::new(AtomicU32::new(0));
@@ -1,5 +1,6 @@
 mod impl_from_rr_event;
 
+pub mod ask_spot_price;
 pub mod cbor_request_response;
 pub mod encrypted_signature;
 pub mod json_pull_codec;
@@ -0,0 +1,173 @@
+use crate::monero;
+use crate::network::cbor_request_response::CborCodec;
+use crate::network::spot_price::BlockchainNetwork;
+use crate::protocol::alice;
+use crate::protocol::alice::taker;
+use libp2p::core::ProtocolName;
+use libp2p::request_response::{
+    ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+    RequestResponseMessage,
+};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const PROTOCOL: &str = "/comit/xmr/btc/ask-spot-price/1.0.0";
+type OutEvent = RequestResponseEvent<Request, Response>;
+type Message = RequestResponseMessage<Request, Response>;
+
+pub type Behaviour = RequestResponse<CborCodec<AskSpotPriceProtocol, Request, Response>>;
+
+/// The ask-spot-price protocol allows a taker to **initiate** a trade in which
+/// they sell XMR by requesting a spot price for a given amount of XMR.
+///
+/// Like the `spot-price` protocol, the spot price is binding for both parties.
+/// After the protocol completes, both parties are expected to follow up with
+/// the `ask-execution-setup` protocol. The taker chooses the swap id, so that
+/// it can be logged and persisted before the execution setup starts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AskSpotPriceProtocol;
+
+impl ProtocolName for AskSpotPriceProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL.as_bytes()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Request {
+    pub swap_id: Uuid,
+    pub xmr: monero::Amount,
+    pub blockchain_network: BlockchainNetwork,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Response {
+    Btc(#[serde(with = "::bitcoin::util::amount::serde::as_sat")] bitcoin::Amount),
+    Error(Error),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Error {
+    NoSwapsAccepted,
+    AmountBelowMinimum {
+        min: monero::Amount,
+        sell: monero::Amount,
+    },
+    AmountAboveMaximum {
+        max: monero::Amount,
+        sell: monero::Amount,
+    },
+    BalanceTooLow {
+        sell: monero::Amount,
+    },
+    BlockchainNetworkMismatch {
+        cli: BlockchainNetwork,
+        asb: BlockchainNetwork,
+    },
+    /// To be used for errors that cannot be explained on the CLI side (e.g.
+    /// rate update problems on the buyer side)
+    Other,
+}
+
+/// Constructs a new instance of the `ask-spot-price` behaviour to be used by
+/// the maker.
+///
+/// The maker only supports inbound connections, i.e. handing out spot prices
+/// for buying XMR.
+pub fn maker() -> Behaviour {
+    Behaviour::new(
+        CborCodec::default(),
+        vec![(AskSpotPriceProtocol, ProtocolSupport::Inbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+/// Constructs a new instance of the `ask-spot-price` behaviour to be used by
+/// the taker.
+///
+/// The taker only supports outbound connections, i.e. requesting a spot price
+/// for a given amount of XMR in BTC.
+pub fn taker() -> Behaviour {
+    Behaviour::new(
+        CborCodec::default(),
+        vec![(AskSpotPriceProtocol, ProtocolSupport::Outbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+impl From<(PeerId, Message)> for alice::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request {
+                request, channel, ..
+            } => Self::AskSpotPriceRequested {
+                request: Box::new(request),
+                channel,
+                peer,
+            },
+            Message::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, alice::OutEvent, PROTOCOL);
+
+impl From<(PeerId, Message)> for taker::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Response {
+                response,
+                request_id,
+            } => Self::SpotPriceReceived {
+                id: request_id,
+                response,
+            },
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, taker::OutEvent, PROTOCOL);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_test_serialize() {
+        let amount = bitcoin::Amount::from_sat(100_000);
+        let btc = r#"{"Btc":100000}"#.to_string();
+        let serialized = serde_json::to_string(&Response::Btc(amount)).unwrap();
+        assert_eq!(btc, serialized);
+
+        let error = r#"{"Error":"NoSwapsAccepted"}"#.to_string();
+        let serialized = serde_json::to_string(&Response::Error(Error::NoSwapsAccepted)).unwrap();
+        assert_eq!(error, serialized);
+
+        let error = r#"{"Error":{"AmountBelowMinimum":{"min":0,"sell":0}}}"#.to_string();
+        let serialized = serde_json::to_string(&Response::Error(Error::AmountBelowMinimum {
+            min: monero::Amount::ZERO,
+            sell: monero::Amount::ZERO,
+        }))
+        .unwrap();
+        assert_eq!(error, serialized);
+
+        let error = r#"{"Error":{"AmountAboveMaximum":{"max":0,"sell":0}}}"#.to_string();
+        let serialized = serde_json::to_string(&Response::Error(Error::AmountAboveMaximum {
+            max: monero::Amount::ZERO,
+            sell: monero::Amount::ZERO,
+        }))
+        .unwrap();
+        assert_eq!(error, serialized);
+
+        let error = r#"{"Error":{"BalanceTooLow":{"sell":0}}}"#.to_string();
+        let serialized = serde_json::to_string(&Response::Error(Error::BalanceTooLow {
+            sell: monero::Amount::ZERO,
+        }))
+        .unwrap();
+        assert_eq!(error, serialized);
+
+        let error = r#"{"Error":"Other"}"#.to_string();
+        let serialized = serde_json::to_string(&Response::Error(Error::Other)).unwrap();
+        assert_eq!(error, serialized);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::network::cbor_request_response::CborCodec;
+use crate::protocol::alice::taker;
 use crate::protocol::{alice, bob};
 use libp2p::core::ProtocolName;
 use libp2p::request_response::{
@@ -38,6 +39,19 @@ pub fn alice() -> Behaviour {
     )
 }
 
+/// Constructs a new instance of the `encrypted_signature` behaviour to be used
+/// by the ASB.
+///
+/// The ASB receives encrypted signatures as Alice and sends them as Bob when it
+/// buys XMR from a taker.
+pub fn asb() -> Behaviour {
+    Behaviour::new(
+        CborCodec::default(),
+        vec![(EncryptedSignatureProtocol, ProtocolSupport::Full)],
+        RequestResponseConfig::default(),
+    )
+}
+
 pub fn bob() -> Behaviour {
     Behaviour::new(
         CborCodec::default(),
@@ -56,12 +70,31 @@ impl From<(PeerId, Message)> for alice::OutEvent {
                 channel,
                 peer,
             },
-            Message::Response { .. } => Self::unexpected_response(peer),
+            Message::Response { request_id, .. } => Self::EncryptedSignatureAcknowledged {
+                peer,
+                id: request_id,
+            },
         }
     }
 }
 crate::impl_from_rr_event!(OutEvent, alice::OutEvent, PROTOCOL);
 
+impl From<(PeerId, Message)> for taker::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request {
+                request, channel, ..
+            } => Self::EncryptedSignatureReceived {
+                msg: Box::new(request),
+                channel,
+                peer,
+            },
+            Message::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, taker::OutEvent, PROTOCOL);
+
 impl From<(PeerId, Message)> for bob::OutEvent {
     fn from((peer, message): (PeerId, Message)) -> Self {
         match message {
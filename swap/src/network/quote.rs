@@ -1,13 +1,17 @@
-use crate::bitcoin;
 use crate::network::json_pull_codec::JsonPullCodec;
+use crate::network::rendezvous::discovery;
+use crate::protocol::alice::taker;
 use crate::protocol::{alice, bob};
+use crate::{bitcoin, monero};
+use anyhow::anyhow;
 use libp2p::core::ProtocolName;
 use libp2p::request_response::{
-    ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+    OutboundFailure, ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
     RequestResponseMessage,
 };
 use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const PROTOCOL: &str = "/comit/xmr/btc/bid-quote/1.0.0";
 type OutEvent = RequestResponseEvent<(), BidQuote>;
@@ -15,6 +19,18 @@ type Message = RequestResponseMessage<(), BidQuote>;
 
 pub type Behaviour = RequestResponse<JsonPullCodec<BidQuoteProtocol, BidQuote>>;
 
+const TIERED_PROTOCOL: &str = "/comit/xmr/btc/bid-quote/2.0.0";
+type TieredOutEvent = RequestResponseEvent<(), TieredBidQuote>;
+type TieredMessage = RequestResponseMessage<(), TieredBidQuote>;
+
+pub type TieredBehaviour = RequestResponse<JsonPullCodec<TieredBidQuoteProtocol, TieredBidQuote>>;
+
+const ASK_PROTOCOL: &str = "/comit/xmr/btc/ask-quote/1.0.0";
+type AskOutEvent = RequestResponseEvent<(), AskQuote>;
+type AskMessage = RequestResponseMessage<(), AskQuote>;
+
+pub type AskBehaviour = RequestResponse<JsonPullCodec<AskQuoteProtocol, AskQuote>>;
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct BidQuoteProtocol;
 
@@ -24,6 +40,24 @@ impl ProtocolName for BidQuoteProtocol {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TieredBidQuoteProtocol;
+
+impl ProtocolName for TieredBidQuoteProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        TIERED_PROTOCOL.as_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AskQuoteProtocol;
+
+impl ProtocolName for AskQuoteProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        ASK_PROTOCOL.as_bytes()
+    }
+}
+
 /// Represents a quote for buying XMR.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BidQuote {
@@ -38,6 +72,97 @@ pub struct BidQuote {
     pub max_quantity: bitcoin::Amount,
 }
 
+/// Represents a quote for buying XMR whose price depends on the amount.
+///
+/// The maker honours the price of the tier the amount falls into if the spot
+/// price is requested before the quote expires.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TieredBidQuote {
+    /// The price tiers, ordered by their quantities.
+    pub tiers: Vec<PriceTier>,
+    /// The time after which the quote no longer holds, in seconds since the
+    /// Unix epoch.
+    pub expires_at: u64,
+}
+
+/// The price at which the maker is willing to buy XMR for BTC amounts between
+/// `min_quantity` and `max_quantity`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PriceTier {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub price: bitcoin::Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub min_quantity: bitcoin::Amount,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub max_quantity: bitcoin::Amount,
+}
+
+impl TieredBidQuote {
+    pub fn new(tiers: Vec<PriceTier>, valid_for: Duration) -> Self {
+        Self {
+            tiers,
+            expires_at: (unix_time() + valid_for).as_secs(),
+        }
+    }
+
+    /// Wraps a quote of the `bid-quote/1.0.0` protocol, which does not
+    /// promise to hold its price and hence expires right away.
+    pub fn from_legacy(quote: BidQuote) -> Self {
+        Self::new(
+            vec![PriceTier {
+                price: quote.price,
+                min_quantity: quote.min_quantity,
+                max_quantity: quote.max_quantity,
+            }],
+            Duration::from_secs(0),
+        )
+    }
+
+    pub fn min_quantity(&self) -> bitcoin::Amount {
+        self.tiers
+            .first()
+            .map_or(bitcoin::Amount::ZERO, |tier| tier.min_quantity)
+    }
+
+    pub fn max_quantity(&self) -> bitcoin::Amount {
+        self.tiers
+            .last()
+            .map_or(bitcoin::Amount::ZERO, |tier| tier.max_quantity)
+    }
+
+    /// The tier `btc` falls into.
+    pub fn tier(&self, btc: bitcoin::Amount) -> Option<&PriceTier> {
+        self.tiers
+            .iter()
+            .find(|tier| tier.min_quantity <= btc && btc <= tier.max_quantity)
+    }
+
+    /// How much longer the quote holds, `None` if it expired.
+    pub fn valid_for(&self) -> Option<Duration> {
+        Duration::from_secs(self.expires_at)
+            .checked_sub(unix_time())
+            .filter(|remaining| *remaining > Duration::from_secs(0))
+    }
+}
+
+fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time to be after the Unix epoch")
+}
+
+/// Represents a quote for selling XMR.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AskQuote {
+    /// The price at which the maker is willing to buy 1 XMR at.
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub price: bitcoin::Amount,
+    /// The minimum quantity of XMR the maker is willing to buy.
+    pub min_quantity: monero::Amount,
+    /// The maximum quantity of XMR the maker is willing to buy.
+    pub max_quantity: monero::Amount,
+}
+
 /// Constructs a new instance of the `quote` behaviour to be used by Alice.
 ///
 /// Alice only supports inbound connections, i.e. handing out quotes.
@@ -85,3 +210,206 @@ impl From<(PeerId, Message)> for bob::OutEvent {
     }
 }
 crate::impl_from_rr_event!(OutEvent, bob::OutEvent, PROTOCOL);
+
+impl From<(PeerId, Message)> for discovery::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Response { response, .. } => Self::QuoteReceived { peer, response },
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, discovery::OutEvent, PROTOCOL);
+
+/// Constructs a new instance of the tiered `quote` behaviour to be used by
+/// Alice.
+pub fn tiered_alice() -> TieredBehaviour {
+    TieredBehaviour::new(
+        JsonPullCodec::default(),
+        vec![(TieredBidQuoteProtocol, ProtocolSupport::Inbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+/// Constructs a new instance of the tiered `quote` behaviour to be used by
+/// Bob.
+pub fn tiered_bob() -> TieredBehaviour {
+    TieredBehaviour::new(
+        JsonPullCodec::default(),
+        vec![(TieredBidQuoteProtocol, ProtocolSupport::Outbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+impl From<(PeerId, TieredMessage)> for alice::OutEvent {
+    fn from((peer, message): (PeerId, TieredMessage)) -> Self {
+        match message {
+            TieredMessage::Request { channel, .. } => Self::TieredQuoteRequested { channel, peer },
+            TieredMessage::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+crate::impl_from_rr_event!(TieredOutEvent, alice::OutEvent, TIERED_PROTOCOL);
+
+impl From<(PeerId, TieredMessage)> for bob::OutEvent {
+    fn from((peer, message): (PeerId, TieredMessage)) -> Self {
+        match message {
+            TieredMessage::Request { .. } => Self::unexpected_request(peer),
+            TieredMessage::Response {
+                response,
+                request_id,
+            } => Self::TieredQuoteReceived {
+                id: request_id,
+                response,
+            },
+        }
+    }
+}
+
+/// Unlike for the other protocols, Bob needs to learn if Alice does not
+/// support tiered quotes, so he can fall back to `bid-quote/1.0.0`.
+impl From<TieredOutEvent> for bob::OutEvent {
+    fn from(event: TieredOutEvent) -> Self {
+        match event {
+            RequestResponseEvent::Message { peer, message } => Self::from((peer, message)),
+            RequestResponseEvent::OutboundFailure {
+                request_id,
+                error: OutboundFailure::UnsupportedProtocols,
+                ..
+            } => Self::TieredQuoteUnsupported { id: request_id },
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => Self::Failure {
+                peer,
+                error: anyhow!("{} failed: {:?}", TIERED_PROTOCOL, error),
+            },
+            RequestResponseEvent::InboundFailure { peer, error, .. } => Self::Failure {
+                peer,
+                error: anyhow!("{} failed: {:?}", TIERED_PROTOCOL, error),
+            },
+            RequestResponseEvent::ResponseSent { .. } => Self::Other,
+        }
+    }
+}
+
+/// Constructs a new instance of the `ask-quote` behaviour to be used by the
+/// maker.
+///
+/// The maker only supports inbound connections, i.e. handing out quotes for
+/// buying XMR.
+pub fn ask_maker() -> AskBehaviour {
+    AskBehaviour::new(
+        JsonPullCodec::default(),
+        vec![(AskQuoteProtocol, ProtocolSupport::Inbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+/// Constructs a new instance of the `ask-quote` behaviour to be used by the
+/// taker.
+///
+/// The taker only supports outbound connections, i.e. requesting quotes for
+/// selling XMR.
+pub fn ask_taker() -> AskBehaviour {
+    AskBehaviour::new(
+        JsonPullCodec::default(),
+        vec![(AskQuoteProtocol, ProtocolSupport::Outbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+impl From<(PeerId, AskMessage)> for alice::OutEvent {
+    fn from((peer, message): (PeerId, AskMessage)) -> Self {
+        match message {
+            AskMessage::Request { channel, .. } => Self::AskQuoteRequested { channel, peer },
+            AskMessage::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+crate::impl_from_rr_event!(AskOutEvent, alice::OutEvent, ASK_PROTOCOL);
+
+impl From<(PeerId, AskMessage)> for taker::OutEvent {
+    fn from((peer, message): (PeerId, AskMessage)) -> Self {
+        match message {
+            AskMessage::Request { .. } => Self::unexpected_request(peer),
+            AskMessage::Response {
+                response,
+                request_id,
+            } => Self::QuoteReceived {
+                id: request_id,
+                response,
+            },
+        }
+    }
+}
+crate::impl_from_rr_event!(AskOutEvent, taker::OutEvent, ASK_PROTOCOL);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiered_quote(valid_for: Duration) -> TieredBidQuote {
+        TieredBidQuote::new(
+            vec![
+                PriceTier {
+                    price: bitcoin::Amount::from_sat(400_000),
+                    min_quantity: bitcoin::Amount::from_sat(10_000),
+                    max_quantity: bitcoin::Amount::from_sat(9_999_999),
+                },
+                PriceTier {
+                    price: bitcoin::Amount::from_sat(404_000),
+                    min_quantity: bitcoin::Amount::from_sat(10_000_000),
+                    max_quantity: bitcoin::Amount::from_sat(50_000_000),
+                },
+            ],
+            valid_for,
+        )
+    }
+
+    #[test]
+    fn finds_tier_of_amount() {
+        let quote = tiered_quote(Duration::from_secs(60));
+
+        assert_eq!(quote.tier(bitcoin::Amount::from_sat(9_999)), None);
+        assert_eq!(
+            quote.tier(bitcoin::Amount::from_sat(9_999_999)),
+            Some(&quote.tiers[0])
+        );
+        assert_eq!(
+            quote.tier(bitcoin::Amount::from_sat(10_000_000)),
+            Some(&quote.tiers[1])
+        );
+        assert_eq!(quote.tier(bitcoin::Amount::from_sat(50_000_001)), None);
+        assert_eq!(quote.min_quantity(), bitcoin::Amount::from_sat(10_000));
+        assert_eq!(quote.max_quantity(), bitcoin::Amount::from_sat(50_000_000));
+    }
+
+    #[test]
+    fn legacy_quote_expires_right_away() {
+        let quote = TieredBidQuote::from_legacy(BidQuote {
+            price: bitcoin::Amount::from_sat(400_000),
+            min_quantity: bitcoin::Amount::from_sat(10_000),
+            max_quantity: bitcoin::Amount::from_sat(50_000_000),
+        });
+
+        assert!(quote.valid_for().is_none());
+        assert!(tiered_quote(Duration::from_secs(60)).valid_for().is_some());
+    }
+
+    #[test]
+    fn snapshot_test_serialize() {
+        let quote = TieredBidQuote {
+            tiers: vec![PriceTier {
+                price: bitcoin::Amount::from_sat(400_000),
+                min_quantity: bitcoin::Amount::from_sat(10_000),
+                max_quantity: bitcoin::Amount::from_sat(50_000_000),
+            }],
+            expires_at: 1_625_000_000,
+        };
+
+        let serialized = serde_json::to_string(&quote).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"tiers":[{"price":400000,"min_quantity":10000,"max_quantity":50000000}],"expires_at":1625000000}"#
+        );
+    }
+}
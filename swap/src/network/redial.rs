@@ -1,3 +1,4 @@
+use crate::protocol::alice::taker;
 use crate::protocol::bob;
 use backoff::backoff::Backoff;
 use backoff::ExponentialBackoff;
@@ -128,3 +129,13 @@ impl From<OutEvent> for bob::OutEvent {
         }
     }
 }
+
+impl From<OutEvent> for taker::OutEvent {
+    fn from(event: OutEvent) -> Self {
+        match event {
+            OutEvent::AllAttemptsExhausted { peer } => {
+                taker::OutEvent::AllRedialAttemptsExhausted { peer }
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::network::cbor_request_response::CborCodec;
+use anyhow::{bail, Context, Result};
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::{Multiaddr, ProtocolName};
+use libp2p::request_response::{
+    ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+    RequestResponseMessage,
+};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub mod discovery;
+pub mod server;
+
+const PROTOCOL: &str = "/comit/xmr/btc/rendezvous/1.0.0";
+pub type OutEvent = RequestResponseEvent<Request, Response>;
+type Message = RequestResponseMessage<Request, Response>;
+
+pub type Behaviour = RequestResponse<CborCodec<RendezvousProtocol, Request, Response>>;
+
+/// The rendezvous protocol allows ASBs to announce themselves at a well-known
+/// rendezvous point and allows CLIs to discover them there.
+///
+/// ASBs register their peer id and addresses under a namespace for a limited
+/// amount of time and are expected to refresh their registration before it
+/// expires. CLIs query the rendezvous point for all active registrations
+/// within a namespace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RendezvousProtocol;
+
+impl ProtocolName for RendezvousProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL.as_bytes()
+    }
+}
+
+/// The namespace under which ASBs register.
+///
+/// Separating mainnet and testnet ASBs ensures that a CLI never sees an ASB
+/// that operates on a different network.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub enum XmrBtcNamespace {
+    Mainnet,
+    Testnet,
+}
+
+impl XmrBtcNamespace {
+    pub fn from_is_testnet(testnet: bool) -> XmrBtcNamespace {
+        if testnet {
+            XmrBtcNamespace::Testnet
+        } else {
+            XmrBtcNamespace::Mainnet
+        }
+    }
+}
+
+impl fmt::Display for XmrBtcNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmrBtcNamespace::Mainnet => write!(f, "xmr-btc-swap-mainnet"),
+            XmrBtcNamespace::Testnet => write!(f, "xmr-btc-swap-testnet"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Request {
+    /// Register the sending peer under the given namespace.
+    ///
+    /// If the peer is already registered in this namespace the registration
+    /// is replaced.
+    Register {
+        namespace: XmrBtcNamespace,
+        addresses: Vec<Multiaddr>,
+        /// The requested time-to-live of the registration in seconds.
+        ttl: u64,
+    },
+    /// Retrieve all active registrations of the given namespace.
+    Discover { namespace: XmrBtcNamespace },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Response {
+    /// The registration was accepted for `ttl` seconds.
+    Registered { ttl: u64 },
+    Discovered { registrations: Vec<Registration> },
+    Error(Error),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, thiserror::Error)]
+pub enum Error {
+    #[error("Registration did not contain any addresses")]
+    NoAddresses,
+    #[error("Requested registration ttl exceeds maximum of {max} seconds")]
+    TtlTooLong { max: u64 },
+    #[error("Registration contains more than {max} addresses")]
+    TooManyAddresses { max: usize },
+    #[error("Registration contains an address longer than {max} bytes")]
+    AddressTooLong { max: usize },
+    #[error("Rendezvous point does not accept any more registrations")]
+    TooManyRegistrations,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Registration {
+    #[serde(with = "peer_id")]
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+}
+
+/// Constructs a new instance of the `rendezvous` behaviour to be used by the
+/// rendezvous point.
+///
+/// The rendezvous point only supports inbound connections, i.e. accepting
+/// registrations and answering discovery requests.
+pub fn server() -> Behaviour {
+    Behaviour::new(
+        CborCodec::default(),
+        vec![(RendezvousProtocol, ProtocolSupport::Inbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+/// Constructs a new instance of the `rendezvous` behaviour to be used by the
+/// ASB and the CLI.
+///
+/// Both only support outbound connections, i.e. registering at or discovering
+/// peers from a rendezvous point.
+pub fn client() -> Behaviour {
+    Behaviour::new(
+        CborCodec::default(),
+        vec![(RendezvousProtocol, ProtocolSupport::Outbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+/// Splits the address of a rendezvous point into its peer id and the address
+/// it can be dialled at.
+///
+/// The address is expected to end with a `/p2p/<peer-id>` component.
+pub fn split_peer_id(address: &Multiaddr) -> Result<(PeerId, Multiaddr)> {
+    let mut address = address.clone();
+
+    match address.pop() {
+        Some(Protocol::P2p(hash)) => {
+            let peer_id = PeerId::from_multihash(hash)
+                .ok()
+                .context("Failed to parse peer id of rendezvous point address")?;
+
+            if address.is_empty() {
+                bail!("Rendezvous point address must not only contain a peer id")
+            }
+
+            Ok((peer_id, address))
+        }
+        _ => bail!(
+            "Rendezvous point address must end with the peer id of the rendezvous point, e.g. /dns4/example.com/tcp/8888/p2p/<peer-id>"
+        ),
+    }
+}
+
+impl From<(PeerId, Message)> for discovery::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Response {
+                response: Response::Discovered { registrations },
+                ..
+            } => Self::Discovered {
+                peer,
+                registrations,
+            },
+            Message::Response {
+                response: Response::Error(error),
+                ..
+            } => Self::Failure {
+                peer,
+                error: error.into(),
+            },
+            Message::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, discovery::OutEvent, PROTOCOL);
+
+mod peer_id {
+    use libp2p::PeerId;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S>(peer_id: &PeerId, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        s.serialize_str(&peer_id.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<PeerId, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let string = String::deserialize(deserializer)?;
+        let peer_id = PeerId::from_str(&string).map_err(D::Error::custom)?;
+
+        Ok(peer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_address_with_peer_id_then_splits_it_off() {
+        let peer_id = PeerId::random();
+        let address = format!("/dns4/example.com/tcp/8888/p2p/{}", peer_id)
+            .parse::<Multiaddr>()
+            .unwrap();
+
+        let (split_peer_id, split_address) = split_peer_id(&address).unwrap();
+
+        assert_eq!(split_peer_id, peer_id);
+        assert_eq!(
+            split_address,
+            "/dns4/example.com/tcp/8888".parse::<Multiaddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn given_address_without_peer_id_then_fails() {
+        let address = "/dns4/example.com/tcp/8888".parse::<Multiaddr>().unwrap();
+
+        assert!(split_peer_id(&address).is_err());
+    }
+
+    #[test]
+    fn registration_round_trips_through_cbor() {
+        let registration = Registration {
+            peer_id: PeerId::random(),
+            addresses: vec!["/ip4/127.0.0.1/tcp/9939".parse().unwrap()],
+        };
+
+        let serialized = serde_cbor::to_vec(&registration).unwrap();
+        let deserialized = serde_cbor::from_slice::<Registration>(&serialized).unwrap();
+
+        assert_eq!(deserialized, registration);
+    }
+}
@@ -0,0 +1,69 @@
+//! The behaviour with which the CLI discovers sellers at a rendezvous point
+//! and requests quotes from them.
+use crate::network::quote::BidQuote;
+use crate::network::rendezvous::Registration;
+use crate::network::{quote, rendezvous};
+use anyhow::{anyhow, Error};
+use libp2p::core::Multiaddr;
+use libp2p::{NetworkBehaviour, PeerId};
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Discovered {
+        peer: PeerId,
+        registrations: Vec<Registration>,
+    },
+    QuoteReceived {
+        peer: PeerId,
+        response: BidQuote,
+    },
+    Failure {
+        peer: PeerId,
+        error: Error,
+    },
+    /// "Fallback" variant that allows the event mapping code to swallow certain
+    /// events that we don't want the caller to deal with.
+    Other,
+}
+
+impl OutEvent {
+    pub fn unexpected_request(peer: PeerId) -> OutEvent {
+        OutEvent::Failure {
+            peer,
+            error: anyhow!("Unexpected request received"),
+        }
+    }
+
+    pub fn unexpected_response(peer: PeerId) -> OutEvent {
+        OutEvent::Failure {
+            peer,
+            error: anyhow!("Unexpected response received"),
+        }
+    }
+}
+
+/// A `NetworkBehaviour` that discovers sellers at a rendezvous point and
+/// requests quotes from them.
+///
+/// In contrast to the behaviour used for swapping this does not contain a
+/// ping behaviour; connections to sellers that don't answer our quote request
+/// are closed once they become idle.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    pub rendezvous: rendezvous::Behaviour,
+    pub quote: quote::Behaviour,
+}
+
+impl Behaviour {
+    pub fn new(rendezvous_node_peer_id: PeerId, rendezvous_node_addr: Multiaddr) -> Self {
+        let mut rendezvous = rendezvous::client();
+        rendezvous.add_address(&rendezvous_node_peer_id, rendezvous_node_addr);
+
+        Self {
+            rendezvous,
+            quote: quote::bob(),
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::network::rendezvous;
+use crate::network::rendezvous::{Error, Registration, Request, Response, XmrBtcNamespace};
+use libp2p::core::Multiaddr;
+use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::swarm::{NetworkBehaviourAction, NetworkBehaviourEventProcess, PollParameters};
+use libp2p::{NetworkBehaviour, PeerId};
+use std::collections::{HashMap, VecDeque};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The maximum time-to-live of a registration, in seconds.
+pub const MAX_TTL: u64 = 60 * 60 * 24;
+
+/// Limits on the registrations, so that they neither exhaust our memory nor
+/// make the response to a discovery request exceed the size of a message.
+pub const MAX_REGISTRATIONS: usize = 1000;
+pub const MAX_ADDRESSES: usize = 4;
+pub const MAX_ADDRESS_LENGTH: usize = 128;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    PeerRegistered {
+        peer: PeerId,
+        namespace: XmrBtcNamespace,
+    },
+    RegistrationDeclined {
+        peer: PeerId,
+        error: Error,
+    },
+    DiscoverServed {
+        peer: PeerId,
+        namespace: XmrBtcNamespace,
+    },
+}
+
+/// Behaviour of a rendezvous point.
+///
+/// Keeps track of the registrations of all peers and hands out the ones that
+/// have not yet expired upon discovery requests.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", poll_method = "poll", event_process = true)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    behaviour: rendezvous::Behaviour,
+
+    #[behaviour(ignore)]
+    registrations: HashMap<(XmrBtcNamespace, PeerId), (Vec<Multiaddr>, Instant)>,
+    #[behaviour(ignore)]
+    events: VecDeque<OutEvent>,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self {
+            behaviour: rendezvous::server(),
+            registrations: Default::default(),
+            events: Default::default(),
+        }
+    }
+}
+
+impl Behaviour {
+    fn register(
+        &mut self,
+        peer: PeerId,
+        namespace: XmrBtcNamespace,
+        addresses: Vec<Multiaddr>,
+        ttl: u64,
+    ) -> Result<u64, Error> {
+        if addresses.is_empty() {
+            return Err(Error::NoAddresses);
+        }
+
+        if ttl > MAX_TTL {
+            return Err(Error::TtlTooLong { max: MAX_TTL });
+        }
+
+        if addresses.len() > MAX_ADDRESSES {
+            return Err(Error::TooManyAddresses { max: MAX_ADDRESSES });
+        }
+
+        if addresses
+            .iter()
+            .any(|address| address.to_vec().len() > MAX_ADDRESS_LENGTH)
+        {
+            return Err(Error::AddressTooLong {
+                max: MAX_ADDRESS_LENGTH,
+            });
+        }
+
+        self.remove_expired_registrations();
+        if self.registrations.len() >= MAX_REGISTRATIONS
+            && !self.registrations.contains_key(&(namespace, peer))
+        {
+            return Err(Error::TooManyRegistrations);
+        }
+
+        let expires_at = Instant::now() + Duration::from_secs(ttl);
+        self.registrations
+            .insert((namespace, peer), (addresses, expires_at));
+
+        Ok(ttl)
+    }
+
+    fn discover(&mut self, namespace: XmrBtcNamespace) -> Vec<Registration> {
+        self.remove_expired_registrations();
+
+        self.registrations
+            .iter()
+            .filter(|((registered_namespace, _), _)| *registered_namespace == namespace)
+            .map(|((_, peer_id), (addresses, _))| Registration {
+                peer_id: *peer_id,
+                addresses: addresses.clone(),
+            })
+            .collect()
+    }
+
+    fn remove_expired_registrations(&mut self) {
+        let now = Instant::now();
+        self.registrations
+            .retain(|_, (_, expires_at)| *expires_at > now);
+    }
+
+    fn poll<BIE>(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<BIE, OutEvent>> {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
+        }
+
+        // We trust in libp2p to poll us.
+        Poll::Pending
+    }
+}
+
+impl NetworkBehaviourEventProcess<rendezvous::OutEvent> for Behaviour {
+    fn inject_event(&mut self, event: rendezvous::OutEvent) {
+        let (peer, message) = match event {
+            RequestResponseEvent::Message { peer, message } => (peer, message),
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                tracing::error!(%peer, "Failure sending rendezvous response: {:#}", error);
+                return;
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                tracing::warn!(%peer, "Inbound failure when handling rendezvous request: {:#}", error);
+                return;
+            }
+            RequestResponseEvent::ResponseSent { .. } => return,
+        };
+
+        let (request, channel) = match message {
+            RequestResponseMessage::Request {
+                request, channel, ..
+            } => (request, channel),
+            RequestResponseMessage::Response { .. } => {
+                tracing::error!("Unexpected message");
+                return;
+            }
+        };
+
+        let (response, event) = match request {
+            Request::Register {
+                namespace,
+                addresses,
+                ttl,
+            } => match self.register(peer, namespace, addresses, ttl) {
+                Ok(ttl) => (
+                    Response::Registered { ttl },
+                    OutEvent::PeerRegistered { peer, namespace },
+                ),
+                Err(error) => (
+                    Response::Error(error.clone()),
+                    OutEvent::RegistrationDeclined { peer, error },
+                ),
+            },
+            Request::Discover { namespace } => (
+                Response::Discovered {
+                    registrations: self.discover(namespace),
+                },
+                OutEvent::DiscoverServed { peer, namespace },
+            ),
+        };
+
+        if self.behaviour.send_response(channel, response).is_err() {
+            tracing::debug!(%peer, "Unable to send rendezvous response");
+            return;
+        }
+
+        self.events.push_back(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address() -> Multiaddr {
+        "/ip4/127.0.0.1/tcp/9939".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn given_too_many_addresses_then_declines_registration() {
+        let mut behaviour = Behaviour::default();
+
+        let result = behaviour.register(
+            PeerId::random(),
+            XmrBtcNamespace::Mainnet,
+            vec![address(); MAX_ADDRESSES + 1],
+            MAX_TTL,
+        );
+
+        assert_eq!(
+            result,
+            Err(Error::TooManyAddresses { max: MAX_ADDRESSES })
+        );
+    }
+
+    #[tokio::test]
+    async fn given_too_long_address_then_declines_registration() {
+        let mut behaviour = Behaviour::default();
+        let address = format!("/dns4/{}.com/tcp/9939", "a".repeat(MAX_ADDRESS_LENGTH))
+            .parse()
+            .unwrap();
+
+        let result = behaviour.register(
+            PeerId::random(),
+            XmrBtcNamespace::Mainnet,
+            vec![address],
+            MAX_TTL,
+        );
+
+        assert_eq!(
+            result,
+            Err(Error::AddressTooLong {
+                max: MAX_ADDRESS_LENGTH
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn given_maximum_of_registrations_then_only_renews_existing_ones() {
+        let mut behaviour = Behaviour::default();
+        let registered_peer = PeerId::random();
+        behaviour
+            .register(
+                registered_peer,
+                XmrBtcNamespace::Mainnet,
+                vec![address()],
+                MAX_TTL,
+            )
+            .unwrap();
+        for _ in 1..MAX_REGISTRATIONS {
+            behaviour
+                .register(
+                    PeerId::random(),
+                    XmrBtcNamespace::Mainnet,
+                    vec![address()],
+                    MAX_TTL,
+                )
+                .unwrap();
+        }
+
+        let new = behaviour.register(
+            PeerId::random(),
+            XmrBtcNamespace::Mainnet,
+            vec![address()],
+            MAX_TTL,
+        );
+        let renewal = behaviour.register(
+            registered_peer,
+            XmrBtcNamespace::Mainnet,
+            vec![address()],
+            MAX_TTL,
+        );
+
+        assert_eq!(new, Err(Error::TooManyRegistrations));
+        assert_eq!(renewal, Ok(MAX_TTL));
+    }
+}
@@ -1,14 +1,22 @@
 use crate::monero;
 use crate::network::cbor_request_response::CborCodec;
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
 use libp2p::core::ProtocolName;
-use libp2p::request_response::{RequestResponse, RequestResponseEvent, RequestResponseMessage};
+use libp2p::request_response::{
+    RequestResponse, RequestResponseCodec, RequestResponseEvent, RequestResponseMessage,
+};
 use serde::{Deserialize, Serialize};
+use std::io;
 
-pub const PROTOCOL: &str = "/comit/xmr/btc/spot-price/1.0.0";
+pub const PROTOCOL: &str = "/comit/xmr/btc/spot-price/1.1.0";
+/// The protocol before [`Error::PriceUnavailable`] was added, still spoken
+/// with peers that do not support [`PROTOCOL`] yet.
+const LEGACY_PROTOCOL: &str = "/comit/xmr/btc/spot-price/1.0.0";
 pub type OutEvent = RequestResponseEvent<Request, Response>;
 pub type Message = RequestResponseMessage<Request, Response>;
 
-pub type Behaviour = RequestResponse<CborCodec<SpotPriceProtocol, Request, Response>>;
+pub type Behaviour = RequestResponse<Codec>;
 
 /// The spot price protocol allows parties to **initiate** a trade by requesting
 /// a spot price.
@@ -19,12 +27,90 @@ pub type Behaviour = RequestResponse<CborCodec<SpotPriceProtocol, Request, Respo
 ///
 /// If a party wishes to only inquire about the current price, they should use
 /// the `quote` protocol instead.
-#[derive(Debug, Clone, Copy, Default)]
-pub struct SpotPriceProtocol;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpotPriceProtocol {
+    Legacy,
+    Current,
+}
+
+impl SpotPriceProtocol {
+    /// The supported versions, the preferred one first.
+    pub const ALL: [SpotPriceProtocol; 2] = [SpotPriceProtocol::Current, SpotPriceProtocol::Legacy];
+}
 
 impl ProtocolName for SpotPriceProtocol {
     fn protocol_name(&self) -> &[u8] {
-        PROTOCOL.as_bytes()
+        match self {
+            SpotPriceProtocol::Legacy => LEGACY_PROTOCOL.as_bytes(),
+            SpotPriceProtocol::Current => PROTOCOL.as_bytes(),
+        }
+    }
+}
+
+/// Encodes the messages as CBOR, answering peers on the legacy protocol with
+/// [`Error::Other`] instead of errors they do not know.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Codec {
+    inner: CborCodec<SpotPriceProtocol, Request, Response>,
+}
+
+#[async_trait]
+impl RequestResponseCodec for Codec {
+    type Protocol = SpotPriceProtocol;
+    type Request = Request;
+    type Response = Response;
+
+    async fn read_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        self.inner.read_request(protocol, io).await
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        self.inner.read_response(protocol, io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        req: Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        self.inner.write_request(protocol, io, req).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        res: Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let res = match (protocol, res) {
+            (SpotPriceProtocol::Legacy, Response::Error(Error::PriceUnavailable)) => {
+                Response::Error(Error::Other)
+            }
+            (_, res) => res,
+        };
+
+        self.inner.write_response(protocol, io, res).await
     }
 }
 
@@ -64,6 +150,12 @@ pub enum Error {
         cli: BlockchainNetwork,
         asb: BlockchainNetwork,
     },
+    /// The seller has no price recent enough to quote on, e.g. because its
+    /// price sources stopped sending updates.
+    ///
+    /// Only sent on [`PROTOCOL`], peers on the legacy protocol get
+    /// [`Error::Other`] instead.
+    PriceUnavailable,
     /// To be used for errors that cannot be explained on the CLI side (e.g.
     /// rate update problems on the seller side)
     Other,
@@ -131,8 +223,48 @@ mod tests {
             .unwrap();
         assert_eq!(error, serialized);
 
+        let error = r#"{"Error":"PriceUnavailable"}"#.to_string();
+        let serialized = serde_json::to_string(&Response::Error(Error::PriceUnavailable)).unwrap();
+        assert_eq!(error, serialized);
+
         let error = r#"{"Error":"Other"}"#.to_string();
         let serialized = serde_json::to_string(&Response::Error(Error::Other)).unwrap();
         assert_eq!(error, serialized);
     }
+
+    async fn roundtrip(protocol: SpotPriceProtocol, response: Response) -> Response {
+        let mut codec = Codec::default();
+        let mut bytes = Vec::new();
+
+        codec
+            .write_response(&protocol, &mut bytes, response)
+            .await
+            .unwrap();
+        codec
+            .read_response(&protocol, &mut bytes.as_slice())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn given_legacy_protocol_then_price_unavailable_is_sent_as_other() {
+        let response = roundtrip(
+            SpotPriceProtocol::Legacy,
+            Response::Error(Error::PriceUnavailable),
+        )
+        .await;
+
+        assert!(matches!(response, Response::Error(Error::Other)));
+    }
+
+    #[tokio::test]
+    async fn given_current_protocol_then_price_unavailable_is_sent_as_is() {
+        let response = roundtrip(
+            SpotPriceProtocol::Current,
+            Response::Error(Error::PriceUnavailable),
+        )
+        .await;
+
+        assert!(matches!(response, Response::Error(Error::PriceUnavailable)));
+    }
 }
@@ -1,5 +1,7 @@
-use crate::network::transport;
+use crate::asb::VolumeTiers;
+use crate::network::{rendezvous, transport};
 use crate::protocol::alice::event_loop::LatestRate;
+use crate::protocol::alice::taker;
 use crate::protocol::{alice, bob};
 use crate::seed::Seed;
 use crate::{env, monero, tor};
@@ -7,6 +9,7 @@ use anyhow::Result;
 use libp2p::swarm::{NetworkBehaviour, SwarmBuilder};
 use libp2p::{PeerId, Swarm};
 use std::fmt::Debug;
+use std::time::Duration;
 
 #[allow(clippy::too_many_arguments)]
 pub fn alice<LR>(
@@ -16,8 +19,11 @@ pub fn alice<LR>(
     min_buy: bitcoin::Amount,
     max_buy: bitcoin::Amount,
     latest_rate: LR,
+    volume_tiers: VolumeTiers,
+    quote_validity: Duration,
     resume_only: bool,
     env_config: env::Config,
+    rendezvous: Option<alice::rendezvous::Behaviour>,
 ) -> Result<Swarm<alice::Behaviour<LR>>>
 where
     LR: LatestRate + Send + 'static + Debug,
@@ -30,22 +36,48 @@ where
             min_buy,
             max_buy,
             latest_rate,
+            volume_tiers,
+            quote_validity,
             resume_only,
             env_config,
+            rendezvous,
         ),
     )
 }
 
+/// Constructs the swarm of a rendezvous point, which only listens on the
+/// clear net.
+pub fn rendezvous_node(seed: &Seed) -> Result<Swarm<rendezvous::server::Behaviour>> {
+    with_clear_net(seed, rendezvous::server::Behaviour::default())
+}
+
 pub async fn bob(
     seed: &Seed,
     alice: PeerId,
     tor_socks5_port: u16,
 ) -> Result<Swarm<bob::Behaviour>> {
+    cli(seed, tor_socks5_port, bob::Behaviour::new(alice)).await
+}
+
+pub async fn taker(
+    seed: &Seed,
+    maker: PeerId,
+    tor_socks5_port: u16,
+) -> Result<Swarm<taker::Behaviour>> {
+    cli(seed, tor_socks5_port, taker::Behaviour::new(maker)).await
+}
+
+/// Constructs a swarm for the CLI, routing all connections through Tor if it
+/// is running on the given socks5 port.
+pub async fn cli<B>(seed: &Seed, tor_socks5_port: u16, behaviour: B) -> Result<Swarm<B>>
+where
+    B: NetworkBehaviour,
+{
     let client = tor::Client::new(tor_socks5_port);
     if client.assert_tor_running().await.is_ok() {
-        return with_tor(seed, bob::Behaviour::new(alice), tor_socks5_port).await;
+        return with_tor(seed, behaviour, tor_socks5_port).await;
     }
-    with_clear_net(seed, bob::Behaviour::new(alice))
+    with_clear_net(seed, behaviour)
 }
 
 fn with_clear_net<B>(seed: &Seed, behaviour: B) -> Result<Swarm<B>>
@@ -1,5 +1,6 @@
 use crate::monero;
 use crate::network::cbor_request_response::CborCodec;
+use crate::protocol::alice::taker;
 use crate::protocol::{alice, bob};
 use libp2p::core::ProtocolName;
 use libp2p::request_response::{
@@ -39,6 +40,19 @@ pub fn alice() -> Behaviour {
     )
 }
 
+/// Constructs a new instance of the `transfer_proof` behaviour to be used by
+/// the ASB.
+///
+/// The ASB sends transfer proofs as Alice and receives them as Bob when it buys
+/// XMR from a taker.
+pub fn asb() -> Behaviour {
+    Behaviour::new(
+        CborCodec::default(),
+        vec![(TransferProofProtocol, ProtocolSupport::Full)],
+        RequestResponseConfig::default(),
+    )
+}
+
 pub fn bob() -> Behaviour {
     Behaviour::new(
         CborCodec::default(),
@@ -50,7 +64,13 @@ pub fn bob() -> Behaviour {
 impl From<(PeerId, Message)> for alice::OutEvent {
     fn from((peer, message): (PeerId, Message)) -> Self {
         match message {
-            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Request {
+                request, channel, ..
+            } => Self::TransferProofReceived {
+                msg: Box::new(request),
+                channel,
+                peer,
+            },
             Message::Response { request_id, .. } => Self::TransferProofAcknowledged {
                 peer,
                 id: request_id,
@@ -60,6 +80,18 @@ impl From<(PeerId, Message)> for alice::OutEvent {
 }
 crate::impl_from_rr_event!(OutEvent, alice::OutEvent, PROTOCOL);
 
+impl From<(PeerId, Message)> for taker::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Response { request_id, .. } => {
+                Self::TransferProofAcknowledged { id: request_id }
+            }
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, taker::OutEvent, PROTOCOL);
+
 impl From<(PeerId, Message)> for bob::OutEvent {
     fn from((peer, message): (PeerId, Message)) -> Self {
         match message {
@@ -0,0 +1,487 @@
+//! The price sources the ASB bases its rate on.
+//!
+//! Every source keeps a connection to an exchange open and publishes the
+//! latest ask price of XMR in BTC it received, together with the time it was
+//! received. [`Median`] combines the fresh prices of all sources.
+use anyhow::{anyhow, Result};
+use futures::stream::BoxStream;
+use futures::{Future, TryStreamExt};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use url::Url;
+
+pub mod binance;
+pub mod bitfinex;
+pub mod kraken;
+pub mod rest;
+mod websocket;
+
+const DEFAULT_REST_POLL_INTERVAL_SECS: u64 = 30;
+
+/// A price source and the endpoint to get the prices from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Source {
+    Kraken {
+        url: Url,
+    },
+    Bitfinex {
+        url: Url,
+    },
+    Binance {
+        url: Url,
+    },
+    /// Polls a JSON API, `ask_pointer` is the JSON pointer to the ask price
+    /// in BTC within the response, e.g. `/result/XXMRXXBT/a/0`.
+    Rest {
+        url: Url,
+        ask_pointer: String,
+        #[serde(default = "default_rest_poll_interval_secs")]
+        poll_interval_secs: u64,
+    },
+}
+
+impl Source {
+    pub fn kraken() -> Self {
+        Source::Kraken {
+            url: Url::parse(kraken::DEFAULT_WEBSOCKET_URL).expect("static url to be valid"),
+        }
+    }
+
+    /// Connects to the source in the background and reconnects whenever the
+    /// connection fails.
+    pub fn connect(&self) -> PriceUpdates {
+        match self {
+            Source::Kraken { url } => kraken::connect(url.clone()),
+            Source::Bitfinex { url } => bitfinex::connect(url.clone()),
+            Source::Binance { url } => binance::connect(url.clone()),
+            Source::Rest {
+                url,
+                ask_pointer,
+                poll_interval_secs,
+            } => rest::connect(
+                url.clone(),
+                ask_pointer.clone(),
+                Duration::from_secs(*poll_interval_secs),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Kraken { url } => write!(f, "Kraken at {}", url),
+            Source::Bitfinex { url } => write!(f, "Bitfinex at {}", url),
+            Source::Binance { url } => write!(f, "Binance at {}", url),
+            Source::Rest { url, .. } => write!(f, "REST API at {}", url),
+        }
+    }
+}
+
+fn default_rest_poll_interval_secs() -> u64 {
+    DEFAULT_REST_POLL_INTERVAL_SECS
+}
+
+/// The ask price of 1 XMR in BTC as reported by a source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PriceUpdate {
+    pub ask: bitcoin::Amount,
+    pub received_at: Instant,
+}
+
+impl PriceUpdate {
+    pub fn age(&self) -> Duration {
+        self.received_at.elapsed()
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Rate is not yet available")]
+    NotYetAvailable,
+    #[error("Permanently failed to retrieve rate")]
+    PermanentFailure,
+    #[error("Only {agreeing} price sources agree on a fresh rate, {required} are required")]
+    NotEnoughSources { agreeing: usize, required: usize },
+    #[error("The latest price was received {}s ago, prices older than {}s are not used", .age.as_secs(), .max_age.as_secs())]
+    Stale { age: Duration, max_age: Duration },
+}
+
+#[derive(Clone, Debug)]
+pub struct PriceUpdates {
+    inner: watch::Receiver<Result<PriceUpdate, Error>>,
+}
+
+impl PriceUpdates {
+    pub async fn wait_for_next_update(&mut self) -> Result<Result<PriceUpdate, Error>> {
+        self.inner.changed().await?;
+
+        Ok(self.inner.borrow().clone())
+    }
+
+    pub fn latest_update(&mut self) -> Result<PriceUpdate, Error> {
+        self.inner.borrow().clone()
+    }
+}
+
+/// Publishes the prices of the streams returned by `connect`.
+///
+/// Whenever a stream fails or ends, `connect` is called again after an
+/// exponential backoff.
+fn spawn<C, F>(source: &'static str, connect: C) -> PriceUpdates
+where
+    C: Fn() -> F + Send + Sync + 'static,
+    F: Future<Output = Result<BoxStream<'static, Result<bitcoin::Amount>>>> + Send + 'static,
+{
+    let (price_update, price_update_receiver) = watch::channel(Err(Error::NotYetAvailable));
+    let price_update = Arc::new(price_update);
+
+    tokio::spawn(async move {
+        // The default backoff config is fine for us apart from one thing:
+        // `max_elapsed_time`. If we don't get an error within this timeframe,
+        // backoff won't actually retry the operation.
+        let backoff = backoff::ExponentialBackoff {
+            max_elapsed_time: None,
+            ..backoff::ExponentialBackoff::default()
+        };
+
+        let result = backoff::future::retry_notify::<Infallible, _, _, _, _, _>(
+            backoff,
+            || {
+                let price_update = price_update.clone();
+                let stream = connect();
+                async move {
+                    let mut stream = stream.await?;
+
+                    while let Some(ask) = stream.try_next().await? {
+                        let send_result = price_update.send(Ok(PriceUpdate {
+                            ask,
+                            received_at: Instant::now(),
+                        }));
+
+                        if send_result.is_err() {
+                            return Err(backoff::Error::Permanent(anyhow!(
+                                "receiver disconnected"
+                            )));
+                        }
+                    }
+
+                    Err(backoff::Error::Transient(anyhow!("stream ended")))
+                }
+            },
+            |error, next: Duration| {
+                tracing::info!(
+                    %source,
+                    "Price source connection failed, retrying in {}ms. Error {:#}",
+                    next.as_millis(),
+                    error
+                );
+            },
+        )
+        .await;
+
+        match result {
+            Err(e) => {
+                tracing::warn!(%source, "Rate updates incurred an unrecoverable error: {:#}", e);
+
+                // in case the retries fail permanently, let the subscribers know
+                price_update.send(Err(Error::PermanentFailure))
+            }
+            Ok(never) => match never {},
+        }
+    });
+
+    PriceUpdates {
+        inner: price_update_receiver,
+    }
+}
+
+/// Takes the median of the fresh prices of several sources.
+///
+/// A price is fresh if it was received no longer than `max_age` ago. Sources
+/// whose price deviates from the median of all fresh prices by more than
+/// `max_deviation` do not agree with the others and are left out. If fewer
+/// than `min_agreeing` sources remain, no price is returned at all.
+#[derive(Clone, Debug)]
+pub struct Median {
+    sources: Vec<PriceUpdates>,
+    min_agreeing: usize,
+    max_deviation: Decimal,
+    max_age: Duration,
+}
+
+impl Median {
+    pub fn new(
+        sources: Vec<PriceUpdates>,
+        min_agreeing: usize,
+        max_deviation: Decimal,
+        max_age: Duration,
+    ) -> Self {
+        Self {
+            sources,
+            min_agreeing,
+            max_deviation,
+            max_age,
+        }
+    }
+
+    pub fn latest_ask(&mut self) -> Result<bitcoin::Amount, Error> {
+        let updates = self
+            .sources
+            .iter_mut()
+            .map(PriceUpdates::latest_update)
+            .collect::<Vec<_>>();
+
+        if updates
+            .iter()
+            .all(|update| matches!(update, Err(Error::NotYetAvailable)))
+        {
+            return Err(Error::NotYetAvailable);
+        }
+
+        let received = updates
+            .into_iter()
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        let fresh_asks = received
+            .iter()
+            .filter(|update| update.age() <= self.max_age)
+            .map(|update| update.ask)
+            .collect::<Vec<_>>();
+
+        // The connections to the sources may well be alive, but none of them
+        // sent a price for a while, e.g. because the exchange froze its ticker.
+        if fresh_asks.is_empty() {
+            if let Some(age) = received.iter().map(PriceUpdate::age).min() {
+                return Err(Error::Stale {
+                    age,
+                    max_age: self.max_age,
+                });
+            }
+        }
+
+        median_of_agreeing(fresh_asks, self.min_agreeing, self.max_deviation)
+    }
+}
+
+fn median_of_agreeing(
+    asks: Vec<bitcoin::Amount>,
+    min_agreeing: usize,
+    max_deviation: Decimal,
+) -> Result<bitcoin::Amount, Error> {
+    let not_enough = |agreeing| Error::NotEnoughSources {
+        agreeing,
+        required: min_agreeing,
+    };
+
+    let overall = median(&asks).ok_or_else(|| not_enough(0))?;
+    let agreeing = asks
+        .into_iter()
+        .filter(|ask| deviation(*ask, overall) <= max_deviation)
+        .collect::<Vec<_>>();
+
+    if agreeing.len() < min_agreeing {
+        return Err(not_enough(agreeing.len()));
+    }
+
+    median(&agreeing).ok_or_else(|| not_enough(0))
+}
+
+fn median(asks: &[bitcoin::Amount]) -> Option<bitcoin::Amount> {
+    let mut asks = asks.to_vec();
+    asks.sort();
+
+    let middle = asks.len() / 2;
+    match asks.len() {
+        0 => None,
+        len if len % 2 == 0 => Some(bitcoin::Amount::from_sat(
+            (asks[middle - 1].as_sat() + asks[middle].as_sat()) / 2,
+        )),
+        _ => Some(asks[middle]),
+    }
+}
+
+/// The relative deviation of `ask` from `reference`.
+fn deviation(ask: bitcoin::Amount, reference: bitcoin::Amount) -> Decimal {
+    if reference == bitcoin::Amount::ZERO {
+        return Decimal::MAX;
+    }
+
+    let ask = Decimal::from(ask.as_sat());
+    let reference = Decimal::from(reference.as_sat());
+
+    ((ask - reference) / reference).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{SinkExt, StreamExt};
+    use rust_decimal_macros::dec;
+    use tokio::net::TcpListener;
+
+    fn sats(sats: Vec<u64>) -> Vec<bitcoin::Amount> {
+        sats.into_iter().map(bitcoin::Amount::from_sat).collect()
+    }
+
+    #[test]
+    fn median_of_odd_and_even_number_of_asks() {
+        assert_eq!(
+            median(&sats(vec![300, 100, 200])),
+            Some(bitcoin::Amount::from_sat(200))
+        );
+        assert_eq!(
+            median(&sats(vec![400, 100, 200, 300])),
+            Some(bitcoin::Amount::from_sat(250))
+        );
+        assert_eq!(median(&[]), None);
+    }
+
+    #[test]
+    fn outlier_is_left_out() {
+        let ask = median_of_agreeing(sats(vec![10_000, 10_100, 20_000]), 2, dec!(0.02)).unwrap();
+
+        assert_eq!(ask, bitcoin::Amount::from_sat(10_050));
+    }
+
+    #[test]
+    fn refuses_if_too_few_sources_agree() {
+        let error =
+            median_of_agreeing(sats(vec![10_000, 20_000, 30_000]), 2, dec!(0.02)).unwrap_err();
+
+        assert!(matches!(error, Error::NotEnoughSources {
+            agreeing: 1,
+            required: 2
+        }));
+    }
+
+    #[test]
+    fn refuses_without_fresh_prices() {
+        let error = median_of_agreeing(vec![], 1, dec!(0.02)).unwrap_err();
+
+        assert!(matches!(error, Error::NotEnoughSources {
+            agreeing: 0,
+            required: 1
+        }));
+    }
+
+    #[test]
+    fn ignores_stale_prices() {
+        let max_age = Duration::from_secs(60);
+        let mut median = Median::new(
+            vec![
+                received(10_000, Duration::from_secs(120)),
+                received(20_000, Duration::from_secs(1)),
+            ],
+            1,
+            dec!(0.02),
+            max_age,
+        );
+
+        assert_eq!(
+            median.latest_ask().unwrap(),
+            bitcoin::Amount::from_sat(20_000)
+        );
+    }
+
+    #[test]
+    fn refuses_if_all_prices_are_stale() {
+        let max_age = Duration::from_secs(60);
+        let mut median = Median::new(
+            vec![
+                received(10_000, Duration::from_secs(120)),
+                received(20_000, Duration::from_secs(90)),
+            ],
+            1,
+            dec!(0.02),
+            max_age,
+        );
+
+        match median.latest_ask().unwrap_err() {
+            Error::Stale { age, max_age: max } => {
+                assert!(age >= Duration::from_secs(90));
+                assert_eq!(max, max_age);
+            }
+            error => panic!("Unexpected error {:?}", error),
+        }
+    }
+
+    /// A source that received `sats` as its latest price `ago`.
+    fn received(sats: u64, ago: Duration) -> PriceUpdates {
+        let (_, inner) = watch::channel(Ok(PriceUpdate {
+            ask: bitcoin::Amount::from_sat(sats),
+            received_at: Instant::now() - ago,
+        }));
+
+        PriceUpdates { inner }
+    }
+
+    #[tokio::test]
+    async fn median_of_mock_websocket_sources() {
+        let kraken = mock_websocket_server(r#"[980,{"a":["0.00440700",7,"7.35318535"],"b":["0.00440200",7,"7.57416678"],"c":["0.00440700","0.22579000"],"v":["273.75489000","4049.91233351"],"p":["0.00446205","0.00441699"],"t":[123,1310],"l":["0.00439400","0.00429900"],"h":["0.00450000","0.00450000"],"o":["0.00449100","0.00433700"]},"ticker","XMR/XBT"]"#).await;
+        let binance = mock_websocket_server(r#"{"u":400900217,"s":"XMRBTC","b":"0.00440200","B":"31.21000000","a":"0.00441700","A":"40.66000000"}"#).await;
+
+        let mut sources = vec![
+            Source::Kraken { url: kraken }.connect(),
+            Source::Binance { url: binance }.connect(),
+        ];
+        for source in sources.iter_mut() {
+            source.wait_for_next_update().await.unwrap().unwrap();
+        }
+        let mut median = Median::new(sources, 2, dec!(0.02), Duration::from_secs(60));
+
+        assert_eq!(
+            median.latest_ask().unwrap(),
+            bitcoin::Amount::from_sat(441_200)
+        );
+    }
+
+    /// Accepts a single websocket connection and sends `message` over it.
+    async fn mock_websocket_server(message: &'static str) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut websocket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            websocket.send(message.into()).await.unwrap();
+
+            // keep the connection open until the client goes away
+            while websocket.next().await.is_some() {}
+        });
+
+        url
+    }
+
+    #[test]
+    fn source_config_roundtrip() {
+        let sources = vec![Source::kraken(), Source::Rest {
+            url: "https://api.kraken.com/0/public/Ticker?pair=XMRXBT"
+                .parse()
+                .unwrap(),
+            ask_pointer: "/result/XXMRXXBT/a/0".to_string(),
+            poll_interval_secs: 30,
+        }];
+
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        struct Sources {
+            sources: Vec<Source>,
+        }
+
+        let serialized = toml::to_string(&Sources {
+            sources: sources.clone(),
+        })
+        .unwrap();
+        let deserialized = toml::from_str::<Sources>(&serialized).unwrap();
+
+        assert_eq!(deserialized.sources, sources);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::price_feed::{websocket, PriceUpdates};
+use serde::Deserialize;
+use url::Url;
+
+/// The book ticker stream pushes the best ask whenever it changes, without
+/// having to subscribe first.
+pub const DEFAULT_WEBSOCKET_URL: &str = "wss://stream.binance.com:9443/ws/xmrbtc@bookTicker";
+
+/// Connect to Binance websocket API for a constant stream of rate updates.
+///
+/// If the connection fails, it will automatically be re-established.
+pub fn connect(url: Url) -> PriceUpdates {
+    websocket::connect("Binance", url, None, parse_message)
+}
+
+fn parse_message(msg: &str) -> Option<bitcoin::Amount> {
+    let ticker = match serde_json::from_str::<BookTicker>(msg) {
+        Ok(ticker) => ticker,
+        Err(error) => {
+            tracing::warn!(%msg, "Failed to deserialize message as book ticker update. Error {:#}", error);
+            return None;
+        }
+    };
+
+    match bitcoin::Amount::from_str_in(&ticker.ask, bitcoin::Denomination::Bitcoin) {
+        Ok(ask) => Some(ask),
+        Err(error) => {
+            tracing::warn!(%msg, "Failed to parse Binance ask price. Error {:#}", error);
+            None
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BookTicker {
+    #[serde(rename = "a")]
+    ask: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ask_of_book_ticker_update() {
+        let message = r#"{"u":400900217,"s":"XMRBTC","b":"0.00440200","B":"31.21000000","a":"0.00440700","A":"40.66000000"}"#;
+
+        assert_eq!(
+            parse_message(message),
+            Some(bitcoin::Amount::from_sat(440_700))
+        );
+    }
+}
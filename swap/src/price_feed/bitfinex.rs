@@ -0,0 +1,92 @@
+use crate::price_feed::{websocket, PriceUpdates};
+use serde_json::Value;
+use url::Url;
+
+pub const DEFAULT_WEBSOCKET_URL: &str = "wss://api-pub.bitfinex.com/ws/2";
+
+const SUBSCRIBE_XMR_BTC_TICKER_PAYLOAD: &str =
+    r#"{ "event": "subscribe", "channel": "ticker", "symbol": "tXMRBTC" }"#;
+
+/// The position of the ask price within a ticker update.
+const ASK_INDEX: usize = 2;
+
+/// Connect to Bitfinex websocket API for a constant stream of rate updates.
+///
+/// If the connection fails, it will automatically be re-established.
+pub fn connect(url: Url) -> PriceUpdates {
+    websocket::connect(
+        "Bitfinex",
+        url,
+        Some(SUBSCRIBE_XMR_BTC_TICKER_PAYLOAD),
+        parse_message,
+    )
+}
+
+/// Bitfinex sends events as JSON objects and channel messages as arrays.
+/// Ticker updates look like `[CHANNEL_ID, [BID, BID_SIZE, ASK, ...]]`,
+/// heartbeats like `[CHANNEL_ID, "hb"]`.
+fn parse_message(msg: &str) -> Option<bitcoin::Amount> {
+    let message = match serde_json::from_str::<Value>(msg) {
+        Ok(message) => message,
+        Err(error) => {
+            tracing::warn!(%msg, "Failed to deserialize Bitfinex message. Error {:#}", error);
+            return None;
+        }
+    };
+
+    let ticker = match &message {
+        Value::Object(event) => {
+            tracing::debug!(event = ?event.get("event"), "Received Bitfinex event");
+            return None;
+        }
+        Value::Array(fields) => match fields.get(1) {
+            Some(Value::Array(ticker)) => ticker,
+            _ => {
+                tracing::trace!("Received heartbeat message");
+                return None;
+            }
+        },
+        _ => return None,
+    };
+
+    let ask = match ticker.get(ASK_INDEX).and_then(Value::as_f64) {
+        Some(ask) => ask,
+        None => {
+            tracing::warn!(%msg, "Bitfinex ticker update has no ask price");
+            return None;
+        }
+    };
+
+    match bitcoin::Amount::from_btc(ask) {
+        Ok(ask) => Some(ask),
+        Err(error) => {
+            tracing::warn!(%msg, "Failed to parse Bitfinex ask price. Error {:#}", error);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ask_of_ticker_update() {
+        let message =
+            r#"[17082,[0.0044,120.5,0.004407,98.1,0.0000127,0.0029,0.0044,1530.7,0.0045,0.0043]]"#;
+
+        assert_eq!(
+            parse_message(message),
+            Some(bitcoin::Amount::from_sat(440_700))
+        );
+    }
+
+    #[test]
+    fn ignores_events_and_heartbeats() {
+        let event = r#"{"event":"subscribed","channel":"ticker","chanId":17082,"symbol":"tXMRBTC","pair":"XMRBTC"}"#;
+        let heartbeat = r#"[17082,"hb"]"#;
+
+        assert_eq!(parse_message(event), None);
+        assert_eq!(parse_message(heartbeat), None);
+    }
+}
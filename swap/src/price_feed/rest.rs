@@ -0,0 +1,95 @@
+use crate::price_feed::{spawn, PriceUpdates};
+use anyhow::{bail, Context, Result};
+use futures::stream::{self, StreamExt};
+use serde_json::Value;
+use std::time::Duration;
+use url::Url;
+
+/// Polls a JSON API for the ask price every `poll_interval`.
+///
+/// `ask_pointer` is the JSON pointer to the ask price in BTC within the
+/// response. If a request fails, polling is resumed after a backoff.
+pub fn connect(url: Url, ask_pointer: String, poll_interval: Duration) -> PriceUpdates {
+    let client = reqwest::Client::new();
+
+    spawn("REST", move || {
+        let poller = Poller {
+            client: client.clone(),
+            url: url.clone(),
+            ask_pointer: ask_pointer.clone(),
+            interval: tokio::time::interval(poll_interval),
+        };
+
+        async move {
+            let stream = stream::unfold(poller, |mut poller| async move {
+                poller.interval.tick().await;
+                let ask = poller.fetch_ask().await;
+
+                Some((ask, poller))
+            })
+            .boxed();
+
+            Ok(stream)
+        }
+    })
+}
+
+struct Poller {
+    client: reqwest::Client,
+    url: Url,
+    ask_pointer: String,
+    interval: tokio::time::Interval,
+}
+
+impl Poller {
+    async fn fetch_ask(&self) -> Result<bitcoin::Amount> {
+        let response = self
+            .client
+            .get(self.url.clone())
+            .send()
+            .await
+            .with_context(|| format!("Failed to request {}", self.url))?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        let ask = response
+            .pointer(&self.ask_pointer)
+            .with_context(|| format!("Response has no value at {}", self.ask_pointer))?;
+
+        parse_ask(ask)
+    }
+}
+
+fn parse_ask(ask: &Value) -> Result<bitcoin::Amount> {
+    let ask = match ask {
+        Value::String(ask) => bitcoin::Amount::from_str_in(ask, bitcoin::Denomination::Bitcoin)?,
+        Value::Number(ask) => {
+            bitcoin::Amount::from_btc(ask.as_f64().context("Ask price is not a float")?)?
+        }
+        other => bail!("Expected ask price to be a string or number, got {}", other),
+    };
+
+    Ok(ask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_ask_given_as_string_or_number() {
+        let response = json!({ "result": { "XXMRXXBT": { "a": ["0.00440700", "7", "7.353"] } }, "ask": 0.004407 });
+
+        assert_eq!(
+            parse_ask(response.pointer("/result/XXMRXXBT/a/0").unwrap()).unwrap(),
+            bitcoin::Amount::from_sat(440_700)
+        );
+        assert_eq!(
+            parse_ask(response.pointer("/ask").unwrap()).unwrap(),
+            bitcoin::Amount::from_sat(440_700)
+        );
+        assert!(parse_ask(response.pointer("/result").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+//! Plumbing shared by the price sources that push their prices through a
+//! websocket.
+use super::{spawn, PriceUpdates};
+use anyhow::{bail, Context, Result};
+use futures::{future, SinkExt, StreamExt, TryStreamExt};
+use tokio_tungstenite::tungstenite;
+use url::Url;
+
+/// Connects to the websocket API of a price source and keeps the connection
+/// alive.
+///
+/// The `subscribe` message is sent right after connecting. Every text message
+/// is handed to `parse`, which returns `None` for messages that are not price
+/// updates.
+pub fn connect(
+    source: &'static str,
+    url: Url,
+    subscribe: Option<&'static str>,
+    parse: fn(&str) -> Option<bitcoin::Amount>,
+) -> PriceUpdates {
+    spawn(source, move || {
+        let url = url.clone();
+
+        async move {
+            let (mut rate_stream, _) = tokio_tungstenite::connect_async(url.as_str())
+                .await
+                .with_context(|| format!("Failed to connect to {} websocket API", source))?;
+
+            if let Some(subscribe) = subscribe {
+                rate_stream.send(subscribe.into()).await?;
+            }
+
+            let stream = rate_stream
+                .err_into()
+                .try_filter_map(move |msg| future::ready(parse_message(source, msg, parse)))
+                .boxed();
+
+            Ok(stream)
+        }
+    })
+}
+
+fn parse_message(
+    source: &'static str,
+    msg: tungstenite::Message,
+    parse: fn(&str) -> Option<bitcoin::Amount>,
+) -> Result<Option<bitcoin::Amount>> {
+    match msg {
+        tungstenite::Message::Text(msg) => Ok(parse(&msg)),
+        tungstenite::Message::Close(close_frame) => {
+            if let Some(tungstenite::protocol::CloseFrame { code, reason }) = close_frame {
+                tracing::debug!(
+                    "{} rate stream was closed with code {} and reason: {}",
+                    source,
+                    code,
+                    reason
+                );
+            } else {
+                tracing::debug!("{} rate stream was closed without code and reason", source);
+            }
+
+            bail!("The {} server closed the websocket connection", source)
+        }
+        msg => {
+            tracing::trace!(
+                "{} rate stream returned non text message that will be ignored: {}",
+                source,
+                msg
+            );
+
+            Ok(None)
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use crate::bitcoin::wallet::Watchable;
+use crate::database::Database;
 use crate::{bitcoin, monero};
+use anyhow::Result;
 use conquer_once::Lazy;
 use ecdsa_fun::fun::marker::Mark;
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use sigma_fun::ext::dl_secp256k1_ed25519_eq::{CrossCurveDLEQ, CrossCurveDLEQProof};
 use sigma_fun::HashTranscript;
+use time::OffsetDateTime;
+use tokio::sync::watch;
 use uuid::Uuid;
 
 pub mod alice;
@@ -32,6 +37,10 @@ pub struct Message0 {
     tx_refund_fee: bitcoin::Amount,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     tx_cancel_fee: bitcoin::Amount,
+    /// Whether Bob accepts an anchor output in the redeem and punish
+    /// transactions, missing for Bobs that predate it.
+    #[serde(default)]
+    anchor_outputs: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -47,6 +56,9 @@ pub struct Message1 {
     tx_redeem_fee: bitcoin::Amount,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     tx_punish_fee: bitcoin::Amount,
+    /// Only sent to Bobs that accept anchor outputs.
+    #[serde(default)]
+    anchor_address: Option<bitcoin::Address>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -65,3 +77,33 @@ pub struct Message4 {
     tx_punish_sig: bitcoin::Signature,
     tx_cancel_sig: bitcoin::Signature,
 }
+
+/// Makes the Bitcoin wallet watch the lock and cancel outputs of a swap from
+/// the start of the swap on. All other Bitcoin transactions of the swap
+/// spend one of them.
+pub async fn watch_swap_outputs(
+    bitcoin_wallet: &bitcoin::Wallet,
+    db: &Database,
+    swap_id: Uuid,
+    tx_lock: &bitcoin::TxLock,
+    tx_cancel: &bitcoin::TxCancel,
+) -> Result<()> {
+    let started_at = db
+        .history(swap_id)?
+        .first()
+        .map_or_else(OffsetDateTime::now_utc, |transition| transition.timestamp);
+
+    bitcoin_wallet
+        .watch(&[tx_lock.script(), tx_cancel.script()], started_at)
+        .await
+}
+
+/// Resolves once a stop of a swap is requested through `stop`.
+pub(crate) async fn stop_requested(stop: &mut watch::Receiver<bool>) {
+    while !*stop.borrow() {
+        if stop.changed().await.is_err() {
+            // Without a sender a stop can never be requested.
+            std::future::pending::<()>().await
+        }
+    }
+}
@@ -15,15 +15,17 @@ pub use self::recovery::refund::refund;
 pub use self::recovery::safely_abort::safely_abort;
 pub use self::recovery::{cancel, punish, redeem, refund, safely_abort};
 pub use self::state::*;
-pub use self::swap::{run, run_until};
+pub use self::swap::{run, run_until, run_until_stopped};
 
 mod behaviour;
 pub mod event_loop;
 mod execution_setup;
 mod recovery;
-mod spot_price;
+pub mod rendezvous;
+pub mod spot_price;
 pub mod state;
 pub mod swap;
+pub mod taker;
 
 pub struct Swap {
     pub state: AliceState,
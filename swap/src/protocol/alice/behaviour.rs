@@ -1,12 +1,16 @@
-use crate::network::quote::BidQuote;
-use crate::network::{encrypted_signature, quote, transfer_proof};
+use crate::asb::VolumeTiers;
+use crate::network::quote::{AskQuote, BidQuote, TieredBidQuote};
+use crate::network::{ask_spot_price, encrypted_signature, quote, transfer_proof};
 use crate::protocol::alice::event_loop::LatestRate;
-use crate::protocol::alice::{execution_setup, spot_price, State3};
+use crate::protocol::alice::{execution_setup, rendezvous, spot_price, State3};
+use crate::protocol::bob;
 use crate::{env, monero};
 use anyhow::{anyhow, Error};
 use libp2p::ping::{Ping, PingEvent};
 use libp2p::request_response::{RequestId, ResponseChannel};
+use libp2p::swarm::toggle::Toggle;
 use libp2p::{NetworkBehaviour, PeerId};
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -24,6 +28,10 @@ pub enum OutEvent {
         channel: ResponseChannel<BidQuote>,
         peer: PeerId,
     },
+    TieredQuoteRequested {
+        channel: ResponseChannel<TieredBidQuote>,
+        peer: PeerId,
+    },
     ExecutionSetupDone {
         bob_peer_id: PeerId,
         swap_id: Uuid,
@@ -38,6 +46,33 @@ pub enum OutEvent {
         channel: ResponseChannel<()>,
         peer: PeerId,
     },
+    AskQuoteRequested {
+        channel: ResponseChannel<AskQuote>,
+        peer: PeerId,
+    },
+    AskSpotPriceRequested {
+        request: Box<ask_spot_price::Request>,
+        channel: ResponseChannel<ask_spot_price::Response>,
+        peer: PeerId,
+    },
+    AskExecutionSetupDone {
+        taker_peer_id: PeerId,
+        swap_id: Uuid,
+        state2: Box<bob::State2>,
+    },
+    TransferProofReceived {
+        msg: Box<transfer_proof::Request>,
+        channel: ResponseChannel<()>,
+        peer: PeerId,
+    },
+    EncryptedSignatureAcknowledged {
+        peer: PeerId,
+        id: RequestId,
+    },
+    Registered {
+        rendezvous_node: PeerId,
+        ttl: u64,
+    },
     Failure {
         peer: PeerId,
         error: Error,
@@ -64,6 +99,10 @@ impl OutEvent {
 }
 
 /// A `NetworkBehaviour` that represents an XMR/BTC swap node as Alice.
+///
+/// The `ask_*` protocols allow takers to sell XMR to us, in which case we act
+/// as Bob in the swap. If a rendezvous point is configured, we register at it
+/// so that CLIs can discover us.
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
 #[allow(missing_debug_implementations)]
@@ -72,10 +111,15 @@ where
     LR: LatestRate + Send + 'static,
 {
     pub quote: quote::Behaviour,
+    pub tiered_quote: quote::TieredBehaviour,
     pub spot_price: spot_price::Behaviour<LR>,
     pub execution_setup: execution_setup::Behaviour,
     pub transfer_proof: transfer_proof::Behaviour,
     pub encrypted_signature: encrypted_signature::Behaviour,
+    pub ask_quote: quote::AskBehaviour,
+    pub ask_spot_price: ask_spot_price::Behaviour,
+    pub ask_execution_setup: bob::maker::execution_setup::Behaviour,
+    pub rendezvous: Toggle<rendezvous::Behaviour>,
 
     /// Ping behaviour that ensures that the underlying network connection is
     /// still alive. If the ping fails a connection close event will be
@@ -87,17 +131,22 @@ impl<LR> Behaviour<LR>
 where
     LR: LatestRate + Send + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         balance: monero::Amount,
         lock_fee: monero::Amount,
         min_buy: bitcoin::Amount,
         max_buy: bitcoin::Amount,
         latest_rate: LR,
+        volume_tiers: VolumeTiers,
+        quote_validity: Duration,
         resume_only: bool,
         env_config: env::Config,
+        rendezvous: Option<rendezvous::Behaviour>,
     ) -> Self {
         Self {
             quote: quote::alice(),
+            tiered_quote: quote::tiered_alice(),
             spot_price: spot_price::Behaviour::new(
                 balance,
                 lock_fee,
@@ -105,11 +154,17 @@ where
                 max_buy,
                 env_config,
                 latest_rate,
+                volume_tiers,
+                quote_validity,
                 resume_only,
             ),
             execution_setup: Default::default(),
-            transfer_proof: transfer_proof::alice(),
-            encrypted_signature: encrypted_signature::alice(),
+            transfer_proof: transfer_proof::asb(),
+            encrypted_signature: encrypted_signature::asb(),
+            ask_quote: quote::ask_maker(),
+            ask_spot_price: ask_spot_price::maker(),
+            ask_execution_setup: Default::default(),
+            rendezvous: Toggle::from(rendezvous),
             ping: Ping::default(),
         }
     }
@@ -1,12 +1,18 @@
-use crate::asb::Rate;
-use crate::database::Database;
+use crate::asb::admin::RunningSwaps;
+use crate::asb::metrics::Metrics;
+use crate::asb::{Inventory, Ledger, Rate, RateLimit, SpreadCurve};
+use crate::database::{Alice, Bob, Database};
 use crate::env::Config;
-use crate::network::quote::BidQuote;
-use crate::network::transfer_proof;
+use crate::network::quote::{AskQuote, BidQuote};
+use crate::network::spot_price::BlockchainNetwork;
+use crate::network::{ask_spot_price, encrypted_signature, transfer_proof};
 use crate::protocol::alice::spot_price::Error;
 use crate::protocol::alice::{AliceState, Behaviour, OutEvent, State0, State3, Swap};
-use crate::{bitcoin, kraken, monero};
-use anyhow::{Context, Result};
+use crate::protocol::bob;
+use crate::protocol::bob::maker;
+use crate::protocol::bob::BobState;
+use crate::{bitcoin, database, monero, price_feed};
+use anyhow::{anyhow, Context, Result};
 use futures::future;
 use futures::future::{BoxFuture, FutureExt};
 use futures::stream::{FuturesUnordered, StreamExt};
@@ -18,7 +24,7 @@ use rust_decimal::Decimal;
 use std::collections::HashMap;
 use std::convert::Infallible;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use tokio::sync::mpsc;
 use uuid::Uuid;
 
@@ -32,6 +38,14 @@ use uuid::Uuid;
 type OutgoingTransferProof =
     BoxFuture<'static, Result<(PeerId, transfer_proof::Request, bmrng::Responder<()>)>>;
 
+/// A future that resolves to a tuple of `PeerId`,
+/// `encrypted_signature::Request` and `Responder`.
+///
+/// This is the counterpart of [`OutgoingTransferProof`] for swaps in which we
+/// are Bob.
+type OutgoingEncryptedSignature =
+    BoxFuture<'static, Result<(PeerId, encrypted_signature::Request, bmrng::Responder<()>)>>;
+
 #[allow(missing_debug_implementations)]
 pub struct EventLoop<LR>
 where
@@ -40,14 +54,34 @@ where
     swarm: libp2p::Swarm<Behaviour<LR>>,
     env_config: Config,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
+    /// Receives the BTC of redeemed and punished swaps instead of the
+    /// internal wallet, if configured.
+    cold_storage: Option<Arc<bitcoin::ColdStorage>>,
     monero_wallet: Arc<monero::Wallet>,
     db: Arc<Database>,
     latest_rate: LR,
     min_buy: bitcoin::Amount,
     max_buy: bitcoin::Amount,
+    min_buy_xmr: monero::Amount,
+    max_buy_xmr: monero::Amount,
+    /// The BTC reserved for swaps in which we buy XMR.
+    btc_ledger: Ledger<bitcoin::Amount>,
+    /// Limits the swaps in which we buy XMR, because we lock our BTC first.
+    buy_xmr_rate_limit: RateLimit,
+    /// The cold storage addresses handed to peers whose execution setup is
+    /// not done yet.
+    pending_proceeds_addresses: HashMap<PeerId, [bitcoin::Address; 2]>,
+    /// Runs the swaps in which we buy XMR. Shared with the receiver of
+    /// `swap_sender`, which runs the others, so that any swap can be stopped.
+    running_swaps: RunningSwaps,
 
     swap_sender: mpsc::Sender<Swap>,
 
+    control_sender: mpsc::Sender<ControlCommand>,
+    control_receiver: mpsc::Receiver<ControlCommand>,
+
+    metrics: Metrics,
+
     /// Stores incoming [`EncryptedSignature`]s per swap.
     recv_encrypted_signature: HashMap<Uuid, bmrng::RequestSender<bitcoin::EncryptedSignature, ()>>,
     inflight_encrypted_signatures: FuturesUnordered<BoxFuture<'static, ResponseChannel<()>>>,
@@ -61,6 +95,21 @@ where
     /// Tracks [`transfer_proof::Request`]s which are currently inflight and
     /// awaiting an acknowledgement.
     inflight_transfer_proofs: HashMap<RequestId, bmrng::Responder<()>>,
+
+    /// Stores incoming [`TransferProof`]s per swap in which we are Bob.
+    recv_transfer_proof: HashMap<Uuid, bmrng::RequestSender<monero::TransferProof, ()>>,
+    inflight_received_transfer_proofs: FuturesUnordered<BoxFuture<'static, ResponseChannel<()>>>,
+
+    send_encrypted_signature: FuturesUnordered<OutgoingEncryptedSignature>,
+
+    /// Tracks [`encrypted_signature::Request`]s which could not yet be sent
+    /// because we are currently disconnected from the peer.
+    buffered_encrypted_signatures:
+        HashMap<PeerId, Vec<(encrypted_signature::Request, bmrng::Responder<()>)>>,
+
+    /// Tracks [`encrypted_signature::Request`]s which are currently inflight
+    /// and awaiting an acknowledgement.
+    inflight_sent_encrypted_signatures: HashMap<RequestId, bmrng::Responder<()>>,
 }
 
 impl<LR> EventLoop<LR>
@@ -72,29 +121,49 @@ where
         swarm: Swarm<Behaviour<LR>>,
         env_config: Config,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
+        cold_storage: Option<Arc<bitcoin::ColdStorage>>,
         monero_wallet: Arc<monero::Wallet>,
         db: Arc<Database>,
         latest_rate: LR,
         min_buy: bitcoin::Amount,
         max_buy: bitcoin::Amount,
+        min_buy_xmr: monero::Amount,
+        max_buy_xmr: monero::Amount,
+        max_buy_xmr_swaps_per_hour: usize,
     ) -> Result<(Self, mpsc::Receiver<Swap>)> {
         let swap_channel = MpscChannels::default();
+        let control_channel = MpscChannels::default();
 
         let event_loop = EventLoop {
             swarm,
             env_config,
             bitcoin_wallet,
+            cold_storage,
             monero_wallet,
             db,
             latest_rate,
             swap_sender: swap_channel.sender,
+            control_sender: control_channel.sender,
+            control_receiver: control_channel.receiver,
+            metrics: Metrics::default(),
             min_buy,
             max_buy,
+            min_buy_xmr,
+            max_buy_xmr,
+            btc_ledger: Ledger::new(bitcoin::Amount::ZERO),
+            buy_xmr_rate_limit: RateLimit::per_hour(max_buy_xmr_swaps_per_hour),
+            pending_proceeds_addresses: Default::default(),
+            running_swaps: RunningSwaps::default(),
             recv_encrypted_signature: Default::default(),
             inflight_encrypted_signatures: Default::default(),
             send_transfer_proof: Default::default(),
             buffered_transfer_proofs: Default::default(),
             inflight_transfer_proofs: Default::default(),
+            recv_transfer_proof: Default::default(),
+            inflight_received_transfer_proofs: Default::default(),
+            send_encrypted_signature: Default::default(),
+            buffered_encrypted_signatures: Default::default(),
+            inflight_sent_encrypted_signatures: Default::default(),
         };
         Ok((event_loop, swap_channel.receiver))
     }
@@ -103,12 +172,34 @@ where
         *Swarm::local_peer_id(&self.swarm)
     }
 
+    /// Returns a controller that changes the settings of this event loop while
+    /// it is running.
+    pub fn controller(&self) -> EventLoopController {
+        EventLoopController {
+            sender: self.control_sender.clone(),
+        }
+    }
+
+    /// Returns the metrics collected by this event loop.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Returns the swaps in which we buy XMR that this event loop runs. The
+    /// swaps handed out by the swap receiver should be spawned into them too.
+    pub fn running_swaps(&self) -> RunningSwaps {
+        self.running_swaps.clone()
+    }
+
     pub async fn run(mut self) {
         // ensure that these streams are NEVER empty, otherwise it will
         // terminate forever.
         self.send_transfer_proof.push(future::pending().boxed());
         self.inflight_encrypted_signatures
             .push(future::pending().boxed());
+        self.send_encrypted_signature.push(future::pending().boxed());
+        self.inflight_received_transfer_proofs
+            .push(future::pending().boxed());
 
         let unfinished_swaps = match self.db.unfinished_alice() {
             Ok(unfinished_swaps) => unfinished_swaps,
@@ -119,32 +210,19 @@ where
         };
 
         for (swap_id, state) in unfinished_swaps {
-            let peer_id = match self.db.get_peer_id(swap_id) {
-                Ok(peer_id) => peer_id,
-                Err(_) => {
-                    tracing::warn!(%swap_id, "Resuming swap skipped because no peer-id found for swap in database");
-                    continue;
-                }
-            };
-
-            let handle = self.new_handle(peer_id, swap_id);
-
-            let swap = Swap {
-                event_loop_handle: handle,
-                bitcoin_wallet: self.bitcoin_wallet.clone(),
-                monero_wallet: self.monero_wallet.clone(),
-                env_config: self.env_config,
-                db: self.db.clone(),
-                state: state.into(),
-                swap_id,
-            };
-
-            match self.swap_sender.send(swap).await {
-                Ok(_) => tracing::info!(%swap_id, "Resuming swap"),
-                Err(_) => {
-                    tracing::warn!(%swap_id, "Failed to resume swap because receiver has been dropped")
-                }
+            self.resume_swap(swap_id, state.into()).await;
+        }
+
+        let unfinished_maker_swaps = match self.db.unfinished_bob() {
+            Ok(unfinished_maker_swaps) => unfinished_maker_swaps,
+            Err(_) => {
+                tracing::error!("Failed to load unfinished swaps in which we buy XMR");
+                return;
             }
+        };
+
+        for (swap_id, state) in unfinished_maker_swaps {
+            self.resume_maker_swap(swap_id, state.into());
         }
 
         loop {
@@ -152,38 +230,47 @@ where
                 swarm_event = self.swarm.next_event() => {
                     match swarm_event {
                         SwarmEvent::Behaviour(OutEvent::ExecutionSetupStart { peer, btc, xmr }) => {
+                            self.metrics.spot_price_requested();
 
+                            let anchor_weight = match self.cold_storage {
+                                Some(_) => bitcoin::ANCHOR_WEIGHT,
+                                None => 0,
+                            };
                             let tx_redeem_fee = self.bitcoin_wallet
-                                .estimate_fee(bitcoin::TxRedeem::weight(), btc)
+                                .estimate_fee(bitcoin::TxRedeem::weight() + anchor_weight, btc)
                                 .await;
                             let tx_punish_fee = self.bitcoin_wallet
-                                .estimate_fee(bitcoin::TxPunish::weight(), btc)
+                                .estimate_fee(bitcoin::TxPunish::weight() + anchor_weight, btc)
                                 .await;
-                            let redeem_address = self.bitcoin_wallet.new_address().await;
-                            let punish_address = self.bitcoin_wallet.new_address().await;
 
-                            let (redeem_address, punish_address) = match (
-                                redeem_address,
-                                punish_address,
+                            let (tx_redeem_fee, tx_punish_fee) = match (
+                                tx_redeem_fee,
+                                tx_punish_fee,
                             ) {
-                                (Ok(redeem_address), Ok(punish_address)) => {
-                                    (redeem_address, punish_address)
+                                (Ok(tx_redeem_fee), Ok(tx_punish_fee)) => {
+                                    (tx_redeem_fee, tx_punish_fee)
                                 }
                                 _ => {
-                                    tracing::error!(%peer, "Failed to get new address during execution setup");
+                                    tracing::error!(%peer, "Failed to calculate transaction fees during execution setup");
+                                    self.ledger().release_peer(&peer);
                                     continue;
                                 }
                             };
 
-                            let (tx_redeem_fee, tx_punish_fee) = match (
-                                tx_redeem_fee,
-                                tx_punish_fee,
+                            let proceeds_addresses = self.proceeds_addresses(peer).await;
+                            let anchor_address = self.anchor_address().await;
+
+                            let ((redeem_address, punish_address), anchor_address) = match (
+                                proceeds_addresses,
+                                anchor_address,
                             ) {
-                                (Ok(tx_redeem_fee), Ok(tx_punish_fee)) => {
-                                    (tx_redeem_fee, tx_punish_fee)
+                                (Ok(proceeds_addresses), Ok(anchor_address)) => {
+                                    (proceeds_addresses, anchor_address)
                                 }
                                 _ => {
-                                    tracing::error!(%peer, "Failed to calculate transaction fees during execution setup");
+                                    tracing::error!(%peer, "Failed to get new address during execution setup");
+                                    self.release_proceeds_addresses(&peer).await;
+                                    self.ledger().release_peer(&peer);
                                     continue;
                                 }
                             };
@@ -194,6 +281,7 @@ where
                                 self.env_config,
                                 redeem_address,
                                 punish_address,
+                                anchor_address,
                                 tx_redeem_fee,
                                 tx_punish_fee,
                                 &mut OsRng
@@ -201,6 +289,8 @@ where
                                 Ok(state) => state,
                                 Err(error) => {
                                     tracing::warn!(%peer, "Failed to make State0 for execution setup. Error {:#}", error);
+                                    self.release_proceeds_addresses(&peer).await;
+                                    self.ledger().release_peer(&peer);
                                     continue;
                                 }
                             };
@@ -208,6 +298,9 @@ where
                             self.swarm.behaviour_mut().execution_setup.run(peer, state0);
                         }
                         SwarmEvent::Behaviour(OutEvent::SwapRequestDeclined { peer, error }) => {
+                            self.metrics.spot_price_requested();
+                            self.metrics.swap_request_declined(&error);
+
                             match error {
                                 Error::ResumeOnlyMode
                                 | Error::AmountBelowMinimum { .. }
@@ -223,16 +316,10 @@ where
                             }
                         }
                         SwarmEvent::Behaviour(OutEvent::QuoteRequested { channel, peer }) => {
+                            self.metrics.quote_requested();
+
                             // TODO: Move the spot-price update into dedicated update stream to decouple it from quote requests
-                            let current_balance = self.monero_wallet.get_balance().await;
-                            match current_balance {
-                                Ok(balance) => {
-                                    self.swarm.behaviour_mut().spot_price.update_balance(balance);
-                                }
-                                Err(e) => {
-                                    tracing::error!("Failed to fetch Monero balance: {:#}", e);
-                                }
-                            }
+                            self.update_balances().await;
 
                             let quote = match self.make_quote(self.min_buy, self.max_buy).await {
                                 Ok(quote) => quote,
@@ -246,7 +333,127 @@ where
                                 tracing::debug!(%peer, "Failed to respond with quote");
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::TieredQuoteRequested { channel, peer }) => {
+                            self.metrics.quote_requested();
+                            self.update_balances().await;
+
+                            let quote = match self.swarm.behaviour_mut().spot_price.make_tiered_quote(peer) {
+                                Ok(quote) => quote,
+                                Err(error) => {
+                                    tracing::warn!(%peer, "Failed to make tiered quote. Error {:#}", error);
+                                    continue;
+                                }
+                            };
+
+                            if self.swarm.behaviour_mut().tiered_quote.send_response(channel, quote).is_err() {
+                                tracing::debug!(%peer, "Failed to respond with tiered quote");
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::AskQuoteRequested { channel, peer }) => {
+                            let quote = match self.make_ask_quote() {
+                                Ok(quote) => quote,
+                                Err(error) => {
+                                    tracing::warn!(%peer, "Failed to make ask quote. Error {:#}", error);
+                                    continue;
+                                }
+                            };
+
+                            if self.swarm.behaviour_mut().ask_quote.send_response(channel, quote).is_err() {
+                                tracing::debug!(%peer, "Failed to respond with ask quote");
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::AskSpotPriceRequested { request, channel, peer }) => {
+                            let response = match self.handle_ask_spot_price_request(peer, *request).await {
+                                Ok(btc) => ask_spot_price::Response::Btc(btc),
+                                Err(error) => {
+                                    match error {
+                                        maker::spot_price::Error::ResumeOnlyMode
+                                        | maker::spot_price::Error::BuyingXmrDisabled
+                                        | maker::spot_price::Error::AmountBelowMinimum { .. }
+                                        | maker::spot_price::Error::AmountAboveMaximum { .. }
+                                        | maker::spot_price::Error::BlockchainNetworkMismatch { .. }
+                                        | maker::spot_price::Error::DuplicateSwapId(_)
+                                        | maker::spot_price::Error::TooManySwaps { .. }
+                                        | maker::spot_price::Error::SetupInProgress => {
+                                            tracing::warn!(%peer, "Ignoring ask spot price request because: {}", error);
+                                        }
+                                        maker::spot_price::Error::BalanceTooLow { .. }
+                                        | maker::spot_price::Error::LatestRateFetchFailed(_)
+                                        | maker::spot_price::Error::BuyQuoteCalculationFailed(_)
+                                        | maker::spot_price::Error::ExecutionSetupPreparationFailed(_) => {
+                                            tracing::error!(%peer, "Ignoring ask spot price request because: {}", error);
+                                        }
+                                    }
+
+                                    ask_spot_price::Response::Error(error.to_error_response())
+                                }
+                            };
+
+                            if self.swarm.behaviour_mut().ask_spot_price.send_response(channel, response).is_err() {
+                                tracing::debug!(%peer, "Failed to respond with ask spot price");
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::AskExecutionSetupDone { taker_peer_id, swap_id, state2 }) => {
+                            self.handle_ask_execution_setup_done(taker_peer_id, swap_id, *state2).await;
+                        }
+                        SwarmEvent::Behaviour(OutEvent::TransferProofReceived { msg, channel, peer }) => {
+                            let swap_id = msg.swap_id;
+                            let swap_peer = self.db.get_peer_id(swap_id);
+
+                            // Ensure that an incoming transfer proof is sent by the peer-id associated with the swap
+                            let swap_peer = match swap_peer {
+                                Ok(swap_peer) => swap_peer,
+                                Err(_) => {
+                                    tracing::warn!(
+                                        unknown_swap_id = %swap_id,
+                                        from = %peer,
+                                        "Ignoring transfer proof for unknown swap");
+                                    continue;
+                                }
+                            };
+
+                            if swap_peer != peer {
+                                tracing::warn!(
+                                    %swap_id,
+                                    received_from = %peer,
+                                    expected_from = %swap_peer,
+                                    "Ignoring malicious transfer proof which was not expected from this peer",
+                                    );
+                                continue;
+                            }
+
+                            let sender = match self.recv_transfer_proof.remove(&swap_id) {
+                                Some(sender) => sender,
+                                None => {
+                                    // The swap already received the transfer proof, we still have to acknowledge it
+                                    tracing::warn!(%swap_id, "No running swap for transfer proof, ignoring it");
+                                    let _ = self.swarm.behaviour_mut().transfer_proof.send_response(channel, ());
+                                    continue;
+                                }
+                            };
+
+                            let mut responder = match sender.send(msg.tx_lock_proof).await {
+                                Ok(responder) => responder,
+                                Err(_) => {
+                                    tracing::warn!(%swap_id, "Failed to relay transfer proof to swap");
+                                    continue;
+                                }
+                            };
+
+                            self.inflight_received_transfer_proofs.push(async move {
+                                let _ = responder.recv().await;
+
+                                channel
+                            }.boxed());
+                        }
+                        SwarmEvent::Behaviour(OutEvent::EncryptedSignatureAcknowledged { peer, id }) => {
+                            tracing::debug!(%peer, "Taker acknowledged encrypted signature");
+                            if let Some(responder) = self.inflight_sent_encrypted_signatures.remove(&id) {
+                                let _ = responder.respond(());
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::ExecutionSetupDone{bob_peer_id, swap_id, state3}) => {
+                            self.pending_proceeds_addresses.remove(&bob_peer_id);
                             let _ = self.handle_execution_setup_done(bob_peer_id, swap_id, *state3).await;
                         }
                         SwarmEvent::Behaviour(OutEvent::TransferProofAcknowledged { peer, id }) => {
@@ -284,16 +491,17 @@ where
                             let sender = match self.recv_encrypted_signature.remove(&swap_id) {
                                 Some(sender) => sender,
                                 None => {
-                                    // TODO: Don't just drop encsig if we currently don't have a running swap for it, save in db
-                                    tracing::warn!(%swap_id, "No sender for encrypted signature, maybe already handled?");
+                                    tracing::info!(%swap_id, "No running swap for encrypted signature, saving it in the database");
+                                    self.save_encrypted_signature(swap_id, msg.tx_redeem_encsig, channel).await;
                                     continue;
                                 }
                             };
 
-                            let mut responder = match sender.send(msg.tx_redeem_encsig).await {
+                            let mut responder = match sender.send(msg.tx_redeem_encsig.clone()).await {
                                 Ok(responder) => responder,
                                 Err(_) => {
-                                    tracing::warn!(%swap_id, "Failed to relay encrypted signature to swap");
+                                    tracing::warn!(%swap_id, "Failed to relay encrypted signature to swap, saving it in the database");
+                                    self.save_encrypted_signature(swap_id, msg.tx_redeem_encsig, channel).await;
                                     continue;
                                 }
                             };
@@ -304,13 +512,23 @@ where
                                 channel
                             }.boxed());
                         }
+                        SwarmEvent::Behaviour(OutEvent::Registered { rendezvous_node, ttl }) => {
+                            tracing::info!(%rendezvous_node, "Registered with rendezvous point for {}s", ttl);
+                        }
                         SwarmEvent::Behaviour(OutEvent::Failure {peer, error}) => {
                             tracing::error!(
                                 %peer,
                                 "Communication error. Error {:#}", error);
+
+                            // The peer may have failed the execution setup, which leaves the funds we
+                            // reserved for it without a swap
+                            self.release_proceeds_addresses(&peer).await;
+                            self.ledger().release_peer(&peer);
+                            self.btc_ledger.release_peer(&peer);
                         }
                         SwarmEvent::ConnectionEstablished { peer_id: peer, endpoint, .. } => {
                             tracing::debug!(%peer, address = %endpoint.get_remote_address(), "New connection established");
+                            self.metrics.set_connected_peers(self.swarm.network_info().num_peers());
 
                             if let Some(transfer_proofs) = self.buffered_transfer_proofs.remove(&peer) {
                                 for (transfer_proof, responder) in transfer_proofs {
@@ -320,11 +538,25 @@ where
                                     self.inflight_transfer_proofs.insert(id, responder);
                                 }
                             }
+
+                            if let Some(encrypted_signatures) = self.buffered_encrypted_signatures.remove(&peer) {
+                                for (encrypted_signature, responder) in encrypted_signatures {
+                                    tracing::debug!(%peer, "Found buffered encrypted signature for peer");
+
+                                    let id = self.swarm.behaviour_mut().encrypted_signature.send_request(&peer, encrypted_signature);
+                                    self.inflight_sent_encrypted_signatures.insert(id, responder);
+                                }
+                            }
                         }
                         SwarmEvent::IncomingConnectionError { send_back_addr: address, error, .. } => {
                             tracing::warn!(%address, "Failed to set up connection with peer. Error {:#}", error);
                         }
                         SwarmEvent::ConnectionClosed { peer_id: peer, num_established, endpoint, cause } if num_established == 0 => {
+                            self.metrics.set_connected_peers(self.swarm.network_info().num_peers());
+
+                            // An execution setup cannot complete without a connection
+                            self.release_proceeds_addresses(&peer).await;
+
                             match cause {
                                 Some(error) => {
                                     tracing::warn!(%peer, address = %endpoint.get_remote_address(), "Lost connection. Error {:#}", error);
@@ -363,10 +595,132 @@ where
                 Some(response_channel) = self.inflight_encrypted_signatures.next() => {
                     let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(response_channel, ());
                 }
+                next_encrypted_signature = self.send_encrypted_signature.next() => {
+                    match next_encrypted_signature {
+                        Some(Ok((peer, encrypted_signature, responder))) => {
+                            if !self.swarm.behaviour_mut().encrypted_signature.is_connected(&peer) {
+                                tracing::warn!(%peer, "No active connection to peer, buffering encrypted signature");
+                                self.buffered_encrypted_signatures.entry(peer).or_insert_with(Vec::new).push((encrypted_signature, responder));
+                                continue;
+                            }
+
+                            let id = self.swarm.behaviour_mut().encrypted_signature.send_request(&peer, encrypted_signature);
+                            self.inflight_sent_encrypted_signatures.insert(id, responder);
+                        },
+                        Some(Err(error)) => {
+                            tracing::debug!("A swap stopped without sending an encrypted signature. Error {:#}", error);
+                        }
+                        None => {
+                            unreachable!("stream of encrypted signature receivers must never terminate")
+                        }
+                    }
+                }
+                Some(response_channel) = self.inflight_received_transfer_proofs.next() => {
+                    let _ = self.swarm.behaviour_mut().transfer_proof.send_response(response_channel, ());
+                }
+                Some(command) = self.control_receiver.recv() => {
+                    self.handle_control_command(command).await;
+                }
             }
         }
     }
 
+    /// Addresses for the BTC we receive by redeeming or punishing the swap
+    /// that `peer` sets up.
+    ///
+    /// Cold storage addresses are remembered until the execution setup is
+    /// done, so that they can be handed out again if it fails. Otherwise
+    /// peers that abandon the setup would use up addresses beyond the gap
+    /// limit of the cold storage wallet.
+    async fn proceeds_addresses(
+        &mut self,
+        peer: PeerId,
+    ) -> Result<(bitcoin::Address, bitcoin::Address)> {
+        let cold_storage = match &self.cold_storage {
+            Some(cold_storage) => cold_storage.clone(),
+            None => {
+                let redeem_address = self.bitcoin_wallet.new_address().await?;
+                let punish_address = self.bitcoin_wallet.new_address().await?;
+
+                return Ok((redeem_address, punish_address));
+            }
+        };
+
+        // A new setup of the same peer replaces its previous one
+        self.release_proceeds_addresses(&peer).await;
+
+        let redeem_address = cold_storage.new_address().await?;
+        let punish_address = match cold_storage.new_address().await {
+            Ok(punish_address) => punish_address,
+            Err(error) => {
+                cold_storage.reuse(redeem_address).await;
+                return Err(error);
+            }
+        };
+
+        self.pending_proceeds_addresses
+            .insert(peer, [redeem_address.clone(), punish_address.clone()]);
+
+        Ok((redeem_address, punish_address))
+    }
+
+    /// Hands the cold storage addresses of a peer whose execution setup did
+    /// not complete out again.
+    async fn release_proceeds_addresses(&mut self, peer: &PeerId) {
+        let addresses = match self.pending_proceeds_addresses.remove(peer) {
+            Some(addresses) => addresses,
+            None => return,
+        };
+
+        if let Some(cold_storage) = &self.cold_storage {
+            for address in addresses.iter().cloned() {
+                cold_storage.reuse(address).await;
+            }
+        }
+    }
+
+    /// An address of our wallet for the anchor output of the redeem and
+    /// punish transactions, which we only need if their proceeds go to cold
+    /// storage.
+    async fn anchor_address(&self) -> Result<Option<bitcoin::Address>> {
+        match &self.cold_storage {
+            Some(_) => Ok(Some(self.bitcoin_wallet.new_address().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Passes our current balances on to the spot price behaviour and the
+    /// rate.
+    async fn update_balances(&mut self) {
+        let balance = match self.monero_wallet.get_balance().await {
+            Ok(balance) => balance,
+            Err(e) => {
+                tracing::error!("Failed to fetch Monero balance: {:#}", e);
+                return;
+            }
+        };
+        self.swarm
+            .behaviour_mut()
+            .spot_price
+            .update_balance(balance);
+
+        match self.bitcoin_wallet.balance().await {
+            Ok(btc) => {
+                let inventory = Inventory { xmr: balance, btc };
+                self.latest_rate.update_inventory(inventory);
+                self.swarm
+                    .behaviour_mut()
+                    .spot_price
+                    .update_inventory(inventory);
+            }
+            Err(e) => {
+                tracing::error!("Failed to fetch Bitcoin balance: {:#}", e);
+            }
+        }
+    }
+
+    /// Quotes the price of the lowest volume tier, which is what peers that
+    /// do not know about tiers pay for the minimum amount.
     async fn make_quote(
         &mut self,
         min_buy: bitcoin::Amount,
@@ -376,6 +730,12 @@ where
             .latest_rate
             .latest_rate()
             .context("Failed to get latest rate")?;
+        let rate = self
+            .swarm
+            .behaviour()
+            .spot_price
+            .volume_tiers()
+            .rate_for(rate, min_buy);
 
         Ok(BidQuote {
             price: rate.ask().context("Failed to compute asking price")?,
@@ -384,12 +744,188 @@ where
         })
     }
 
+    fn make_ask_quote(&mut self) -> Result<AskQuote> {
+        let rate = self
+            .latest_rate
+            .latest_rate()
+            .context("Failed to get latest rate")?;
+
+        Ok(AskQuote {
+            price: rate.bid().context("Failed to compute bidding price")?,
+            min_quantity: self.min_buy_xmr,
+            max_quantity: self.max_buy_xmr,
+        })
+    }
+
+    /// Checks whether we are willing to buy the requested amount of XMR and
+    /// prepares the execution setup if so.
+    ///
+    /// The execution setup is started before the spot price is sent to the
+    /// taker, so that we are already listening once the taker opens the
+    /// substream.
+    async fn handle_ask_spot_price_request(
+        &mut self,
+        peer: PeerId,
+        request: ask_spot_price::Request,
+    ) -> Result<bitcoin::Amount, maker::spot_price::Error> {
+        let blockchain_network = BlockchainNetwork {
+            bitcoin: self.env_config.bitcoin_network,
+            monero: self.env_config.monero_network,
+        };
+
+        if request.blockchain_network != blockchain_network {
+            return Err(maker::spot_price::Error::BlockchainNetworkMismatch {
+                cli: request.blockchain_network,
+                asb: blockchain_network,
+            });
+        }
+
+        if self.swarm.behaviour().spot_price.resume_only() {
+            return Err(maker::spot_price::Error::ResumeOnlyMode);
+        }
+
+        if self.max_buy_xmr == monero::Amount::ZERO {
+            return Err(maker::spot_price::Error::BuyingXmrDisabled);
+        }
+
+        let xmr = request.xmr;
+
+        if xmr < self.min_buy_xmr {
+            return Err(maker::spot_price::Error::AmountBelowMinimum {
+                min: self.min_buy_xmr,
+                sell: xmr,
+            });
+        }
+
+        if xmr > self.max_buy_xmr {
+            return Err(maker::spot_price::Error::AmountAboveMaximum {
+                max: self.max_buy_xmr,
+                sell: xmr,
+            });
+        }
+
+        let swap_id = request.swap_id;
+
+        if self.db.get_state(swap_id).is_ok() {
+            return Err(maker::spot_price::Error::DuplicateSwapId(swap_id));
+        }
+
+        // Every setup costs us an address and fee estimates, a peer gets one at a
+        // time and counts towards the limit until it is done
+        if self.btc_ledger.is_pending(&peer) {
+            return Err(maker::spot_price::Error::SetupInProgress);
+        }
+
+        let pending = self.btc_ledger.pending();
+        if self.buy_xmr_rate_limit.is_exceeded_with(pending) {
+            return Err(maker::spot_price::Error::TooManySwaps {
+                max: self.buy_xmr_rate_limit.max(),
+            });
+        }
+
+        let rate = self
+            .latest_rate
+            .latest_rate()
+            .map_err(|e| maker::spot_price::Error::LatestRateFetchFailed(Box::new(e)))?;
+        let btc = rate
+            .buy_quote(xmr)
+            .map_err(maker::spot_price::Error::BuyQuoteCalculationFailed)?;
+
+        let balance = self
+            .bitcoin_wallet
+            .max_giveable(bitcoin::TxLock::script_size())
+            .await
+            .map_err(maker::spot_price::Error::ExecutionSetupPreparationFailed)?;
+        self.btc_ledger.update_balance(balance);
+
+        // BTC we promised to other swaps is still in our wallet until they lock it
+        let balance = self.btc_ledger.available_for(&peer);
+        if balance < btc {
+            return Err(maker::spot_price::Error::BalanceTooLow { balance, sell: xmr });
+        }
+
+        let state0 = self
+            .make_maker_state0(swap_id, btc, xmr)
+            .await
+            .map_err(maker::spot_price::Error::ExecutionSetupPreparationFailed)?;
+
+        self.btc_ledger.reserve(peer, btc);
+        self.swarm.behaviour_mut().ask_execution_setup.run(
+            peer,
+            swap_id,
+            state0,
+            self.bitcoin_wallet.clone(),
+        );
+
+        Ok(btc)
+    }
+
+    async fn make_maker_state0(
+        &self,
+        swap_id: Uuid,
+        btc: bitcoin::Amount,
+        xmr: monero::Amount,
+    ) -> Result<bob::State0> {
+        let refund_address = self.bitcoin_wallet.new_address().await?;
+        let tx_refund_fee = self
+            .bitcoin_wallet
+            .estimate_fee(bitcoin::TxRefund::weight(), btc)
+            .await?;
+        let tx_cancel_fee = self
+            .bitcoin_wallet
+            .estimate_fee(bitcoin::TxCancel::weight(), btc)
+            .await?;
+
+        Ok(bob::State0::new(
+            swap_id,
+            &mut OsRng,
+            btc,
+            xmr,
+            self.env_config.bitcoin_cancel_timelock,
+            self.env_config.bitcoin_punish_timelock,
+            refund_address,
+            self.env_config.monero_finality_confirmations,
+            tx_refund_fee,
+            tx_cancel_fee,
+        ))
+    }
+
+    async fn handle_ask_execution_setup_done(
+        &mut self,
+        taker_peer_id: PeerId,
+        swap_id: Uuid,
+        state2: bob::State2,
+    ) {
+        // swaps save peer id so we can resume
+        if let Err(error) = self.db.insert_peer_id(swap_id, taker_peer_id).await {
+            tracing::warn!(%swap_id, "Unable to save peer-id, swap cannot be spawned: {}", error);
+            self.btc_ledger.release_peer(&taker_peer_id);
+            return;
+        }
+
+        self.btc_ledger
+            .assign(taker_peer_id, swap_id, state2.tx_lock_amount());
+        self.buy_xmr_rate_limit.record();
+
+        let swap = self.new_maker_swap(
+            taker_peer_id,
+            swap_id,
+            BobState::ExecutionSetupDone(state2),
+        );
+
+        self.spawn_maker_swap(swap);
+    }
+
     async fn handle_execution_setup_done(
         &mut self,
         bob_peer_id: PeerId,
         swap_id: Uuid,
         state3: State3,
     ) {
+        let xmr = state3.lock_xmr_transfer_request().amount;
+        let lock_fee = self.swarm.behaviour().spot_price.lock_fee();
+        self.ledger().assign(bob_peer_id, swap_id, xmr + lock_fee);
+
         let handle = self.new_handle(bob_peer_id, swap_id);
 
         let initial_state = AliceState::Started {
@@ -417,10 +953,207 @@ where
             }
             Err(error) => {
                 tracing::warn!(%swap_id, "Unable to save peer-id, swap cannot be spawned: {}", error);
+                self.ledger().release(swap_id);
             }
         }
     }
 
+    /// Hands a swap that was loaded from the database to the swap receiver.
+    async fn resume_swap(&mut self, swap_id: Uuid, state: AliceState) {
+        let peer_id = match self.db.get_peer_id(swap_id) {
+            Ok(peer_id) => peer_id,
+            Err(_) => {
+                tracing::warn!(%swap_id, "Resuming swap skipped because no peer-id found for swap in database");
+                return;
+            }
+        };
+
+        // The XMR of swaps that did not lock it yet is still promised to them
+        if let AliceState::Started { state3 } | AliceState::BtcLocked { state3 } = &state {
+            let xmr = state3.lock_xmr_transfer_request().amount;
+            let lock_fee = self.swarm.behaviour().spot_price.lock_fee();
+            self.ledger().reserve_swap(swap_id, xmr + lock_fee);
+        }
+
+        let handle = self.new_handle(peer_id, swap_id);
+
+        let swap = Swap {
+            event_loop_handle: handle,
+            bitcoin_wallet: self.bitcoin_wallet.clone(),
+            monero_wallet: self.monero_wallet.clone(),
+            env_config: self.env_config,
+            db: self.db.clone(),
+            state,
+            swap_id,
+        };
+
+        match self.swap_sender.send(swap).await {
+            Ok(_) => tracing::info!(%swap_id, "Resuming swap"),
+            Err(_) => {
+                tracing::warn!(%swap_id, "Failed to resume swap because receiver has been dropped")
+            }
+        }
+    }
+
+    async fn handle_control_command(&mut self, command: ControlCommand) {
+        match command {
+            ControlCommand::SetResumeOnly(resume_only) => {
+                tracing::info!(%resume_only, "Changing resume-only mode");
+                self.swarm
+                    .behaviour_mut()
+                    .spot_price
+                    .set_resume_only(resume_only);
+            }
+            ControlCommand::SetBuyLimits { min_buy, max_buy } => {
+                tracing::info!(%min_buy, %max_buy, "Changing buy limits");
+                self.min_buy = min_buy;
+                self.max_buy = max_buy;
+                self.swarm
+                    .behaviour_mut()
+                    .spot_price
+                    .update_buy_limits(min_buy, max_buy);
+            }
+            ControlCommand::ResumeSwap(swap_id) => {
+                let swap = match self.db.get_state(swap_id) {
+                    Ok(swap) => swap,
+                    Err(error) => {
+                        tracing::warn!(%swap_id, "Failed to load swap to resume. Error {:#}", error);
+                        return;
+                    }
+                };
+
+                match swap {
+                    database::Swap::Alice(Alice::Done(_)) | database::Swap::Bob(Bob::Done(_)) => {
+                        tracing::debug!(%swap_id, "Not resuming swap because it is finished");
+                    }
+                    database::Swap::Alice(state) => self.resume_swap(swap_id, state.into()).await,
+                    database::Swap::Bob(state) => self.resume_maker_swap(swap_id, state.into()),
+                }
+            }
+        }
+    }
+
+    /// Persists an encrypted signature for which no swap is currently waiting.
+    ///
+    /// The swap picks up the encrypted signature from the database once it is
+    /// resumed. Bob is only acknowledged once the signature is stored, so that
+    /// he keeps retrying if saving fails.
+    async fn save_encrypted_signature(
+        &mut self,
+        swap_id: Uuid,
+        encrypted_signature: bitcoin::EncryptedSignature,
+        channel: ResponseChannel<()>,
+    ) {
+        if let Err(error) = self
+            .db
+            .insert_encrypted_signature(swap_id, encrypted_signature)
+            .await
+        {
+            tracing::error!(%swap_id, "Failed to save encrypted signature. Error {:#}", error);
+            return;
+        }
+
+        let _ = self
+            .swarm
+            .behaviour_mut()
+            .encrypted_signature
+            .send_response(channel, ());
+    }
+
+    /// Spawns a swap in which we buy XMR that was loaded from the database.
+    fn resume_maker_swap(&mut self, swap_id: Uuid, state: BobState) {
+        let peer_id = match self.db.get_peer_id(swap_id) {
+            Ok(peer_id) => peer_id,
+            Err(_) => {
+                tracing::warn!(%swap_id, "Resuming swap skipped because no peer-id found for swap in database");
+                return;
+            }
+        };
+
+        if let BobState::ExecutionSetupDone(state2) = &state {
+            self.btc_ledger
+                .reserve_swap(swap_id, state2.tx_lock_amount());
+        }
+
+        let swap = self.new_maker_swap(peer_id, swap_id, state);
+
+        tracing::info!(%swap_id, "Resuming swap in which we buy XMR");
+        self.spawn_maker_swap(swap);
+    }
+
+    /// Spawns a swap in which we buy XMR.
+    ///
+    /// The BTC reserved for the swap is released once the swap stops, unless
+    /// the swap locked it.
+    fn spawn_maker_swap(&self, swap: bob::Swap) {
+        let swap_id = swap.id;
+        let btc_ledger = self.btc_ledger.clone();
+
+        self.running_swaps.spawn(swap_id, |stop| async move {
+            match maker::run(swap, stop).await {
+                Ok(Some(state)) => {
+                    tracing::debug!(%swap_id, %state, "Swap finished with state")
+                }
+                Ok(None) => tracing::info!(%swap_id, "Stopped swap"),
+                Err(error) => {
+                    tracing::error!(%swap_id, "Swap failed. Error {:#}", error)
+                }
+            }
+            btc_ledger.release(swap_id);
+        });
+    }
+
+    /// Create a new swap in which we buy XMR from the given peer.
+    fn new_maker_swap(&mut self, peer: PeerId, swap_id: Uuid, state: BobState) -> bob::Swap {
+        let handle = self.new_maker_handle(peer, swap_id);
+
+        bob::Swap {
+            state,
+            event_loop_handle: handle,
+            db: self.db.clone(),
+            bitcoin_wallet: self.bitcoin_wallet.clone(),
+            monero_wallet: self.monero_wallet.clone(),
+            env_config: self.env_config,
+            id: swap_id,
+            receive_monero_address: self.monero_wallet.get_main_address(),
+            monero_splits: Vec::new(),
+            external_signer: None,
+        }
+    }
+
+    /// Create a new [`bob::EventLoopHandle`] that is scoped for communication
+    /// with the given peer.
+    fn new_maker_handle(&mut self, peer: PeerId, swap_id: Uuid) -> bob::EventLoopHandle {
+        // we deliberately don't put timeouts on these channels because the swap always
+        // races these futures against a timelock
+
+        let transfer_proof = bmrng::channel(1);
+        let (encrypted_signature_sender, mut encrypted_signature_receiver) = bmrng::channel(1);
+
+        self.recv_transfer_proof.insert(swap_id, transfer_proof.0);
+
+        self.send_encrypted_signature.push(
+            async move {
+                let (tx_redeem_encsig, responder) = encrypted_signature_receiver.recv().await?;
+
+                let request = encrypted_signature::Request {
+                    swap_id,
+                    tx_redeem_encsig,
+                };
+
+                Ok((peer, request, responder))
+            }
+            .boxed(),
+        );
+
+        bob::EventLoopHandle::for_maker(
+            transfer_proof.1,
+            encrypted_signature_sender,
+            self.env_config,
+            (self.btc_ledger.clone(), swap_id),
+        )
+    }
+
     /// Create a new [`EventLoopHandle`] that is scoped for communication with
     /// the given peer.
     fn new_handle(&mut self, peer: PeerId, swap_id: Uuid) -> EventLoopHandle {
@@ -450,14 +1183,23 @@ where
         EventLoopHandle {
             recv_encrypted_signature: Some(encrypted_signature.1),
             send_transfer_proof: Some(transfer_proof_sender),
+            reservation: Some((self.ledger(), swap_id)),
         }
     }
+
+    fn ledger(&self) -> Ledger {
+        self.swarm.behaviour().spot_price.ledger()
+    }
 }
 
 pub trait LatestRate {
     type Error: std::error::Error + Send + Sync + 'static;
 
     fn latest_rate(&mut self) -> Result<Rate, Self::Error>;
+
+    /// Lets the rate take our current balances into account. Rates that do
+    /// not depend on them ignore this.
+    fn update_inventory(&mut self, _inventory: Inventory) {}
 }
 
 #[derive(Clone, Debug)]
@@ -488,41 +1230,171 @@ impl LatestRate for FixedRate {
     }
 }
 
-/// Produces [`Rate`]s based on [`PriceUpdate`]s from kraken and a configured
-/// spread.
+/// A [`LatestRate`] for swaps whose price was not set by us, e.g. when the CLI
+/// sells XMR at the price offered by the buyer.
+///
+/// The swap only uses the rate for logging purposes, hence there is no need
+/// to track the market.
+#[derive(Clone, Copy, Debug)]
+pub struct NoRate;
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("No rate is tracked for this swap")]
+pub struct NoRateError;
+
+impl LatestRate for NoRate {
+    type Error = NoRateError;
+
+    fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
+        Err(NoRateError)
+    }
+}
+
+/// Produces [`Rate`]s based on the median price of the configured price
+/// sources and a configured spread.
+///
+/// If a [`SpreadCurve`] is configured, the spread is adapted to the last
+/// [`Inventory`] passed to [`LatestRate::update_inventory`].
+///
+/// The spread and the inventory are shared between all clones, changing them
+/// through [`MedianRate::set_ask_spread`] or updating the inventory affects
+/// all of them.
 #[derive(Debug, Clone)]
-pub struct KrakenRate {
-    ask_spread: Decimal,
-    price_updates: kraken::PriceUpdates,
+pub struct MedianRate {
+    ask_spread: Arc<RwLock<Decimal>>,
+    spread_curve: Option<SpreadCurve>,
+    inventory: Arc<RwLock<Option<Inventory>>>,
+    price_feed: price_feed::Median,
 }
 
-impl KrakenRate {
-    pub fn new(ask_spread: Decimal, price_updates: kraken::PriceUpdates) -> Self {
+impl MedianRate {
+    pub fn new(
+        ask_spread: Decimal,
+        spread_curve: Option<SpreadCurve>,
+        price_feed: price_feed::Median,
+    ) -> Self {
         Self {
-            ask_spread,
-            price_updates,
+            ask_spread: Arc::new(RwLock::new(ask_spread)),
+            spread_curve,
+            inventory: Arc::new(RwLock::new(None)),
+            price_feed,
+        }
+    }
+
+    pub fn ask_spread(&self) -> Decimal {
+        *self
+            .ask_spread
+            .read()
+            .expect("ask spread lock to never be poisoned")
+    }
+
+    pub fn set_ask_spread(&self, ask_spread: Decimal) {
+        *self
+            .ask_spread
+            .write()
+            .expect("ask spread lock to never be poisoned") = ask_spread;
+    }
+
+    /// The spread we currently apply, i.e. the ask spread adapted to our
+    /// inventory.
+    pub fn spread(&self) -> Decimal {
+        let ask_spread = self.ask_spread();
+        let inventory = *self
+            .inventory
+            .read()
+            .expect("inventory lock to never be poisoned");
+
+        match (self.spread_curve, inventory) {
+            (Some(curve), Some(inventory)) => curve.spread(ask_spread, inventory),
+            _ => ask_spread,
         }
     }
 }
 
-impl LatestRate for KrakenRate {
-    type Error = kraken::Error;
+impl LatestRate for MedianRate {
+    type Error = price_feed::Error;
 
     fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
-        let update = self.price_updates.latest_update()?;
-        let rate = Rate::new(update.ask, self.ask_spread);
+        let ask = self.price_feed.latest_ask()?;
+        let rate = Rate::new(ask, self.spread());
 
         Ok(rate)
     }
+
+    fn update_inventory(&mut self, inventory: Inventory) {
+        *self
+            .inventory
+            .write()
+            .expect("inventory lock to never be poisoned") = Some(inventory);
+    }
+}
+
+#[derive(Debug)]
+enum ControlCommand {
+    SetResumeOnly(bool),
+    SetBuyLimits {
+        min_buy: bitcoin::Amount,
+        max_buy: bitcoin::Amount,
+    },
+    ResumeSwap(Uuid),
+}
+
+/// Changes the settings of a running [`EventLoop`].
+#[derive(Clone, Debug)]
+pub struct EventLoopController {
+    sender: mpsc::Sender<ControlCommand>,
+}
+
+impl EventLoopController {
+    pub async fn set_resume_only(&self, resume_only: bool) -> Result<()> {
+        self.send(ControlCommand::SetResumeOnly(resume_only)).await
+    }
+
+    pub async fn set_buy_limits(
+        &self,
+        min_buy: bitcoin::Amount,
+        max_buy: bitcoin::Amount,
+    ) -> Result<()> {
+        self.send(ControlCommand::SetBuyLimits { min_buy, max_buy })
+            .await
+    }
+
+    /// Loads the swap from the database and starts it again, unless it is
+    /// finished.
+    pub async fn resume_swap(&self, swap_id: Uuid) -> Result<()> {
+        self.send(ControlCommand::ResumeSwap(swap_id)).await
+    }
+
+    async fn send(&self, command: ControlCommand) -> Result<()> {
+        self.sender
+            .send(command)
+            .await
+            .map_err(|_| anyhow!("Event loop is not running"))
+    }
 }
 
 #[derive(Debug)]
 pub struct EventLoopHandle {
     recv_encrypted_signature: Option<bmrng::RequestReceiver<bitcoin::EncryptedSignature, ()>>,
     send_transfer_proof: Option<bmrng::RequestSender<monero::TransferProof, ()>>,
+    /// The XMR reserved for the swap in the ledger of the ASB.
+    reservation: Option<(Ledger, Uuid)>,
 }
 
 impl EventLoopHandle {
+    /// Creates a handle from channels that are served by an event loop other
+    /// than the ASB's, e.g. the one of the CLI when selling XMR.
+    pub fn new(
+        recv_encrypted_signature: bmrng::RequestReceiver<bitcoin::EncryptedSignature, ()>,
+        send_transfer_proof: bmrng::RequestSender<monero::TransferProof, ()>,
+    ) -> Self {
+        Self {
+            recv_encrypted_signature: Some(recv_encrypted_signature),
+            send_transfer_proof: Some(send_transfer_proof),
+            reservation: None,
+        }
+    }
+
     pub async fn recv_encrypted_signature(&mut self) -> Result<bitcoin::EncryptedSignature> {
         let (tx_redeem_encsig, responder) = self
             .recv_encrypted_signature
@@ -548,6 +1420,22 @@ impl EventLoopHandle {
 
         Ok(())
     }
+
+    /// Gives the XMR reserved for the swap back, because the swap was aborted
+    /// before locking it.
+    pub fn release_reservation(&mut self) {
+        if let Some((ledger, swap_id)) = self.reservation.take() {
+            ledger.release(swap_id);
+        }
+    }
+
+    /// Takes the XMR reserved for the swap off the balance, because the swap
+    /// sent it to the lock address.
+    pub fn commit_reservation(&mut self) {
+        if let Some((ledger, swap_id)) = self.reservation.take() {
+            ledger.commit(swap_id);
+        }
+    }
 }
 
 #[allow(missing_debug_implementations)]
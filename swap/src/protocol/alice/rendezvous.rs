@@ -0,0 +1,207 @@
+use crate::network::rendezvous;
+use crate::network::rendezvous::{Request, Response, XmrBtcNamespace};
+use crate::protocol::alice;
+use futures::FutureExt;
+use libp2p::core::Multiaddr;
+use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::swarm::{NetworkBehaviourAction, NetworkBehaviourEventProcess, PollParameters};
+use libp2p::{NetworkBehaviour, PeerId};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Sleep;
+
+/// The time-to-live we request for our registration, in seconds.
+const REGISTRATION_TTL: u64 = 2 * 60 * 60;
+
+/// How long to wait before registering again after a registration failed.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Registered { rendezvous_node: PeerId, ttl: u64 },
+}
+
+/// Behaviour that registers the ASB at a rendezvous point.
+///
+/// The registration is refreshed once half of its time-to-live has passed, so
+/// that we stay discoverable as long as we are running.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", poll_method = "poll", event_process = true)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    behaviour: rendezvous::Behaviour,
+
+    #[behaviour(ignore)]
+    rendezvous_node: PeerId,
+    #[behaviour(ignore)]
+    namespace: XmrBtcNamespace,
+    #[behaviour(ignore)]
+    external_addresses: Vec<Multiaddr>,
+    #[behaviour(ignore)]
+    next_registration: Pin<Box<Sleep>>,
+    #[behaviour(ignore)]
+    events: VecDeque<OutEvent>,
+}
+
+impl Behaviour {
+    pub fn new(
+        rendezvous_node: PeerId,
+        rendezvous_address: Multiaddr,
+        namespace: XmrBtcNamespace,
+        external_addresses: Vec<Multiaddr>,
+    ) -> Self {
+        let mut behaviour = rendezvous::client();
+        behaviour.add_address(&rendezvous_node, rendezvous_address);
+
+        Self {
+            behaviour,
+            rendezvous_node,
+            namespace,
+            external_addresses,
+            next_registration: Box::pin(tokio::time::sleep(Duration::from_secs(0))),
+            events: Default::default(),
+        }
+    }
+
+    fn schedule_registration(&mut self, after: Duration) {
+        self.next_registration = Box::pin(tokio::time::sleep(after));
+    }
+
+    fn poll<BIE>(
+        &mut self,
+        cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<BIE, OutEvent>> {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
+        }
+
+        while self.next_registration.poll_unpin(cx).is_ready() {
+            self.behaviour.send_request(&self.rendezvous_node, Request::Register {
+                namespace: self.namespace,
+                addresses: self.external_addresses.clone(),
+                ttl: REGISTRATION_TTL,
+            });
+
+            // Only used if we never hear back, a response reschedules the registration.
+            self.schedule_registration(RETRY_INTERVAL);
+        }
+
+        Poll::Pending
+    }
+}
+
+impl NetworkBehaviourEventProcess<rendezvous::OutEvent> for Behaviour {
+    fn inject_event(&mut self, event: rendezvous::OutEvent) {
+        let (peer, message) = match event {
+            RequestResponseEvent::Message { peer, message } => (peer, message),
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                tracing::warn!(%peer, "Failed to register with rendezvous point: {:#}", error);
+                self.schedule_registration(RETRY_INTERVAL);
+                return;
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                tracing::warn!(%peer, "Inbound failure when handling rendezvous request: {:#}", error);
+                return;
+            }
+            RequestResponseEvent::ResponseSent { .. } => return,
+        };
+
+        if peer != self.rendezvous_node {
+            tracing::warn!(%peer, "Ignoring rendezvous message from unknown peer");
+            return;
+        }
+
+        let response = match message {
+            RequestResponseMessage::Response { response, .. } => response,
+            RequestResponseMessage::Request { .. } => {
+                tracing::error!("Unexpected message");
+                return;
+            }
+        };
+
+        match response {
+            Response::Registered { ttl } => {
+                self.schedule_registration(Duration::from_secs(ttl / 2));
+                self.events.push_back(OutEvent::Registered {
+                    rendezvous_node: peer,
+                    ttl,
+                });
+            }
+            Response::Error(error) => {
+                tracing::warn!(%peer, "Rendezvous point declined registration: {}", error);
+                self.schedule_registration(RETRY_INTERVAL);
+            }
+            Response::Discovered { .. } => {
+                tracing::error!(%peer, "Unexpected rendezvous response");
+            }
+        }
+    }
+}
+
+impl From<OutEvent> for alice::OutEvent {
+    fn from(event: OutEvent) -> Self {
+        match event {
+            OutEvent::Registered {
+                rendezvous_node,
+                ttl,
+            } => Self::Registered {
+                rendezvous_node,
+                ttl,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::rendezvous::server;
+    use crate::network::test::{await_events_or_timeout, new_swarm};
+    use libp2p::swarm::SwarmEvent;
+
+    #[tokio::test]
+    async fn registers_at_rendezvous_point() {
+        let (mut rendezvous_node, rendezvous_address, rendezvous_peer_id) =
+            new_swarm(|_, _| server::Behaviour::default());
+        let external_address = "/ip4/127.0.0.1/tcp/9939".parse::<Multiaddr>().unwrap();
+        let (mut asb, _, asb_peer_id) = new_swarm(|_, _| {
+            Behaviour::new(
+                rendezvous_peer_id,
+                rendezvous_address.clone(),
+                XmrBtcNamespace::Testnet,
+                vec![external_address.clone()],
+            )
+        });
+
+        let rendezvous_node_registered = async {
+            loop {
+                if let SwarmEvent::Behaviour(server::OutEvent::PeerRegistered { peer, namespace }) =
+                    rendezvous_node.next_event().await
+                {
+                    break (peer, namespace);
+                }
+            }
+        };
+        let asb_registered = async {
+            loop {
+                if let SwarmEvent::Behaviour(OutEvent::Registered {
+                    rendezvous_node: registered_at,
+                    ..
+                }) = asb.next_event().await
+                {
+                    break registered_at;
+                }
+            }
+        };
+
+        let ((registered_peer, namespace), registered_at) =
+            await_events_or_timeout(rendezvous_node_registered, asb_registered).await;
+
+        assert_eq!(registered_peer, asb_peer_id);
+        assert_eq!(namespace, XmrBtcNamespace::Testnet);
+        assert_eq!(registered_at, rendezvous_peer_id);
+    }
+}
@@ -1,18 +1,22 @@
-use crate::network::cbor_request_response::CborCodec;
+use crate::asb::{Inventory, Ledger, Rate, VolumeTiers};
+use crate::network::quote::TieredBidQuote;
 use crate::network::spot_price;
-use crate::network::spot_price::{BlockchainNetwork, SpotPriceProtocol};
+use crate::network::spot_price::{BlockchainNetwork, Codec, SpotPriceProtocol};
 use crate::protocol::alice;
 use crate::protocol::alice::event_loop::LatestRate;
 use crate::{env, monero};
+use anyhow::Context as _;
 use libp2p::request_response::{
     ProtocolSupport, RequestResponseConfig, RequestResponseEvent, RequestResponseMessage,
     ResponseChannel,
 };
 use libp2p::swarm::{NetworkBehaviourAction, NetworkBehaviourEventProcess, PollParameters};
 use libp2p::{NetworkBehaviour, PeerId};
-use std::collections::VecDeque;
+use rust_decimal::Decimal;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub enum OutEvent {
@@ -40,7 +44,7 @@ where
     events: VecDeque<OutEvent>,
 
     #[behaviour(ignore)]
-    balance: monero::Amount,
+    ledger: Ledger,
     #[behaviour(ignore)]
     lock_fee: monero::Amount,
     #[behaviour(ignore)]
@@ -52,6 +56,14 @@ where
     #[behaviour(ignore)]
     latest_rate: LR,
     #[behaviour(ignore)]
+    volume_tiers: VolumeTiers,
+    #[behaviour(ignore)]
+    quote_validity: Duration,
+    /// The last tiered quote we sent to each peer, whose prices we honour
+    /// until it expires.
+    #[behaviour(ignore)]
+    quotes: HashMap<PeerId, TieredBidQuote>,
+    #[behaviour(ignore)]
     resume_only: bool,
 }
 
@@ -63,6 +75,7 @@ impl<LR> Behaviour<LR>
 where
     LR: LatestRate + Send + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         balance: monero::Amount,
         lock_fee: monero::Amount,
@@ -70,27 +83,100 @@ where
         max_buy: bitcoin::Amount,
         env_config: env::Config,
         latest_rate: LR,
+        volume_tiers: VolumeTiers,
+        quote_validity: Duration,
         resume_only: bool,
     ) -> Self {
         Self {
             behaviour: spot_price::Behaviour::new(
-                CborCodec::default(),
-                vec![(SpotPriceProtocol, ProtocolSupport::Inbound)],
+                Codec::default(),
+                SpotPriceProtocol::ALL
+                    .iter()
+                    .map(|protocol| (*protocol, ProtocolSupport::Inbound))
+                    .collect::<Vec<_>>(),
                 RequestResponseConfig::default(),
             ),
             events: Default::default(),
-            balance,
+            ledger: Ledger::new(balance),
             lock_fee,
             min_buy,
             max_buy,
             env_config,
             latest_rate,
+            volume_tiers,
+            quote_validity,
+            quotes: HashMap::default(),
             resume_only,
         }
     }
 
     pub fn update_balance(&mut self, balance: monero::Amount) {
-        self.balance = balance;
+        self.ledger.update_balance(balance);
+    }
+
+    /// The ledger of the XMR reserved for swaps, shared with the swaps so
+    /// they can release or commit their reservation.
+    pub fn ledger(&self) -> Ledger {
+        self.ledger.clone()
+    }
+
+    pub fn lock_fee(&self) -> monero::Amount {
+        self.lock_fee
+    }
+
+    pub fn update_inventory(&mut self, inventory: Inventory) {
+        self.latest_rate.update_inventory(inventory);
+    }
+
+    pub fn resume_only(&self) -> bool {
+        self.resume_only
+    }
+
+    pub fn set_resume_only(&mut self, resume_only: bool) {
+        self.resume_only = resume_only;
+    }
+
+    pub fn update_buy_limits(&mut self, min_buy: bitcoin::Amount, max_buy: bitcoin::Amount) {
+        self.min_buy = min_buy;
+        self.max_buy = max_buy;
+    }
+
+    pub fn volume_tiers(&self) -> &VolumeTiers {
+        &self.volume_tiers
+    }
+
+    /// Prices the current buy limits in tiers and remembers the quote, so a
+    /// spot price request of `peer` is priced accordingly until the quote
+    /// expires.
+    pub fn make_tiered_quote(&mut self, peer: PeerId) -> anyhow::Result<TieredBidQuote> {
+        let rate = self
+            .latest_rate
+            .latest_rate()
+            .context("Failed to get latest rate")?;
+        let tiers = self
+            .volume_tiers
+            .price_tiers(rate, self.min_buy, self.max_buy)
+            .context("Failed to compute price tiers")?;
+        let quote = TieredBidQuote::new(tiers, self.quote_validity);
+
+        self.quotes.retain(|_, quote| quote.valid_for().is_some());
+        self.quotes.insert(peer, quote.clone());
+
+        Ok(quote)
+    }
+
+    /// The rate of the tier `btc` falls into if we sent `peer` a quote that
+    /// has not yet expired.
+    ///
+    /// A quote is only honoured once. Callers have to make sure the latest
+    /// rate is available, quotes are not honoured while the price feed is
+    /// stale.
+    fn quoted_rate(&mut self, peer: PeerId, btc: bitcoin::Amount) -> Option<Rate> {
+        let quote = self.quotes.remove(&peer)?;
+        quote.valid_for()?;
+        let tier = quote.tier(btc)?;
+
+        Some(Rate::new(tier.price, Decimal::from(0u64)))
     }
 
     fn decline(
@@ -194,13 +280,19 @@ where
             return;
         }
 
-        let rate = match self.latest_rate.latest_rate() {
+        // A stale price feed means the market may have moved away from any
+        // quote we sent, so it is not honoured either
+        let latest_rate = match self.latest_rate.latest_rate() {
             Ok(rate) => rate,
             Err(e) => {
                 self.decline(peer, channel, Error::LatestRateFetchFailed(Box::new(e)));
                 return;
             }
         };
+        let rate = match self.quoted_rate(peer, btc) {
+            Some(rate) => rate,
+            None => self.volume_tiers.rate_for(latest_rate, btc),
+        };
         let xmr = match rate.sell_quote(btc) {
             Ok(xmr) => xmr,
             Err(e) => {
@@ -209,7 +301,7 @@ where
             }
         };
 
-        let xmr_balance = self.balance;
+        let xmr_balance = self.ledger.available_for(&peer);
         let xmr_lock_fees = self.lock_fee;
 
         if xmr_balance < xmr + xmr_lock_fees {
@@ -225,9 +317,12 @@ where
             .send_response(channel, spot_price::Response::Xmr(xmr))
             .is_err()
         {
-            tracing::error!(%peer, "Failed to send spot price response of {} for {}", xmr, btc)
+            tracing::error!(%peer, "Failed to send spot price response of {} for {}", xmr, btc);
+            return;
         }
 
+        self.ledger.reserve(peer, xmr + xmr_lock_fees);
+
         self.events
             .push_back(OutEvent::ExecutionSetupParams { peer, btc, xmr });
     }
@@ -263,7 +358,7 @@ pub enum Error {
         balance: monero::Amount,
         buy: bitcoin::Amount,
     },
-    #[error("Failed to fetch latest rate")]
+    #[error("Failed to fetch latest rate: {0}")]
     LatestRateFetchFailed(#[source] Box<dyn std::error::Error + Send + 'static>),
     #[error("Failed to calculate quote: {0}")]
     SellQuoteCalculationFailed(#[source] anyhow::Error),
@@ -293,9 +388,8 @@ impl Error {
                     asb: *asb,
                 }
             }
-            Error::LatestRateFetchFailed(_) | Error::SellQuoteCalculationFailed(_) => {
-                spot_price::Error::Other
-            }
+            Error::LatestRateFetchFailed(_) => spot_price::Error::PriceUnavailable,
+            Error::SellQuoteCalculationFailed(_) => spot_price::Error::Other,
         }
     }
 }
@@ -303,13 +397,15 @@ impl Error {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::asb::Rate;
+    use crate::asb::{Rate, VolumeTier};
     use crate::env::GetConfig;
     use crate::monero;
+    use crate::network::quote::PriceTier;
     use crate::network::test::{await_events_or_timeout, connect, new_swarm};
     use crate::protocol::{alice, bob};
     use anyhow::anyhow;
-    use libp2p::Swarm;
+    use libp2p::swarm::SwarmEvent;
+    use libp2p::{Multiaddr, Swarm};
     use rust_decimal::Decimal;
 
     impl Default for AliceBehaviourValues {
@@ -320,6 +416,7 @@ mod tests {
                 min_buy: bitcoin::Amount::from_btc(0.001).unwrap(),
                 max_buy: bitcoin::Amount::from_btc(0.01).unwrap(),
                 rate: TestRate::default(), // 0.01
+                volume_tiers: VolumeTiers::default(),
                 resume_only: false,
                 env_config: env::Testnet::get_config(),
             }
@@ -384,6 +481,51 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn given_balance_is_reserved_for_another_bob_then_returns_error() {
+        let mut test = SpotPriceTest::setup(AliceBehaviourValues::default()).await;
+
+        let btc_to_swap = bitcoin::Amount::from_btc(0.01).unwrap();
+        let expected_xmr = monero::Amount::from_monero(1.0).unwrap();
+
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_price((btc_to_swap, expected_xmr), expected_xmr)
+            .await;
+
+        test.connect_another_bob().await;
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_error(
+            alice::spot_price::Error::BalanceTooLow {
+                balance: monero::Amount::ZERO,
+                buy: btc_to_swap,
+            },
+            bob::spot_price::Error::BalanceTooLow { buy: btc_to_swap },
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn given_reservation_of_other_bob_released_then_returns_price() {
+        let mut test = SpotPriceTest::setup(AliceBehaviourValues::default()).await;
+
+        let btc_to_swap = bitcoin::Amount::from_btc(0.01).unwrap();
+        let expected_xmr = monero::Amount::from_monero(1.0).unwrap();
+
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_price((btc_to_swap, expected_xmr), expected_xmr)
+            .await;
+        let first_bob = *test.bob_swarm.local_peer_id();
+        test.alice_swarm
+            .behaviour()
+            .ledger()
+            .release_peer(&first_bob);
+
+        test.connect_another_bob().await;
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_price((btc_to_swap, expected_xmr), expected_xmr)
+            .await;
+    }
+
     #[tokio::test]
     async fn given_alice_has_insufficient_balance_because_of_lock_fee_then_returns_error() {
         let balance = monero::Amount::from_monero(1.0).unwrap();
@@ -476,7 +618,70 @@ mod tests {
         test.construct_and_send_request(btc_to_swap);
         test.assert_error(
             alice::spot_price::Error::LatestRateFetchFailed(Box::new(TestRateError {})),
-            bob::spot_price::Error::Other,
+            bob::spot_price::Error::PriceUnavailable,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn given_volume_tier_then_returns_price_with_extra_spread() {
+        let mut test = SpotPriceTest::setup(AliceBehaviourValues::default().with_volume_tiers(
+            VolumeTiers::new(vec![VolumeTier {
+                min_btc: bitcoin::Amount::from_btc(0.005).unwrap(),
+                extra_spread: Decimal::new(25, 2),
+            }]),
+        ))
+        .await;
+
+        let btc_to_swap = bitcoin::Amount::from_btc(0.01).unwrap();
+        let expected_xmr = monero::Amount::from_monero(0.8).unwrap();
+
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_price((btc_to_swap, expected_xmr), expected_xmr)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn given_valid_tiered_quote_then_returns_quoted_price() {
+        let mut test = SpotPriceTest::setup(AliceBehaviourValues::default()).await;
+        test.insert_quote(Duration::from_secs(60));
+
+        let btc_to_swap = bitcoin::Amount::from_btc(0.01).unwrap();
+        let expected_xmr = monero::Amount::from_monero(0.5).unwrap();
+
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_price((btc_to_swap, expected_xmr), expected_xmr)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn given_valid_tiered_quote_but_rate_fetch_problem_then_returns_error() {
+        let mut test =
+            SpotPriceTest::setup(AliceBehaviourValues::default().with_rate(TestRate::error_rate()))
+                .await;
+        test.insert_quote(Duration::from_secs(60));
+
+        let btc_to_swap = bitcoin::Amount::from_btc(0.01).unwrap();
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_error(
+            alice::spot_price::Error::LatestRateFetchFailed(Box::new(TestRateError {})),
+            bob::spot_price::Error::PriceUnavailable,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn given_expired_tiered_quote_then_uses_latest_rate() {
+        let mut test =
+            SpotPriceTest::setup(AliceBehaviourValues::default().with_rate(TestRate::error_rate()))
+                .await;
+        test.insert_quote(Duration::from_secs(0));
+
+        let btc_to_swap = bitcoin::Amount::from_btc(0.01).unwrap();
+        test.construct_and_send_request(btc_to_swap);
+        test.assert_error(
+            alice::spot_price::Error::LatestRateFetchFailed(Box::new(TestRateError {})),
+            bob::spot_price::Error::PriceUnavailable,
         )
         .await;
     }
@@ -577,12 +782,13 @@ mod tests {
         alice_swarm: Swarm<alice::spot_price::Behaviour<TestRate>>,
         bob_swarm: Swarm<spot_price::Behaviour>,
 
+        alice_address: Multiaddr,
         alice_peer_id: PeerId,
     }
 
     impl SpotPriceTest {
         pub async fn setup(values: AliceBehaviourValues) -> Self {
-            let (mut alice_swarm, _, alice_peer_id) = new_swarm(|_, _| {
+            let (mut alice_swarm, alice_address, alice_peer_id) = new_swarm(|_, _| {
                 Behaviour::new(
                     values.balance,
                     values.lock_fee,
@@ -590,6 +796,8 @@ mod tests {
                     values.max_buy,
                     values.env_config,
                     values.rate.clone(),
+                    values.volume_tiers.clone(),
+                    Duration::from_secs(60),
                     values.resume_only,
                 )
             });
@@ -600,10 +808,51 @@ mod tests {
             Self {
                 alice_swarm,
                 bob_swarm,
+                alice_address,
                 alice_peer_id,
             }
         }
 
+        /// Replaces Bob with another CLI that connects to Alice.
+        pub async fn connect_another_bob(&mut self) {
+            let (mut bob_swarm, ..) = new_swarm(|_, _| bob::spot_price::bob());
+            bob_swarm.dial_addr(self.alice_address.clone()).unwrap();
+
+            let mut alice_connected = false;
+            let mut bob_connected = false;
+
+            while !alice_connected || !bob_connected {
+                tokio::select! {
+                    event = self.alice_swarm.next_event(), if !alice_connected => {
+                        alice_connected = matches!(event, SwarmEvent::ConnectionEstablished { .. });
+                    }
+                    event = bob_swarm.next_event(), if !bob_connected => {
+                        bob_connected = matches!(event, SwarmEvent::ConnectionEstablished { .. });
+                    }
+                }
+            }
+
+            self.bob_swarm = bob_swarm;
+        }
+
+        /// Pretends Alice quoted Bob a price of 0.02 BTC per XMR.
+        pub fn insert_quote(&mut self, valid_for: Duration) {
+            let bob_peer_id = *self.bob_swarm.local_peer_id();
+            let quote = TieredBidQuote::new(
+                vec![PriceTier {
+                    price: bitcoin::Amount::from_btc(0.02).unwrap(),
+                    min_quantity: bitcoin::Amount::from_btc(0.001).unwrap(),
+                    max_quantity: bitcoin::Amount::from_btc(0.01).unwrap(),
+                }],
+                valid_for,
+            );
+
+            self.alice_swarm
+                .behaviour_mut()
+                .quotes
+                .insert(bob_peer_id, quote);
+        }
+
         pub fn construct_and_send_request(&mut self, btc_to_swap: bitcoin::Amount) {
             let request = spot_price::Request {
                 btc: btc_to_swap,
@@ -741,6 +990,7 @@ mod tests {
         pub min_buy: bitcoin::Amount,
         pub max_buy: bitcoin::Amount,
         pub rate: TestRate, // 0.01
+        pub volume_tiers: VolumeTiers,
         pub resume_only: bool,
         pub env_config: env::Config,
     }
@@ -776,6 +1026,11 @@ mod tests {
             self
         }
 
+        pub fn with_volume_tiers(mut self, volume_tiers: VolumeTiers) -> AliceBehaviourValues {
+            self.volume_tiers = volume_tiers;
+            self
+        }
+
         pub fn with_env_config(mut self, env_config: env::Config) -> AliceBehaviourValues {
             self.env_config = env_config;
             self
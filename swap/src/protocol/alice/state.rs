@@ -1,3 +1,4 @@
+use crate::bitcoin::wallet::Deadline;
 use crate::bitcoin::{
     current_epoch, CancelTimelock, ExpiredTimelocks, PunishTimelock, Transaction, TxCancel,
     TxPunish, TxRedeem, TxRefund, Txid,
@@ -115,11 +116,16 @@ pub struct State0 {
     punish_timelock: PunishTimelock,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    anchor_address: Option<bitcoin::Address>,
     tx_redeem_fee: bitcoin::Amount,
     tx_punish_fee: bitcoin::Amount,
 }
 
 impl State0 {
+    /// With an `anchor_address` of our wallet, the redeem and punish
+    /// transactions get an anchor output that we can speed them up with, if
+    /// Bob accepts it. Needed if `redeem_address` and `punish_address` do
+    /// not belong to our wallet.
     #[allow(clippy::too_many_arguments)]
     pub fn new<R>(
         btc: bitcoin::Amount,
@@ -127,6 +133,7 @@ impl State0 {
         env_config: Config,
         redeem_address: bitcoin::Address,
         punish_address: bitcoin::Address,
+        anchor_address: Option<bitcoin::Address>,
         tx_redeem_fee: bitcoin::Amount,
         tx_punish_fee: bitcoin::Amount,
         rng: &mut R,
@@ -151,6 +158,7 @@ impl State0 {
             dleq_proof_s_a,
             redeem_address,
             punish_address,
+            anchor_address,
             btc,
             xmr,
             cancel_timelock: env_config.bitcoin_cancel_timelock,
@@ -178,6 +186,17 @@ impl State0 {
 
         let v = self.v_a + msg.v_b;
 
+        let anchor_address = match self.anchor_address {
+            Some(_) if !msg.anchor_outputs => {
+                tracing::warn!(
+                    swap_id = %msg.swap_id,
+                    "Bob does not accept anchor outputs, we cannot speed up the redeem or punish transaction"
+                );
+                None
+            }
+            anchor_address => anchor_address,
+        };
+
         Ok((msg.swap_id, State1 {
             a: self.a,
             B: msg.B,
@@ -196,6 +215,7 @@ impl State0 {
             refund_address: msg.refund_address,
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
+            anchor_address,
             tx_redeem_fee: self.tx_redeem_fee,
             tx_punish_fee: self.tx_punish_fee,
             tx_refund_fee: msg.tx_refund_fee,
@@ -223,6 +243,7 @@ pub struct State1 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    anchor_address: Option<bitcoin::Address>,
     tx_redeem_fee: bitcoin::Amount,
     tx_punish_fee: bitcoin::Amount,
     tx_refund_fee: bitcoin::Amount,
@@ -241,12 +262,20 @@ impl State1 {
             punish_address: self.punish_address.clone(),
             tx_redeem_fee: self.tx_redeem_fee,
             tx_punish_fee: self.tx_punish_fee,
+            anchor_address: self.anchor_address.clone(),
         }
     }
 
     pub fn receive(self, msg: Message2) -> Result<State2> {
         let tx_lock = bitcoin::TxLock::from_psbt(msg.psbt, self.a.public(), self.B, self.btc)
             .context("Failed to re-construct TxLock from received PSBT")?;
+        TxRedeem::new(
+            &tx_lock,
+            &self.redeem_address,
+            self.anchor_address.as_ref(),
+            self.tx_redeem_fee,
+        )
+        .context("Redeem transaction has no room for the anchor output")?;
 
         Ok(State2 {
             a: self.a,
@@ -262,6 +291,7 @@ impl State1 {
             refund_address: self.refund_address,
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
+            anchor_address: self.anchor_address,
             tx_lock,
             tx_redeem_fee: self.tx_redeem_fee,
             tx_punish_fee: self.tx_punish_fee,
@@ -286,6 +316,7 @@ pub struct State2 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    anchor_address: Option<bitcoin::Address>,
     tx_lock: bitcoin::TxLock,
     tx_redeem_fee: bitcoin::Amount,
     tx_punish_fee: bitcoin::Amount,
@@ -332,9 +363,11 @@ impl State2 {
         let tx_punish = bitcoin::TxPunish::new(
             &tx_cancel,
             &self.punish_address,
+            self.anchor_address.as_ref(),
             self.punish_timelock,
             self.tx_punish_fee,
-        );
+        )
+        .context("Punish transaction has no room for the anchor output")?;
         bitcoin::verify_sig(&self.B, &tx_punish.digest(), &msg.tx_punish_sig)
             .context("Failed to verify punish transaction")?;
 
@@ -352,6 +385,7 @@ impl State2 {
             refund_address: self.refund_address,
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
+            anchor_address: self.anchor_address,
             tx_lock: self.tx_lock,
             tx_punish_sig_bob: msg.tx_punish_sig,
             tx_cancel_sig_bob: msg.tx_cancel_sig,
@@ -379,6 +413,8 @@ pub struct State3 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    #[serde(default)]
+    anchor_address: Option<bitcoin::Address>,
     pub tx_lock: bitcoin::TxLock,
     tx_punish_sig_bob: bitcoin::Signature,
     tx_cancel_sig_bob: bitcoin::Signature,
@@ -410,6 +446,25 @@ impl State3 {
         ))
     }
 
+    /// The deadline for transactions we publish, i.e. the next timelock.
+    pub fn fee_bump_deadline(&self) -> Deadline {
+        Deadline::new(
+            &self.tx_lock,
+            &self.tx_cancel(),
+            self.cancel_timelock,
+            self.punish_timelock,
+        )
+    }
+
+    /// The output of the redeem and punish transactions that we spend to
+    /// speed them up with CPFP.
+    pub fn cpfp_vout(&self) -> u32 {
+        match self.anchor_address {
+            Some(_) => bitcoin::ANCHOR_VOUT,
+            None => 0,
+        }
+    }
+
     pub fn lock_xmr_transfer_request(&self) -> TransferRequest {
         let S_a = monero::PublicKey::from_private_key(&monero::PrivateKey { scalar: self.s_a });
 
@@ -456,7 +511,13 @@ impl State3 {
     }
 
     pub fn tx_redeem(&self) -> TxRedeem {
-        TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_redeem_fee)
+        TxRedeem::new(
+            &self.tx_lock,
+            &self.redeem_address,
+            self.anchor_address.as_ref(),
+            self.tx_redeem_fee,
+        )
+        .expect("anchor output to fit, checked during the execution setup")
     }
 
     pub fn extract_monero_private_key(
@@ -523,8 +584,18 @@ impl State3 {
     pub async fn punish_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
         let signed_tx_punish = self.signed_punish_transaction()?;
 
-        let (txid, subscription) = bitcoin_wallet.broadcast(signed_tx_punish, "punish").await?;
-        subscription.wait_until_final().await?;
+        let (txid, subscription) = bitcoin_wallet
+            .broadcast(signed_tx_punish.clone(), "punish")
+            .await?;
+        bitcoin_wallet
+            .wait_until_final_bumping_fee(
+                &subscription,
+                signed_tx_punish,
+                "punish",
+                self.cpfp_vout(),
+                Some(self.fee_bump_deadline()),
+            )
+            .await?;
 
         Ok(txid)
     }
@@ -533,7 +604,7 @@ impl State3 {
         &self,
         sig: bitcoin::EncryptedSignature,
     ) -> Result<bitcoin::Transaction> {
-        bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_redeem_fee)
+        self.tx_redeem()
             .complete(sig, self.a.clone(), self.s_a.to_secpfun_scalar(), self.B)
             .context("Failed to complete Bitcoin redeem transaction")
     }
@@ -554,8 +625,10 @@ impl State3 {
         bitcoin::TxPunish::new(
             &self.tx_cancel(),
             &self.punish_address,
+            self.anchor_address.as_ref(),
             self.punish_timelock,
             self.tx_punish_fee,
         )
+        .expect("anchor output to fit, checked during the execution setup")
     }
 }
@@ -1,12 +1,14 @@
 //! Run an XMR/BTC swap in the role of Alice.
 //! Alice holds XMR and wishes receive BTC.
 use crate::bitcoin::ExpiredTimelocks;
+use crate::database::Database;
 use crate::env::Config;
 use crate::protocol::alice::event_loop::{EventLoopHandle, LatestRate};
 use crate::protocol::alice::{AliceState, Swap};
-use crate::{bitcoin, database, monero};
+use crate::{bitcoin, database, monero, protocol};
 use anyhow::{bail, Context, Result};
 use tokio::select;
+use tokio::sync::watch;
 use tokio::time::timeout;
 use tracing::{error, info, warn};
 use uuid::Uuid;
@@ -18,28 +20,99 @@ where
     run_until(swap, |_| false, rate_service).await
 }
 
-#[tracing::instrument(name = "swap", skip(swap,exit_early,rate_service), fields(id = %swap.swap_id), err)]
 pub async fn run_until<LR>(
-    mut swap: Swap,
+    swap: Swap,
     exit_early: fn(&AliceState) -> bool,
     rate_service: LR,
 ) -> Result<AliceState>
+where
+    LR: LatestRate + Clone,
+{
+    // Nobody can request a stop while we hold the only sender.
+    let (_stop_sender, stop) = watch::channel(false);
+
+    let state = run_until_stopped_or(swap, exit_early, rate_service, stop)
+        .await?
+        .expect("swap to only stop on request");
+
+    Ok(state)
+}
+
+/// Runs the swap until it completes or a stop is requested through `stop`,
+/// returns `None` in the latter case.
+///
+/// A swap only stops while it waits for the other party or the blockchain,
+/// transitions that publish something are finished and persisted first. The
+/// swap can be resumed from the database afterwards.
+pub async fn run_until_stopped<LR>(
+    swap: Swap,
+    rate_service: LR,
+    stop: watch::Receiver<bool>,
+) -> Result<Option<AliceState>>
+where
+    LR: LatestRate + Clone,
+{
+    run_until_stopped_or(swap, |_| false, rate_service, stop).await
+}
+
+#[tracing::instrument(name = "swap", skip(swap,exit_early,rate_service,stop), fields(id = %swap.swap_id), err)]
+async fn run_until_stopped_or<LR>(
+    mut swap: Swap,
+    exit_early: fn(&AliceState) -> bool,
+    rate_service: LR,
+    mut stop: watch::Receiver<bool>,
+) -> Result<Option<AliceState>>
 where
     LR: LatestRate + Clone,
 {
     let mut current_state = swap.state;
 
     while !is_complete(&current_state) && !exit_early(&current_state) {
-        current_state = next_state(
+        if *stop.borrow() {
+            return Ok(None);
+        }
+
+        if let AliceState::Started { state3 } = &current_state {
+            protocol::watch_swap_outputs(
+                swap.bitcoin_wallet.as_ref(),
+                swap.db.as_ref(),
+                swap.swap_id,
+                &state3.tx_lock,
+                &state3.tx_cancel(),
+            )
+            .await?;
+        }
+
+        let interruptible = only_waits(&current_state);
+        let transition = next_state(
             swap.swap_id,
             current_state,
             &mut swap.event_loop_handle,
             swap.bitcoin_wallet.as_ref(),
             swap.monero_wallet.as_ref(),
+            swap.db.as_ref(),
             &swap.env_config,
             rate_service.clone(),
-        )
-        .await?;
+        );
+
+        current_state = if interruptible {
+            select! {
+                biased;
+
+                _ = protocol::stop_requested(&mut stop) => return Ok(None),
+                state = transition => state?,
+            }
+        } else {
+            transition.await?
+        };
+
+        match current_state {
+            AliceState::XmrLockTransactionSent { .. } => {
+                swap.event_loop_handle.commit_reservation()
+            }
+            AliceState::SafelyAborted => swap.event_loop_handle.release_reservation(),
+            _ => {}
+        }
 
         let db_state = (&current_state).into();
         swap.db
@@ -47,7 +120,25 @@ where
             .await?;
     }
 
-    Ok(current_state)
+    Ok(Some(current_state))
+}
+
+/// Whether the transition out of `state` only waits for the other party or
+/// the blockchain, so that interrupting it is no different from restarting
+/// the ASB.
+///
+/// Waiting for the redeem transaction to be final re-publishes it with a
+/// higher fee, which is done again when the swap is resumed.
+fn only_waits(state: &AliceState) -> bool {
+    matches!(
+        state,
+        AliceState::Started { .. }
+            | AliceState::XmrLockTransactionSent { .. }
+            | AliceState::XmrLocked { .. }
+            | AliceState::XmrLockTransferProofSent { .. }
+            | AliceState::BtcRedeemTransactionPublished { .. }
+            | AliceState::BtcCancelled { .. }
+    )
 }
 
 async fn next_state<LR>(
@@ -56,6 +147,7 @@ async fn next_state<LR>(
     event_loop_handle: &mut EventLoopHandle,
     bitcoin_wallet: &bitcoin::Wallet,
     monero_wallet: &monero::Wallet,
+    db: &Database,
     env_config: &Config,
     mut rate_service: LR,
 ) -> Result<AliceState>
@@ -170,6 +262,19 @@ where
             transfer_proof,
             state3,
         } => {
+            // The encrypted signature might have been received and saved by the event loop
+            // while this swap was not running.
+            if let Some(encrypted_signature) = db.get_encrypted_signature(swap_id)? {
+                info!("Found encrypted signature in database");
+
+                return Ok(AliceState::EncSigLearned {
+                    monero_wallet_restore_blockheight,
+                    transfer_proof,
+                    encrypted_signature: Box::new(encrypted_signature),
+                    state3,
+                });
+            }
+
             let tx_lock_status = bitcoin_wallet.subscribe_to(state3.tx_lock.clone()).await;
 
             select! {
@@ -248,9 +353,31 @@ where
             },
         },
         AliceState::BtcRedeemTransactionPublished { state3 } => {
-            let subscription = bitcoin_wallet.subscribe_to(state3.tx_redeem()).await;
+            let tx_redeem = state3.tx_redeem();
+            let subscription = bitcoin_wallet.subscribe_to(tx_redeem.clone()).await;
+
+            let finality = match bitcoin_wallet.get_raw_transaction(tx_redeem.txid()).await {
+                Ok(transaction) => {
+                    bitcoin_wallet
+                        .wait_until_final_bumping_fee(
+                            &subscription,
+                            transaction,
+                            "redeem",
+                            state3.cpfp_vout(),
+                            Some(state3.fee_bump_deadline()),
+                        )
+                        .await
+                }
+                Err(error) => {
+                    warn!(
+                        "Unable to bump fee of Bitcoin redeem transaction. Error {:#}",
+                        error
+                    );
+                    subscription.wait_until_final().await
+                }
+            };
 
-            match subscription.wait_until_final().await {
+            match finality {
                 Ok(_) => AliceState::BtcRedeemed,
                 Err(e) => {
                     bail!("The Bitcoin redeem transaction was seen in mempool, but waiting for finality timed out with {}. Manual investigation might be needed to ensure that the transaction was included.", e)
@@ -0,0 +1,12 @@
+//! Run an XMR/BTC swap in the role of Alice on behalf of the taker.
+//!
+//! The taker sells XMR to a maker that is configured to buy XMR. The swap
+//! itself is executed with [`crate::protocol::alice::run`], this module only
+//! provides the networking to negotiate the swap with the maker.
+pub use self::behaviour::{Behaviour, OutEvent};
+pub use self::event_loop::{EventLoop, EventLoopHandle};
+
+mod behaviour;
+pub mod event_loop;
+mod execution_setup;
+pub mod spot_price;
@@ -0,0 +1,103 @@
+use crate::network::quote::AskQuote;
+use crate::network::{ask_spot_price, encrypted_signature, quote, redial, transfer_proof};
+use crate::protocol::alice::taker::execution_setup;
+use crate::protocol::alice::State3;
+use anyhow::{anyhow, Error, Result};
+use libp2p::core::Multiaddr;
+use libp2p::ping::{Ping, PingEvent};
+use libp2p::request_response::{RequestId, ResponseChannel};
+use libp2p::{NetworkBehaviour, PeerId};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    QuoteReceived {
+        id: RequestId,
+        response: AskQuote,
+    },
+    SpotPriceReceived {
+        id: RequestId,
+        response: ask_spot_price::Response,
+    },
+    ExecutionSetupDone(Box<Result<State3>>),
+    TransferProofAcknowledged {
+        id: RequestId,
+    },
+    EncryptedSignatureReceived {
+        msg: Box<encrypted_signature::Request>,
+        channel: ResponseChannel<()>,
+        peer: PeerId,
+    },
+    AllRedialAttemptsExhausted {
+        peer: PeerId,
+    },
+    Failure {
+        peer: PeerId,
+        error: Error,
+    },
+    /// "Fallback" variant that allows the event mapping code to swallow certain
+    /// events that we don't want the caller to deal with.
+    Other,
+}
+
+impl OutEvent {
+    pub fn unexpected_request(peer: PeerId) -> OutEvent {
+        OutEvent::Failure {
+            peer,
+            error: anyhow!("Unexpected request received"),
+        }
+    }
+
+    pub fn unexpected_response(peer: PeerId) -> OutEvent {
+        OutEvent::Failure {
+            peer,
+            error: anyhow!("Unexpected response received"),
+        }
+    }
+}
+
+/// A `NetworkBehaviour` that represents a taker selling XMR as Alice.
+#[derive(NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    pub quote: quote::AskBehaviour,
+    pub spot_price: ask_spot_price::Behaviour,
+    pub execution_setup: execution_setup::Behaviour,
+    pub transfer_proof: transfer_proof::Behaviour,
+    pub encrypted_signature: encrypted_signature::Behaviour,
+    pub redial: redial::Behaviour,
+
+    /// Ping behaviour that ensures that the underlying network connection is
+    /// still alive. If the ping fails a connection close event will be
+    /// emitted that is picked up as swarm event.
+    ping: Ping,
+}
+
+impl Behaviour {
+    pub fn new(maker: PeerId) -> Self {
+        Self {
+            quote: quote::ask_taker(),
+            spot_price: ask_spot_price::taker(),
+            execution_setup: Default::default(),
+            transfer_proof: transfer_proof::alice(),
+            encrypted_signature: encrypted_signature::alice(),
+            redial: redial::Behaviour::new(maker, Duration::from_secs(2)),
+            ping: Ping::default(),
+        }
+    }
+
+    /// Add a known address for the given peer
+    pub fn add_address(&mut self, peer_id: PeerId, address: Multiaddr) {
+        self.quote.add_address(&peer_id, address.clone());
+        self.spot_price.add_address(&peer_id, address.clone());
+        self.transfer_proof.add_address(&peer_id, address.clone());
+        self.encrypted_signature.add_address(&peer_id, address);
+    }
+}
+
+impl From<PingEvent> for OutEvent {
+    fn from(_: PingEvent) -> Self {
+        OutEvent::Other
+    }
+}
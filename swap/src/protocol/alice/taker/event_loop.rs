@@ -0,0 +1,283 @@
+use crate::bitcoin::EncryptedSignature;
+use crate::network::ask_spot_price::Response;
+use crate::network::quote::AskQuote;
+use crate::network::spot_price::BlockchainNetwork;
+use crate::network::{ask_spot_price, transfer_proof};
+use crate::protocol::alice;
+use crate::protocol::alice::taker::{Behaviour, OutEvent};
+use crate::protocol::alice::{State0, State3};
+use crate::{bitcoin, env, monero};
+use anyhow::{bail, Result};
+use futures::future::{BoxFuture, OptionFuture};
+use futures::{FutureExt, StreamExt};
+use libp2p::request_response::{RequestId, ResponseChannel};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{PeerId, Swarm};
+use std::collections::HashMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[allow(missing_debug_implementations)]
+pub struct EventLoop {
+    swap_id: Uuid,
+    swarm: libp2p::Swarm<Behaviour>,
+    maker_peer_id: PeerId,
+
+    // these streams represents outgoing requests that we have to make
+    quote_requests: bmrng::RequestReceiverStream<(), AskQuote>,
+    spot_price_requests:
+        bmrng::RequestReceiverStream<ask_spot_price::Request, ask_spot_price::Response>,
+    transfer_proofs: bmrng::RequestReceiverStream<monero::TransferProof, ()>,
+    execution_setup_requests: bmrng::RequestReceiverStream<State0, Result<State3>>,
+
+    // these represents requests that are currently in-flight.
+    // once we get a response to a matching [`RequestId`], we will use the responder to relay the
+    // response.
+    inflight_spot_price_requests: HashMap<RequestId, bmrng::Responder<ask_spot_price::Response>>,
+    inflight_quote_requests: HashMap<RequestId, bmrng::Responder<AskQuote>>,
+    inflight_transfer_proof_requests: HashMap<RequestId, bmrng::Responder<()>>,
+    inflight_execution_setup: Option<bmrng::Responder<Result<State3>>>,
+
+    /// The sender we will use to relay incoming encrypted signatures.
+    encrypted_signature: bmrng::RequestSender<EncryptedSignature, ()>,
+    /// The future representing the successful handling of an incoming
+    /// encrypted signature.
+    ///
+    /// Once we've sent the encrypted signature to the ongoing swap, this
+    /// future waits until the swap took it "out" of the `EventLoopHandle`. As
+    /// this future resolves, we use the `ResponseChannel` returned from it to
+    /// send an ACK to the maker that we have successfully processed the
+    /// encrypted signature.
+    pending_encrypted_signature: OptionFuture<BoxFuture<'static, ResponseChannel<()>>>,
+}
+
+impl EventLoop {
+    /// Creates the event loop together with the handle used to negotiate the
+    /// swap and the [`alice::EventLoopHandle`] used to execute it.
+    pub fn new(
+        swap_id: Uuid,
+        swarm: Swarm<Behaviour>,
+        maker_peer_id: PeerId,
+        env_config: env::Config,
+    ) -> Result<(Self, EventLoopHandle, alice::EventLoopHandle)> {
+        let execution_setup = bmrng::channel_with_timeout(1, Duration::from_secs(30));
+        let spot_price = bmrng::channel_with_timeout(1, Duration::from_secs(30));
+        let quote = bmrng::channel_with_timeout(1, Duration::from_secs(30));
+
+        // we deliberately don't put timeouts on these channels because the swap always
+        // races these futures against a timelock
+        let transfer_proof = bmrng::channel(1);
+        let encrypted_signature = bmrng::channel(1);
+
+        let event_loop = EventLoop {
+            swap_id,
+            swarm,
+            maker_peer_id,
+            execution_setup_requests: execution_setup.1.into(),
+            transfer_proofs: transfer_proof.1.into(),
+            encrypted_signature: encrypted_signature.0,
+            spot_price_requests: spot_price.1.into(),
+            quote_requests: quote.1.into(),
+            inflight_spot_price_requests: HashMap::default(),
+            inflight_quote_requests: HashMap::default(),
+            inflight_execution_setup: None,
+            inflight_transfer_proof_requests: HashMap::default(),
+            pending_encrypted_signature: OptionFuture::from(None),
+        };
+
+        let handle = EventLoopHandle {
+            swap_id,
+            execution_setup: execution_setup.0,
+            spot_price: spot_price.0,
+            quote: quote.0,
+            env_config,
+        };
+
+        let swap_handle = alice::EventLoopHandle::new(encrypted_signature.1, transfer_proof.0);
+
+        Ok((event_loop, handle, swap_handle))
+    }
+
+    pub async fn run(mut self) {
+        match self.swarm.dial(&self.maker_peer_id) {
+            Ok(()) => {}
+            Err(e) => {
+                tracing::error!("Failed to initiate dial to buyer: {}", e);
+                return;
+            }
+        }
+
+        loop {
+            // Note: We are making very elaborate use of `select!` macro's feature here. Make sure to read the documentation thoroughly: https://docs.rs/tokio/1.4.0/tokio/macro.select.html
+            tokio::select! {
+                swarm_event = self.swarm.next_event().fuse() => {
+                    match swarm_event {
+                        SwarmEvent::Behaviour(OutEvent::SpotPriceReceived { id, response }) => {
+                            if let Some(responder) = self.inflight_spot_price_requests.remove(&id) {
+                                let _ = responder.respond(response);
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::QuoteReceived { id, response }) => {
+                            if let Some(responder) = self.inflight_quote_requests.remove(&id) {
+                                let _ = responder.respond(response);
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::ExecutionSetupDone(response)) => {
+                            if let Some(responder) = self.inflight_execution_setup.take() {
+                                let _ = responder.respond(*response);
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::EncryptedSignatureReceived { msg, channel, peer }) => {
+                            let swap_id = msg.swap_id;
+
+                            if peer != self.maker_peer_id {
+                                tracing::warn!(
+                                    %swap_id,
+                                    "Ignoring malicious encrypted signature from {}, expected to receive it from {}",
+                                    peer,
+                                    self.maker_peer_id);
+                                continue;
+                            }
+
+                            if swap_id != self.swap_id {
+                                tracing::warn!("Received unexpected encrypted signature for swap {} while running swap {}. This encrypted signature will be ignored", swap_id, self.swap_id);
+
+                                // When receiving an encrypted signature that is unexpected we still have to acknowledge that it was received
+                                let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(channel, ());
+                                continue;
+                            }
+
+                            let mut responder = match self.encrypted_signature.send(msg.tx_redeem_encsig).await {
+                                Ok(responder) => responder,
+                                Err(e) => {
+                                    tracing::warn!("Failed to pass on encrypted signature: {:#}", e);
+                                    continue;
+                                }
+                            };
+
+                            self.pending_encrypted_signature = OptionFuture::from(Some(async move {
+                                let _ = responder.recv().await;
+
+                                channel
+                            }.boxed()));
+                        }
+                        SwarmEvent::Behaviour(OutEvent::TransferProofAcknowledged { id }) => {
+                            if let Some(responder) = self.inflight_transfer_proof_requests.remove(&id) {
+                                let _ = responder.respond(());
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::AllRedialAttemptsExhausted { peer }) if peer == self.maker_peer_id => {
+                            tracing::error!("Exhausted all re-dial attempts to buyer");
+                            return;
+                        }
+                        SwarmEvent::Behaviour(OutEvent::Failure { peer, error }) => {
+                            tracing::warn!(%peer, "Communication error: {:#}", error);
+                            return;
+                        }
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } if peer_id == self.maker_peer_id => {
+                            tracing::info!("Connected to buyer at {}", endpoint.get_remote_address());
+                        }
+                        SwarmEvent::Dialing(peer_id) if peer_id == self.maker_peer_id => {
+                            tracing::debug!("Dialling buyer at {}", peer_id);
+                        }
+                        SwarmEvent::ConnectionClosed { peer_id, endpoint, num_established, cause } if peer_id == self.maker_peer_id && num_established == 0 => {
+                            match cause {
+                                Some(error) => {
+                                    tracing::warn!("Lost connection to buyer at {}, cause: {}", endpoint.get_remote_address(), error);
+                                },
+                                None => {
+                                    // no error means the disconnection was requested
+                                    tracing::info!("Successfully closed connection to buyer");
+                                    return;
+                                }
+                            }
+                        }
+                        SwarmEvent::UnreachableAddr { peer_id, address, attempts_remaining, error } if peer_id == self.maker_peer_id && attempts_remaining == 0 => {
+                            tracing::warn!(%address, "Failed to dial buyer: {}", error);
+
+                            if let Some(duration) = self.swarm.behaviour_mut().redial.until_next_redial() {
+                                tracing::info!("Next redial attempt in {}s", duration.as_secs());
+                            }
+                        }
+                        _ => {}
+                    }
+                },
+
+                // Handle to-be-sent requests for all our network protocols.
+                // Use `self.is_connected_to_maker` as a guard to "buffer" requests until we are connected.
+                Some((request, responder)) = self.spot_price_requests.next().fuse(), if self.is_connected_to_maker() => {
+                    let id = self.swarm.behaviour_mut().spot_price.send_request(&self.maker_peer_id, request);
+                    self.inflight_spot_price_requests.insert(id, responder);
+                },
+                Some(((), responder)) = self.quote_requests.next().fuse(), if self.is_connected_to_maker() => {
+                    let id = self.swarm.behaviour_mut().quote.send_request(&self.maker_peer_id, ());
+                    self.inflight_quote_requests.insert(id, responder);
+                },
+                Some((request, responder)) = self.execution_setup_requests.next().fuse(), if self.is_connected_to_maker() => {
+                    self.swarm.behaviour_mut().execution_setup.run(self.maker_peer_id, self.swap_id, request);
+                    self.inflight_execution_setup = Some(responder);
+                },
+                Some((tx_lock_proof, responder)) = self.transfer_proofs.next().fuse(), if self.is_connected_to_maker() => {
+                    let request = transfer_proof::Request {
+                        swap_id: self.swap_id,
+                        tx_lock_proof
+                    };
+
+                    let id = self.swarm.behaviour_mut().transfer_proof.send_request(&self.maker_peer_id, request);
+                    self.inflight_transfer_proof_requests.insert(id, responder);
+                },
+
+                Some(response_channel) = &mut self.pending_encrypted_signature => {
+                    let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(response_channel, ());
+
+                    self.pending_encrypted_signature = OptionFuture::from(None);
+                }
+            }
+        }
+    }
+
+    fn is_connected_to_maker(&self) -> bool {
+        self.swarm.is_connected(&self.maker_peer_id)
+    }
+}
+
+#[derive(Debug)]
+pub struct EventLoopHandle {
+    swap_id: Uuid,
+    execution_setup: bmrng::RequestSender<State0, Result<State3>>,
+    spot_price: bmrng::RequestSender<ask_spot_price::Request, ask_spot_price::Response>,
+    quote: bmrng::RequestSender<(), AskQuote>,
+    env_config: env::Config,
+}
+
+impl EventLoopHandle {
+    pub async fn execution_setup(&mut self, state0: State0) -> Result<State3> {
+        self.execution_setup.send_receive(state0).await?
+    }
+
+    pub async fn request_spot_price(&mut self, xmr: monero::Amount) -> Result<bitcoin::Amount> {
+        let response = self
+            .spot_price
+            .send_receive(ask_spot_price::Request {
+                swap_id: self.swap_id,
+                xmr,
+                blockchain_network: BlockchainNetwork {
+                    bitcoin: self.env_config.bitcoin_network,
+                    monero: self.env_config.monero_network,
+                },
+            })
+            .await?;
+
+        match response {
+            Response::Btc(btc) => Ok(btc),
+            Response::Error(error) => {
+                let error: alice::taker::spot_price::Error = error.into();
+                bail!(error);
+            }
+        }
+    }
+
+    pub async fn request_quote(&mut self) -> Result<AskQuote> {
+        Ok(self.quote.send_receive(()).await?)
+    }
+}
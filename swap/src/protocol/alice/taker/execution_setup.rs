@@ -0,0 +1,103 @@
+use crate::network::cbor_request_response::BUF_SIZE;
+use crate::protocol::alice::taker;
+use crate::protocol::alice::{State0, State3};
+use crate::protocol::{Message0, Message2, Message4};
+use anyhow::{bail, Context, Error, Result};
+use libp2p::PeerId;
+use libp2p_async_await::BehaviourOutEvent;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Done(Result<State3>),
+}
+
+impl From<BehaviourOutEvent<(), State3, anyhow::Error>> for OutEvent {
+    fn from(event: BehaviourOutEvent<(), State3, Error>) -> Self {
+        match event {
+            BehaviourOutEvent::Outbound(_, Ok(state3)) => OutEvent::Done(Ok(state3)),
+            BehaviourOutEvent::Outbound(_, Err(e)) => OutEvent::Done(Err(e)),
+            BehaviourOutEvent::Inbound(..) => unreachable!("The taker only supports outbound"),
+        }
+    }
+}
+
+/// The execution setup of a swap in which the taker sells XMR.
+///
+/// The taker is Alice in this swap but, in contrast to the `execution_setup`
+/// protocol, opens the substream to the maker.
+#[derive(libp2p::NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+pub struct Behaviour {
+    inner: libp2p_async_await::Behaviour<(), State3, anyhow::Error>,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self {
+            inner: libp2p_async_await::Behaviour::new(b"/comit/xmr/btc/ask-execution-setup/1.0.0"),
+        }
+    }
+}
+
+impl Behaviour {
+    pub fn run(&mut self, maker: PeerId, swap_id: Uuid, state0: State0) {
+        self.inner.do_protocol_dialer(maker, move |mut substream| {
+            let protocol = async move {
+                tracing::debug!("Starting execution setup with {}", maker);
+
+                let message0 =
+                    serde_cbor::from_slice::<Message0>(&substream.read_message(BUF_SIZE).await?)
+                        .context("Failed to deserialize message0")?;
+                let (received_swap_id, state1) = state0.receive(message0)?;
+
+                if received_swap_id != swap_id {
+                    bail!(
+                        "Maker sent swap id {} but we requested a spot price for swap {}",
+                        received_swap_id,
+                        swap_id
+                    )
+                }
+
+                substream
+                    .write_message(
+                        &serde_cbor::to_vec(&state1.next_message())
+                            .context("Failed to serialize message1")?,
+                    )
+                    .await?;
+
+                let message2 =
+                    serde_cbor::from_slice::<Message2>(&substream.read_message(BUF_SIZE).await?)
+                        .context("Failed to deserialize message2")?;
+                let state2 = state1
+                    .receive(message2)
+                    .context("Failed to receive Message2")?;
+
+                substream
+                    .write_message(
+                        &serde_cbor::to_vec(&state2.next_message())
+                            .context("Failed to serialize message3")?,
+                    )
+                    .await?;
+
+                let message4 =
+                    serde_cbor::from_slice::<Message4>(&substream.read_message(BUF_SIZE).await?)
+                        .context("Failed to deserialize message4")?;
+                let state3 = state2.receive(message4)?;
+
+                Ok(state3)
+            };
+
+            async move { tokio::time::timeout(Duration::from_secs(60), protocol).await? }
+        })
+    }
+}
+
+impl From<OutEvent> for taker::OutEvent {
+    fn from(event: OutEvent) -> Self {
+        match event {
+            OutEvent::Done(res) => Self::ExecutionSetupDone(Box::new(res)),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::monero;
+use crate::network::ask_spot_price;
+use crate::network::spot_price::BlockchainNetwork;
+
+#[derive(Clone, Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("Buyer currently does not accept incoming swap requests, please try again later")]
+    NoSwapsAccepted,
+    #[error("Buyer refused to buy {sell} because the minimum configured sell limit is {min}")]
+    AmountBelowMinimum {
+        min: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Buyer refused to buy {sell} because the maximum configured sell limit is {max}")]
+    AmountAboveMaximum {
+        max: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Buyer's BTC balance is currently too low to fulfill the swap request to sell {sell}, please try again later")]
+    BalanceTooLow { sell: monero::Amount },
+
+    #[error("Buyer blockchain network {asb:?} setup did not match your blockchain network setup {cli:?}")]
+    BlockchainNetworkMismatch {
+        cli: BlockchainNetwork,
+        asb: BlockchainNetwork,
+    },
+
+    /// To be used for errors that cannot be explained on the CLI side (e.g.
+    /// rate update problems on the buyer side)
+    #[error("Buyer encountered a problem, please try again later.")]
+    Other,
+}
+
+impl From<ask_spot_price::Error> for Error {
+    fn from(error: ask_spot_price::Error) -> Self {
+        match error {
+            ask_spot_price::Error::NoSwapsAccepted => Error::NoSwapsAccepted,
+            ask_spot_price::Error::AmountBelowMinimum { min, sell } => {
+                Error::AmountBelowMinimum { min, sell }
+            }
+            ask_spot_price::Error::AmountAboveMaximum { max, sell } => {
+                Error::AmountAboveMaximum { max, sell }
+            }
+            ask_spot_price::Error::BalanceTooLow { sell } => Error::BalanceTooLow { sell },
+            ask_spot_price::Error::BlockchainNetworkMismatch { cli, asb } => {
+                Error::BlockchainNetworkMismatch { cli, asb }
+            }
+            ask_spot_price::Error::Other => Error::Other,
+        }
+    }
+}
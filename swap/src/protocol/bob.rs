@@ -9,12 +9,13 @@ pub use self::cancel::cancel;
 pub use self::event_loop::{EventLoop, EventLoopHandle};
 pub use self::refund::refund;
 pub use self::state::*;
-pub use self::swap::{run, run_until};
+pub use self::swap::{run, run_until, run_until_stopped};
 
 mod behaviour;
 pub mod cancel;
 pub mod event_loop;
 mod execution_setup;
+pub mod maker;
 pub mod refund;
 pub mod spot_price;
 pub mod state;
@@ -23,18 +24,23 @@ pub mod swap;
 pub struct Swap {
     pub state: BobState,
     pub event_loop_handle: EventLoopHandle,
-    pub db: Database,
+    pub db: Arc<Database>,
     pub bitcoin_wallet: Arc<bitcoin::Wallet>,
     pub monero_wallet: Arc<monero::Wallet>,
     pub env_config: env::Config,
     pub id: Uuid,
     pub receive_monero_address: monero::Address,
+    /// Shares of the redeemed XMR that go to other addresses than
+    /// `receive_monero_address`.
+    pub monero_splits: Vec<monero::Split>,
+    /// Signs the Bitcoin lock transaction instead of our wallet.
+    pub external_signer: Option<bitcoin::ExternalSigner>,
 }
 
 impl Swap {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        db: Database,
+        db: Arc<Database>,
         id: Uuid,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
         monero_wallet: Arc<monero::Wallet>,
@@ -52,11 +58,13 @@ impl Swap {
             env_config,
             id,
             receive_monero_address,
+            monero_splits: Vec::new(),
+            external_signer: None,
         }
     }
 
     pub fn from_db(
-        db: Database,
+        db: Arc<Database>,
         id: Uuid,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
         monero_wallet: Arc<monero::Wallet>,
@@ -75,6 +83,18 @@ impl Swap {
             env_config,
             id,
             receive_monero_address,
+            monero_splits: Vec::new(),
+            external_signer: None,
         })
     }
+
+    pub fn with_monero_splits(mut self, monero_splits: Vec<monero::Split>) -> Self {
+        self.monero_splits = monero_splits;
+        self
+    }
+
+    pub fn with_external_signer(mut self, external_signer: bitcoin::ExternalSigner) -> Self {
+        self.external_signer = Some(external_signer);
+        self
+    }
 }
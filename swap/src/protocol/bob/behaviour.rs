@@ -1,4 +1,4 @@
-use crate::network::quote::BidQuote;
+use crate::network::quote::{BidQuote, TieredBidQuote};
 use crate::network::{encrypted_signature, quote, redial, spot_price, transfer_proof};
 use crate::protocol::bob;
 use crate::protocol::bob::{execution_setup, State2};
@@ -15,6 +15,14 @@ pub enum OutEvent {
         id: RequestId,
         response: BidQuote,
     },
+    TieredQuoteReceived {
+        id: RequestId,
+        response: TieredBidQuote,
+    },
+    /// Alice does not support tiered quotes.
+    TieredQuoteUnsupported {
+        id: RequestId,
+    },
     SpotPriceReceived {
         id: RequestId,
         response: spot_price::Response,
@@ -62,6 +70,7 @@ impl OutEvent {
 #[allow(missing_debug_implementations)]
 pub struct Behaviour {
     pub quote: quote::Behaviour,
+    pub tiered_quote: quote::TieredBehaviour,
     pub spot_price: spot_price::Behaviour,
     pub execution_setup: execution_setup::Behaviour,
     pub transfer_proof: transfer_proof::Behaviour,
@@ -78,6 +87,7 @@ impl Behaviour {
     pub fn new(alice: PeerId) -> Self {
         Self {
             quote: quote::bob(),
+            tiered_quote: quote::tiered_bob(),
             spot_price: bob::spot_price::bob(),
             execution_setup: Default::default(),
             transfer_proof: transfer_proof::bob(),
@@ -90,6 +100,7 @@ impl Behaviour {
     /// Add a known address for the given peer
     pub fn add_address(&mut self, peer_id: PeerId, address: Multiaddr) {
         self.quote.add_address(&peer_id, address.clone());
+        self.tiered_quote.add_address(&peer_id, address.clone());
         self.spot_price.add_address(&peer_id, address.clone());
         self.transfer_proof.add_address(&peer_id, address.clone());
         self.encrypted_signature.add_address(&peer_id, address);
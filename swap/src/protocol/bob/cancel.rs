@@ -14,7 +14,7 @@ pub enum Error {
 pub async fn cancel(
     swap_id: Uuid,
     bitcoin_wallet: Arc<Wallet>,
-    db: Database,
+    db: Arc<Database>,
     force: bool,
 ) -> Result<Result<(Txid, BobState), Error>> {
     let state = db.get_state(swap_id)?.try_into_bob()?.into();
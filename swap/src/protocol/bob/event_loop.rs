@@ -1,8 +1,10 @@
+use crate::asb::Ledger;
 use crate::bitcoin::EncryptedSignature;
-use crate::network::quote::BidQuote;
+use crate::network::quote::TieredBidQuote;
 use crate::network::spot_price::{BlockchainNetwork, Response};
 use crate::network::{encrypted_signature, spot_price};
 use crate::protocol::bob;
+use crate::protocol::bob::execution_setup::EXTERNAL_FUNDING_TIMEOUT;
 use crate::protocol::bob::{Behaviour, OutEvent, State0, State2};
 use crate::{bitcoin, env, monero};
 use anyhow::{bail, Context, Result};
@@ -22,9 +24,11 @@ pub struct EventLoop {
     swarm: libp2p::Swarm<Behaviour>,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     alice_peer_id: PeerId,
+    /// Funds the swap instead of our wallet.
+    external_funding: Option<bitcoin::ExternalSigner>,
 
     // these streams represents outgoing requests that we have to make
-    quote_requests: bmrng::RequestReceiverStream<(), BidQuote>,
+    quote_requests: bmrng::RequestReceiverStream<(), TieredBidQuote>,
     spot_price_requests: bmrng::RequestReceiverStream<spot_price::Request, spot_price::Response>,
     encrypted_signatures: bmrng::RequestReceiverStream<EncryptedSignature, ()>,
     execution_setup_requests: bmrng::RequestReceiverStream<State0, Result<State2>>,
@@ -33,7 +37,8 @@ pub struct EventLoop {
     // once we get a response to a matching [`RequestId`], we will use the responder to relay the
     // response.
     inflight_spot_price_requests: HashMap<RequestId, bmrng::Responder<spot_price::Response>>,
-    inflight_quote_requests: HashMap<RequestId, bmrng::Responder<BidQuote>>,
+    inflight_quote_requests: HashMap<RequestId, bmrng::Responder<TieredBidQuote>>,
+    inflight_tiered_quote_requests: HashMap<RequestId, bmrng::Responder<TieredBidQuote>>,
     inflight_encrypted_signature_requests: HashMap<RequestId, bmrng::Responder<()>>,
     inflight_execution_setup: Option<bmrng::Responder<Result<State2>>>,
 
@@ -56,8 +61,14 @@ impl EventLoop {
         alice_peer_id: PeerId,
         bitcoin_wallet: Arc<bitcoin::Wallet>,
         env_config: env::Config,
+        external_funding: Option<bitcoin::ExternalSigner>,
     ) -> Result<(Self, EventLoopHandle)> {
-        let execution_setup = bmrng::channel_with_timeout(1, Duration::from_secs(30));
+        let execution_setup_timeout = if external_funding.is_some() {
+            EXTERNAL_FUNDING_TIMEOUT
+        } else {
+            Duration::from_secs(30)
+        };
+        let execution_setup = bmrng::channel_with_timeout(1, execution_setup_timeout);
         let transfer_proof = bmrng::channel_with_timeout(1, Duration::from_secs(30));
         let encrypted_signature = bmrng::channel_with_timeout(1, Duration::from_secs(30));
         let spot_price = bmrng::channel_with_timeout(1, Duration::from_secs(30));
@@ -68,6 +79,7 @@ impl EventLoop {
             swarm,
             alice_peer_id,
             bitcoin_wallet,
+            external_funding,
             execution_setup_requests: execution_setup.1.into(),
             transfer_proof: transfer_proof.0,
             encrypted_signatures: encrypted_signature.1.into(),
@@ -75,6 +87,7 @@ impl EventLoop {
             quote_requests: quote.1.into(),
             inflight_spot_price_requests: HashMap::default(),
             inflight_quote_requests: HashMap::default(),
+            inflight_tiered_quote_requests: HashMap::default(),
             inflight_execution_setup: None,
             inflight_encrypted_signature_requests: HashMap::default(),
             pending_transfer_proof: OptionFuture::from(None),
@@ -87,6 +100,7 @@ impl EventLoop {
             spot_price: spot_price.0,
             quote: quote.0,
             env_config,
+            reservation: None,
         };
 
         Ok((event_loop, handle))
@@ -113,9 +127,22 @@ impl EventLoop {
                         }
                         SwarmEvent::Behaviour(OutEvent::QuoteReceived { id, response }) => {
                             if let Some(responder) = self.inflight_quote_requests.remove(&id) {
+                                let _ = responder.respond(TieredBidQuote::from_legacy(response));
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::TieredQuoteReceived { id, response }) => {
+                            if let Some(responder) = self.inflight_tiered_quote_requests.remove(&id) {
                                 let _ = responder.respond(response);
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::TieredQuoteUnsupported { id }) => {
+                            if let Some(responder) = self.inflight_tiered_quote_requests.remove(&id) {
+                                tracing::debug!("Alice does not support tiered quotes, requesting a single price instead");
+
+                                let id = self.swarm.behaviour_mut().quote.send_request(&self.alice_peer_id, ());
+                                self.inflight_quote_requests.insert(id, responder);
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::ExecutionSetupDone(response)) => {
                             if let Some(responder) = self.inflight_execution_setup.take() {
                                 let _ = responder.respond(*response);
@@ -206,11 +233,11 @@ impl EventLoop {
                     self.inflight_spot_price_requests.insert(id, responder);
                 },
                 Some(((), responder)) = self.quote_requests.next().fuse(), if self.is_connected_to_alice() => {
-                    let id = self.swarm.behaviour_mut().quote.send_request(&self.alice_peer_id, ());
-                    self.inflight_quote_requests.insert(id, responder);
+                    let id = self.swarm.behaviour_mut().tiered_quote.send_request(&self.alice_peer_id, ());
+                    self.inflight_tiered_quote_requests.insert(id, responder);
                 },
                 Some((request, responder)) = self.execution_setup_requests.next().fuse(), if self.is_connected_to_alice() => {
-                    self.swarm.behaviour_mut().execution_setup.run(self.alice_peer_id, request, self.bitcoin_wallet.clone());
+                    self.swarm.behaviour_mut().execution_setup.run(self.alice_peer_id, request, self.bitcoin_wallet.clone(), self.external_funding.clone());
                     self.inflight_execution_setup = Some(responder);
                 },
                 Some((tx_redeem_encsig, responder)) = self.encrypted_signatures.next().fuse(), if self.is_connected_to_alice() => {
@@ -243,11 +270,35 @@ pub struct EventLoopHandle {
     transfer_proof: bmrng::RequestReceiver<monero::TransferProof, ()>,
     encrypted_signature: bmrng::RequestSender<EncryptedSignature, ()>,
     spot_price: bmrng::RequestSender<spot_price::Request, spot_price::Response>,
-    quote: bmrng::RequestSender<(), BidQuote>,
+    quote: bmrng::RequestSender<(), TieredBidQuote>,
     env_config: env::Config,
+    /// The BTC reserved for the swap in the ledger of the ASB.
+    reservation: Option<(Ledger<bitcoin::Amount>, Uuid)>,
 }
 
 impl EventLoopHandle {
+    /// Creates a handle for a swap in which the maker acts as Bob.
+    ///
+    /// The maker negotiates the swap in its own event loop before the swap is
+    /// started, hence requesting a quote, a spot price or the execution setup
+    /// through this handle fails.
+    pub fn for_maker(
+        transfer_proof: bmrng::RequestReceiver<monero::TransferProof, ()>,
+        encrypted_signature: bmrng::RequestSender<EncryptedSignature, ()>,
+        env_config: env::Config,
+        reservation: (Ledger<bitcoin::Amount>, Uuid),
+    ) -> Self {
+        Self {
+            execution_setup: bmrng::channel(1).0,
+            transfer_proof,
+            encrypted_signature,
+            spot_price: bmrng::channel(1).0,
+            quote: bmrng::channel(1).0,
+            env_config,
+            reservation: Some(reservation),
+        }
+    }
+
     pub async fn execution_setup(&mut self, state0: State0) -> Result<State2> {
         self.execution_setup.send_receive(state0).await?
     }
@@ -286,8 +337,14 @@ impl EventLoopHandle {
         }
     }
 
-    pub async fn request_quote(&mut self) -> Result<BidQuote> {
-        Ok(self.quote.send_receive(()).await?)
+    pub async fn request_quote(&mut self) -> Result<TieredBidQuote> {
+        let quote = self.quote.send_receive(()).await?;
+
+        if quote.tiers.is_empty() {
+            bail!("Seller sent a quote without any prices")
+        }
+
+        Ok(quote)
     }
 
     pub async fn send_encrypted_signature(
@@ -299,4 +356,12 @@ impl EventLoopHandle {
             .send_receive(tx_redeem_encsig)
             .await?)
     }
+
+    /// Takes the BTC reserved for the swap off the balance of the ASB, because
+    /// the swap published the lock transaction.
+    pub fn commit_reservation(&mut self) {
+        if let Some((ledger, swap_id)) = self.reservation.take() {
+            ledger.commit(swap_id);
+        }
+    }
 }
@@ -7,6 +7,16 @@ use libp2p_async_await::BehaviourOutEvent;
 use std::sync::Arc;
 use std::time::Duration;
 
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long the user has to sign the lock transaction in their external
+/// wallet, while Alice waits for us to continue the execution setup.
+///
+/// Alice reserves her funds for a swap that is being set up for only two
+/// minutes, after that she may have promised them to someone else. Waiting
+/// longer would also let us sit on her price while it moves.
+pub const EXTERNAL_FUNDING_TIMEOUT: Duration = Duration::from_secs(90);
+
 #[derive(Debug)]
 pub enum OutEvent {
     Done(Result<State2>),
@@ -42,7 +52,14 @@ impl Behaviour {
         alice: PeerId,
         state0: State0,
         bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+        external_funding: Option<crate::bitcoin::ExternalSigner>,
     ) {
+        let timeout = if external_funding.is_some() {
+            EXTERNAL_FUNDING_TIMEOUT
+        } else {
+            TIMEOUT
+        };
+
         self.inner.do_protocol_dialer(alice, move |mut substream| {
             let protocol = async move {
                 tracing::debug!("Starting execution setup with {}", alice);
@@ -57,7 +74,18 @@ impl Behaviour {
                 let message1 =
                     serde_cbor::from_slice::<Message1>(&substream.read_message(BUF_SIZE).await?)
                         .context("Failed to deserialize message1")?;
-                let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
+                let state1 = match external_funding {
+                    Some(external_signer) => {
+                        state0
+                            .receive_externally_funded(
+                                &external_signer,
+                                bitcoin_wallet.get_network(),
+                                message1,
+                            )
+                            .await?
+                    }
+                    None => state0.receive(bitcoin_wallet.as_ref(), message1).await?,
+                };
 
                 substream
                     .write_message(
@@ -81,7 +109,7 @@ impl Behaviour {
                 Ok(state2)
             };
 
-            async move { tokio::time::timeout(Duration::from_secs(60), protocol).await? }
+            async move { tokio::time::timeout(timeout, protocol).await? }
         })
     }
 }
@@ -0,0 +1,63 @@
+//! Run an XMR/BTC swap in the role of Bob on behalf of the maker.
+//! The maker holds BTC and wishes to receive XMR from a taker.
+use crate::database::Swap;
+use crate::protocol::bob;
+use crate::protocol::bob::swap::is_complete;
+use crate::protocol::bob::BobState;
+use anyhow::{Context, Result};
+use tokio::sync::watch;
+
+pub mod execution_setup;
+pub mod spot_price;
+
+/// Runs the swap until completion or until a stop is requested through
+/// `stop`, returns `None` in the latter case.
+///
+/// Unlike the CLI, the maker does not sweep the redeemed XMR to an address.
+/// The XMR are transferred into the maker's wallet instead, which is re-opened
+/// afterwards so that it can keep serving other swaps.
+pub async fn run(swap: bob::Swap, stop: watch::Receiver<bool>) -> Result<Option<BobState>> {
+    let swap_id = swap.id;
+    let db = swap.db.clone();
+    let monero_wallet = swap.monero_wallet.clone();
+
+    let state = match bob::run_until_stopped(swap, is_complete_or_btc_redeemed, stop).await? {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+
+    let state5 = match state {
+        BobState::BtcRedeemed(state5) => state5,
+        state => return Ok(Some(state)),
+    };
+
+    let (spend_key, view_key) = state5.xmr_keys();
+
+    if let Err(error) = monero_wallet
+        .create_from(
+            swap_id.to_string(),
+            spend_key,
+            view_key,
+            state5.monero_wallet_restore_blockheight,
+        )
+        .await
+    {
+        // Generating the wallet from keys fails if it already exists, make sure we do
+        // not leave our own wallet closed in that case.
+        monero_wallet.re_open().await?;
+
+        return Err(error).context("Failed to transfer redeemed Monero into our wallet");
+    }
+
+    let state = BobState::XmrRedeemed {
+        tx_lock_id: state5.tx_lock_id(),
+    };
+    db.insert_latest_state(swap_id, Swap::Bob(state.clone().into()))
+        .await?;
+
+    Ok(Some(state))
+}
+
+fn is_complete_or_btc_redeemed(state: &BobState) -> bool {
+    is_complete(state) || matches!(state, BobState::BtcRedeemed(..))
+}
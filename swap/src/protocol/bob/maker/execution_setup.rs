@@ -0,0 +1,118 @@
+use crate::network::cbor_request_response::BUF_SIZE;
+use crate::protocol::bob::{State0, State2};
+use crate::protocol::{alice, Message1, Message3};
+use anyhow::{Context, Error};
+use libp2p::PeerId;
+use libp2p_async_await::BehaviourOutEvent;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub enum OutEvent {
+    Done {
+        taker_peer_id: PeerId,
+        swap_id: Uuid,
+        state2: State2,
+    },
+    Failure {
+        peer: PeerId,
+        error: Error,
+    },
+}
+
+impl From<BehaviourOutEvent<(PeerId, (Uuid, State2)), (), Error>> for OutEvent {
+    fn from(event: BehaviourOutEvent<(PeerId, (Uuid, State2)), (), Error>) -> Self {
+        match event {
+            BehaviourOutEvent::Inbound(_, Ok((taker_peer_id, (swap_id, state2)))) => {
+                OutEvent::Done {
+                    taker_peer_id,
+                    swap_id,
+                    state2,
+                }
+            }
+            BehaviourOutEvent::Inbound(peer, Err(e)) => OutEvent::Failure { peer, error: e },
+            BehaviourOutEvent::Outbound(..) => unreachable!("The maker only supports inbound"),
+        }
+    }
+}
+
+/// The execution setup of a swap in which the maker buys XMR.
+///
+/// The maker is Bob in this swap but, in contrast to the `execution_setup`
+/// protocol, listens for the taker to open the substream.
+#[derive(libp2p::NetworkBehaviour)]
+#[behaviour(out_event = "OutEvent", event_process = false)]
+pub struct Behaviour {
+    inner: libp2p_async_await::Behaviour<(PeerId, (Uuid, State2)), (), anyhow::Error>,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self {
+            inner: libp2p_async_await::Behaviour::new(b"/comit/xmr/btc/ask-execution-setup/1.0.0"),
+        }
+    }
+}
+
+impl Behaviour {
+    pub fn run(
+        &mut self,
+        taker: PeerId,
+        swap_id: Uuid,
+        state0: State0,
+        bitcoin_wallet: Arc<crate::bitcoin::Wallet>,
+    ) {
+        self.inner
+            .do_protocol_listener(taker, move |mut substream| async move {
+                substream
+                    .write_message(
+                        &serde_cbor::to_vec(&state0.next_message())
+                            .context("Failed to serialize message0")?,
+                    )
+                    .await?;
+
+                let message1 =
+                    serde_cbor::from_slice::<Message1>(&substream.read_message(BUF_SIZE).await?)
+                        .context("Failed to deserialize message1")?;
+                let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
+
+                substream
+                    .write_message(
+                        &serde_cbor::to_vec(&state1.next_message())
+                            .context("Failed to serialize message2")?,
+                    )
+                    .await?;
+
+                let message3 =
+                    serde_cbor::from_slice::<Message3>(&substream.read_message(BUF_SIZE).await?)
+                        .context("Failed to deserialize message3")?;
+                let state2 = state1.receive(message3)?;
+
+                substream
+                    .write_message(
+                        &serde_cbor::to_vec(&state2.next_message())
+                            .context("Failed to serialize message4")?,
+                    )
+                    .await?;
+
+                Ok((taker, (swap_id, state2)))
+            })
+    }
+}
+
+impl From<OutEvent> for alice::OutEvent {
+    fn from(event: OutEvent) -> Self {
+        match event {
+            OutEvent::Done {
+                taker_peer_id,
+                swap_id,
+                state2,
+            } => Self::AskExecutionSetupDone {
+                taker_peer_id,
+                swap_id,
+                state2: Box::new(state2),
+            },
+            OutEvent::Failure { peer, error } => Self::Failure { peer, error },
+        }
+    }
+}
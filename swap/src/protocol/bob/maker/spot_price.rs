@@ -0,0 +1,77 @@
+use crate::network::ask_spot_price;
+use crate::network::spot_price::BlockchainNetwork;
+use crate::{bitcoin, monero};
+use uuid::Uuid;
+
+/// The reasons for which the maker declines a request to buy XMR.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("ASB is running in resume-only mode")]
+    ResumeOnlyMode,
+    #[error("ASB is not configured to buy XMR")]
+    BuyingXmrDisabled,
+    #[error("Amount {sell} below minimum {min}")]
+    AmountBelowMinimum {
+        min: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Amount {sell} above maximum {max}")]
+    AmountAboveMaximum {
+        max: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Balance {balance} too low to fulfill buying {sell}")]
+    BalanceTooLow {
+        balance: bitcoin::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Failed to fetch latest rate")]
+    LatestRateFetchFailed(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("Failed to calculate quote: {0}")]
+    BuyQuoteCalculationFailed(#[source] anyhow::Error),
+    #[error("Failed to prepare the execution setup: {0}")]
+    ExecutionSetupPreparationFailed(#[source] anyhow::Error),
+    #[error("Blockchain networks did not match, we are on {asb:?}, but request from {cli:?}")]
+    BlockchainNetworkMismatch {
+        cli: BlockchainNetwork,
+        asb: BlockchainNetwork,
+    },
+    #[error("A swap with id {0} already exists")]
+    DuplicateSwapId(Uuid),
+    #[error("Already buying XMR in {max} swaps started within the last hour")]
+    TooManySwaps { max: usize },
+    #[error("Already setting up a swap with this peer")]
+    SetupInProgress,
+}
+
+impl Error {
+    pub fn to_error_response(&self) -> ask_spot_price::Error {
+        match self {
+            Error::ResumeOnlyMode | Error::BuyingXmrDisabled | Error::TooManySwaps { .. } => {
+                ask_spot_price::Error::NoSwapsAccepted
+            }
+            Error::AmountBelowMinimum { min, sell } => ask_spot_price::Error::AmountBelowMinimum {
+                min: *min,
+                sell: *sell,
+            },
+            Error::AmountAboveMaximum { max, sell } => ask_spot_price::Error::AmountAboveMaximum {
+                max: *max,
+                sell: *sell,
+            },
+            Error::BalanceTooLow { sell, .. } => {
+                ask_spot_price::Error::BalanceTooLow { sell: *sell }
+            }
+            Error::BlockchainNetworkMismatch { cli, asb } => {
+                ask_spot_price::Error::BlockchainNetworkMismatch {
+                    cli: *cli,
+                    asb: *asb,
+                }
+            }
+            Error::LatestRateFetchFailed(_)
+            | Error::BuyQuoteCalculationFailed(_)
+            | Error::ExecutionSetupPreparationFailed(_)
+            | Error::DuplicateSwapId(_)
+            | Error::SetupInProgress => ask_spot_price::Error::Other,
+        }
+    }
+}
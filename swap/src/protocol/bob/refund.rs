@@ -12,7 +12,7 @@ pub struct SwapNotCancelledYet(pub Uuid);
 pub async fn refund(
     swap_id: Uuid,
     bitcoin_wallet: Arc<Wallet>,
-    db: Database,
+    db: Arc<Database>,
     force: bool,
 ) -> Result<Result<BobState, SwapNotCancelledYet>> {
     let state = db.get_state(swap_id)?.try_into_bob()?.into();
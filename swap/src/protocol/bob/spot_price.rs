@@ -1,6 +1,5 @@
-use crate::network::cbor_request_response::CborCodec;
 use crate::network::spot_price;
-use crate::network::spot_price::SpotPriceProtocol;
+use crate::network::spot_price::{Codec, SpotPriceProtocol};
 use crate::protocol::bob::OutEvent;
 use libp2p::request_response::{ProtocolSupport, RequestResponseConfig};
 use libp2p::PeerId;
@@ -14,8 +13,11 @@ pub type SpotPriceOutEvent = spot_price::OutEvent;
 /// given amount of BTC in XMR.
 pub fn bob() -> spot_price::Behaviour {
     spot_price::Behaviour::new(
-        CborCodec::default(),
-        vec![(SpotPriceProtocol, ProtocolSupport::Outbound)],
+        Codec::default(),
+        SpotPriceProtocol::ALL
+            .iter()
+            .map(|protocol| (*protocol, ProtocolSupport::Outbound))
+            .collect::<Vec<_>>(),
         RequestResponseConfig::default(),
     )
 }
@@ -60,6 +62,9 @@ pub enum Error {
         asb: spot_price::BlockchainNetwork,
     },
 
+    #[error("Seller currently has no up-to-date price, please try again later")]
+    PriceUnavailable,
+
     /// To be used for errors that cannot be explained on the CLI side (e.g.
     /// rate update problems on the seller side)
     #[error("Seller encountered a problem, please try again later.")]
@@ -80,6 +85,7 @@ impl From<spot_price::Error> for Error {
             spot_price::Error::BlockchainNetworkMismatch { cli, asb } => {
                 Error::BlockchainNetworkMismatch { cli, asb }
             }
+            spot_price::Error::PriceUnavailable => Error::PriceUnavailable,
             spot_price::Error::Other => Error::Other,
         }
     }
@@ -1,4 +1,4 @@
-use crate::bitcoin::wallet::EstimateFeeRate;
+use crate::bitcoin::wallet::{Deadline, EstimateFeeRate};
 use crate::bitcoin::{
     self, current_epoch, CancelTimelock, ExpiredTimelocks, PunishTimelock, Transaction, TxCancel,
     TxLock, Txid,
@@ -142,6 +142,7 @@ impl State0 {
             refund_address: self.refund_address.clone(),
             tx_refund_fee: self.tx_refund_fee,
             tx_cancel_fee: self.tx_cancel_fee,
+            anchor_outputs: true,
         }
     }
 
@@ -154,6 +155,42 @@ impl State0 {
         C: EstimateFeeRate,
         D: BatchDatabase,
     {
+        self.verify_dleq_proof(&msg)?;
+
+        let tx_lock = bitcoin::TxLock::new(wallet, self.btc, msg.A, self.b.public()).await?;
+
+        self.into_state1(msg, tx_lock)
+    }
+
+    /// Like [`State0::receive`], but the lock transaction is built and signed
+    /// by the wallet behind `external_signer` instead of ours.
+    pub async fn receive_externally_funded(
+        self,
+        external_signer: &bitcoin::ExternalSigner,
+        network: bitcoin::Network,
+        msg: Message1,
+    ) -> Result<State1> {
+        self.verify_dleq_proof(&msg)?;
+
+        let address = TxLock::address(msg.A, self.b.public(), network);
+        let psbt = external_signer
+            .fund(self.swap_id, &address, self.btc)
+            .await
+            .context("Failed to get Bitcoin lock transaction from external wallet")?;
+        let tx_lock = TxLock::from_psbt(psbt, msg.A, self.b.public(), self.btc)
+            .context("Transaction of external wallet is not a valid lock transaction")?;
+
+        if !tx_lock.is_signed() {
+            bail!("Transaction of external wallet is not signed")
+        }
+        if !tx_lock.spends_only_witness_outputs() {
+            bail!("Transaction of external wallet spends non-segwit outputs, its txid could be changed before it confirms")
+        }
+
+        self.into_state1(msg, tx_lock)
+    }
+
+    fn verify_dleq_proof(&self, msg: &Message1) -> Result<()> {
         let valid = CROSS_CURVE_PROOF_SYSTEM.verify(
             &msg.dleq_proof_s_a,
             (
@@ -169,7 +206,35 @@ impl State0 {
             bail!("Alice's dleq proof doesn't verify")
         }
 
-        let tx_lock = bitcoin::TxLock::new(wallet, self.btc, msg.A, self.b.public()).await?;
+        Ok(())
+    }
+
+    fn into_state1(self, msg: Message1, tx_lock: TxLock) -> Result<State1> {
+        if let Some(anchor_address) = msg.anchor_address.as_ref() {
+            let tx_cancel = TxCancel::new(
+                &tx_lock,
+                self.cancel_timelock,
+                msg.A,
+                self.b.public(),
+                self.tx_cancel_fee,
+            );
+            bitcoin::TxRedeem::new(
+                &tx_lock,
+                &msg.redeem_address,
+                Some(anchor_address),
+                msg.tx_redeem_fee,
+            )
+            .context("Alice's redeem transaction has no room for her anchor output")?;
+            bitcoin::TxPunish::new(
+                &tx_cancel,
+                &msg.punish_address,
+                Some(anchor_address),
+                self.punish_timelock,
+                msg.tx_punish_fee,
+            )
+            .context("Alice's punish transaction has no room for her anchor output")?;
+        }
+
         let v = msg.v_a + self.v_b;
 
         Ok(State1 {
@@ -185,6 +250,7 @@ impl State0 {
             refund_address: self.refund_address,
             redeem_address: msg.redeem_address,
             punish_address: msg.punish_address,
+            anchor_address: msg.anchor_address,
             tx_lock,
             min_monero_confirmations: self.min_monero_confirmations,
             tx_redeem_fee: msg.tx_redeem_fee,
@@ -209,6 +275,7 @@ pub struct State1 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    anchor_address: Option<bitcoin::Address>,
     tx_lock: bitcoin::TxLock,
     min_monero_confirmations: u64,
     tx_redeem_fee: bitcoin::Amount,
@@ -256,6 +323,7 @@ impl State1 {
             refund_address: self.refund_address,
             redeem_address: self.redeem_address,
             punish_address: self.punish_address,
+            anchor_address: self.anchor_address,
             tx_lock: self.tx_lock,
             tx_cancel_sig_a: msg.tx_cancel_sig,
             tx_refund_encsig: msg.tx_refund_encsig,
@@ -282,6 +350,8 @@ pub struct State2 {
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
     punish_address: bitcoin::Address,
+    #[serde(default)]
+    anchor_address: Option<bitcoin::Address>,
     tx_lock: bitcoin::TxLock,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
@@ -297,6 +367,32 @@ pub struct State2 {
 }
 
 impl State2 {
+    pub fn tx_lock_id(&self) -> bitcoin::Txid {
+        self.tx_lock.txid()
+    }
+
+    pub fn tx_lock(&self) -> &bitcoin::TxLock {
+        &self.tx_lock
+    }
+
+    pub fn tx_cancel(&self) -> bitcoin::TxCancel {
+        bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_cancel_fee,
+        )
+    }
+
+    pub fn tx_lock_amount(&self) -> bitcoin::Amount {
+        self.tx_lock.lock_amount()
+    }
+
+    pub fn xmr(&self) -> monero::Amount {
+        self.xmr
+    }
+
     pub fn next_message(&self) -> Message4 {
         let tx_cancel = TxCancel::new(
             &self.tx_lock,
@@ -309,9 +405,11 @@ impl State2 {
         let tx_punish = bitcoin::TxPunish::new(
             &tx_cancel,
             &self.punish_address,
+            self.anchor_address.as_ref(),
             self.punish_timelock,
             self.tx_punish_fee,
-        );
+        )
+        .expect("anchor output to fit, checked during the execution setup");
         let tx_punish_sig = self.b.sign(tx_punish.digest());
 
         Message4 {
@@ -334,6 +432,7 @@ impl State2 {
                 punish_timelock: self.punish_timelock,
                 refund_address: self.refund_address,
                 redeem_address: self.redeem_address,
+                anchor_address: self.anchor_address,
                 tx_lock: self.tx_lock.clone(),
                 tx_cancel_sig_a: self.tx_cancel_sig_a,
                 tx_refund_encsig: self.tx_refund_encsig,
@@ -360,6 +459,8 @@ pub struct State3 {
     punish_timelock: PunishTimelock,
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
+    #[serde(default)]
+    anchor_address: Option<bitcoin::Address>,
     pub tx_lock: bitcoin::TxLock,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
@@ -398,6 +499,7 @@ impl State3 {
             punish_timelock: self.punish_timelock,
             refund_address: self.refund_address,
             redeem_address: self.redeem_address,
+            anchor_address: self.anchor_address,
             tx_lock: self.tx_lock,
             tx_cancel_sig_a: self.tx_cancel_sig_a,
             tx_refund_encsig: self.tx_refund_encsig,
@@ -428,6 +530,33 @@ impl State3 {
         self.tx_lock.txid()
     }
 
+    pub fn xmr(&self) -> monero::Amount {
+        self.xmr
+    }
+
+    /// Checks that `psbt` is our lock transaction after it was signed by an
+    /// external wallet.
+    ///
+    /// The signer must not change the transaction, because Alice signed the
+    /// cancel transaction for the txid of the lock transaction.
+    pub fn verify_signed_tx_lock(
+        &self,
+        psbt: bitcoin::PartiallySignedTransaction,
+    ) -> Result<bitcoin::PartiallySignedTransaction> {
+        let signed = TxLock::from_psbt(psbt, self.A, self.b.public(), self.tx_lock.lock_amount())
+            .context("Signed PSBT is not a valid lock transaction")?;
+
+        if signed.txid() != self.tx_lock.txid() {
+            bail!(
+                "Signed PSBT has txid {} but the lock transaction has txid {}",
+                signed.txid(),
+                self.tx_lock.txid()
+            )
+        }
+
+        Ok(signed.into())
+    }
+
     pub async fn current_epoch(
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
@@ -463,6 +592,8 @@ pub struct State4 {
     punish_timelock: PunishTimelock,
     refund_address: bitcoin::Address,
     redeem_address: bitcoin::Address,
+    #[serde(default)]
+    anchor_address: Option<bitcoin::Address>,
     pub tx_lock: bitcoin::TxLock,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
@@ -477,14 +608,23 @@ pub struct State4 {
 
 impl State4 {
     pub fn tx_redeem_encsig(&self) -> bitcoin::EncryptedSignature {
-        let tx_redeem =
-            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_redeem_fee);
+        let tx_redeem = bitcoin::TxRedeem::new(
+            &self.tx_lock,
+            &self.redeem_address,
+            self.anchor_address.as_ref(),
+            self.tx_redeem_fee,
+        )
+        .expect("anchor output to fit, checked during the execution setup");
         self.b.encsign(self.S_a_bitcoin, tx_redeem.digest())
     }
 
     pub async fn watch_for_redeem_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<State5> {
-        let tx_redeem =
-            bitcoin::TxRedeem::new(&self.tx_lock, &self.redeem_address, self.tx_redeem_fee);
+        let tx_redeem = bitcoin::TxRedeem::new(
+            &self.tx_lock,
+            &self.redeem_address,
+            self.anchor_address.as_ref(),
+            self.tx_redeem_fee,
+        )?;
         let tx_redeem_encsig = self.b.encsign(self.S_a_bitcoin, tx_redeem.digest());
 
         bitcoin_wallet
@@ -570,6 +710,10 @@ impl State5 {
     pub fn tx_lock_id(&self) -> bitcoin::Txid {
         self.tx_lock.txid()
     }
+
+    pub fn tx_lock_amount(&self) -> bitcoin::Amount {
+        self.tx_lock.lock_amount()
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -590,6 +734,24 @@ pub struct State6 {
 }
 
 impl State6 {
+    /// The deadline for transactions we publish, i.e. the next timelock.
+    pub fn fee_bump_deadline(&self) -> Deadline {
+        let tx_cancel = TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_cancel_fee,
+        );
+
+        Deadline::new(
+            &self.tx_lock,
+            &tx_cancel,
+            self.cancel_timelock,
+            self.punish_timelock,
+        )
+    }
+
     pub async fn expired_timelock(
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
@@ -648,8 +810,18 @@ impl State6 {
 
     pub async fn publish_refund_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<()> {
         let signed_tx_refund = self.signed_refund_transaction()?;
-        let (_, subscription) = bitcoin_wallet.broadcast(signed_tx_refund, "refund").await?;
-        subscription.wait_until_final().await?;
+        let (_, subscription) = bitcoin_wallet
+            .broadcast(signed_tx_refund.clone(), "refund")
+            .await?;
+        bitcoin_wallet
+            .wait_until_final_bumping_fee(
+                &subscription,
+                signed_tx_refund,
+                "refund",
+                0,
+                Some(self.fee_bump_deadline()),
+            )
+            .await?;
 
         Ok(())
     }
@@ -679,4 +851,26 @@ impl State6 {
     pub fn tx_lock_id(&self) -> bitcoin::Txid {
         self.tx_lock.txid()
     }
+
+    pub fn tx_lock_amount(&self) -> bitcoin::Amount {
+        self.tx_lock.lock_amount()
+    }
+
+    pub fn tx_cancel_id(&self) -> bitcoin::Txid {
+        self.tx_cancel().txid()
+    }
+
+    pub fn tx_refund_id(&self) -> bitcoin::Txid {
+        bitcoin::TxRefund::new(&self.tx_cancel(), &self.refund_address, self.tx_refund_fee).txid()
+    }
+
+    fn tx_cancel(&self) -> bitcoin::TxCancel {
+        bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_cancel_fee,
+        )
+    }
 }
@@ -1,13 +1,16 @@
+use crate::bitcoin::wallet::{ScriptStatus, Strategy};
 use crate::bitcoin::{ExpiredTimelocks, TxCancel, TxRefund};
 use crate::database::Swap;
 use crate::env::Config;
 use crate::protocol::bob;
 use crate::protocol::bob::event_loop::EventLoopHandle;
 use crate::protocol::bob::state::*;
-use crate::{bitcoin, monero};
+use crate::{bitcoin, monero, protocol};
 use anyhow::{bail, Context, Result};
+use futures::future;
 use rand::rngs::OsRng;
 use tokio::select;
+use tokio::sync::watch;
 use uuid::Uuid;
 
 pub fn is_complete(state: &BobState) -> bool {
@@ -26,13 +29,50 @@ pub async fn run(swap: bob::Swap) -> Result<BobState> {
 }
 
 pub async fn run_until(
-    mut swap: bob::Swap,
+    swap: bob::Swap,
     is_target_state: fn(&BobState) -> bool,
 ) -> Result<BobState> {
+    // Nobody can request a stop while we hold the only sender.
+    let (_stop_sender, stop) = watch::channel(false);
+
+    let state = run_until_stopped(swap, is_target_state, stop)
+        .await?
+        .expect("swap to only stop on request");
+
+    Ok(state)
+}
+
+/// Runs the swap until it reaches the target state or a stop is requested
+/// through `stop`, returns `None` in the latter case.
+///
+/// A swap only stops while it waits for the other party or the blockchain,
+/// transitions that publish something are finished and persisted first. The
+/// swap can be resumed from the database afterwards.
+pub async fn run_until_stopped(
+    mut swap: bob::Swap,
+    is_target_state: fn(&BobState) -> bool,
+    mut stop: watch::Receiver<bool>,
+) -> Result<Option<BobState>> {
     let mut current_state = swap.state;
 
     while !is_target_state(&current_state) {
-        current_state = next_state(
+        if *stop.borrow() {
+            return Ok(None);
+        }
+
+        if let BobState::ExecutionSetupDone(state2) = &current_state {
+            protocol::watch_swap_outputs(
+                swap.bitcoin_wallet.as_ref(),
+                swap.db.as_ref(),
+                swap.id,
+                state2.tx_lock(),
+                &state2.tx_cancel(),
+            )
+            .await?;
+        }
+
+        let interruptible = only_waits(&current_state);
+        let transition = next_state(
             swap.id,
             current_state,
             &mut swap.event_loop_handle,
@@ -40,8 +80,24 @@ pub async fn run_until(
             swap.monero_wallet.as_ref(),
             &swap.env_config,
             swap.receive_monero_address,
-        )
-        .await?;
+            &swap.monero_splits,
+            swap.external_signer.as_ref(),
+        );
+
+        current_state = if interruptible {
+            select! {
+                biased;
+
+                _ = protocol::stop_requested(&mut stop) => return Ok(None),
+                state = transition => state?,
+            }
+        } else {
+            transition.await?
+        };
+
+        if let BobState::BtcLocked(..) = current_state {
+            swap.event_loop_handle.commit_reservation()
+        }
 
         let db_state = current_state.clone().into();
         swap.db
@@ -49,9 +105,25 @@ pub async fn run_until(
             .await?;
     }
 
-    Ok(current_state)
+    Ok(Some(current_state))
+}
+
+/// Whether the transition out of `state` only waits for the other party or
+/// the blockchain, so that interrupting it is no different from a restart.
+///
+/// Waiting for the lock transaction bumps its fee, which is done again when
+/// the swap is resumed. So is sending the encrypted signature.
+fn only_waits(state: &BobState) -> bool {
+    matches!(
+        state,
+        BobState::BtcLocked(..)
+            | BobState::XmrLockProofReceived { .. }
+            | BobState::XmrLocked(..)
+            | BobState::EncSigSent(..)
+    )
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn next_state(
     swap_id: Uuid,
     state: BobState,
@@ -60,6 +132,8 @@ async fn next_state(
     monero_wallet: &monero::Wallet,
     env_config: &Config,
     receive_monero_address: monero::Address,
+    monero_splits: &[monero::Split],
+    external_signer: Option<&bitcoin::ExternalSigner>,
 ) -> Result<BobState> {
     tracing::trace!(%state, "Advancing state");
 
@@ -89,11 +163,38 @@ async fn next_state(
         BobState::ExecutionSetupDone(state2) => {
             // Alice and Bob have exchanged info
             let (state3, tx_lock) = state2.lock_btc().await?;
-            let signed_tx = bitcoin_wallet
-                .sign_and_finalize(tx_lock.clone().into())
-                .await
-                .context("Failed to sign Bitcoin lock transaction")?;
-            let (..) = bitcoin_wallet.broadcast(signed_tx, "lock").await?;
+            let signed_tx = match external_signer {
+                // An external wallet funded the swap during the execution setup
+                _ if tx_lock.is_signed() => {
+                    bitcoin::PartiallySignedTransaction::from(tx_lock.clone()).extract_tx()
+                }
+                Some(external_signer) => {
+                    let psbt = external_signer
+                        .sign(swap_id, tx_lock.clone().into())
+                        .await
+                        .context("Failed to get Bitcoin lock transaction signed externally")?;
+                    let psbt = state3.verify_signed_tx_lock(psbt)?;
+
+                    bitcoin_wallet
+                        .finalize(psbt)
+                        .await
+                        .context("Failed to finalize externally signed Bitcoin lock transaction")?
+                }
+                None => bitcoin_wallet
+                    .sign_and_finalize(tx_lock.clone().into())
+                    .await
+                    .context("Failed to sign Bitcoin lock transaction")?,
+            };
+            if let Err(error) = bitcoin_wallet.broadcast(signed_tx, "lock").await {
+                // The external wallet might have published the lock transaction already
+                if !tx_lock.is_signed()
+                    || bitcoin_wallet.status_of_script(&tx_lock).await? == ScriptStatus::Unseen
+                {
+                    return Err(error);
+                }
+
+                tracing::info!(txid = %tx_lock.txid(), "Bitcoin lock transaction was already published");
+            }
 
             BobState::BtcLocked(state3)
         }
@@ -106,6 +207,7 @@ async fn next_state(
                 let transfer_proof_watcher = event_loop_handle.recv_transfer_proof();
                 let cancel_timelock_expires =
                     tx_lock_status.wait_until_confirmed_with(state3.cancel_timelock);
+                let tx_lock_id = state3.tx_lock.txid();
 
                 // Record the current monero wallet block height so we don't have to scan from
                 // block 0 once we create the redeem wallet.
@@ -131,6 +233,10 @@ async fn next_state(
                         let state4 = state3.cancel();
                         BobState::CancelTimelockExpired(state4)
                     }
+                    _ = async {
+                        bump_lock_fee(bitcoin_wallet, tx_lock_id).await;
+                        future::pending::<()>().await
+                    } => unreachable!("pending future never resolves")
                 }
             } else {
                 let state4 = state3.cancel();
@@ -227,12 +333,17 @@ async fn next_state(
 
             // Ensure that the generated wallet is synced so we have a proper balance
             monero_wallet.refresh().await?;
-            // Sweep (transfer all funds) to the given address
-            let tx_hashes = monero_wallet.sweep_all(receive_monero_address).await?;
+            // Sweep (transfer all funds) to the given address and splits
+            let tx_hashes = monero_wallet
+                .sweep_all_split(receive_monero_address, monero_splits)
+                .await?;
 
             for tx_hash in tx_hashes {
                 tracing::info!(%receive_monero_address, txid=%tx_hash.0, "Sent XMR to");
             }
+            for split in monero_splits {
+                tracing::info!(monero_address = %split.address, percent = %split.percent, "Sent share of XMR to");
+            }
 
             BobState::XmrRedeemed {
                 tx_lock_id: state.tx_lock_id(),
@@ -299,3 +410,46 @@ pub async fn request_price_and_setup(
 
     Ok(state2)
 }
+
+/// Bumps the fee of the lock transaction through its change output until it
+/// is confirmed.
+///
+/// The lock transaction itself must not be replaced, the cancel and refund
+/// transactions are signed for its txid.
+async fn bump_lock_fee(bitcoin_wallet: &bitcoin::Wallet, tx_lock_id: bitcoin::Txid) {
+    let transaction = match bitcoin_wallet.get_raw_transaction(tx_lock_id).await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            tracing::warn!(
+                "Unable to bump fee of Bitcoin lock transaction. Error {:#}",
+                error
+            );
+            return;
+        }
+    };
+
+    match bitcoin_wallet.change_output(&transaction).await {
+        Ok(Some(vout)) => {
+            if let Err(error) = bitcoin_wallet
+                .bump_fee_until_confirmed(transaction, "lock", Strategy::Cpfp { vout }, None)
+                .await
+            {
+                tracing::warn!(
+                    "Unable to bump fee of Bitcoin lock transaction. Error {:#}",
+                    error
+                );
+            }
+        }
+        Ok(None) => {
+            tracing::debug!(
+                "Bitcoin lock transaction has no change output, its fee cannot be bumped"
+            )
+        }
+        Err(error) => {
+            tracing::warn!(
+                "Unable to bump fee of Bitcoin lock transaction. Error {:#}",
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,663 @@
+//! A minimal JSON-RPC 2.0 server.
+//!
+//! Messages are exchanged as newline-delimited JSON over a TCP connection.
+//! Since the connection is kept open, the server can push notifications to
+//! the client, which is used to implement subscriptions.
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Display;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+const JSONRPC_VERSION: &str = "2.0";
+
+/// The longest request we accept, so that a client cannot make us buffer an
+/// arbitrary amount of data.
+const MAX_REQUEST_LENGTH: u64 = 1024 * 1024;
+
+const TOKEN_LENGTH: usize = 32;
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+pub const UNAUTHORIZED: i64 = -32001;
+
+/// The method a client has to call first on a server that requires
+/// authentication.
+pub const AUTHENTICATE_METHOD: &str = "authenticate";
+
+/// Handles the requests of all connections to the server.
+#[async_trait]
+pub trait Handler: Send + Sync + 'static {
+    /// Handles a single request.
+    ///
+    /// The `subscriber` can be used to push notifications to the connection
+    /// the request was received on.
+    async fn handle(
+        &self,
+        method: &str,
+        params: Value,
+        subscriber: &Subscriber,
+    ) -> Result<Value, Error>;
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    /// Requests without an id are notifications and are not answered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Notification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: Value,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, thiserror::Error)]
+#[error("{message}")]
+pub struct Error {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Error {
+    pub fn method_not_found(method: &str) -> Self {
+        Self {
+            code: METHOD_NOT_FOUND,
+            message: format!("Method {} not found", method),
+        }
+    }
+
+    pub fn invalid_params(error: impl Display) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: format!("Invalid params: {}", error),
+        }
+    }
+
+    pub fn unauthorized() -> Self {
+        Self {
+            code: UNAUTHORIZED,
+            message: format!("Not authenticated, call {} first", AUTHENTICATE_METHOD),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            code: INTERNAL_ERROR,
+            message: format!("{:#}", error),
+        }
+    }
+}
+
+/// Deserializes the params of a request.
+pub fn params<T>(params: Value) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_value(params).map_err(Error::invalid_params)
+}
+
+/// Pushes notifications to a single connection.
+#[derive(Clone, Debug)]
+pub struct Subscriber {
+    sender: mpsc::UnboundedSender<String>,
+}
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("The connection of the subscriber is closed")]
+pub struct ConnectionClosed;
+
+impl Subscriber {
+    pub fn notify(&self, method: &str, params: impl Serialize) -> Result<(), ConnectionClosed> {
+        let params = serde_json::to_value(params).map_err(|_| ConnectionClosed)?;
+        let notification = Notification {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            method: method.to_string(),
+            params,
+        };
+
+        self.send(&notification)
+    }
+
+    /// Creates a subscriber whose notifications end up in the returned
+    /// receiver instead of a connection.
+    #[cfg(test)]
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<String>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        (Self { sender }, receiver)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    fn send(&self, message: &impl Serialize) -> Result<(), ConnectionClosed> {
+        let line = serde_json::to_string(message).map_err(|_| ConnectionClosed)?;
+
+        self.sender.send(line).map_err(|_| ConnectionClosed)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthenticateParams {
+    token: String,
+}
+
+/// Reads the token clients have to authenticate with from the given file,
+/// generating it if the file does not exist yet.
+///
+/// A generated token file is only readable by its owner.
+pub fn read_or_generate_token(path: &Path) -> Result<String> {
+    if path.exists() {
+        let token = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read token from {}", path.display()))?;
+        let token = token.trim();
+
+        if token.is_empty() {
+            bail!(
+                "Token file {} is empty, delete it to generate a new token",
+                path.display()
+            )
+        }
+
+        return Ok(token.to_string());
+    }
+
+    tracing::debug!("No token found, creating at: {}", path.display());
+
+    let token = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect::<String>();
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(token.as_bytes()))
+        .with_context(|| format!("Failed to write token to {}", path.display()))?;
+
+    Ok(token)
+}
+
+/// Accepts connections on the given listener and dispatches their requests to
+/// the handler until the listener fails.
+pub async fn serve<H>(listener: TcpListener, handler: Arc<H>) -> Result<()>
+where
+    H: Handler,
+{
+    serve_with_token(listener, handler, None).await
+}
+
+/// Like [`serve`], but every connection has to call the `authenticate` method
+/// with the given token before any other request is dispatched.
+pub async fn serve_authenticated<H>(
+    listener: TcpListener,
+    handler: Arc<H>,
+    token: String,
+) -> Result<()>
+where
+    H: Handler,
+{
+    serve_with_token(listener, handler, Some(Arc::new(token))).await
+}
+
+async fn serve_with_token<H>(
+    listener: TcpListener,
+    handler: Arc<H>,
+    token: Option<Arc<String>>,
+) -> Result<()>
+where
+    H: Handler,
+{
+    loop {
+        let (stream, address) = listener.accept().await?;
+        tracing::debug!(%address, "New RPC connection");
+
+        let handler = handler.clone();
+        let token = token.clone();
+        tokio::spawn(async move {
+            match handle_connection(stream, handler, token).await {
+                Ok(()) => tracing::debug!(%address, "RPC connection closed"),
+                Err(error) => tracing::debug!(%address, "RPC connection failed: {:#}", error),
+            }
+        });
+    }
+}
+
+async fn handle_connection<H>(
+    stream: TcpStream,
+    handler: Arc<H>,
+    token: Option<Arc<String>>,
+) -> Result<()>
+where
+    H: Handler,
+{
+    let (reader, mut writer) = stream.into_split();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    let subscriber = Subscriber { sender };
+
+    let read = async move {
+        let mut reader = BufReader::new(reader);
+        let mut authenticated = token.is_none();
+
+        while let Some(line) = read_request(&mut reader).await? {
+            // authentication is handled in order so that no request can overtake it
+            if let Some(token) = token.as_ref().filter(|_| !authenticated) {
+                let (response, success) = authenticate(&line, token);
+                authenticated = success;
+
+                if let Some(response) = response {
+                    let _ = subscriber.send(&response);
+                }
+                continue;
+            }
+
+            let handler = handler.clone();
+            let subscriber = subscriber.clone();
+
+            // handle requests concurrently so that a slow request does not block
+            // the connection
+            tokio::spawn(async move {
+                if let Some(response) = handle_line(&line, handler.as_ref(), &subscriber).await {
+                    let _ = subscriber.send(&response);
+                }
+            });
+        }
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let write = async move {
+        while let Some(line) = receiver.recv().await {
+            writer.write_all(line.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    tokio::select! {
+        result = read => result,
+        result = write => result,
+    }
+}
+
+/// Reads the next newline-delimited request, failing if it is longer than
+/// [`MAX_REQUEST_LENGTH`].
+async fn read_request<R>(reader: &mut R) -> Result<Option<String>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_REQUEST_LENGTH + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    } else if line.len() as u64 > MAX_REQUEST_LENGTH {
+        bail!("Request is longer than {} bytes", MAX_REQUEST_LENGTH)
+    }
+
+    let line = String::from_utf8(line).context("Request is not valid UTF-8")?;
+
+    Ok(Some(line))
+}
+
+/// Handles a request on a connection that is not yet authenticated.
+///
+/// Returns the response to send and whether the connection is authenticated
+/// now.
+fn authenticate(line: &str, token: &str) -> (Option<Response>, bool) {
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(error) => return (Some(parse_error(error)), false),
+    };
+
+    let result = if request.method == AUTHENTICATE_METHOD {
+        params::<AuthenticateParams>(request.params).and_then(|params| {
+            if tokens_match(&params.token, token) {
+                Ok(Value::Bool(true))
+            } else {
+                Err(Error {
+                    code: UNAUTHORIZED,
+                    message: "Invalid token".to_string(),
+                })
+            }
+        })
+    } else {
+        Err(Error::unauthorized())
+    };
+    let success = result.is_ok();
+
+    let response = request.id.map(|id| response(id, result));
+
+    (response, success)
+}
+
+/// Compares the tokens in a time that only depends on their length, so that
+/// the response time does not reveal how much of a guess was correct.
+///
+/// An empty token never matches, not even an empty guess.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    !expected.is_empty()
+        && given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+async fn handle_line<H>(line: &str, handler: &H, subscriber: &Subscriber) -> Option<Response>
+where
+    H: Handler,
+{
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(error) => return Some(parse_error(error)),
+    };
+
+    let result = handler
+        .handle(&request.method, request.params, subscriber)
+        .await;
+
+    if let Err(error) = &result {
+        tracing::debug!(method = %request.method, "RPC request failed: {}", error);
+    }
+
+    let id = request.id?;
+
+    Some(response(id, result))
+}
+
+fn response(id: Value, result: Result<Value, Error>) -> Response {
+    match result {
+        Ok(result) => Response {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        },
+        Err(error) => Response {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(error),
+        },
+    }
+}
+
+fn parse_error(error: serde_json::Error) -> Response {
+    Response {
+        jsonrpc: JSONRPC_VERSION.to_string(),
+        id: Value::Null,
+        result: None,
+        error: Some(Error {
+            code: PARSE_ERROR,
+            message: format!("Failed to parse request: {}", error),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::Lines;
+    use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+    struct TestHandler;
+
+    #[async_trait]
+    impl Handler for TestHandler {
+        async fn handle(
+            &self,
+            method: &str,
+            params: Value,
+            subscriber: &Subscriber,
+        ) -> Result<Value, Error> {
+            match method {
+                "echo" => Ok(params),
+                "subscribe" => {
+                    subscriber.notify("update", json!({ "n": 1 })).unwrap();
+                    Ok(Value::Bool(true))
+                }
+                method => Err(Error::method_not_found(method)),
+            }
+        }
+    }
+
+    async fn connect() -> (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Arc::new(TestHandler)));
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        let (reader, writer) = stream.into_split();
+
+        (BufReader::new(reader).lines(), writer)
+    }
+
+    async fn connect_with_token(token: &str) -> (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve_authenticated(
+            listener,
+            Arc::new(TestHandler),
+            token.to_string(),
+        ));
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        let (reader, writer) = stream.into_split();
+
+        (BufReader::new(reader).lines(), writer)
+    }
+
+    async fn next_message(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Value {
+        let line = lines.next_line().await.unwrap().unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn responds_with_result() {
+        let (mut lines, mut writer) = connect().await;
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\",\"params\":[42]}\n")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            next_message(&mut lines).await,
+            json!({ "jsonrpc": "2.0", "id": 1, "result": [42] })
+        );
+    }
+
+    #[tokio::test]
+    async fn given_unknown_method_then_responds_with_error() {
+        let (mut lines, mut writer) = connect().await;
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"method\":\"unknown\"}\n")
+            .await
+            .unwrap();
+
+        let response = next_message(&mut lines).await;
+        assert_eq!(response["id"], json!("a"));
+        assert_eq!(response["error"]["code"], json!(METHOD_NOT_FOUND));
+    }
+
+    #[tokio::test]
+    async fn given_invalid_json_then_responds_with_parse_error() {
+        let (mut lines, mut writer) = connect().await;
+
+        writer.write_all(b"not json\n").await.unwrap();
+
+        let response = next_message(&mut lines).await;
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], json!(PARSE_ERROR));
+    }
+
+    #[tokio::test]
+    async fn pushes_notifications_to_subscriber() {
+        let (mut lines, mut writer) = connect().await;
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"subscribe\"}\n")
+            .await
+            .unwrap();
+
+        // the notification is sent before the response
+        assert_eq!(
+            next_message(&mut lines).await,
+            json!({ "jsonrpc": "2.0", "method": "update", "params": { "n": 1 } })
+        );
+        assert_eq!(
+            next_message(&mut lines).await,
+            json!({ "jsonrpc": "2.0", "id": 1, "result": true })
+        );
+    }
+
+    #[tokio::test]
+    async fn given_unauthenticated_connection_then_rejects_requests() {
+        let (mut lines, mut writer) = connect_with_token("secret").await;
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"echo\",\"params\":[42]}\n")
+            .await
+            .unwrap();
+        let response = next_message(&mut lines).await;
+        assert_eq!(response["error"]["code"], json!(UNAUTHORIZED));
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"authenticate\",\"params\":{\"token\":\"wrong\"}}\n")
+            .await
+            .unwrap();
+        let response = next_message(&mut lines).await;
+        assert_eq!(response["error"]["code"], json!(UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn given_too_long_request_then_closes_connection() {
+        let (mut lines, mut writer) = connect().await;
+
+        let request = vec![b'a'; MAX_REQUEST_LENGTH as usize + 1];
+        writer.write_all(&request).await.unwrap();
+
+        assert!(!matches!(lines.next_line().await, Ok(Some(_))));
+    }
+
+    #[test]
+    fn generated_token_is_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+
+        let token = read_or_generate_token(&path).unwrap();
+
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert_eq!(read_or_generate_token(&path).unwrap(), token);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn token_file_is_only_accessible_by_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+
+        read_or_generate_token(&path).unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn given_blank_token_file_then_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        fs::write(&path, " \n").unwrap();
+
+        assert!(read_or_generate_token(&path).is_err());
+    }
+
+    #[test]
+    fn only_equal_tokens_match() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret", "secret2"));
+        assert!(!tokens_match("", "secret"));
+        assert!(!tokens_match("", ""));
+    }
+
+    #[tokio::test]
+    async fn given_authenticated_connection_then_dispatches_requests() {
+        let (mut lines, mut writer) = connect_with_token("secret").await;
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"authenticate\",\"params\":{\"token\":\"secret\"}}\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            next_message(&mut lines).await,
+            json!({ "jsonrpc": "2.0", "id": 1, "result": true })
+        );
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"echo\",\"params\":[42]}\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            next_message(&mut lines).await,
+            json!({ "jsonrpc": "2.0", "id": 2, "result": [42] })
+        );
+    }
+}
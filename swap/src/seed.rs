@@ -3,19 +3,39 @@ use ::bitcoin::secp256k1::constants::SECRET_KEY_SIZE;
 use ::bitcoin::secp256k1::{self, SecretKey};
 use anyhow::{Context, Result};
 use bdk::bitcoin::util::bip32::ExtendedPrivKey;
+use bip39::Mnemonic;
 use bitcoin::hashes::{sha256, Hash, HashEngine};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Password;
 use libp2p::identity;
 use pem::{encode, Pem};
 use rand::prelude::*;
 use std::ffi::OsStr;
 use std::fmt;
-use std::fs::{self, File};
+use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use torut::onion::TorSecretKeyV3;
 
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+mod encryption;
+
+pub use encryption::PassphraseSource;
+
 pub const SEED_LENGTH: usize = 32;
 
+const SEED_FILE_NAME: &str = "seed.pem";
+
+/// PEM tags of a plaintext seed and one encrypted with a passphrase.
+const SEED_TAG: &str = "SEED";
+const ENCRYPTED_SEED_TAG: &str = "ENCRYPTED SEED";
+
+/// Salt of the Argon2id that stretches a mnemonic passphrase. It is fixed,
+/// since the mnemonic has no room for a random one.
+const MNEMONIC_PASSPHRASE_SALT: &[u8] = b"xmr-btc-swap seed mnemonic";
+
 #[derive(Eq, PartialEq)]
 pub struct Seed([u8; SEED_LENGTH]);
 
@@ -56,22 +76,137 @@ impl Seed {
         esk.to_bytes().into()
     }
 
-    pub fn from_file_or_generate(data_dir: &Path) -> Result<Self, Error> {
-        let file_path_buf = data_dir.join("seed.pem");
+    /// Encodes the seed as a 24 word BIP39 mnemonic.
+    ///
+    /// A non-empty `passphrase` is stretched with Argon2id into a key the seed
+    /// is XORed with, so the mnemonic alone does not reveal the seed. Restoring
+    /// with another passphrase silently yields another seed, only the wallet
+    /// restored from it tells that the passphrase was wrong.
+    pub fn to_mnemonic(&self, passphrase: &str) -> Result<Mnemonic, Error> {
+        let entropy = xor(self.bytes(), passphrase_key(passphrase)?);
+
+        Ok(Mnemonic::from_entropy(&entropy).expect("32 bytes are valid BIP39 entropy"))
+    }
+
+    pub fn from_mnemonic(mnemonic: &str, passphrase: &str) -> Result<Self, Error> {
+        // The words of the English word list need no unicode normalization
+        let mnemonic = Mnemonic::parse_normalized(mnemonic.trim()).map_err(Error::Mnemonic)?;
+        let (entropy, length) = mnemonic.to_entropy_array();
+
+        if length != SEED_LENGTH {
+            return Err(Error::IncorrectWordCount(mnemonic.word_count()));
+        }
+
+        let mut bytes = [0u8; SEED_LENGTH];
+        bytes.copy_from_slice(&entropy[..SEED_LENGTH]);
+        let bytes = xor(bytes, passphrase_key(passphrase)?);
+
+        let _ = SecretKey::from_slice(&bytes)?;
+
+        Ok(Seed(bytes))
+    }
+
+    /// Reads the seed from the seed file in `data_dir`, without generating
+    /// one if there is none.
+    pub fn from_data_dir(data_dir: &Path, passphrase: &PassphraseSource) -> Result<Self, Error> {
+        Self::from_file(data_dir.join(SEED_FILE_NAME), passphrase)
+    }
+
+    pub fn exists_in(data_dir: &Path) -> bool {
+        data_dir.join(SEED_FILE_NAME).exists()
+    }
+
+    /// Writes the seed to the seed file in `data_dir`, replacing an existing
+    /// seed file only if `overwrite` is set.
+    ///
+    /// Like a generated seed, the restored one is only encrypted if the
+    /// passphrase does not come from a prompt.
+    pub fn restore_to(
+        &self,
+        data_dir: &Path,
+        overwrite: bool,
+        passphrase: &PassphraseSource,
+    ) -> Result<(), Error> {
+        let file_path = data_dir.join(SEED_FILE_NAME);
+
+        if file_path.exists() && !overwrite {
+            return Err(Error::SeedFileExists(file_path));
+        }
+
+        tracing::debug!("Restoring seed to {}", file_path.display());
+
+        self.write_to(file_path, new_file_passphrase(passphrase)?.as_deref())
+    }
+
+    pub fn from_file_or_generate(
+        data_dir: &Path,
+        passphrase: &PassphraseSource,
+    ) -> Result<Self, Error> {
+        let file_path_buf = data_dir.join(SEED_FILE_NAME);
         let file_path = Path::new(&file_path_buf);
 
         if file_path.exists() {
-            return Self::from_file(&file_path);
+            return Self::from_file(&file_path, passphrase);
         }
 
         tracing::debug!("No seed file found, creating at: {}", file_path.display());
 
         let random_seed = Seed::random()?;
-        random_seed.write_to(file_path.to_path_buf())?;
+        random_seed.write_to(
+            file_path.to_path_buf(),
+            new_file_passphrase(passphrase)?.as_deref(),
+        )?;
 
         Ok(random_seed)
     }
 
+    /// Encrypts the plaintext seed file in `data_dir` with a passphrase.
+    pub fn encrypt_file(data_dir: &Path, passphrase: &PassphraseSource) -> Result<(), Error> {
+        let file_path = data_dir.join(SEED_FILE_NAME);
+        let pem = pem::parse(fs::read_to_string(&file_path)?)?;
+
+        if pem.tag == ENCRYPTED_SEED_TAG {
+            return Err(Error::AlreadyEncrypted(file_path));
+        }
+
+        let seed = Self::from_pem(pem)?;
+        seed.write_to(file_path, Some(&passphrase.read_new()?))
+    }
+
+    /// The seed file in `data_dir` as it is stored, i.e. still encrypted if it
+    /// is encrypted, e.g. to move it to another machine.
+    pub fn file_contents(data_dir: &Path) -> Result<String, Error> {
+        Ok(fs::read_to_string(data_dir.join(SEED_FILE_NAME))?)
+    }
+
+    /// Writes a seed file exported with [`Seed::file_contents`] to `data_dir`.
+    ///
+    /// An existing seed file is only accepted if it is the same file.
+    pub fn import_file(data_dir: &Path, contents: &str) -> Result<(), Error> {
+        let pem = pem::parse(contents)?;
+        match pem.tag.as_str() {
+            SEED_TAG => {
+                let _ = Self::from_pem(pem)?;
+            }
+            ENCRYPTED_SEED_TAG => {}
+            tag => return Err(Error::UnknownSeedFile(tag.to_owned())),
+        }
+
+        let file_path = data_dir.join(SEED_FILE_NAME);
+        if file_path.exists() {
+            if fs::read_to_string(&file_path)?.trim() == contents.trim() {
+                return Ok(());
+            }
+
+            return Err(Error::SeedFileExists(file_path));
+        }
+
+        tracing::debug!("Importing seed to {}", file_path.display());
+
+        ensure_directory_exists(&file_path)?;
+        write_atomically(file_path, contents)
+    }
+
     /// Derive a new seed using the given scope.
     ///
     /// This function is purposely kept private because it is only a helper
@@ -92,7 +227,7 @@ impl Seed {
         self.0
     }
 
-    fn from_file<D>(seed_file: D) -> Result<Self, Error>
+    fn from_file<D>(seed_file: D, passphrase: &PassphraseSource) -> Result<Self, Error>
     where
         D: AsRef<OsStr>,
     {
@@ -102,6 +237,19 @@ impl Seed {
 
         tracing::debug!("Reading in seed from {}", file.display());
 
+        if pem.tag == ENCRYPTED_SEED_TAG {
+            let bytes = encryption::decrypt(&pem.contents, &passphrase.read()?)?;
+
+            return Ok(Self::from(bytes));
+        }
+
+        if !passphrase.is_prompt() {
+            tracing::warn!(
+                "The seed file {} is not encrypted, encrypt it with the encrypt-seed command",
+                file.display()
+            );
+        }
+
         Self::from_pem(pem)
     }
 
@@ -118,22 +266,103 @@ impl Seed {
         }
     }
 
-    fn write_to(&self, seed_file: PathBuf) -> Result<(), Error> {
+    /// Writes the seed, encrypted if a passphrase is given.
+    fn write_to(&self, seed_file: PathBuf, passphrase: Option<&str>) -> Result<(), Error> {
         ensure_directory_exists(&seed_file)?;
 
         let data = self.bytes();
-        let pem = Pem {
-            tag: String::from("SEED"),
-            contents: data.to_vec(),
+        let pem = match passphrase {
+            Some(passphrase) => Pem {
+                tag: String::from(ENCRYPTED_SEED_TAG),
+                contents: encryption::encrypt(&data, passphrase)?,
+            },
+            None => {
+                tracing::warn!(
+                    "Writing the seed to {} unencrypted, anyone who can read the file can take the funds of the wallet. Encrypt it with the encrypt-seed command",
+                    seed_file.display()
+                );
+
+                Pem {
+                    tag: String::from(SEED_TAG),
+                    contents: data.to_vec(),
+                }
+            }
         };
 
-        let pem_string = encode(&pem);
+        write_atomically(seed_file, &encode(&pem))
+    }
+}
+
+/// Replaces the seed file at once, so a crash never leaves a truncated seed
+/// file behind.
+///
+/// Only the owner may read the seed file.
+fn write_atomically(seed_file: PathBuf, contents: &str) -> Result<(), Error> {
+    let tmp_file = seed_file.with_extension("pem.tmp");
+    // A leftover of a crash may have been created with other permissions
+    if tmp_file.exists() {
+        fs::remove_file(&tmp_file)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
 
-        let mut file = File::create(seed_file)?;
-        file.write_all(pem_string.as_bytes())?;
+    let mut file = options.open(&tmp_file)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(tmp_file, seed_file)?;
 
-        Ok(())
+    Ok(())
+}
+
+/// The passphrase to encrypt a new seed file with, none if it would have to be
+/// prompted for.
+fn new_file_passphrase(passphrase: &PassphraseSource) -> Result<Option<String>, Error> {
+    if passphrase.is_prompt() {
+        return Ok(None);
     }
+
+    Ok(Some(passphrase.read_new()?))
+}
+
+fn passphrase_key(passphrase: &str) -> Result<[u8; SEED_LENGTH], Error> {
+    if passphrase.is_empty() {
+        return Ok([0u8; SEED_LENGTH]);
+    }
+
+    encryption::derive_key(passphrase, MNEMONIC_PASSPHRASE_SALT)
+}
+
+fn xor(mut bytes: [u8; SEED_LENGTH], key: [u8; SEED_LENGTH]) -> [u8; SEED_LENGTH] {
+    for (byte, key) in bytes.iter_mut().zip(key.iter()) {
+        *byte ^= key;
+    }
+
+    bytes
+}
+
+/// Asks for the passphrase of a mnemonic, repeating it if it is a new one.
+pub fn query_user_for_passphrase(new: bool) -> Result<String> {
+    let theme = ColorfulTheme::default();
+    let mut prompt = Password::with_theme(&theme);
+    prompt
+        .with_prompt("Enter the passphrase of the mnemonic")
+        .allow_empty_password(true);
+    if new {
+        prompt.with_confirmation("Repeat the passphrase", "The passphrases do not match");
+    }
+
+    Ok(prompt.interact()?)
+}
+
+pub fn query_user_for_mnemonic() -> Result<String> {
+    let mnemonic = Password::with_theme(&ColorfulTheme::default())
+        .with_prompt("Enter the 24 words of the mnemonic, separated by spaces")
+        .interact()?;
+
+    Ok(mnemonic)
 }
 
 impl fmt::Debug for Seed {
@@ -168,6 +397,26 @@ pub enum Error {
     Rand(#[from] rand::Error),
     #[error("no default path")]
     NoDefaultPath,
+    #[error("invalid BIP39 mnemonic: {0}")]
+    Mnemonic(bip39::Error),
+    #[error("expected a mnemonic of 24 words, got {0} words")]
+    IncorrectWordCount(usize),
+    #[error("refusing to overwrite the existing seed file {}", .0.display())]
+    SeedFileExists(PathBuf),
+    #[error("the seed file {} is encrypted already", .0.display())]
+    AlreadyEncrypted(PathBuf),
+    #[error("environment variable {0} with the seed passphrase is not set")]
+    MissingPassphrase(String),
+    #[error("the seed passphrase must not be empty")]
+    EmptyPassphrase,
+    #[error("failed to decrypt the seed file, wrong passphrase?")]
+    WrongPassphrase,
+    #[error("unsupported seed encryption version {0}")]
+    UnsupportedEncryption(u8),
+    #[error("KDF: {0}")]
+    Kdf(argon2::Error),
+    #[error("not a seed file, found PEM tag {0}")]
+    UnknownSeedFile(String),
 }
 
 #[cfg(test)]
@@ -245,10 +494,149 @@ mbKANv2qKGmNVg1qtquj6Hx1pFPelpqOfE2JaJJAMEg1FlFhNRNlFlE=
         let tmpfile = temp_dir().join("seed.pem");
 
         let seed = Seed::random().unwrap();
-        seed.write_to(tmpfile.clone())
+        seed.write_to(tmpfile.clone(), None)
             .expect("Write seed to temp file");
 
-        let rinsed = Seed::from_file(tmpfile).expect("Read from temp file");
+        let rinsed =
+            Seed::from_file(tmpfile, &PassphraseSource::Prompt).expect("Read from temp file");
         assert_eq!(seed.0, rinsed.0);
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn seed_file_is_only_readable_by_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let seed_file = dir.path().join(SEED_FILE_NAME);
+        Seed::random()
+            .unwrap()
+            .write_to(seed_file.clone(), None)
+            .unwrap();
+
+        let mode = fs::metadata(&seed_file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn round_trip_through_encrypted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed_file = dir.path().join(SEED_FILE_NAME);
+
+        let seed = Seed::random().unwrap();
+        seed.write_to(seed_file.clone(), Some("passphrase"))
+            .unwrap();
+
+        let pem = pem::parse(fs::read_to_string(&seed_file).unwrap()).unwrap();
+        assert_eq!(pem.tag, ENCRYPTED_SEED_TAG);
+
+        std::env::set_var("SEED_TEST_ROUND_TRIP_PASSPHRASE", "passphrase");
+        let source = PassphraseSource::Env("SEED_TEST_ROUND_TRIP_PASSPHRASE".to_owned());
+        assert_eq!(Seed::from_file(&seed_file, &source).unwrap(), seed);
+    }
+
+    #[test]
+    fn migrates_plaintext_seed_file_to_encrypted_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = Seed::from_file_or_generate(dir.path(), &PassphraseSource::Prompt).unwrap();
+
+        std::env::set_var("SEED_TEST_MIGRATION_PASSPHRASE", "passphrase");
+        let source = PassphraseSource::Env("SEED_TEST_MIGRATION_PASSPHRASE".to_owned());
+        Seed::encrypt_file(dir.path(), &source).unwrap();
+
+        assert_eq!(
+            Seed::from_file_or_generate(dir.path(), &source).unwrap(),
+            seed
+        );
+        assert!(matches!(
+            Seed::encrypt_file(dir.path(), &source),
+            Err(Error::AlreadyEncrypted(_))
+        ));
+    }
+
+    #[test]
+    fn round_trip_through_mnemonic_keeps_identities() {
+        let seed = Seed::random().unwrap();
+
+        let mnemonic = seed.to_mnemonic("").unwrap().to_string();
+        let restored = Seed::from_mnemonic(&mnemonic, "").unwrap();
+
+        assert_eq!(mnemonic.split_whitespace().count(), 24);
+        assert_eq!(restored, seed);
+        assert_eq!(
+            restored.derive_libp2p_identity().public(),
+            seed.derive_libp2p_identity().public()
+        );
+        assert_eq!(
+            restored.derive_torv3_key().as_bytes().to_vec(),
+            seed.derive_torv3_key().as_bytes().to_vec()
+        );
+    }
+
+    #[test]
+    fn passphrase_is_required_to_restore_seed() {
+        let seed = Seed::random().unwrap();
+
+        let mnemonic = seed.to_mnemonic("correct horse").unwrap().to_string();
+
+        assert_ne!(mnemonic, seed.to_mnemonic("").unwrap().to_string());
+        assert_eq!(
+            Seed::from_mnemonic(&mnemonic, "correct horse").unwrap(),
+            seed
+        );
+        assert_ne!(Seed::from_mnemonic(&mnemonic, "").unwrap(), seed);
+    }
+
+    #[test]
+    fn rejects_mnemonic_of_fewer_words() {
+        let twelve_words = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+        match Seed::from_mnemonic(twelve_words, "") {
+            Err(Error::IncorrectWordCount(12)) => {}
+            other => panic!("expected IncorrectWordCount, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn restore_does_not_overwrite_seed_file_unless_asked_to() {
+        let dir = tempfile::tempdir().unwrap();
+        let passphrase = PassphraseSource::Prompt;
+        let existing = Seed::from_file_or_generate(dir.path(), &passphrase).unwrap();
+        let restored = Seed::random().unwrap();
+
+        assert!(restored.restore_to(dir.path(), false, &passphrase).is_err());
+        assert_eq!(
+            Seed::from_data_dir(dir.path(), &passphrase).unwrap(),
+            existing
+        );
+
+        restored.restore_to(dir.path(), true, &passphrase).unwrap();
+        assert_eq!(
+            Seed::from_data_dir(dir.path(), &passphrase).unwrap(),
+            restored
+        );
+    }
+
+    #[test]
+    fn imported_seed_file_does_not_replace_a_different_seed() {
+        let passphrase = PassphraseSource::Prompt;
+        let old_dir = tempfile::tempdir().unwrap();
+        let seed = Seed::from_file_or_generate(old_dir.path(), &passphrase).unwrap();
+        let contents = Seed::file_contents(old_dir.path()).unwrap();
+
+        let new_dir = tempfile::tempdir().unwrap();
+        Seed::import_file(new_dir.path(), &contents).unwrap();
+        Seed::import_file(new_dir.path(), &contents).unwrap();
+        assert_eq!(
+            Seed::from_data_dir(new_dir.path(), &passphrase).unwrap(),
+            seed
+        );
+
+        let other_dir = tempfile::tempdir().unwrap();
+        Seed::from_file_or_generate(other_dir.path(), &passphrase).unwrap();
+        assert!(matches!(
+            Seed::import_file(other_dir.path(), &contents),
+            Err(Error::SeedFileExists(_))
+        ));
+    }
 }
@@ -0,0 +1,202 @@
+use crate::seed::{Error, SEED_LENGTH};
+use argon2::{Algorithm, Argon2, Version};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::Password;
+use rand::prelude::*;
+use std::fs;
+
+/// Identifies the KDF parameters and the layout of an encrypted seed, which is
+/// `version || salt || nonce || ciphertext`.
+const VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+/// Argon2id parameters, memory in KiB.
+const ARGON2_MEMORY: u32 = 19 * 1024;
+const ARGON2_ITERATIONS: u32 = 2;
+const ARGON2_PARALLELISM: u32 = 1;
+
+/// Where the passphrase of the seed file comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PassphraseSource {
+    /// Ask on the terminal, but only once an encrypted seed file is read.
+    /// Seed files are not encrypted when they are created.
+    Prompt,
+    /// The environment variable of the given name.
+    Env(String),
+    /// The file descriptor, e.g. one end of a pipe the passphrase is written
+    /// to.
+    Fd(u32),
+}
+
+impl PassphraseSource {
+    pub fn from_options(env_var: Option<String>, fd: Option<u32>) -> Self {
+        match (env_var, fd) {
+            (Some(env_var), _) => PassphraseSource::Env(env_var),
+            (None, Some(fd)) => PassphraseSource::Fd(fd),
+            (None, None) => PassphraseSource::Prompt,
+        }
+    }
+
+    pub fn is_prompt(&self) -> bool {
+        matches!(self, PassphraseSource::Prompt)
+    }
+
+    pub fn read(&self) -> Result<String, Error> {
+        self.read_with_confirmation(false)
+    }
+
+    /// Reads a passphrase a file is about to be encrypted with, which has to
+    /// be repeated when prompted for.
+    pub fn read_new(&self) -> Result<String, Error> {
+        self.read_with_confirmation(true)
+    }
+
+    fn read_with_confirmation(&self, confirm: bool) -> Result<String, Error> {
+        let passphrase = match self {
+            PassphraseSource::Prompt => {
+                let theme = ColorfulTheme::default();
+                let mut prompt = Password::with_theme(&theme);
+                prompt.with_prompt("Enter the passphrase of the seed file");
+                if confirm {
+                    prompt
+                        .with_confirmation("Repeat the passphrase", "The passphrases do not match");
+                }
+
+                prompt.interact()?
+            }
+            PassphraseSource::Env(name) => {
+                std::env::var(name).map_err(|_| Error::MissingPassphrase(name.clone()))?
+            }
+            PassphraseSource::Fd(fd) => {
+                let passphrase = fs::read_to_string(format!("/dev/fd/{}", fd))?;
+
+                passphrase.trim_end_matches(&['\r', '\n'][..]).to_owned()
+            }
+        };
+
+        if passphrase.is_empty() {
+            return Err(Error::EmptyPassphrase);
+        }
+
+        Ok(passphrase)
+    }
+}
+
+pub fn encrypt(seed: &[u8; SEED_LENGTH], passphrase: &str) -> Result<Vec<u8>, Error> {
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase, &salt)?;
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), Payload {
+            msg: &seed[..],
+            aad: &[VERSION],
+        })
+        .expect("encrypting 32 bytes does not fail");
+
+    let mut encrypted = vec![VERSION];
+    encrypted.extend_from_slice(&salt);
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend_from_slice(&ciphertext);
+
+    Ok(encrypted)
+}
+
+pub fn decrypt(encrypted: &[u8], passphrase: &str) -> Result<[u8; SEED_LENGTH], Error> {
+    match encrypted.first() {
+        Some(&VERSION) => {}
+        Some(version) => return Err(Error::UnsupportedEncryption(*version)),
+        None => return Err(Error::IncorrectLength(0)),
+    }
+
+    let expected_length = 1 + SALT_LENGTH + NONCE_LENGTH + SEED_LENGTH + TAG_LENGTH;
+    if encrypted.len() != expected_length {
+        return Err(Error::IncorrectLength(encrypted.len()));
+    }
+
+    let (salt, rest) = encrypted[1..].split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+    let key = derive_key(passphrase, salt)?;
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(nonce), Payload {
+            msg: ciphertext,
+            aad: &[VERSION],
+        })
+        .map_err(|_| Error::WrongPassphrase)?;
+
+    let mut seed = [0u8; SEED_LENGTH];
+    seed.copy_from_slice(&plaintext);
+
+    Ok(seed)
+}
+
+pub(super) fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], Error> {
+    let argon2 = Argon2::new(
+        None,
+        ARGON2_ITERATIONS,
+        ARGON2_MEMORY,
+        ARGON2_PARALLELISM,
+        Version::V0x13,
+    )
+    .map_err(Error::Kdf)?;
+
+    let mut key = [0u8; 32];
+    argon2
+        .hash_password_into(
+            Algorithm::Argon2id,
+            passphrase.as_bytes(),
+            salt,
+            &[],
+            &mut key,
+        )
+        .map_err(Error::Kdf)?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrypts_with_the_passphrase_it_was_encrypted_with() {
+        let seed = [7u8; SEED_LENGTH];
+
+        let encrypted = encrypt(&seed, "passphrase").unwrap();
+
+        assert_eq!(decrypt(&encrypted, "passphrase").unwrap(), seed);
+        assert!(matches!(
+            decrypt(&encrypted, "wrong"),
+            Err(Error::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn encrypted_seed_does_not_contain_the_seed() {
+        let seed = [7u8; SEED_LENGTH];
+
+        let encrypted = encrypt(&seed, "passphrase").unwrap();
+
+        assert!(!encrypted
+            .windows(SEED_LENGTH)
+            .any(|window| window == &seed[..]));
+    }
+
+    #[test]
+    fn reads_passphrase_from_env_var() {
+        std::env::set_var("SEED_ENCRYPTION_TEST_PASSPHRASE", "from env");
+        let source = PassphraseSource::from_options(
+            Some("SEED_ENCRYPTION_TEST_PASSPHRASE".to_owned()),
+            None,
+        );
+
+        assert_eq!(source.read().unwrap(), "from env");
+    }
+}
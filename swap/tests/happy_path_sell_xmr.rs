@@ -0,0 +1,19 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use swap::protocol::alice;
+use swap::protocol::alice::event_loop::NoRate;
+
+#[tokio::test]
+async fn happy_path_sell_xmr() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let (taker_swap, _event_loop) = ctx.taker_sell_xmr_swap().await;
+
+        let taker_state = alice::run(taker_swap, NoRate).await?;
+
+        ctx.assert_taker_sold_xmr(taker_state).await;
+
+        Ok(())
+    })
+    .await;
+}
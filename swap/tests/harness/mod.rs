@@ -9,17 +9,20 @@ use get_port::get_port;
 use libp2p::core::Multiaddr;
 use libp2p::PeerId;
 use monero_harness::{image, Monero};
+use rand::rngs::OsRng;
 use std::cmp::Ordering;
 use std::fmt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
+use swap::asb::VolumeTiers;
+use swap::bitcoin::wallet::BackendConfig;
 use swap::bitcoin::{CancelTimelock, PunishTimelock, TxCancel, TxPunish, TxRedeem, TxRefund};
 use swap::database::Database;
 use swap::env::{Config, GetConfig};
 use swap::network::swarm;
 use swap::protocol::alice::event_loop::FixedRate;
-use swap::protocol::alice::{AliceState, Swap};
+use swap::protocol::alice::{taker, AliceState, Swap};
 use swap::protocol::bob::BobState;
 use swap::protocol::{alice, bob};
 use swap::seed::Seed;
@@ -56,8 +59,9 @@ where
     let btc_amount = bitcoin::Amount::from_sat(1_000_000);
     let xmr_amount = monero::Amount::from_monero(btc_amount.as_btc() / FixedRate::RATE).unwrap();
 
-    let alice_starting_balances =
-        StartingBalances::new(bitcoin::Amount::ZERO, xmr_amount, Some(10));
+    // Both parties hold BTC and XMR, so that the tests can swap in either
+    // direction
+    let alice_starting_balances = StartingBalances::new(btc_amount * 10, xmr_amount, Some(10));
 
     let electrs_rpc_port = containers
         .electrs
@@ -94,7 +98,7 @@ where
     .await;
 
     let bob_seed = Seed::random().unwrap();
-    let bob_starting_balances = StartingBalances::new(btc_amount * 10, monero::Amount::ZERO, None);
+    let bob_starting_balances = StartingBalances::new(btc_amount * 10, xmr_amount, Some(10));
 
     let (bob_bitcoin_wallet, bob_monero_wallet) = init_test_wallets(
         MONERO_WALLET_NAME_BOB,
@@ -238,8 +242,11 @@ async fn start_alice(
         min_buy,
         max_buy,
         latest_rate,
+        VolumeTiers::default(),
+        Duration::from_secs(60),
         resume_only,
         env_config,
+        None,
     )
     .unwrap();
     swarm.listen_on(listen_address).unwrap();
@@ -248,11 +255,15 @@ async fn start_alice(
         swarm,
         env_config,
         bitcoin_wallet,
+        None,
         monero_wallet,
         db,
         FixedRate::default(),
         min_buy,
         max_buy,
+        monero::Amount::ZERO,
+        monero::Amount::from_piconero(u64::MAX),
+        5,
     )
     .unwrap();
 
@@ -299,7 +310,10 @@ async fn init_test_wallets(
     };
 
     let btc_wallet = swap::bitcoin::Wallet::new(
-        electrum_rpc_url,
+        BackendConfig::Electrum {
+            urls: vec![electrum_rpc_url],
+            cross_check: false,
+        },
         datadir,
         seed.derive_extended_private_key(env_config.bitcoin_network)
             .expect("Could not create extended private key from seed"),
@@ -408,7 +422,7 @@ impl BobParams {
         let db = Database::open(&self.db_path)?;
 
         let swap = bob::Swap::from_db(
-            db,
+            Arc::new(db),
             swap_id,
             self.bitcoin_wallet.clone(),
             self.monero_wallet.clone(),
@@ -430,7 +444,7 @@ impl BobParams {
         let db = Database::open(&self.db_path)?;
 
         let swap = bob::Swap::new(
-            db,
+            Arc::new(db),
             swap_id,
             self.bitcoin_wallet.clone(),
             self.monero_wallet.clone(),
@@ -460,6 +474,7 @@ impl BobParams {
             self.alice_peer_id,
             self.bitcoin_wallet.clone(),
             self.env_config,
+            None,
         )
     }
 }
@@ -541,6 +556,72 @@ impl TestContext {
         (swap, BobApplicationHandle(join_handle))
     }
 
+    /// Starts a swap in which Bob's wallets sell XMR to Alice's ASB, the way
+    /// the CLI does with `sell-xmr`.
+    ///
+    /// The ASB runs its side of the swap in its own event loop.
+    pub async fn taker_sell_xmr_swap(&mut self) -> (alice::Swap, JoinHandle<()>) {
+        let swap_id = Uuid::new_v4();
+        let asb_peer_id = self.bob_params.alice_peer_id;
+
+        let tor_socks5_port = get_port()
+            .expect("We don't care about Tor in the tests so we get a free port to disable it.");
+        let mut swarm = swarm::taker(&self.bob_params.seed, asb_peer_id, tor_socks5_port)
+            .await
+            .unwrap();
+        swarm
+            .behaviour_mut()
+            .add_address(asb_peer_id, self.bob_params.alice_address.clone());
+
+        let (event_loop, mut handle, swap_handle) =
+            taker::EventLoop::new(swap_id, swarm, asb_peer_id, self.env_config).unwrap();
+        let join_handle = tokio::spawn(event_loop.run());
+
+        let btc = handle.request_spot_price(self.xmr_amount).await.unwrap();
+
+        let receive_address = self.bob_bitcoin_wallet.new_address().await.unwrap();
+        let tx_redeem_fee = self
+            .bob_bitcoin_wallet
+            .estimate_fee(TxRedeem::weight(), btc)
+            .await
+            .unwrap();
+        let tx_punish_fee = self
+            .bob_bitcoin_wallet
+            .estimate_fee(TxPunish::weight(), btc)
+            .await
+            .unwrap();
+        let state0 = alice::State0::new(
+            btc,
+            self.xmr_amount,
+            self.env_config,
+            receive_address.clone(),
+            receive_address,
+            None,
+            tx_redeem_fee,
+            tx_punish_fee,
+            &mut OsRng,
+        )
+        .unwrap();
+        let state3 = handle.execution_setup(state0).await.unwrap();
+
+        let db = Database::open(&self.bob_params.db_path).unwrap();
+        db.insert_peer_id(swap_id, asb_peer_id).await.unwrap();
+
+        let swap = alice::Swap {
+            state: AliceState::Started {
+                state3: Box::new(state3),
+            },
+            event_loop_handle: swap_handle,
+            bitcoin_wallet: self.bob_bitcoin_wallet.clone(),
+            monero_wallet: self.bob_monero_wallet.clone(),
+            env_config: self.env_config,
+            swap_id,
+            db: Arc::new(db),
+        };
+
+        (swap, join_handle)
+    }
+
     pub async fn stop_and_resume_bob_from_db(
         &mut self,
         join_handle: BobApplicationHandle,
@@ -637,6 +718,47 @@ impl TestContext {
         .unwrap();
     }
 
+    /// Asserts that the taker of a `sell-xmr` swap, which uses Bob's wallets,
+    /// received the BTC of the ASB in exchange for its XMR.
+    pub async fn assert_taker_sold_xmr(&self, state: AliceState) {
+        assert!(matches!(state, AliceState::BtcRedeemed));
+
+        assert_eventual_balance(
+            self.bob_bitcoin_wallet.as_ref(),
+            Ordering::Greater,
+            self.bob_starting_balances.btc,
+        )
+        .await
+        .unwrap();
+
+        assert_eventual_balance(
+            self.bob_monero_wallet.as_ref(),
+            Ordering::Less,
+            self.bob_starting_balances.xmr - self.xmr_amount,
+        )
+        .await
+        .unwrap();
+
+        assert_eventual_balance(
+            self.alice_bitcoin_wallet.as_ref(),
+            Ordering::Less,
+            self.alice_starting_balances.btc,
+        )
+        .await
+        .unwrap();
+
+        // the ASB only redeems the XMR once it saw our redeem transaction and
+        // then transfers them into its own wallet
+        assert_eventual_balance_within(
+            self.alice_monero_wallet.as_ref(),
+            Ordering::Greater,
+            self.alice_starting_balances.xmr,
+            Duration::from_secs(60),
+        )
+        .await
+        .unwrap();
+    }
+
     pub async fn assert_bob_refunded(&self, state: BobState) {
         self.bob_bitcoin_wallet.sync().await.unwrap();
 
@@ -780,6 +902,15 @@ async fn assert_eventual_balance<A: fmt::Display + PartialOrd>(
     wallet: &impl Wallet<Amount = A>,
     ordering: Ordering,
     expected: A,
+) -> Result<()> {
+    assert_eventual_balance_within(wallet, ordering, expected, Duration::from_secs(10)).await
+}
+
+async fn assert_eventual_balance_within<A: fmt::Display + PartialOrd>(
+    wallet: &impl Wallet<Amount = A>,
+    ordering: Ordering,
+    expected: A,
+    timeout: Duration,
 ) -> Result<()> {
     let ordering_str = match ordering {
         Ordering::Less => "less than",
@@ -807,8 +938,6 @@ async fn assert_eventual_balance<A: fmt::Display + PartialOrd>(
         Result::<_, anyhow::Error>::Ok(())
     };
 
-    let timeout = Duration::from_secs(10);
-
     tokio::time::timeout(timeout, assertion)
         .await
         .with_context(|| {